@@ -1,5 +1,6 @@
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use clap_complete::Shell;
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(
@@ -12,30 +13,82 @@ pub struct Cli {
     #[arg(long, global = true)]
     pub dry_run: bool,
 
+    /// Refuse to run commands that modify anything (also GIT_ID_READONLY=1)
+    #[arg(long, global = true)]
+    pub read_only: bool,
+
+    /// Manage another user's git-id setup (resolves their home directory)
+    #[arg(long, global = true, value_name = "NAME", conflicts_with = "home")]
+    pub user: Option<String>,
+
+    /// Use this directory instead of $HOME
+    #[arg(long, global = true, value_name = "PATH")]
+    pub home: Option<PathBuf>,
+
+    /// Print credentials embedded in remote URLs instead of masking them
+    #[arg(long, global = true)]
+    pub show_secrets: bool,
+
+    /// Exit with status 3 when any warning was printed, even if the command succeeded
+    #[arg(long, global = true)]
+    pub strict_warnings: bool,
+
+    /// Log external commands and file access to stderr (-vv adds command output)
+    #[arg(long, short = 'v', global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
-    /// Add a new account (interactive wizard)
-    Add,
+    /// Add a new account (interactive wizard unless flags are given)
+    Add(AddArgs),
     /// List all accounts with status
-    List,
+    List {
+        /// Output format
+        #[arg(long, value_parser = crate::output::format_parser(true), conflicts_with = "json")]
+        format: Option<String>,
+        /// Print a JSON document instead of text (same as --format json)
+        #[arg(long)]
+        json: bool,
+        /// List the SSH keys under ~/.ssh and what uses each of them
+        #[arg(long)]
+        keys: bool,
+        /// With --keys, also check which keys are registered on each forge
+        #[arg(long, requires = "keys")]
+        network: bool,
+    },
     /// Set identity for repo or globally
-    Use {
-        /// GitHub username (or username@host)
-        username: String,
-        /// Apply to global git config instead of current repo
-        #[arg(long = "global")]
+    Use(UseArgs),
+    /// Switch back to the previously used account (like `cd -`)
+    Switch {
+        /// Switch the global identity instead of the repo's
+        #[arg(long)]
         global: bool,
-        /// Convert remote URL to SSH format
+        /// Switch this worktree's identity (set with `use --worktree`)
+        #[arg(long, conflicts_with = "global")]
+        worktree: bool,
+    },
+    /// Clone a repo with an account's remote URL and identity
+    Clone {
+        /// Repository URL (SSH or HTTPS)
+        url: String,
+        /// Directory to clone into (defaults to the repo name)
+        dest: Option<PathBuf>,
+        /// Account to clone as (defaults to the account owning the repo)
+        #[arg(long, short = 'a')]
+        account: Option<String>,
+        /// Clone over SSH
         #[arg(long = "ssh")]
         force_ssh: bool,
-        /// Convert remote URL to HTTPS format
+        /// Clone over HTTPS
         #[arg(long = "https")]
         force_https: bool,
     },
+    /// Change an account's email, host, SSH key or token
+    Edit(EditArgs),
     /// Remove an account and its SSH config stanza
     Remove {
         /// GitHub username (or username@host)
@@ -53,28 +106,727 @@ pub enum Commands {
         subcommand: SshCommands,
     },
     /// Show current identity and loaded SSH keys
-    Status,
+    Status {
+        /// Output format
+        #[arg(long, value_parser = crate::output::format_parser(false), conflicts_with = "json")]
+        format: Option<String>,
+        /// Print a JSON document instead of text (same as --format json)
+        #[arg(long)]
+        json: bool,
+        /// Show this remote instead of the primary one
+        #[arg(long, value_name = "NAME", conflicts_with = "all_remotes")]
+        remote: Option<String>,
+        /// Show every remote
+        #[arg(long)]
+        all_remotes: bool,
+    },
+    /// Exit non-zero unless this repo commits as the account it expects (for hooks and CI)
+    Check {
+        /// Expect this account instead of the pinned one or the remote's owner
+        #[arg(long)]
+        account: Option<String>,
+        /// Output format
+        #[arg(long, value_parser = crate::output::format_parser(false), conflicts_with = "json")]
+        format: Option<String>,
+        /// Print a JSON document instead of text (same as --format json)
+        #[arg(long)]
+        json: bool,
+    },
+    /// Re-run a fetch (or push) with `ssh -v` and explain why it fails
+    ExplainFailure {
+        /// Remote to contact [default: origin]
+        remote: Option<String>,
+        /// Try a push (`git push --dry-run`) instead of a fetch
+        #[arg(long)]
+        push: bool,
+    },
+    /// Check accounts, keys, SSH stanzas, agents and tokens for problems
+    Doctor {
+        /// Also connect to each account's host, hop by hop through any ProxyJump
+        #[arg(long)]
+        network: bool,
+    },
+    /// Print shell exports for an account's `env` table (eval the output)
+    Env {
+        /// Account to export (defaults to the one matching the active email)
+        account: Option<String>,
+    },
+    /// Print the effective identity on one line (for prompts and scripts)
+    Whoami,
+    /// Print the account the effective identity belongs to
+    Current {
+        /// Print only its username, or nothing, fast and silently (for prompt segments)
+        #[arg(long)]
+        prompt: bool,
+    },
+    /// Print an account's HTTPS token (mints GitHub App tokens on demand)
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Token {
+        /// GitHub username (or username@host)
+        #[arg(required = true)]
+        username: Option<String>,
+        #[command(subcommand)]
+        subcommand: Option<TokenCommands>,
+    },
+    /// Run a git command as an account without changing any config
+    With {
+        /// GitHub username (or username@host)
+        username: String,
+        /// The git command to run, e.g. `git push origin main`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Move the current repo from one account to another
+    Transfer {
+        /// Account the repo currently uses (username or username@host)
+        from: String,
+        /// Account the repo should use from now on (username or username@host)
+        to: String,
+        /// Reset the author of the last N unpushed commits to the new identity
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        reauthor: usize,
+    },
+    /// Check that an account is set up correctly on its forge
+    Verify {
+        /// Account to check (defaults to the one matching the active email)
+        account: Option<String>,
+        /// Only check that commits with the account's email are credited to it
+        #[arg(long)]
+        attribution: bool,
+    },
+    /// Give every repo under a directory an account's identity
+    Dirs {
+        #[command(subcommand)]
+        subcommand: DirsCommands,
+    },
+    /// Manage the registry of repositories git-id knows about
+    Repos {
+        #[command(subcommand)]
+        subcommand: ReposCommands,
+    },
+    /// List, test and pick the hosts your accounts live on
+    Host {
+        #[command(subcommand)]
+        subcommand: HostCommands,
+    },
+    /// Require an email in repos of an owner or host
+    Rules {
+        #[command(subcommand)]
+        subcommand: RulesCommands,
+    },
+    /// Block commits in this repo made with an email no account uses
+    Hook {
+        #[command(subcommand)]
+        subcommand: HookCommands,
+    },
+    /// Compare accounts against a declarative spec
+    Accounts {
+        #[command(subcommand)]
+        subcommand: AccountsCommands,
+    },
+    /// List the backups of accounts.toml and the SSH config, or restore one
+    Restore {
+        /// Which file to restore (default: any)
+        #[arg(value_parser = crate::commands::restore::TARGETS)]
+        target: Option<String>,
+        /// Only list the backups
+        #[arg(long, conflicts_with = "from")]
+        list: bool,
+        /// Backup to restore, by its number in --list (default: ask)
+        #[arg(long, value_name = "N")]
+        from: Option<usize>,
+    },
+    /// Browse accounts, keys and the current repo in a full-screen dashboard
+    Tui,
+    /// Tidy the backups git-id keeps of accounts.toml and the SSH config
+    Backups {
+        #[command(subcommand)]
+        subcommand: BackupsCommands,
+    },
+    /// Remove everything git-id set up: SSH stanzas, directory rules, credential
+    /// helpers, completions and the config dir
+    Uninstall {
+        /// Also clean git-id settings from this repository (repeatable; the
+        /// current repo and registered repos are always cleaned)
+        #[arg(long = "repo", value_name = "PATH")]
+        repos: Vec<PathBuf>,
+        /// Keep backup files (*.bak.*) instead of deleting them
+        #[arg(long)]
+        keep_backups: bool,
+        /// Skip confirmation prompt
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Write all accounts (optionally with their SSH keys) to one TOML file
+    Export {
+        /// File to write [default: stdout]
+        #[arg(long, short = 'o', value_name = "PATH")]
+        output: Option<PathBuf>,
+        /// Bundle the accounts' SSH key files, private keys included
+        #[arg(long)]
+        include_keys: bool,
+        /// Leave HTTPS tokens out of the export (secret references are kept)
+        #[arg(long)]
+        no_tokens: bool,
+    },
+    /// Add the accounts from a `git-id export` file and write their SSH config,
+    /// or find the identities commit history uses that no account covers
+    Import {
+        /// File written by `git-id export`
+        #[arg(required_unless_present = "from_history", conflicts_with = "from_history")]
+        file: Option<PathBuf>,
+        /// Scan the history of the repositories under these directories
+        /// (default: the registered repositories and the current one)
+        #[arg(long, value_name = "DIR", num_args = 0..)]
+        from_history: Option<Vec<PathBuf>>,
+        /// With --from-history, skip identities with fewer commits than this
+        #[arg(long, value_name = "N", default_value_t = 3)]
+        min_commits: usize,
+    },
+    /// git credential helper backed by git-id accounts (used by git itself)
+    #[command(hide = true)]
+    Credential {
+        /// Helper operation passed by git: get, store or erase
+        operation: String,
+        /// Account to answer for (set by `use`; otherwise matched by host)
+        #[arg(long)]
+        account: Option<String>,
+    },
     /// Generate shell completion script
     Completions {
         /// Shell to generate completions for
         shell: Shell,
     },
+    /// Tools for testing git-id itself
+    #[command(hide = true)]
+    Debug {
+        #[command(subcommand)]
+        subcommand: DebugCommands,
+    },
+}
+
+#[derive(Args, Default)]
+pub struct AddArgs {
+    /// Account username (skips the wizard)
+    #[arg(long)]
+    pub username: Option<String>,
+    /// Commit email
+    #[arg(long)]
+    pub email: Option<String>,
+    /// Commit with the account's GitHub noreply address, looked up on the
+    /// host, instead of --email
+    #[arg(long, conflicts_with = "email")]
+    pub noreply: bool,
+    /// Git host [default: github.com, or the one set with `host set-default`]
+    #[arg(long)]
+    pub host: Option<String>,
+    /// Existing private key to use for SSH
+    #[arg(long, value_name = "PATH")]
+    pub ssh_key: Option<String>,
+    /// Read an HTTPS personal access token from stdin
+    #[arg(long)]
+    pub token_stdin: bool,
+    /// Save the token without asking GitHub whether it can push
+    #[arg(long)]
+    pub skip_token_check: bool,
+    /// Remote name this account's repos use [default: origin]
+    #[arg(long)]
+    pub remote: Option<String>,
+    /// Date (YYYY-MM-DD) after which the account should no longer be used
+    #[arg(long, value_name = "DATE")]
+    pub expires: Option<String>,
+    /// Use this SSH Host alias (e.g. one your remotes already use) instead of
+    /// generating `<host>-<username>`
+    #[arg(long, value_name = "ALIAS")]
+    pub ssh_alias: Option<String>,
+    /// Transport `use` and `clone` switch remotes to [default: current]
+    #[arg(long, value_parser = ["ssh", "https", "current"])]
+    pub protocol: Option<String>,
+    /// Client certificate to present over HTTPS, for servers that require
+    /// mutual TLS
+    #[arg(long, value_name = "PATH")]
+    pub https_cert: Option<String>,
+    /// Private key of --https-cert, when the certificate file lacks it
+    #[arg(long, value_name = "PATH")]
+    pub https_key: Option<String>,
+    /// Timezone to date the account's commits in, e.g. Europe/Berlin
+    /// [default: local time]
+    #[arg(long, value_name = "TZ")]
+    pub commit_timezone: Option<String>,
+    /// Sign commits with this GPG key id, or SSH public key file
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: Option<String>,
+    /// Commit message template `use` sets up
+    #[arg(long, value_name = "PATH")]
+    pub commit_template: Option<String>,
+    /// What the host runs, for GitHub Enterprise servers `host test` cannot
+    /// reach [default: auto]
+    #[arg(long, value_parser = ["github", "gitlab", "auto"])]
+    pub forge: Option<String>,
+    /// Also use the account for repos of this user or organisation (repeatable)
+    #[arg(long = "owner", value_name = "OWNER")]
+    pub owners: Vec<String>,
+    /// A login the account had before it was renamed (repeatable)
+    #[arg(long = "aka", value_name = "LOGIN")]
+    pub aka: Vec<String>,
+    /// Another email the account commits with, e.g. a work alias or the
+    /// noreply address; `use --email-index` picks it (repeatable)
+    #[arg(long = "alt-email", value_name = "EMAIL")]
+    pub alt_emails: Vec<String>,
+    /// Agent socket that holds the key instead of a file on disk, or
+    /// "1password" for the 1Password SSH agent
+    #[arg(long, value_name = "SOCKET")]
+    pub identity_agent: Option<String>,
+    /// How long ssh-agent keeps the key (`ssh-add -t`), e.g. 3600 or 1h30m
+    #[arg(long, value_name = "TIME")]
+    pub agent_lifetime: Option<String>,
+    /// Have ssh-agent ask before each use of the key (`ssh-add -c`)
+    #[arg(long, value_parser = ["yes", "no", "default"])]
+    pub agent_confirm: Option<String>,
+    /// If the account already exists, change the given fields instead of failing
+    #[arg(long)]
+    pub update: bool,
+}
+
+impl AddArgs {
+    /// True when no field flag was given and the wizard should run.
+    pub fn is_empty(&self) -> bool {
+        self.username.is_none()
+            && self.email.is_none()
+            && !self.noreply
+            && self.host.is_none()
+            && self.ssh_key.is_none()
+            && !self.token_stdin
+            && self.remote.is_none()
+            && self.expires.is_none()
+            && self.ssh_alias.is_none()
+            && self.protocol.is_none()
+            && self.https_cert.is_none()
+            && self.https_key.is_none()
+            && self.commit_timezone.is_none()
+            && self.signing_key.is_none()
+            && self.commit_template.is_none()
+            && self.forge.is_none()
+            && self.owners.is_empty()
+            && self.aka.is_empty()
+            && self.alt_emails.is_empty()
+            && self.identity_agent.is_none()
+            && self.agent_lifetime.is_none()
+            && self.agent_confirm.is_none()
+    }
+}
+
+#[derive(Args)]
+pub struct EditArgs {
+    /// GitHub username (or username@host)
+    pub username: String,
+    /// New commit email
+    #[arg(long)]
+    pub email: Option<String>,
+    /// New git host
+    #[arg(long)]
+    pub host: Option<String>,
+    /// New private key to use for SSH
+    #[arg(long, value_name = "PATH")]
+    pub ssh_key: Option<String>,
+    /// Read a new HTTPS token from stdin (an empty line clears it)
+    #[arg(long)]
+    pub token_stdin: bool,
+    /// Transport `use` and `clone` switch remotes to ("current" keeps it)
+    #[arg(long, value_parser = ["ssh", "https", "current"])]
+    pub protocol: Option<String>,
+    /// New HTTPS client certificate (empty removes it)
+    #[arg(long, value_name = "PATH")]
+    pub https_cert: Option<String>,
+    /// New private key of the HTTPS client certificate (empty removes it)
+    #[arg(long, value_name = "PATH")]
+    pub https_key: Option<String>,
+    /// Timezone to date the account's commits in (empty goes back to local
+    /// time)
+    #[arg(long, value_name = "TZ")]
+    pub commit_timezone: Option<String>,
+    /// Sign commits with this GPG key id, or SSH public key file (empty
+    /// stops signing)
+    #[arg(long, value_name = "KEY")]
+    pub signing_key: Option<String>,
+    /// Commit message template `use` sets up (empty removes it)
+    #[arg(long, value_name = "PATH")]
+    pub commit_template: Option<String>,
+    /// What the host runs ("auto" goes by the host name and `host test`)
+    #[arg(long, value_parser = ["github", "gitlab", "auto"])]
+    pub forge: Option<String>,
+    /// Agent socket that holds the key, or "1password" (empty goes back to
+    /// the key file and ssh-agent)
+    #[arg(long, value_name = "SOCKET")]
+    pub identity_agent: Option<String>,
+    /// How long ssh-agent keeps the key, e.g. 3600 or 1h30m (empty uses
+    /// settings.toml)
+    #[arg(long, value_name = "TIME")]
+    pub agent_lifetime: Option<String>,
+    /// Have ssh-agent ask before each use of the key ("default" uses
+    /// settings.toml)
+    #[arg(long, value_parser = ["yes", "no", "default"])]
+    pub agent_confirm: Option<String>,
+}
+
+impl EditArgs {
+    /// True when no field flag was given and the prompts should run.
+    pub fn is_empty(&self) -> bool {
+        self.email.is_none()
+            && self.host.is_none()
+            && self.ssh_key.is_none()
+            && !self.token_stdin
+            && self.protocol.is_none()
+            && self.https_cert.is_none()
+            && self.https_key.is_none()
+            && self.commit_timezone.is_none()
+            && self.signing_key.is_none()
+            && self.commit_template.is_none()
+            && self.forge.is_none()
+            && self.identity_agent.is_none()
+            && self.agent_lifetime.is_none()
+            && self.agent_confirm.is_none()
+    }
+}
+
+#[derive(Args, Default)]
+pub struct UseArgs {
+    /// GitHub username (or username@host)
+    #[arg(required_unless_present = "auto", conflicts_with = "auto")]
+    pub username: Option<String>,
+    /// Pick the account that owns the repo's remotes (by username, `owners`
+    /// or `aka`) instead of naming it
+    #[arg(long, conflicts_with = "global")]
+    pub auto: bool,
+    /// Apply to global git config instead of current repo
+    #[arg(long = "global")]
+    pub global: bool,
+    /// Apply to this worktree only, so other worktrees of the repo can use
+    /// other accounts (turns on extensions.worktreeConfig; remotes are left alone)
+    #[arg(long, conflicts_with_all = [
+        "global", "dual", "remotes", "all_remotes", "force_ssh", "force_https", "refresh"
+    ])]
+    pub worktree: bool,
+    /// Load the account's key into ssh-agent and unload every other
+    /// account's key (default: `exclusive` in settings.toml)
+    #[arg(long)]
+    pub exclusive: bool,
+    /// Convert remote URL to SSH format (overrides the account's protocol)
+    #[arg(long = "ssh")]
+    pub force_ssh: bool,
+    /// Convert remote URL to HTTPS format (overrides the account's protocol)
+    #[arg(long = "https")]
+    pub force_https: bool,
+    /// Keep the remote on SSH and maintain a `<remote>-https` companion
+    #[arg(long)]
+    pub dual: bool,
+    /// Use the account even if its expiry date has passed
+    #[arg(long)]
+    pub allow_expired: bool,
+    /// Use the account even if an email rule requires another email here
+    #[arg(long)]
+    pub ignore_rules: bool,
+    /// Which of the account's emails to commit with: 0 is its main email,
+    /// 1 and up its alternate emails in order [default: ask when it has any]
+    #[arg(long, value_name = "N")]
+    pub email_index: Option<usize>,
+    /// Fetch the account's remote afterwards (pruning, updating its HEAD)
+    /// to check that the new URL authenticates
+    #[arg(long)]
+    pub refresh: bool,
+    /// Rewrite only this remote (repeatable)
+    #[arg(long = "remote", value_name = "NAME", conflicts_with = "global")]
+    pub remotes: Vec<String>,
+    /// Rewrite every remote, not just the ones that belong to the account
+    #[arg(long, conflicts_with_all = ["remotes", "global"])]
+    pub all_remotes: bool,
 }
 
 #[derive(Subcommand)]
 pub enum SshCommands {
-    /// Generate a new ed25519 key
+    /// Generate a new SSH key (ed25519 unless --type says otherwise)
     Gen {
         /// GitHub username (or username@host)
         username: String,
+        /// Key type; some older servers only accept rsa, *-sk keys live on a FIDO2 token
+        #[arg(
+            long = "type",
+            default_value = "ed25519",
+            value_parser = ["ed25519", "rsa", "ecdsa", "ed25519-sk", "ecdsa-sk"]
+        )]
+        key_type: String,
+        /// Key size: 2048-16384 for rsa (default 4096), 256, 384 or 521 for ecdsa (default 256)
+        #[arg(long)]
+        bits: Option<u32>,
+        /// Protect the key with a passphrase (asked twice)
+        #[arg(long)]
+        passphrase: bool,
+        /// Read the key's passphrase from the first line of stdin
+        #[arg(long, conflicts_with = "passphrase")]
+        passphrase_stdin: bool,
+    },
+    /// Replace the account's key with a new one, keeping the old pair aside
+    Rotate {
+        /// GitHub username (or username@host)
+        username: String,
+        /// Type of the new key [default: the current key's type, or ed25519]
+        #[arg(
+            long = "type",
+            value_parser = ["ed25519", "rsa", "ecdsa", "ed25519-sk", "ecdsa-sk"]
+        )]
+        key_type: Option<String>,
+        /// Protect the new key with a passphrase (asked twice)
+        #[arg(long)]
+        passphrase: bool,
     },
-    /// Pick an existing ~/.ssh/*.pub key
+    /// Pick an existing ~/.ssh/*.pub key, or install a private key from
+    /// elsewhere into ~/.ssh
     Pick {
         /// GitHub username (or username@host)
         username: String,
+        /// Install this private key (e.g. on a mounted secrets volume)
+        #[arg(long, value_name = "PATH", conflicts_with = "paste")]
+        from: Option<PathBuf>,
+        /// Install a private key pasted on stdin
+        #[arg(long)]
+        paste: bool,
+    },
+    /// Write the SSH config stanzas for all accounts (~/.ssh/config.d/git-id.conf)
+    Config {
+        /// Regenerate outdated stanzas without asking; hand-edited ones are kept
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Print the ssh command (or GIT_SSH_COMMAND) that uses the account's key
+    Command {
+        /// GitHub username (or username@host)
+        username: String,
+        /// Print a GIT_SSH_COMMAND assignment instead of a full ssh command
+        #[arg(long)]
+        env: bool,
+    },
+    /// Export the private key age-encrypted to team recovery recipients
+    ExportEscrow {
+        /// GitHub username (or username@host)
+        username: String,
+        /// age or SSH public keys (or files of them) that may decrypt the export
+        #[arg(long, num_args = 1.., required = true, value_name = "KEY")]
+        recipients: Vec<String>,
+        /// Where to write the encrypted key [default: ./<account>.key.age]
+        #[arg(long, short = 'o', value_name = "PATH")]
+        output: Option<PathBuf>,
+        /// Skip the confirmation prompt
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Write the account and its key pair to a passphrase-encrypted bundle
+    Export {
+        /// GitHub username (or username@host)
+        username: String,
+        /// Where to write the bundle [default: ./<account>.key.enc]
+        #[arg(long, short = 'o', value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Restore an account's key pair from a bundle written by `ssh export`
+    Import {
+        /// GitHub username (or username@host)
+        username: String,
+        /// Bundle written by `ssh export`
+        file: PathBuf,
+    },
+    /// Check that the account's key authenticates as the right login
+    Test {
+        /// GitHub username (or username@host)
+        username: String,
+    },
+}
+
+impl Commands {
+    /// Whether the command writes config, keys or remotes.
+    pub fn is_mutating(&self) -> bool {
+        !matches!(
+            self,
+            Commands::List { .. }
+                | Commands::Status { .. }
+                | Commands::Whoami
+                | Commands::Current { .. }
+                | Commands::Env { .. }
+                | Commands::Check { .. }
+                | Commands::ExplainFailure { .. }
+                | Commands::Doctor { .. }
+                | Commands::Credential { .. }
+                | Commands::Ssh { subcommand: SshCommands::Test { .. } }
+                | Commands::Ssh { subcommand: SshCommands::Command { .. } }
+                | Commands::Verify { .. }
+                | Commands::Accounts { subcommand: AccountsCommands::Diff { .. } }
+                | Commands::Dirs { subcommand: DirsCommands::List | DirsCommands::Tree }
+                | Commands::Repos { subcommand: ReposCommands::List }
+                | Commands::Restore { list: true, .. }
+                | Commands::Token { subcommand: None | Some(TokenCommands::Check { .. }), .. }
+                | Commands::Host { subcommand: HostCommands::List }
+                | Commands::Host { subcommand: HostCommands::Test { .. } }
+                | Commands::Hook { subcommand: HookCommands::Check }
+                | Commands::Hook { subcommand: HookCommands::PrePush { .. } }
+                | Commands::Rules { subcommand: RulesCommands::List }
+                | Commands::Export { output: None, .. }
+        )
+    }
+}
+
+#[derive(Subcommand)]
+pub enum DirsCommands {
+    /// Use an account for every repo under a directory (gitconfig includeIf)
+    Add {
+        /// Directory containing the repos
+        path: String,
+        /// GitHub username (or username@host)
+        username: String,
+    },
+    /// Stop applying an identity to a directory
+    Remove {
+        /// Directory passed to `dirs add`
+        path: String,
+    },
+    /// Show directory rules from the global gitconfig
+    List,
+    /// Show directory rules as a tree, with the registered repos under them
+    /// and the account each one gets
+    Tree,
+    /// Remove directory rules and include fragments left behind by deleted
+    /// accounts and moved directories (backed up first)
+    Gc,
+}
+
+#[derive(Subcommand)]
+pub enum HostCommands {
+    /// Show each host with its accounts and API base
+    List,
+    /// Check SSH connectivity, each account's key and the REST API of a host
+    Test {
+        /// Host name as used by accounts (e.g. github.example.com)
+        host: String,
+    },
+    /// Make a host the default for `git-id add`
+    SetDefault {
+        /// Host name as used by accounts
+        host: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RulesCommands {
+    /// Show the rules and which one covers the current repo
+    List,
+    /// Require an email for commits in repos of an owner and/or host
+    Add {
+        /// Email commits must use
+        email: String,
+        /// User or organisation owning the repos (e.g. acme-corp)
+        #[arg(long)]
+        owner: Option<String>,
+        /// Host of the repos (any host when omitted)
+        #[arg(long)]
+        host: Option<String>,
+    },
+    /// Remove a rule by its number in `rules list`
+    Remove {
+        index: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TokenCommands {
+    /// Check that the token is accepted, including SAML single sign-on
+    Check {
+        /// GitHub username (or username@host)
+        username: String,
+        /// Open the pages that authorize the token for single sign-on
+        #[arg(long)]
+        open: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ReposCommands {
+    /// Register repositories git-id did not set up (default: the current one)
+    Add {
+        /// Directories inside the repositories
+        paths: Vec<PathBuf>,
+    },
+    /// Take repositories out of the registry (the repositories are left alone)
+    Remove {
+        /// Registered repositories
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// List the registered repositories and the account each one uses
+    List,
+    /// Drop entries whose directory is gone or no longer a git repository
+    Clean,
+}
+
+#[derive(Subcommand)]
+pub enum BackupsCommands {
+    /// Delete backups past the retention in settings.toml
+    Clean {
+        /// Keep this many of each file's newest backups instead
+        #[arg(long, value_name = "N")]
+        keep: Option<usize>,
+        /// Also keep backups younger than this many days
+        #[arg(long, value_name = "DAYS")]
+        days: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HookCommands {
+    /// Add the identity check to the repo's pre-commit hook
+    Install {
+        /// Require this account's email instead of any account's
+        #[arg(long)]
+        account: Option<String>,
+        /// Also stop pushes GitHub would reject for exposing a private email
+        #[arg(long)]
+        pre_push: bool,
+    },
+    /// Remove the identity checks from the repo's hooks
+    Uninstall,
+    /// Run the check (called by the hook)
+    #[command(hide = true)]
+    Check,
+    /// Run the private email check (called by the pre-push hook)
+    #[command(hide = true)]
+    PrePush {
+        /// Name of the remote pushed to
+        remote: String,
+        /// URL of the remote pushed to
+        url: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AccountsCommands {
+    /// Show how the live configuration differs from a desired-state accounts file
+    Diff {
+        /// Desired-state TOML in the accounts.toml format
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DebugCommands {
+    /// Build a throwaway HOME with accounts, keys, SSH stanzas and sample repos
+    Fixture {
+        /// What to set up: basic, hosts (several forges) or broken (problems to diagnose)
+        #[arg(value_parser = crate::commands::debug::SCENARIOS)]
+        scenario: String,
+        /// Number of accounts
+        #[arg(long, default_value_t = 2)]
+        accounts: usize,
+        /// Directory to build it in [default: a new one under the temp dir]
+        #[arg(long, value_name = "PATH")]
+        dir: Option<PathBuf>,
     },
-    /// Write ~/.ssh/config stanzas for all accounts
-    Config,
 }
 
 pub fn build_command() -> clap::Command {
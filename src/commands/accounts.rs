@@ -0,0 +1,143 @@
+use crate::config::{account_id, load_accounts, read_accounts_file};
+use crate::models::Account;
+use crate::ssh::{all_ssh_config, current_stanza, make_stanza};
+use crate::error::{GitIdError, Result};
+use crate::ui::{color, print_hdr, print_ok};
+use std::path::Path;
+
+/// Compares the live configuration with a desired-state accounts file and
+/// prints the differences. Fails with `CheckFailed` when anything differs.
+pub fn cmd_accounts_diff(file: &Path) -> Result<()> {
+    if !file.exists() {
+        return Err(GitIdError::Usage(format!("Spec file not found: {}", file.display())));
+    }
+    let spec = read_accounts_file(file)?;
+    let live = load_accounts()?;
+    let ssh_config = all_ssh_config();
+
+    print_hdr(&format!("Live configuration vs {}", file.display()));
+    let mut differences = 0;
+
+    for want in &spec {
+        let id = account_id(want);
+        let Some(have) = live.iter().find(|a| account_id(a) == id) else {
+            println!("  {} {id}  {}", color("green", "+"), color("dim", "(missing)"));
+            differences += 1;
+            continue;
+        };
+        for (field, expected, actual) in field_changes(want, have) {
+            println!(
+                "  {} {id}  {field}: {} -> {}",
+                color("yellow", "~"),
+                display_value(&actual),
+                display_value(&expected)
+            );
+            differences += 1;
+        }
+        if !have.ssh_key.is_empty() || !want.ssh_key.is_empty() {
+            let expected =
+                make_stanza(&crate::secrets::resolved(want).unwrap_or_else(|_| want.clone()));
+            match current_stanza(&ssh_config, &id) {
+                Some(stanza) if stanza == expected => {}
+                Some(_) => {
+                    println!("  {} {id}  ssh stanza differs", color("yellow", "~"));
+                    differences += 1;
+                }
+                None => {
+                    println!("  {} {id}  ssh stanza missing", color("yellow", "~"));
+                    differences += 1;
+                }
+            }
+        }
+    }
+
+    for have in &live {
+        let id = account_id(have);
+        if !spec.iter().any(|a| account_id(a) == id) {
+            println!("  {} {id}  {}", color("red", "-"), color("dim", "(not in spec)"));
+            differences += 1;
+        }
+    }
+
+    if differences == 0 {
+        print_ok("No differences");
+        return Ok(());
+    }
+    println!("\n  {differences} difference(s)");
+    Err(GitIdError::CheckFailed)
+}
+
+/// Fields whose values differ, as (name, spec value, live value). Token
+/// values are masked so secrets never reach the terminal; secret references
+/// are shown since they hold nothing secret.
+fn field_changes(want: &Account, have: &Account) -> Vec<(&'static str, String, String)> {
+    let pairs = [
+        ("email", want.email.clone(), have.email.clone()),
+        ("ssh_key", want.ssh_key.clone(), have.ssh_key.clone()),
+        ("remote", want.remote.clone(), have.remote.clone()),
+        ("identity_agent", want.identity_agent.clone(), have.identity_agent.clone()),
+        ("exclude_remotes", want.exclude_remotes.join(","), have.exclude_remotes.join(",")),
+        ("control_persist", want.control_persist.clone(), have.control_persist.clone()),
+        ("control_path", want.control_path.clone(), have.control_path.clone()),
+        ("proxy_jump", want.proxy_jump.clone(), have.proxy_jump.clone()),
+        ("app_id", want.app_id.clone(), have.app_id.clone()),
+        ("app_key", want.app_key.clone(), have.app_key.clone()),
+        ("app_installation_id", want.app_installation_id.clone(), have.app_installation_id.clone()),
+        ("expires", want.expires.clone(), have.expires.clone()),
+        ("ssh_alias", want.ssh_alias.clone(), have.ssh_alias.clone()),
+        ("protocol", want.protocol.clone(), have.protocol.clone()),
+        ("https_cert", want.https_cert.clone(), have.https_cert.clone()),
+        ("https_key", want.https_key.clone(), have.https_key.clone()),
+        ("key_created", want.key_created.clone(), have.key_created.clone()),
+        ("commit_timezone", want.commit_timezone.clone(), have.commit_timezone.clone()),
+        ("signing_key", want.signing_key.clone(), have.signing_key.clone()),
+        ("commit_template", want.commit_template.clone(), have.commit_template.clone()),
+        ("forge", want.forge.clone(), have.forge.clone()),
+        ("agent_lifetime", want.agent_lifetime.clone(), have.agent_lifetime.clone()),
+        (
+            "agent_confirm",
+            want.agent_confirm.map(|c| c.to_string()).unwrap_or_default(),
+            have.agent_confirm.map(|c| c.to_string()).unwrap_or_default(),
+        ),
+        ("owners", want.owners.join(","), have.owners.join(",")),
+        ("aka", want.aka.join(","), have.aka.join(",")),
+        (
+            "previous_emails",
+            want.previous_emails.join(","),
+            have.previous_emails.join(","),
+        ),
+        ("alt_emails", want.alt_emails.join(","), have.alt_emails.join(",")),
+        (
+            "ssh_keep_manual",
+            want.ssh_keep_manual.to_string(),
+            have.ssh_keep_manual.to_string(),
+        ),
+    ];
+    let mut changes: Vec<_> = pairs.into_iter().filter(|(_, w, h)| w != h).collect();
+    if want.https_token != have.https_token {
+        let mask = |v: &str, label: &str| match v {
+            "" => String::new(),
+            v if crate::secrets::is_reference(v) => v.to_string(),
+            _ => label.to_string(),
+        };
+        changes.push((
+            "https_token",
+            mask(&want.https_token, "<spec token>"),
+            mask(&have.https_token, "<live token>"),
+        ));
+    }
+    if want.env != have.env {
+        // Values are often registry tokens: compare them, show only names.
+        let names = |env: &std::collections::BTreeMap<String, String>| {
+            env.keys().cloned().collect::<Vec<_>>().join(",")
+        };
+        let (w, h) = (names(&want.env), names(&have.env));
+        let (w, h) = if w == h { (format!("{w} (values differ)"), h) } else { (w, h) };
+        changes.push(("env", w, h));
+    }
+    changes
+}
+
+fn display_value(v: &str) -> String {
+    if v.is_empty() { color("dim", "(unset)") } else { format!("{v:?}") }
+}
@@ -1,42 +1,554 @@
-use crate::config::{ensure_accounts_file, load_accounts, save_accounts};
+use crate::cli::AddArgs;
+use crate::commands::edit::{edit_interactive, save_edit};
+use crate::commands::ssh::{confirm_key_upload, read_passphrase, upload_key};
+use crate::commands::token::new_token_problem;
+use crate::config::{
+    account_id, accounts_file, commit_emails, ensure_accounts_file, expand_home, load_accounts,
+    owns, remote_name, save_accounts, ssh_host_alias,
+};
+use crate::dates::{parse_date, today};
+use crate::error::{GitIdError, Result};
+use crate::forge::{is_github, noreply_email};
+use crate::git::{default_host, get_remote_url, in_git_repo, parse_remote_url};
 use crate::models::Account;
-use crate::ssh::{add_key_to_agent, fix_key_permissions, gen_ssh_key, ssh_dir, update_ssh_config};
-use crate::ui::{color, die, print_hdr, print_info, print_ok, print_warn};
+use crate::secrets::warn_unresolved;
+use crate::ssh::{
+    KEY_TYPES, KeyOptions, add_key_to_agent, agent_public_keys, default_key_path,
+    fix_key_permissions, gen_ssh_key, onepassword_agent_socket, ssh_dir, unmanaged_aliases,
+    update_ssh_config, valid_agent_lifetime, write_agent_public_key,
+};
+use crate::ui::{color, print_hdr, print_info, print_ok, print_warn};
 use dialoguer::{Input, Select};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-pub fn cmd_add(dry_run: bool) {
-    ensure_accounts_file();
-    let mut accounts = load_accounts();
+pub fn cmd_add(args: &AddArgs, dry_run: bool) -> Result<()> {
+    ensure_accounts_file()?;
+    let accounts = load_accounts()?;
+    if args.is_empty() {
+        add_interactive(accounts, args.update, dry_run)
+    } else {
+        add_from_flags(accounts, args, dry_run)
+    }
+}
+
+/// Creates the account from command-line flags without any prompts.
+fn add_from_flags(accounts: Vec<Account>, args: &AddArgs, dry_run: bool) -> Result<()> {
+    let username = args.username.clone().ok_or_else(|| {
+        GitIdError::Usage("--username is required when adding with flags".to_string())
+    })?;
+    let host = args.host.clone().unwrap_or_else(default_host);
+    if let Some(old) = existing_account(&accounts, &username, &host) {
+        if !args.update {
+            return Err(already_exists(old));
+        }
+        return update_from_flags(&accounts, old, args, dry_run);
+    }
+    if args.email.is_none() && !args.noreply {
+        return Err(GitIdError::Usage(
+            "--email (or --noreply) is required when adding with flags".to_string(),
+        ));
+    }
+    let ssh_alias = args.ssh_alias.clone().unwrap_or_default();
+    if !ssh_alias.is_empty() {
+        check_alias(&accounts, &ssh_alias)?;
+    }
+
+    let identity_agent = match &args.identity_agent {
+        Some(agent) => stored_identity_agent(agent)?,
+        None => String::new(),
+    };
+    let ssh_key = match &args.ssh_key {
+        Some(key) => {
+            let path = expand_home(key);
+            if !path.exists() {
+                return Err(GitIdError::Usage(format!("SSH key not found: {}", path.display())));
+            }
+            fix_key_permissions(&path);
+            key.clone()
+        }
+        None if !identity_agent.is_empty() => agent_key_file(&username, &identity_agent, dry_run)?,
+        None => String::new(),
+    };
+
+    let https_token = if args.token_stdin { read_token_stdin()? } else { String::new() };
+    let expires = args.expires.clone().unwrap_or_default();
+    check_expires(&expires)?;
+
+    let https_cert = args.https_cert.clone().unwrap_or_default();
+    check_client_file("--https-cert", &https_cert)?;
+    let https_key = args.https_key.clone().unwrap_or_default();
+    check_client_file("--https-key", &https_key)?;
+    let commit_timezone = args.commit_timezone.clone().unwrap_or_default();
+    check_timezone(&commit_timezone)?;
+    let signing_key = args.signing_key.clone().unwrap_or_default();
+    check_signing_key(&signing_key)?;
+    let commit_template = args.commit_template.clone().unwrap_or_default();
+    check_client_file("--commit-template", &commit_template)?;
+
+    let remote = args.remote.clone().unwrap_or_default();
+    let agent_lifetime = args.agent_lifetime.clone().unwrap_or_default();
+    check_agent_lifetime(&agent_lifetime)?;
+    let mut acc = Account {
+        username,
+        email: args.email.clone().unwrap_or_default(),
+        host,
+        ssh_key,
+        https_token,
+        remote: if remote == "origin" { String::new() } else { remote },
+        expires,
+        ssh_alias,
+        protocol: args.protocol.as_deref().map(stored_protocol).unwrap_or_default(),
+        https_cert,
+        https_key,
+        commit_timezone,
+        signing_key,
+        commit_template,
+        forge: args.forge.as_deref().map(stored_forge).unwrap_or_default(),
+        owners: args.owners.clone(),
+        aka: args.aka.clone(),
+        identity_agent,
+        agent_lifetime,
+        agent_confirm: args.agent_confirm.as_deref().and_then(stored_confirm),
+        ..Default::default()
+    };
+    if !args.skip_token_check {
+        check_new_token(&acc)?;
+    }
+    if args.noreply {
+        acc.email = lookup_noreply(&acc)?;
+    }
+    add_alt_emails(&mut acc, &args.alt_emails)?;
+    if !acc.ssh_key.is_empty() {
+        add_key_to_agent(&expand_home(&acc.ssh_key), &acc, dry_run);
+    }
+    let hint = repo_owner(&accounts, &acc).filter(|o| !acc.owners.contains(o));
+    let (username, host) = (acc.username.clone(), acc.host.clone());
+    finish_add(accounts, acc, dry_run)?;
+    if let Some(owner) = hint {
+        print_info(&format!(
+            "This repo belongs to '{owner}'. To use this account for all its repos, run: \
+             git-id add --update --username {username} --host {host} --owner {owner}"
+        ));
+    }
+    Ok(())
+}
+
+/// Owner of the current repo's origin when it is on the account's host and
+/// no account covers it yet, as a candidate for the account's `owners`.
+fn repo_owner(accounts: &[Account], acc: &Account) -> Option<String> {
+    if !in_git_repo() {
+        return None;
+    }
+    let (_, host, owner, _) = parse_remote_url(&get_remote_url("origin"))?;
+    let new_rule = !owns(acc, &host, &owner) && !accounts.iter().any(|a| owns(a, &host, &owner));
+    let acc_host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    (host.eq_ignore_ascii_case(acc_host) && new_rule).then_some(owner)
+}
+
+/// Asks whether the account should also cover the current repo's owner.
+fn confirm_repo_owner(acc: &Account, owner: &str) -> bool {
+    let ans: String = Input::new()
+        .with_prompt(format!(
+            "\n  This repo belongs to '{owner}'. Use '{}' for all {owner} repos? [y/N]",
+            account_id(acc)
+        ))
+        .default("N".to_string())
+        .interact_text()
+        .unwrap_or_default();
+    ans.to_lowercase() == "y"
+}
+
+/// Applies the given flags to an account that already exists (`--update`).
+fn update_from_flags(
+    accounts: &[Account],
+    old: &Account,
+    args: &AddArgs,
+    dry_run: bool,
+) -> Result<()> {
+    let mut acc = old.clone();
+    if let Some(email) = &args.email {
+        acc.email = email.clone();
+    }
+    if let Some(key) = &args.ssh_key {
+        acc.ssh_key = key.clone();
+    }
+    if args.token_stdin {
+        acc.https_token = read_token_stdin()?;
+        if !args.skip_token_check {
+            check_new_token(&acc)?;
+        }
+    }
+    if args.noreply {
+        acc.email = lookup_noreply(&acc)?;
+    }
+    if let Some(remote) = &args.remote {
+        acc.remote = if remote == "origin" { String::new() } else { remote.clone() };
+    }
+    if let Some(expires) = &args.expires {
+        check_expires(expires)?;
+        acc.expires = expires.clone();
+    }
+    if let Some(alias) = &args.ssh_alias
+        && *alias != ssh_host_alias(old)
+    {
+        check_alias(accounts, alias)?;
+        acc.ssh_alias = alias.clone();
+    }
+    if let Some(protocol) = &args.protocol {
+        acc.protocol = stored_protocol(protocol);
+    }
+    if let Some(cert) = &args.https_cert {
+        check_client_file("--https-cert", cert)?;
+        acc.https_cert = cert.clone();
+    }
+    if let Some(key) = &args.https_key {
+        check_client_file("--https-key", key)?;
+        acc.https_key = key.clone();
+    }
+    if let Some(tz) = &args.commit_timezone {
+        check_timezone(tz)?;
+        acc.commit_timezone = tz.clone();
+    }
+    if let Some(key) = &args.signing_key {
+        check_signing_key(key)?;
+        acc.signing_key = key.clone();
+    }
+    if let Some(template) = &args.commit_template {
+        check_client_file("--commit-template", template)?;
+        acc.commit_template = template.clone();
+    }
+    if let Some(forge) = &args.forge {
+        acc.forge = stored_forge(forge);
+    }
+    if let Some(agent) = &args.identity_agent {
+        acc.identity_agent = stored_identity_agent(agent)?;
+        if acc.ssh_key.is_empty() && !acc.identity_agent.is_empty() {
+            acc.ssh_key = agent_key_file(&acc.username, &acc.identity_agent, dry_run)?;
+        }
+    }
+    if let Some(lifetime) = &args.agent_lifetime {
+        check_agent_lifetime(lifetime)?;
+        acc.agent_lifetime = lifetime.clone();
+    }
+    if let Some(confirm) = &args.agent_confirm {
+        acc.agent_confirm = stored_confirm(confirm);
+    }
+    for owner in &args.owners {
+        if !acc.owners.iter().any(|o| o.eq_ignore_ascii_case(owner)) {
+            acc.owners.push(owner.clone());
+        }
+    }
+    for old in &args.aka {
+        if !acc.aka.iter().any(|o| o.eq_ignore_ascii_case(old)) {
+            acc.aka.push(old.clone());
+        }
+    }
+    add_alt_emails(&mut acc, &args.alt_emails)?;
+    save_edit(old, acc, dry_run)
+}
+
+/// Adds the `--alt-email` values the account does not have yet, its main
+/// email included.
+fn add_alt_emails(acc: &mut Account, emails: &[String]) -> Result<()> {
+    for email in emails {
+        if !email.contains('@') {
+            return Err(GitIdError::Usage(format!("'{email}' is not an email address")));
+        }
+        if !commit_emails(acc).any(|e| e.eq_ignore_ascii_case(email)) {
+            acc.alt_emails.push(email.clone());
+        }
+    }
+    Ok(())
+}
+
+/// The account's GitHub noreply address for `--noreply`.
+fn lookup_noreply(acc: &Account) -> Result<String> {
+    if !is_github(acc) {
+        return Err(GitIdError::Usage(format!(
+            "--noreply needs a GitHub host, not {}",
+            acc.host
+        )));
+    }
+    let email = noreply_email(acc).map_err(|e| {
+        GitIdError::Io(format!("Cannot look up the noreply address of '{}': {e}", acc.username))
+    })?;
+    print_info(&format!("Commit email: {email}"));
+    Ok(email)
+}
+
+/// Asks for the commit email, offering the account's GitHub noreply
+/// address first so a personal address does not end up in public commits
+/// by accident.
+fn prompt_email(username: &str, host: &str) -> Result<String> {
+    let acc = Account {
+        username: username.to_string(),
+        host: host.to_string(),
+        ..Default::default()
+    };
+    if is_github(&acc)
+        && let Ok(noreply) = noreply_email(&acc)
+    {
+        let choices = [
+            format!("{noreply} - GitHub noreply, keeps your address private"),
+            "Another address".to_string(),
+        ];
+        let idx = Select::new()
+            .with_prompt(format!("  {}", color("cyan", "Commit email")))
+            .items(&choices)
+            .default(0)
+            .interact()
+            .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
+        if idx == 0 {
+            return Ok(noreply);
+        }
+    }
+    Input::new()
+        .with_prompt(format!("  {}", color("cyan", "Commit email")))
+        .interact_text()
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))
+}
+
+/// Refuses a token `new_token_problem` finds fault with.
+fn check_new_token(acc: &Account) -> Result<()> {
+    match new_token_problem(acc) {
+        Some(why) => Err(GitIdError::Usage(format!(
+            "{why}. Pass --skip-token-check to save it anyway."
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn read_token_stdin() -> Result<String> {
+    let mut token = String::new();
+    std::io::stdin()
+        .read_line(&mut token)
+        .map_err(|e| GitIdError::Io(format!("Failed to read token from stdin: {e}")))?;
+    warn_unresolved("https_token", token.trim());
+    Ok(token.trim().to_string())
+}
+
+fn check_expires(expires: &str) -> Result<()> {
+    if !expires.is_empty() && parse_date(expires).is_none() {
+        return Err(GitIdError::Usage(format!(
+            "Invalid --expires date {expires:?} (expected YYYY-MM-DD)"
+        )));
+    }
+    Ok(())
+}
+
+/// Fails unless `path`, given with `flag`, names an existing file; empty
+/// means none and passes.
+pub fn check_client_file(flag: &str, path: &str) -> Result<()> {
+    let file = expand_home(path);
+    if !path.is_empty() && !file.is_file() {
+        return Err(GitIdError::Usage(format!("{flag} file not found: {}", file.display())));
+    }
+    Ok(())
+}
+
+/// A `--signing-key` that names a key file must point at one.
+pub fn check_signing_key(key: &str) -> Result<()> {
+    if crate::commands::use_cmd::is_ssh_signing_key(key) && !key.starts_with("key::") {
+        check_client_file("--signing-key", key)?;
+    }
+    Ok(())
+}
+
+/// The `protocol` value saved for a `--protocol` argument: "current" (keep
+/// each remote's transport) is the default and is stored as empty.
+pub fn stored_protocol(arg: &str) -> String {
+    if arg == "current" { String::new() } else { arg.to_string() }
+}
+
+/// The `forge` saved for a `--forge` argument: "auto" leaves it to the host
+/// name and `host test`.
+pub fn stored_forge(arg: &str) -> String {
+    if arg == "auto" { String::new() } else { arg.to_string() }
+}
+
+/// The `identity_agent` saved for an `--identity-agent` argument:
+/// "1password" stands for the 1Password agent's socket, empty clears it.
+pub fn stored_identity_agent(arg: &str) -> Result<String> {
+    if arg != "1password" {
+        return Ok(arg.to_string());
+    }
+    onepassword_agent_socket().map(|s| s.to_string_lossy().to_string()).ok_or_else(|| {
+        GitIdError::Usage(
+            "The 1Password SSH agent socket was not found. Turn on the SSH agent in \
+             1Password's developer settings, or pass the socket path."
+                .to_string(),
+        )
+    })
+}
+
+/// Writes the public half of the key `sock` offers, for an account given
+/// `--identity-agent` but no `--ssh-key`: the stanza's IdentityFile then
+/// tells ssh which of the agent's keys to use.
+fn agent_key_file(username: &str, sock: &str, dry_run: bool) -> Result<String> {
+    let keys = agent_public_keys(Some(&expand_home(sock)));
+    match keys.as_slice() {
+        [key] => Ok(write_agent_public_key(username, key, dry_run)?.to_string_lossy().to_string()),
+        [] => Err(GitIdError::Io(format!(
+            "{sock} offers no keys. Add the account's key to the agent and try again."
+        ))),
+        _ => Err(GitIdError::Usage(format!(
+            "{sock} offers {} keys. Save the account's public key to a file and pass it \
+             with --ssh-key.",
+            keys.len()
+        ))),
+    }
+}
+
+/// Rejects an `--agent-lifetime` that `ssh-add -t` would not accept; empty
+/// falls back to settings.toml.
+pub fn check_agent_lifetime(lifetime: &str) -> Result<()> {
+    if !lifetime.is_empty() && !valid_agent_lifetime(lifetime) {
+        return Err(GitIdError::Usage(format!(
+            "Invalid --agent-lifetime {lifetime:?} (expected seconds or e.g. 30m, 1h30m, 2d)"
+        )));
+    }
+    Ok(())
+}
 
+/// Rejects a `--commit-timezone` that is neither a zone in the system's
+/// timezone database (`TZDIR`, else /usr/share/zoneinfo) nor a POSIX `TZ`
+/// string such as `UTC` or `EST5EDT`. Empty means local time.
+pub fn check_timezone(tz: &str) -> Result<()> {
+    let name = tz.trim_start_matches(':');
+    if name.is_empty() || name == "UTC" {
+        return Ok(());
+    }
+    let dir = std::env::var_os("TZDIR").map_or_else(|| "/usr/share/zoneinfo".into(), PathBuf::from);
+    let known = !name.contains("..") && dir.join(name).is_file();
+    let posix = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().any(|c| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "+-:,./<>".contains(c));
+    if !known && !posix {
+        return Err(GitIdError::Usage(format!(
+            "Unknown --commit-timezone {tz:?} (expected e.g. Europe/Berlin, UTC or EST5EDT)"
+        )));
+    }
+    Ok(())
+}
+
+/// The `agent_confirm` value saved for an `--agent-confirm` argument:
+/// "default" leaves it to settings.toml.
+pub fn stored_confirm(arg: &str) -> Option<bool> {
+    match arg {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Rejects an alias that is not a plain host name or that another account
+/// already uses.
+fn check_alias(accounts: &[Account], alias: &str) -> Result<()> {
+    if alias.contains(|c: char| c.is_whitespace() || "*?!\"".contains(c)) {
+        return Err(GitIdError::Usage(format!(
+            "Invalid --ssh-alias {alias:?}: must be a single host name without wildcards"
+        )));
+    }
+    if let Some(other) = accounts.iter().find(|a| ssh_host_alias(a) == alias) {
+        return Err(GitIdError::Usage(format!(
+            "SSH alias '{alias}' is already used by '{}'",
+            account_id(other)
+        )));
+    }
+    Ok(())
+}
+
+/// Offers the hand-written `Host` aliases for the account's host, so repos
+/// whose remotes already use one keep working. Returns the adopted alias, or
+/// an empty string to generate `<host>-<username>`.
+fn choose_ssh_alias(accounts: &[Account], username: &str, host: &str) -> Result<String> {
+    let candidates: Vec<(String, String)> = unmanaged_aliases(host)
+        .into_iter()
+        .filter(|(alias, _)| !accounts.iter().any(|a| ssh_host_alias(a) == *alias))
+        .collect();
+    if candidates.is_empty() {
+        return Ok(String::new());
+    }
+    let mut items = vec![format!("{host}-{username}  (new)")];
+    items.extend(candidates.iter().map(|(alias, key)| {
+        let key = if key.is_empty() { String::new() } else { format!(", {key}") };
+        format!("{alias}  (existing Host in ~/.ssh/config{key})")
+    }));
+    let idx = Select::new()
+        .with_prompt(format!("\n  {}", color("cyan", "SSH host alias")))
+        .items(&items)
+        .default(0)
+        .interact()
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
+    Ok(if idx == 0 { String::new() } else { candidates[idx - 1].0.clone() })
+}
+
+fn existing_account<'a>(
+    accounts: &'a [Account],
+    username: &str,
+    host: &str,
+) -> Option<&'a Account> {
+    let id = format!("{username}@{host}");
+    accounts.iter().find(|a| account_id(a) == id)
+}
+
+fn already_exists(acc: &Account) -> GitIdError {
+    let id = account_id(acc);
+    GitIdError::Usage(format!(
+        "Account '{id}' already exists. Pass --update to change it, \
+         or remove it first with: git-id remove {id}"
+    ))
+}
+
+/// Asks whether to edit an account the wizard was about to add again.
+fn confirm_update(acc: &Account) -> bool {
+    let ans: String = Input::new()
+        .with_prompt(format!(
+            "\n  Account '{}' already exists. Edit it instead? [Y/n]",
+            account_id(acc)
+        ))
+        .default("Y".to_string())
+        .interact_text()
+        .unwrap_or_default();
+    ans.to_lowercase() != "n"
+}
+
+/// How the wizard will provide the account's SSH key. Nothing is created
+/// until the summary has been confirmed.
+enum KeyPlan {
+    None,
+    Generate(KeyOptions),
+    Existing(PathBuf),
+    OnePassword { sock: PathBuf, public_key: String },
+}
+
+fn add_interactive(accounts: Vec<Account>, update: bool, dry_run: bool) -> Result<()> {
     print_hdr("Add a new GitHub account");
     println!();
 
     let username: String = Input::new()
         .with_prompt(format!("  {}", color("cyan", "GitHub username")))
         .interact_text()
-        .unwrap_or_else(|_| die("\nAborted.", 2));
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
 
     let host: String = Input::new()
         .with_prompt(format!("  {}", color("cyan", "Host")))
-        .default("github.com".to_string())
+        .default(default_host())
         .interact_text()
-        .unwrap_or_else(|_| die("\nAborted.", 2));
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
 
-    if accounts.iter().any(|a| a.username == username && a.host == host) {
-        die(
-            &format!(
-                "Account '{}@{}' already exists. Remove it first with: git-id remove {}@{}",
-                username, host, username, host
-            ),
-            2,
-        );
+    if let Some(old) = existing_account(&accounts, &username, &host) {
+        let old = old.clone();
+        if !update && !confirm_update(&old) {
+            return Err(already_exists(&old));
+        }
+        println!();
+        let mut acc = old.clone();
+        edit_interactive(&mut acc)?;
+        return save_edit(&old, acc, dry_run);
     }
 
-    let email: String = Input::new()
-        .with_prompt(format!("  {}", color("cyan", "Commit email")))
-        .interact_text()
-        .unwrap_or_else(|_| die("\nAborted.", 2));
+    let email = prompt_email(&username, &host)?;
 
     let remote_choices = &[
         "ssh - use SSH keys (recommended)",
@@ -48,41 +560,202 @@ pub fn cmd_add(dry_run: bool) {
         .items(remote_choices)
         .default(0)
         .interact()
-        .unwrap_or_else(|_| die("\nAborted.", 2));
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
     let remote_choice = remote_choices[remote_idx];
     let use_ssh = remote_choice.contains("ssh") || remote_choice.contains("both");
     let use_https = remote_choice.contains("https") || remote_choice.contains("both");
 
-    let mut ssh_key_path = String::new();
-    if use_ssh {
-        ssh_key_path = setup_ssh_key(&username, &email, dry_run);
-    }
+    let key_plan = if use_ssh { choose_ssh_key(&username)? } else { KeyPlan::None };
+    let ssh_alias =
+        if use_ssh { choose_ssh_alias(&accounts, &username, &host)? } else { String::new() };
 
     let mut https_token = String::new();
     if use_https {
         print_hdr("HTTPS Token");
-        https_token = Input::new()
-            .with_prompt(format!(
-                "  {}",
-                color("cyan", "GitHub personal access token (PAT) (optional)")
-            ))
-            .allow_empty(true)
-            .interact_text()
-            .unwrap_or_default();
+        loop {
+            https_token = Input::new()
+                .with_prompt(format!(
+                    "  {}",
+                    color("cyan", "GitHub personal access token (PAT) (optional)")
+                ))
+                .allow_empty(true)
+                .interact_text()
+                .unwrap_or_default();
+            warn_unresolved("https_token", &https_token);
+            let probe = Account {
+                username: username.clone(),
+                host: host.clone(),
+                https_token: https_token.clone(),
+                ..Default::default()
+            };
+            match new_token_problem(&probe) {
+                Some(why) => print_warn(&format!("{why} - enter another token, or none")),
+                None => break,
+            }
+        }
     }
 
-    let acc = Account {
+    let remote: String = Input::new()
+        .with_prompt(format!("  {}", color("cyan", "Remote name")))
+        .default("origin".to_string())
+        .interact_text()
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
+
+    let mut acc = Account {
         username: username.clone(),
         email,
         host: host.clone(),
-        ssh_key: ssh_key_path.clone(),
         https_token,
+        remote: if remote == "origin" { String::new() } else { remote },
+        ssh_alias,
+        ..Default::default()
+    };
+    if let Some(owner) = repo_owner(&accounts, &acc)
+        && confirm_repo_owner(&acc, &owner)
+    {
+        acc.owners.push(owner);
+    }
+    let upload = !matches!(key_plan, KeyPlan::None) && confirm_key_upload(&acc);
+
+    print_summary(&acc, &key_plan, upload);
+    let ans: String = Input::new()
+        .with_prompt("\n  Apply these changes? [Y/n]")
+        .default("Y".to_string())
+        .interact_text()
+        .unwrap_or_default();
+    if ans.to_lowercase() == "n" {
+        print_info("Aborted - nothing was changed.");
+        return Ok(());
+    }
+
+    let fresh = match &key_plan {
+        KeyPlan::Generate(opts) => !default_key_path(&acc.username, opts).exists(),
+        _ => false,
     };
+    (acc.ssh_key, acc.identity_agent) = apply_key_plan(&acc, key_plan, dry_run)?;
+    if fresh {
+        acc.key_created = today();
+    }
+    let pub_key = expand_home(&acc.ssh_key).with_extension("pub");
+    if upload && pub_key.exists() {
+        upload_key(&acc, &pub_key, dry_run);
+    }
+    finish_add(accounts, acc, dry_run)
+}
+
+/// Lists every file, key, stanza and agent change the wizard is about to
+/// make.
+fn print_summary(acc: &Account, key_plan: &KeyPlan, upload: bool) {
+    print_hdr("Summary");
+    let alias = ssh_host_alias(acc);
+    let mut actions = vec![];
+    match key_plan {
+        KeyPlan::None => {}
+        KeyPlan::Generate(opts) => {
+            let protected = if opts.passphrase.is_empty() { "" } else { " with a passphrase" };
+            actions.push(format!(
+                "Generate {} key ~/.ssh/{}{protected}",
+                opts.describe(),
+                opts.file_name(&acc.username)
+            ));
+            actions.push("Load the new key into ssh-agent".to_string());
+        }
+        KeyPlan::Existing(key) => {
+            actions.push(format!("Use existing key {} (chmod 600)", key.display()));
+            actions.push("Load the key into ssh-agent".to_string());
+        }
+        KeyPlan::OnePassword { sock, .. } => {
+            actions.push(format!(
+                "Write ~/.ssh/id_1password_{}.pub from the 1Password agent ({})",
+                acc.username,
+                sock.display()
+            ));
+        }
+    }
+    actions.push(format!("Add account '{}' to {}", account_id(acc), accounts_file().display()));
+    if !matches!(key_plan, KeyPlan::None) {
+        actions.push(format!("Write a 'Host {alias}' stanza to ~/.ssh/config.d/git-id.conf"));
+    }
+    if adopts_alias(acc) {
+        actions.push(format!("Keep the hand-written 'Host {alias}' block (it comes first)"));
+    }
+    if upload {
+        actions.push("Upload the public key to GitHub".to_string());
+    }
+
+    println!("    email : {}", acc.email);
+    println!("    token : {}", if acc.https_token.is_empty() { "-" } else { "set" });
+    println!("    remote: {}", remote_name(acc));
+    if !acc.owners.is_empty() {
+        println!("    owners: {}", acc.owners.join(", "));
+    }
+    if !acc.aka.is_empty() {
+        println!("    aka   : {}", acc.aka.join(", "));
+    }
+    println!();
+    for action in actions {
+        println!("    {} {action}", color("cyan", "*"));
+    }
+}
+
+/// Carries out the chosen key setup. Returns the key path and, for keys
+/// held by the 1Password agent, the agent socket to put in the stanza.
+fn apply_key_plan(acc: &Account, plan: KeyPlan, dry_run: bool) -> Result<(String, String)> {
+    Ok(match plan {
+        KeyPlan::None => (String::new(), String::new()),
+        KeyPlan::Generate(opts) => {
+            let new_key = gen_ssh_key(acc, &opts, dry_run)?;
+            let pub_key = new_key.with_extension("pub");
+            if pub_key.exists() && !dry_run {
+                print_hdr("Public key - paste this into GitHub -> Settings -> SSH keys:");
+                println!(
+                    "\n{}\n",
+                    crate::log::read_to_string(&pub_key).unwrap_or_default().trim()
+                );
+            }
+            (new_key.to_string_lossy().to_string(), String::new())
+        }
+        KeyPlan::Existing(key) => {
+            if !dry_run {
+                fix_key_permissions(&key);
+            }
+            add_key_to_agent(&key, acc, dry_run);
+            (key.to_string_lossy().to_string(), String::new())
+        }
+        KeyPlan::OnePassword { sock, public_key } => {
+            let path = write_agent_public_key(&acc.username, &public_key, dry_run)?;
+            (path.to_string_lossy().to_string(), sock.to_string_lossy().to_string())
+        }
+    })
+}
+
+/// Whether the account's `ssh_alias` is a `Host` block the user wrote in
+/// ~/.ssh/config, rather than one git-id made or a name nothing defines.
+fn adopts_alias(acc: &Account) -> bool {
+    let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    !acc.ssh_alias.is_empty()
+        && unmanaged_aliases(host).iter().any(|(alias, _)| *alias == acc.ssh_alias)
+}
+
+/// Saves the new account, writes its SSH stanza and prints next steps.
+fn finish_add(mut accounts: Vec<Account>, acc: Account, dry_run: bool) -> Result<()> {
+    let username = acc.username.clone();
+    let host = acc.host.clone();
+    let has_key = !acc.ssh_key.is_empty();
+    let adopted = adopts_alias(&acc);
+    let alias = ssh_host_alias(&acc);
     accounts.push(acc);
-    save_accounts(&accounts, dry_run);
+    save_accounts(&accounts, dry_run)?;
+
+    if has_key {
+        update_ssh_config(&accounts, dry_run)?;
+    }
 
-    if !ssh_key_path.is_empty() {
-        update_ssh_config(&accounts, dry_run);
+    if has_key && adopted {
+        print_info(&format!(
+            "Your own 'Host {alias}' block in ~/.ssh/config is still there. git-id's stanza is \
+             included above it and wins; remove yours once the new stanza works."
+        ));
     }
 
     println!();
@@ -91,42 +764,96 @@ pub fn cmd_add(dry_run: bool) {
         "Next: git-id use {}   (inside a repo)  or  git-id use {} --global",
         username, username
     ));
+    Ok(())
 }
 
-/// Interactive prompt to set up (generate or pick) an SSH key.
-/// Returns the path to the chosen private key, or empty string on failure.
-fn setup_ssh_key(username: &str, email: &str, dry_run: bool) -> String {
+/// Interactive prompt to choose how the SSH key is provided (generate,
+/// pick an existing one, or use the 1Password agent).
+fn choose_ssh_key(username: &str) -> Result<KeyPlan> {
     print_hdr("SSH Key");
-    let key_choices = vec![
-        format!("Generate new ed25519 key  (~/.ssh/id_ed25519_{username})"),
+    let op_sock = onepassword_agent_socket();
+    let mut key_choices = vec![
+        format!("Generate new key  (~/.ssh/id_<type>_{username})"),
         "Pick from existing ~/.ssh/*.pub keys".to_string(),
     ];
+    if let Some(sock) = &op_sock {
+        key_choices.push(format!("Use a key from the 1Password SSH agent  ({})", sock.display()));
+    }
     let key_idx = Select::new()
         .with_prompt(format!("  {}", color("cyan", "SSH key setup")))
         .items(&key_choices)
         .default(0)
         .interact()
-        .unwrap_or_else(|_| die("\nAborted.", 2));
-
-    if key_idx == 0 {
-        let new_key = gen_ssh_key(username, email, dry_run);
-        let ssh_key_path = new_key.to_string_lossy().to_string();
-        let pub_key = new_key.with_extension("pub");
-        if pub_key.exists() && !dry_run {
-            print_hdr("Public key - paste this into GitHub -> Settings -> SSH keys:");
-            println!(
-                "\n{}\n",
-                std::fs::read_to_string(&pub_key).unwrap_or_default().trim()
-            );
-        }
-        ssh_key_path
-    } else {
-        pick_existing_ssh_key(username, email, dry_run)
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
+
+    let plan = match (key_idx, op_sock) {
+        (0, _) => KeyPlan::Generate(KeyOptions::default()),
+        (2, Some(sock)) => {
+            let public_key = pick_onepassword_key(&sock)?;
+            KeyPlan::OnePassword { sock, public_key }
+        }
+        _ => pick_existing_ssh_key()?,
+    };
+    match plan {
+        KeyPlan::Generate(_) => {
+            let key_type = ask_key_type()?;
+            Ok(KeyPlan::Generate(KeyOptions::new(key_type, None, ask_key_passphrase()?)?))
+        }
+        plan => Ok(plan),
+    }
+}
+
+/// Asks which type of key to generate. ed25519 comes first; RSA is there
+/// for servers that do not accept anything else, the `-sk` types for keys
+/// kept on a hardware token.
+fn ask_key_type() -> Result<&'static str> {
+    let choices = [
+        "ed25519     (recommended)",
+        "rsa         (4096 bits, for servers that require RSA)",
+        "ecdsa       (256 bits)",
+        "ed25519-sk  (on a FIDO2 security key such as a YubiKey)",
+        "ecdsa-sk    (on a FIDO2 security key without ed25519 support)",
+    ];
+    let idx = Select::new()
+        .with_prompt(format!("  {}", color("cyan", "Key type")))
+        .items(&choices)
+        .default(0)
+        .interact()
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
+    Ok(KEY_TYPES[idx])
+}
+
+/// Asks whether the new key gets a passphrase, and for the passphrase.
+fn ask_key_passphrase() -> Result<String> {
+    let yn: String = Input::new()
+        .with_prompt("  Protect the new key with a passphrase? [y/N]")
+        .default("N".to_string())
+        .interact_text()
+        .unwrap_or_default();
+    if yn.to_lowercase() == "y" { read_passphrase(true) } else { Ok(String::new()) }
+}
+
+/// Let the user pick one of the keys served by the 1Password agent. The key
+/// stays in 1Password; only its public half is written to `~/.ssh` later.
+fn pick_onepassword_key(sock: &Path) -> Result<String> {
+    let keys = agent_public_keys(Some(sock));
+    if keys.is_empty() {
+        return Err(GitIdError::Io(
+            "The 1Password SSH agent offered no keys. Add a key in 1Password and try again."
+                .to_string(),
+        ));
     }
+    let idx = Select::new()
+        .with_prompt(format!("  {}", color("cyan", "Pick 1Password key")))
+        .items(&keys)
+        .default(0)
+        .interact()
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
+    Ok(keys[idx].clone())
 }
 
 /// Let the user pick an existing `~/.ssh/*.pub` key.
-fn pick_existing_ssh_key(username: &str, email: &str, dry_run: bool) -> String {
+fn pick_existing_ssh_key() -> Result<KeyPlan> {
     let pub_files: Vec<PathBuf> = {
         let mut v: Vec<PathBuf> = std::fs::read_dir(ssh_dir())
             .map(|rd| {
@@ -141,9 +868,8 @@ fn pick_existing_ssh_key(username: &str, email: &str, dry_run: bool) -> String {
     };
 
     if pub_files.is_empty() {
-        print_warn("No .pub files found in ~/.ssh/ - generating a new key instead");
-        let new_key = gen_ssh_key(username, email, dry_run);
-        return new_key.to_string_lossy().to_string();
+        print_warn("No .pub files found in ~/.ssh/ - a new key will be generated instead");
+        return Ok(KeyPlan::Generate(KeyOptions::default()));
     }
 
     let items: Vec<String> = pub_files
@@ -155,27 +881,21 @@ fn pick_existing_ssh_key(username: &str, email: &str, dry_run: bool) -> String {
         .items(&items)
         .default(0)
         .interact()
-        .unwrap_or_else(|_| die("\nAborted.", 2));
-
-    let chosen_pub = &pub_files[idx];
-    let priv_key = chosen_pub.with_extension("");
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
 
-    if !priv_key.exists() {
-        print_warn(&format!("Private key not found: {}", priv_key.display()));
-        let yn: String = Input::new()
-            .with_prompt("  Generate a new ed25519 key instead? [y/N]")
-            .default("N".to_string())
-            .interact_text()
-            .unwrap_or_default();
-        if yn.to_lowercase() == "y" {
-            let new_key = gen_ssh_key(username, email, dry_run);
-            new_key.to_string_lossy().to_string()
-        } else {
-            die("Cannot proceed without a valid private key.", 2);
-        }
+    let priv_key = pub_files[idx].with_extension("");
+    if priv_key.exists() {
+        return Ok(KeyPlan::Existing(priv_key));
+    }
+    print_warn(&format!("Private key not found: {}", priv_key.display()));
+    let yn: String = Input::new()
+        .with_prompt("  Generate a new key instead? [y/N]")
+        .default("N".to_string())
+        .interact_text()
+        .unwrap_or_default();
+    if yn.to_lowercase() == "y" {
+        Ok(KeyPlan::Generate(KeyOptions::default()))
     } else {
-        fix_key_permissions(&priv_key);
-        add_key_to_agent(&priv_key, dry_run);
-        priv_key.to_string_lossy().to_string()
+        Err(GitIdError::Usage("Cannot proceed without a valid private key.".to_string()))
     }
 }
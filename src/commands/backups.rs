@@ -0,0 +1,38 @@
+use crate::commands::restore::originals;
+use crate::config::load_settings;
+use crate::error::{GitIdError, Result};
+use crate::ui::{expired_backups, print_info, print_ok};
+
+/// Deletes the backups of accounts.toml and the SSH config that fall outside
+/// the retention in settings.toml, or the one given on the command line.
+pub fn cmd_backups_clean(keep: Option<usize>, days: Option<u64>, dry_run: bool) -> Result<()> {
+    let retention = load_settings()?.backups;
+    let keep = keep.unwrap_or(retention.keep);
+    let days = days.unwrap_or(retention.days);
+    if keep == 0 {
+        print_info("Nothing to clean: the retention keeps every backup (keep = 0)");
+        return Ok(());
+    }
+
+    let mut deleted = 0;
+    for original in originals(None) {
+        for old in expired_backups(&original, keep, days) {
+            if dry_run {
+                print_info(&format!("[dry-run] Would delete {}", old.display()));
+            } else {
+                let result = std::fs::remove_file(&old);
+                crate::log::file("deleted", &old, &result);
+                result
+                    .map_err(|e| GitIdError::Io(format!("Cannot delete {}: {e}", old.display())))?;
+                print_ok(&format!("Deleted {}", old.display()));
+            }
+            deleted += 1;
+        }
+    }
+    if deleted == 0 {
+        print_info("No backups past the retention");
+    } else if !dry_run {
+        print_ok(&format!("Deleted {deleted} backup(s)"));
+    }
+    Ok(())
+}
@@ -0,0 +1,227 @@
+use crate::commands::hook::{PINNED_KEY, author_ident};
+use crate::commands::rules::{describe, repo_rule};
+use crate::commands::use_cmd::remote_belongs_to;
+use crate::config::{account_id, find_account_exact, has_email, load_accounts, normalize_email};
+use crate::error::{GitIdError, Result};
+use crate::git::{
+    get_git_config, get_remote_url, in_git_repo, list_remotes, parse_remote_url,
+    strip_plus_address_enabled,
+};
+use crate::models::Account;
+use crate::output::{Format, Report, markdown_table, render};
+use crate::ui::{color, print_err};
+use serde::Serialize;
+
+/// `check`: whether the identity commits get here is the one the repo
+/// expects. `reason` is a stable code for scripts; `message` and `fix` are
+/// for people.
+#[derive(Serialize)]
+struct CheckReport {
+    ok: bool,
+    /// "ok", "not-a-repo", "no-identity", "unknown-account", "ambiguous-owner",
+    /// "mismatch", "unknown-email" or "rule".
+    reason: &'static str,
+    name: String,
+    email: String,
+    /// Account the repo expects, when something names one.
+    expected: Option<String>,
+    /// Where the expectation comes from: "flag", "pinned", "owner" or "any".
+    source: Option<&'static str>,
+    /// Account the email belongs to.
+    account: Option<String>,
+    message: String,
+    fix: Option<String>,
+}
+
+impl CheckReport {
+    fn fail(&mut self, reason: &'static str, message: String, fix: Option<String>) {
+        self.ok = false;
+        self.reason = reason;
+        self.message = message;
+        self.fix = fix;
+    }
+}
+
+impl Report for CheckReport {
+    fn human(&self) {
+        if self.ok {
+            println!("{} {}", color("green", "ok"), self.message);
+            return;
+        }
+        print_err(&format!("{}: {}", self.reason, self.message));
+        if let Some(fix) = &self.fix {
+            eprintln!("  Fix with: {fix}");
+        }
+    }
+
+    fn markdown(&self) -> String {
+        let rows = vec![
+            vec!["Result".to_string(), self.reason.to_string()],
+            vec!["Identity".to_string(), format!("{} <{}>", self.name, self.email)],
+            vec!["Expected".to_string(), self.expected.clone().unwrap_or_default()],
+            vec!["Source".to_string(), self.source.unwrap_or_default().to_string()],
+            vec!["Details".to_string(), self.message.clone()],
+            vec!["Fix".to_string(), self.fix.clone().unwrap_or_default()],
+        ];
+        format!("## git-id check\n\n{}", markdown_table(&["Field", "Value"], &rows))
+    }
+}
+
+/// The account owning the current repo's first remote (origin first) that
+/// some account owns, or all of them when several do.
+fn owner_accounts(accounts: &[Account]) -> Vec<&Account> {
+    let mut remotes = list_remotes();
+    remotes.sort_by_key(|r| r != "origin");
+    for remote in remotes {
+        let url = get_remote_url(&remote);
+        let Some((_, host, owner, _)) = parse_remote_url(&url) else {
+            continue;
+        };
+        let owners: Vec<&Account> =
+            accounts.iter().filter(|a| remote_belongs_to(a, &url, &host, &owner)).collect();
+        if !owners.is_empty() {
+            return owners;
+        }
+    }
+    vec![]
+}
+
+/// Exits 0 when the identity git would commit with here belongs to the
+/// account the repo expects - `--account`, else the account pinned by
+/// `hook install --account`, else the one owning the remotes, else any
+/// account - and satisfies the repo's email rule. Fails with `CheckFailed`
+/// otherwise, after printing why.
+pub fn cmd_check(account: Option<&str>, format: Format) -> Result<()> {
+    let mut report = CheckReport {
+        ok: true,
+        reason: "ok",
+        name: String::new(),
+        email: String::new(),
+        expected: None,
+        source: None,
+        account: None,
+        message: String::new(),
+        fix: None,
+    };
+    evaluate(&mut report, account)?;
+    render(&report, format)?;
+    if !report.ok {
+        return Err(GitIdError::CheckFailed);
+    }
+    Ok(())
+}
+
+fn evaluate(report: &mut CheckReport, account: Option<&str>) -> Result<()> {
+    if !in_git_repo() {
+        report.fail("not-a-repo", "not inside a git repository".to_string(), None);
+        return Ok(());
+    }
+    let (name, email) = author_ident();
+    report.name = name;
+    report.email = email;
+    let strip_plus = strip_plus_address_enabled();
+    let norm = normalize_email(&report.email, strip_plus);
+    let accounts = load_accounts()?;
+    let owner = accounts.iter().find(|a| has_email(a, &norm, strip_plus));
+    report.account = owner.map(account_id);
+
+    let pinned = get_git_config(PINNED_KEY, "local");
+    let (wanted, source) = match account {
+        Some(name) => (Some(name.to_string()), "flag"),
+        None if !pinned.is_empty() => (Some(pinned), "pinned"),
+        None => (None, "owner"),
+    };
+    let expected = match wanted {
+        Some(name) => match find_account_exact(&name)? {
+            Some(acc) => Some(acc),
+            None => {
+                report.source = Some(source);
+                report.expected = Some(name.clone());
+                let fix = match source {
+                    "pinned" => "git-id hook install --account <account>",
+                    _ => "git-id list",
+                };
+                report.fail(
+                    "unknown-account",
+                    format!("no account named '{name}'"),
+                    Some(fix.to_string()),
+                );
+                return Ok(());
+            }
+        },
+        None => match owner_accounts(&accounts).as_slice() {
+            [] => None,
+            [acc] => Some((*acc).clone()),
+            several => {
+                let ids: Vec<String> = several.iter().map(|a| account_id(a)).collect();
+                report.source = Some("owner");
+                report.fail(
+                    "ambiguous-owner",
+                    format!("several accounts own this repo's remote: {}", ids.join(", ")),
+                    Some("git-id hook install --account <account>".to_string()),
+                );
+                return Ok(());
+            }
+        },
+    };
+    report.source = Some(if expected.is_some() { source } else { "any" });
+    report.expected = expected.as_ref().map(account_id);
+
+    if report.email.is_empty() {
+        let target = report.expected.as_deref().unwrap_or("<account>");
+        report.fail(
+            "no-identity",
+            "no user.email is set".to_string(),
+            Some(format!("git-id use {target}")),
+        );
+        return Ok(());
+    }
+    match &expected {
+        Some(acc) if !has_email(acc, &norm, strip_plus) => {
+            let id = account_id(acc);
+            report.fail(
+                "mismatch",
+                format!(
+                    "commits use <{}>, but this repo expects {id} <{}>",
+                    report.email, acc.email
+                ),
+                Some(format!("git-id use {id}")),
+            );
+            return Ok(());
+        }
+        None if owner.is_none() => {
+            report.fail(
+                "unknown-email",
+                format!("<{}> belongs to no git-id account", report.email),
+                Some("git-id use <account>".to_string()),
+            );
+            return Ok(());
+        }
+        _ => {}
+    }
+    if let Some(acc) = &expected {
+        report.account = Some(account_id(acc));
+    }
+    if let Some(rule) = repo_rule()
+        && normalize_email(&rule.email, strip_plus) != norm
+    {
+        let rule_email = normalize_email(&rule.email, false);
+        let fix = match accounts.iter().find(|a| has_email(a, &rule_email, false)) {
+            Some(acc) => format!("git-id use {}", account_id(acc)),
+            None => format!("git config user.email {}", rule.email),
+        };
+        report.fail(
+            "rule",
+            format!("{} must use <{}>, not <{}>", describe(&rule), rule.email, report.email),
+            Some(fix),
+        );
+        return Ok(());
+    }
+    let id = report.account.clone().unwrap_or_default();
+    report.message = match report.source {
+        Some("any") => format!("{} <{}> is account {id}", report.name, report.email),
+        Some(source) => format!("{} <{}> is account {id} ({source})", report.name, report.email),
+        None => String::new(),
+    };
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use crate::commands::credential::{
+    configure_client_cert, configure_helper, has_credentials, helper_command,
+};
+use crate::commands::use_cmd::account_url;
+use crate::config::{account_id, expand_home, find_account, load_accounts, owns, remote_name};
+use crate::error::{GitIdError, Result};
+use crate::git::{display_url, parse_remote_url, run_git, set_git_config};
+use crate::models::Account;
+use crate::ui::{print_info, print_ok};
+use std::path::PathBuf;
+
+pub fn cmd_clone(
+    url: &str,
+    dest: Option<PathBuf>,
+    account: Option<&str>,
+    force_ssh: bool,
+    force_https: bool,
+    dry_run: bool,
+) -> Result<()> {
+    if force_ssh && force_https {
+        return Err(GitIdError::Usage("Cannot use --ssh and --https together.".to_string()));
+    }
+    let (fmt, host, owner, repo) = parse_remote_url(url)
+        .ok_or_else(|| {
+            GitIdError::Usage(format!("Unrecognised repository URL: {:?}", display_url(url)))
+        })?;
+
+    let acc = match account {
+        Some(name) => find_account(name)?.ok_or_else(|| {
+            GitIdError::Usage(format!("Account '{name}' not found. Run: git-id list"))
+        })?,
+        None => account_for_owner(&host, &owner)?.ok_or_else(|| {
+            GitIdError::Usage(format!(
+                "No account owns '{owner}' on {host}. Pass --account <name>."
+            ))
+        })?,
+    };
+    let acc = crate::secrets::resolved(&acc)?;
+
+    let clone_url = account_url(&acc, &fmt, &host, &owner, &repo, force_ssh, force_https);
+    let dest = dest.unwrap_or_else(|| PathBuf::from(&repo));
+    let dest_str = dest.to_string_lossy().to_string();
+    let remote = remote_name(&acc);
+
+    if dry_run {
+        let shown = display_url(&clone_url);
+        print_info(&format!("[dry-run] git clone -o {remote} {shown} {dest_str}"));
+    } else {
+        print_info(&format!("Cloning {owner}/{repo} as '{}'...", account_id(&acc)));
+        let mut args = vec![];
+        let helper = format!("credential.helper={}", helper_command(&acc));
+        if has_credentials(&acc) {
+            // The repo's own helper config does not exist until the clone
+            // finishes, so hand it to this one git invocation.
+            args.extend(["-c", "credential.helper=", "-c", helper.as_str()]);
+        }
+        // Likewise for a client certificate the server asks for.
+        let cert = format!("http.sslCert={}", expand_home(&acc.https_cert).display());
+        let key = format!("http.sslKey={}", expand_home(&acc.https_key).display());
+        if !acc.https_cert.is_empty() {
+            args.extend(["-c", cert.as_str()]);
+        }
+        if !acc.https_key.is_empty() {
+            args.extend(["-c", key.as_str()]);
+        }
+        args.extend(["clone", "-o", remote, &clone_url, &dest_str]);
+        let (code, _, errmsg) = run_git(&args);
+        if code != 0 {
+            return Err(GitIdError::Io(format!("git clone failed: {errmsg}")));
+        }
+        let shown = display_url(&clone_url);
+        print_ok(&format!("Cloned into {dest_str} (remote '{remote}' -> {shown})"));
+        std::env::set_current_dir(&dest)
+            .map_err(|e| GitIdError::Io(format!("Cannot enter {dest_str}: {e}")))?;
+    }
+
+    set_git_config("user.name", &acc.username, "local", dry_run);
+    set_git_config("user.email", &acc.email, "local", dry_run);
+    print_ok(&format!("Git identity (local): {} <{}>", acc.username, acc.email));
+    configure_helper(&acc, "local", dry_run);
+    configure_client_cert(&acc, "local", dry_run);
+    // A dry run clones nothing, so there is no repo to register.
+    if !dry_run {
+        crate::repos::remember_current_repo(false);
+    }
+    Ok(())
+}
+
+/// The account that owns the repo owner on that host (see `config::owns`).
+fn account_for_owner(host: &str, owner: &str) -> Result<Option<Account>> {
+    Ok(load_accounts()?.into_iter().find(|a| owns(a, host, owner)))
+}
@@ -1,29 +1,46 @@
 use crate::cli::build_command;
+use crate::config::{config_home, data_home};
+use crate::error::{GitIdError, Result};
 use clap_complete::{generate, Shell};
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
-
-pub fn cmd_completions(shell: Shell) {
-    let home = dirs::home_dir().expect("Could not determine home directory");
+use std::path::{Path, PathBuf};
+
+/// Where completion scripts are installed: fish and elvish under the XDG
+/// config home, bash under the XDG data home, the rest under the home
+/// directory.
+const FISH_SCRIPT: &str = "fish/completions/git-id.fish";
+const ELVISH_SCRIPT: &str = "elvish/completions/git-id.elv";
+const POWERSHELL_SCRIPT: &str = "Documents/PowerShell/Scripts/git-id.ps1";
+const OMZ_SCRIPT: &str = ".oh-my-zsh/custom/completions/_git-id";
+const ZFUNC_SCRIPT: &str = ".zfunc/_git-id";
+const BASH_SCRIPT: &str = "bash-completion/completions/git-id";
+
+/// Comment heading the lines appended to ~/.zshrc and ~/.bashrc.
+const RC_MARKER: &str = "# git-id shell completions";
+const FPATH_LINE: &str = "fpath=(~/.zfunc $fpath)";
+const COMPINIT_LINE: &str = "autoload -Uz compinit && compinit";
+
+pub fn cmd_completions(shell: Shell) -> Result<()> {
+    let home = crate::config::dirs_home();
 
     match shell {
-        Shell::Zsh => setup_zsh(&home),
-        Shell::Bash => setup_bash(&home),
+        Shell::Zsh => setup_zsh(&home)?,
+        Shell::Bash => setup_bash(&home)?,
         Shell::Fish => {
-            let path = home.join(".config/fish/completions/git-id.fish");
-            write_completion(shell, &path);
+            let path = config_home().join(FISH_SCRIPT);
+            write_completion(shell, &path)?;
             println!("✓ Completion script written to: {}", path.display());
             println!("  Fish auto-loads completions from this directory — no further setup needed.");
         }
         Shell::Elvish => {
-            let path = home.join(".config/elvish/completions/git-id.elv");
-            write_completion(shell, &path);
+            let path = config_home().join(ELVISH_SCRIPT);
+            write_completion(shell, &path)?;
             println!("✓ Completion script written to: {}", path.display());
         }
         Shell::PowerShell => {
-            let path = home.join("Documents/PowerShell/Scripts/git-id.ps1");
-            write_completion(shell, &path);
+            let path = home.join(POWERSHELL_SCRIPT);
+            write_completion(shell, &path)?;
             println!("✓ Completion script written to: {}", path.display());
             println!("  Make sure your PowerShell profile sources scripts in that directory.");
         }
@@ -31,55 +48,51 @@ pub fn cmd_completions(shell: Shell) {
             generate(shell, &mut build_command(), "git-id", &mut std::io::stdout());
         }
     }
+    Ok(())
 }
 
-fn setup_zsh(home: &std::path::Path) {
-    let omz_dir = home.join(".oh-my-zsh");
-    if omz_dir.exists() {
-        let p = omz_dir.join("custom/completions/_git-id");
-        write_completion_zsh(&p);
+fn setup_zsh(home: &std::path::Path) -> Result<()> {
+    if home.join(".oh-my-zsh").exists() {
+        let p = home.join(OMZ_SCRIPT);
+        write_completion_zsh(&p)?;
         println!("✓ Completion script written to: {}", p.display());
         println!("  Detected oh-my-zsh — completions will load automatically.");
-        return;
+        return Ok(());
     }
 
-    let path = home.join(".zfunc/_git-id");
-    write_completion_zsh(&path);
+    let path = home.join(ZFUNC_SCRIPT);
+    write_completion_zsh(&path)?;
     println!("✓ Completion script written to: {}", path.display());
 
     let zshrc = home.join(".zshrc");
-    let zshrc_content = fs::read_to_string(&zshrc).unwrap_or_default();
-    let fpath_line = "fpath=(~/.zfunc $fpath)";
-    let compinit_line = "autoload -Uz compinit && compinit";
-
-    if !zshrc_content.contains(fpath_line) || !zshrc_content.contains(compinit_line) {
-        let mut file = fs::OpenOptions::new()
-            .append(true)
-            .open(&zshrc)
-            .unwrap_or_else(|e| {
-                eprintln!("Error opening {}: {}", zshrc.display(), e);
-                std::process::exit(1);
-            });
-        writeln!(file, "\n# git-id shell completions").unwrap();
-        if !zshrc_content.contains(fpath_line) {
-            writeln!(file, "{}", fpath_line).unwrap();
+    let zshrc_content = crate::log::read_to_string(&zshrc).unwrap_or_default();
+
+    if !zshrc_content.contains(FPATH_LINE) || !zshrc_content.contains(COMPINIT_LINE) {
+        let opened = fs::OpenOptions::new().append(true).open(&zshrc);
+        crate::log::file("appended", &zshrc, &opened);
+        let mut file = opened
+            .map_err(|e| GitIdError::Io(format!("Error opening {}: {e}", zshrc.display())))?;
+        writeln!(file, "\n{RC_MARKER}").unwrap();
+        if !zshrc_content.contains(FPATH_LINE) {
+            writeln!(file, "{}", FPATH_LINE).unwrap();
         }
-        if !zshrc_content.contains(compinit_line) {
-            writeln!(file, "{}", compinit_line).unwrap();
+        if !zshrc_content.contains(COMPINIT_LINE) {
+            writeln!(file, "{}", COMPINIT_LINE).unwrap();
         }
         println!("✓ Added fpath and compinit to ~/.zshrc");
     }
 
     println!("  Restart your shell or run: source ~/.zshrc");
+    Ok(())
 }
 
-fn setup_bash(home: &std::path::Path) {
-    let path = home.join(".local/share/bash-completion/completions/git-id");
-    write_completion(Shell::Bash, &path);
+fn setup_bash(home: &std::path::Path) -> Result<()> {
+    let path = data_home().join(BASH_SCRIPT);
+    write_completion(Shell::Bash, &path)?;
     println!("✓ Completion script written to: {}", path.display());
 
     let bashrc = home.join(".bashrc");
-    let bashrc_content = fs::read_to_string(&bashrc).unwrap_or_default();
+    let bashrc_content = crate::log::read_to_string(&bashrc).unwrap_or_default();
     let already_setup = bashrc_content.contains("bash_completion")
         || bashrc_content.contains("bash-completion");
 
@@ -90,14 +103,12 @@ fn setup_bash(home: &std::path::Path) {
             path.display()
         );
         if !bashrc_content.contains(source_line.as_str()) {
-            let mut file = fs::OpenOptions::new()
-                .append(true)
-                .open(&bashrc)
-                .unwrap_or_else(|e| {
-                    eprintln!("Error opening {}: {}", bashrc.display(), e);
-                    std::process::exit(1);
-                });
-            writeln!(file, "\n# git-id shell completions\n{}", source_line).unwrap();
+            let opened = fs::OpenOptions::new().append(true).open(&bashrc);
+            crate::log::file("appended", &bashrc, &opened);
+            let mut file = opened.map_err(|e| {
+                GitIdError::Io(format!("Error opening {}: {e}", bashrc.display()))
+            })?;
+            writeln!(file, "\n{RC_MARKER}\n{}", source_line).unwrap();
             println!("✓ Added source line to ~/.bashrc");
         }
     } else {
@@ -105,10 +116,61 @@ fn setup_bash(home: &std::path::Path) {
     }
 
     println!("  Restart your shell or run: source ~/.bashrc");
+    Ok(())
+}
+
+/// Completion scripts `cmd_completions` may have written, whichever exist.
+pub fn installed_scripts(home: &Path) -> Vec<PathBuf> {
+    [
+        config_home().join(FISH_SCRIPT),
+        config_home().join(ELVISH_SCRIPT),
+        home.join(POWERSHELL_SCRIPT),
+        home.join(OMZ_SCRIPT),
+        home.join(ZFUNC_SCRIPT),
+        data_home().join(BASH_SCRIPT),
+    ]
+    .into_iter()
+    .filter(|p| p.exists())
+    .collect()
+}
+
+/// `content` without the lines `cmd_completions` appended to a shell rc
+/// file, or None when it has none. Only the lines directly under the marker
+/// comment are git-id's; the blank line written before it goes too.
+pub fn strip_rc_block(content: &str) -> Option<String> {
+    if !content.lines().any(|l| l.trim() == RC_MARKER) {
+        return None;
+    }
+    let mut kept: Vec<&str> = vec![];
+    let mut in_block = false;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed == RC_MARKER {
+            if kept.last().is_some_and(|l| l.trim().is_empty()) {
+                kept.pop();
+            }
+            in_block = true;
+            continue;
+        }
+        if in_block
+            && (trimmed == FPATH_LINE
+                || trimmed == COMPINIT_LINE
+                || (trimmed.starts_with("[ -f ") && trimmed.contains(BASH_SCRIPT)))
+        {
+            continue;
+        }
+        in_block = false;
+        kept.push(line);
+    }
+    let mut out = kept.join("\n");
+    if content.ends_with('\n') {
+        out.push('\n');
+    }
+    Some(out)
 }
 
 /// Generate zsh script, inject account completion function, and write to path.
-fn write_completion_zsh(path: &PathBuf) {
+fn write_completion_zsh(path: &PathBuf) -> Result<()> {
     let mut buf: Vec<u8> = Vec::new();
     generate(Shell::Zsh, &mut build_command(), "git-id", &mut buf);
 
@@ -142,31 +204,31 @@ _git_id_accounts() {
 "#;
 
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).unwrap_or_else(|e| {
-            eprintln!("Error creating directory {}: {}", parent.display(), e);
-            std::process::exit(1);
-        });
+        fs::create_dir_all(parent).map_err(|e| {
+            GitIdError::Io(format!("Error creating directory {}: {e}", parent.display()))
+        })?;
     }
-    let mut file = fs::File::create(path).unwrap_or_else(|e| {
-        eprintln!("Error creating file {}: {}", path.display(), e);
-        std::process::exit(1);
-    });
+    let created = fs::File::create(path);
+    crate::log::file("wrote", path, &created);
+    let mut file = created
+        .map_err(|e| GitIdError::Io(format!("Error creating file {}: {e}", path.display())))?;
     file.write_all(script.as_bytes()).unwrap();
     file.write_all(helper.as_bytes()).unwrap();
     file.flush().unwrap_or_default();
+    Ok(())
 }
 
-fn write_completion(shell: Shell, path: &PathBuf) {
+fn write_completion(shell: Shell, path: &PathBuf) -> Result<()> {
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).unwrap_or_else(|e| {
-            eprintln!("Error creating directory {}: {}", parent.display(), e);
-            std::process::exit(1);
-        });
+        fs::create_dir_all(parent).map_err(|e| {
+            GitIdError::Io(format!("Error creating directory {}: {e}", parent.display()))
+        })?;
     }
-    let mut file = fs::File::create(path).unwrap_or_else(|e| {
-        eprintln!("Error creating file {}: {}", path.display(), e);
-        std::process::exit(1);
-    });
+    let created = fs::File::create(path);
+    crate::log::file("wrote", path, &created);
+    let mut file = created
+        .map_err(|e| GitIdError::Io(format!("Error creating file {}: {e}", path.display())))?;
     generate(shell, &mut build_command(), "git-id", &mut file);
     file.flush().unwrap_or_default();
+    Ok(())
 }
@@ -0,0 +1,121 @@
+use crate::config::{account_id, expand_home, find_account_exact, load_accounts};
+use crate::git::{
+    get_git_config, get_git_config_all, set_git_config, set_git_config_values, unset_git_config,
+};
+use crate::error::Result;
+use crate::models::Account;
+use crate::tokens::account_token;
+use crate::ui::print_warn;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Marks helper entries written by git-id, so switching to an account
+/// without credentials can drop them again.
+const HELPER_MARKER: &str = " credential --account ";
+
+/// Whether the account can answer a credential request.
+pub fn has_credentials(acc: &Account) -> bool {
+    !acc.https_token.is_empty() || !acc.app_id.is_empty()
+}
+
+/// `credential.helper` value that runs this binary for the account.
+pub fn helper_command(acc: &Account) -> String {
+    let exe = std::env::current_exe()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "git-id".to_string());
+    format!("!'{}'{HELPER_MARKER}{}", exe.replace('\'', r"'\''"), account_id(acc))
+}
+
+/// Points the credential helper in `scope` (the repo's or a worktree's) at
+/// the account. The leading empty value clears helpers inherited from
+/// broader config, so a keychain entry for another account on the same host
+/// is never used here.
+pub fn configure_helper(acc: &Account, scope: &str, dry_run: bool) {
+    if has_credentials(acc) {
+        let helper = helper_command(acc);
+        set_git_config_values("credential.helper", &["", &helper], scope, dry_run);
+        return;
+    }
+    if has_own_helper(scope) {
+        set_git_config_values("credential.helper", &[], scope, dry_run);
+    }
+}
+
+/// Points git's HTTPS client certificate for the account's host in `scope`
+/// at the account's `https_cert` and `https_key`. When the account has
+/// none, a certificate some account set there before is removed, so it is
+/// not presented on the new account's behalf.
+pub fn configure_client_cert(acc: &Account, scope: &str, dry_run: bool) {
+    let accounts = load_accounts().unwrap_or_default();
+    let section = format!("http.https://{}/", host_of(acc));
+    for (name, path) in [("sslCert", &acc.https_cert), ("sslKey", &acc.https_key)] {
+        let key = format!("{section}.{name}");
+        if !path.is_empty() {
+            set_git_config(&key, &expand_home(path).to_string_lossy(), scope, dry_run);
+            continue;
+        }
+        let current = get_git_config(&key, scope);
+        let ours = accounts.iter().any(|a| {
+            let file = if name == "sslCert" { &a.https_cert } else { &a.https_key };
+            !file.is_empty() && expand_home(file) == expand_home(&current)
+        });
+        if !current.is_empty() && ours {
+            unset_git_config(&key, scope, dry_run);
+        }
+    }
+}
+
+/// Whether `credential.helper` in `scope` was written by git-id.
+pub fn has_own_helper(scope: &str) -> bool {
+    get_git_config_all("credential.helper", scope)
+        .iter()
+        .any(|h| h.contains(HELPER_MARKER))
+}
+
+/// Implements git's credential helper protocol. Only `get` answers; `store`
+/// and `erase` are accepted and ignored because tokens live in accounts.toml.
+pub fn cmd_credential(operation: &str, account: Option<&str>) -> Result<()> {
+    let mut input = String::new();
+    let _ = std::io::stdin().read_to_string(&mut input);
+    if operation != "get" {
+        return Ok(());
+    }
+    let request: HashMap<&str, &str> = input
+        .lines()
+        .take_while(|l| !l.is_empty())
+        .filter_map(|l| l.split_once('='))
+        .collect();
+    if request.get("protocol").is_some_and(|p| *p != "https") {
+        return Ok(());
+    }
+    let host = request.get("host").copied().unwrap_or_default();
+    let user = request.get("username").copied();
+
+    let acc = match account {
+        Some(id) => find_account_exact(id)?,
+        None => load_accounts()?
+            .into_iter()
+            .find(|a| host_of(a) == host && user.is_none_or(|u| u == a.username)),
+    };
+    // Say nothing when the request is not ours; git then asks the next helper.
+    let Some(acc) = acc.filter(|a| host_of(a) == host) else {
+        return Ok(());
+    };
+    if user.is_some_and(|u| u != acc.username && u != "x-access-token") {
+        return Ok(());
+    }
+    match account_token(&acc) {
+        Ok(token) if token.is_empty() => {}
+        Ok(token) => {
+            let login = if acc.app_id.is_empty() { acc.username.as_str() } else { "x-access-token" };
+            println!("username={login}");
+            println!("password={token}");
+        }
+        Err(e) => print_warn(&format!("git-id: no token for '{}': {e}", account_id(&acc))),
+    }
+    Ok(())
+}
+
+fn host_of(acc: &Account) -> &str {
+    if acc.host.is_empty() { "github.com" } else { &acc.host }
+}
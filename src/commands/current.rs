@@ -0,0 +1,115 @@
+use crate::config::{
+    account_id, accounts_file, cache_dir, commit_emails, load_accounts, normalize_email,
+};
+use crate::error::{GitIdError, Result};
+use crate::git::run_git;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Emails of the accounts, saved next to the token cache so a prompt does
+/// not parse accounts.toml on every redraw. Its first line is the stamp of
+/// the accounts file it was built from; each other line is
+/// `email<TAB>username<TAB>account id`, with the email normalized, and an
+/// account with alternate emails has a line for each.
+fn index_path() -> PathBuf {
+    cache_dir().join("prompt-index")
+}
+
+/// Modification time and size of the accounts file, or None without one.
+fn accounts_stamp(path: &Path) -> Option<String> {
+    let meta = std::fs::metadata(path).ok()?;
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("{}.{:09} {}", modified.as_secs(), modified.subsec_nanos(), meta.len()))
+}
+
+/// (email, username, account id) of every account, from the index while
+/// accounts.toml is unchanged, else parsed and written back to the index.
+/// Emails are normalized without stripping +tags, which callers do.
+fn account_index() -> Vec<(String, String, String)> {
+    let Some(stamp) = accounts_stamp(&accounts_file()) else {
+        return vec![];
+    };
+    let path = index_path();
+    if let Ok(content) = crate::log::read_to_string(&path)
+        && let Some((first, rest)) = content.split_once('\n')
+        && first == stamp
+    {
+        return rest
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                Some((
+                    fields.next()?.to_string(),
+                    fields.next()?.to_string(),
+                    fields.next()?.to_string(),
+                ))
+            })
+            .collect();
+    }
+    let index: Vec<(String, String, String)> = load_accounts()
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|a| {
+            commit_emails(a)
+                .map(|email| (normalize_email(email, false), a.username.clone(), account_id(a)))
+        })
+        .collect();
+    let mut content = format!("{stamp}\n");
+    for (email, username, id) in &index {
+        content.push_str(&format!("{email}\t{username}\t{id}\n"));
+    }
+    if std::fs::create_dir_all(cache_dir()).is_ok() {
+        let _ = crate::log::write(&path, content);
+    }
+    index
+}
+
+/// The email git would commit with here and whether +tags are stripped,
+/// read with a single git call. `GIT_AUTHOR_EMAIL` outranks the config.
+fn effective_email() -> (String, bool) {
+    let (_, out, _) = run_git(&[
+        "config",
+        "--get-regexp",
+        r"^(user\.email|git-id\.stripplusaddress)$",
+    ]);
+    let mut email = String::new();
+    let mut strip_plus = false;
+    // Later files win, so the last value of each key is the effective one.
+    for line in out.lines() {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match key {
+            "user.email" => email = value.to_string(),
+            _ => strip_plus = value.eq_ignore_ascii_case("true"),
+        }
+    }
+    if let Ok(env) = std::env::var("GIT_AUTHOR_EMAIL") {
+        email = env;
+    }
+    (email, strip_plus)
+}
+
+/// Prints the account the effective identity belongs to. With `prompt`,
+/// prints its username or nothing, never fails and never writes to stderr,
+/// for shell prompt segments: one git call, no ssh-agent, and accounts.toml
+/// parsed only when it changed.
+pub fn cmd_current(prompt: bool) -> Result<()> {
+    let (email, strip_plus) = effective_email();
+    let wanted = normalize_email(&email, strip_plus);
+    let found = if wanted.is_empty() {
+        None
+    } else {
+        account_index()
+            .into_iter()
+            .find(|(email, ..)| normalize_email(email, strip_plus) == wanted)
+    };
+    match found {
+        Some((_, username, _)) if prompt => println!("{username}"),
+        Some((_, _, id)) => println!("{id}"),
+        None if prompt => {}
+        None => {
+            println!("(no account)");
+            return Err(GitIdError::CheckFailed);
+        }
+    }
+    Ok(())
+}
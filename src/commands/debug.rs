@@ -0,0 +1,187 @@
+use crate::config::dirs_home;
+use crate::error::{GitIdError, Result};
+use crate::ui::{print_hdr, print_info, print_ok};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Scenarios `debug fixture` can build:
+/// - `basic`: accounts on github.com, each with a key and a repo it is used in;
+/// - `hosts`: the same spread over github.com, gitlab.com and an enterprise host;
+/// - `broken`: `basic` plus problems for `doctor` and `status` to find.
+pub const SCENARIOS: [&str; 3] = ["basic", "hosts", "broken"];
+
+const NAMES: &[&str] = &["alice", "bob", "carol", "dave", "erin", "frank", "grace", "heidi"];
+const HOSTS: &[&str] = &["github.com", "gitlab.com", "github.example.com"];
+
+/// Environment a fixture is built with: its own HOME and nothing that would
+/// point git or git-id back at the real configuration.
+const CLEARED_ENV: &[&str] = &[
+    "XDG_CONFIG_HOME",
+    "XDG_CACHE_HOME",
+    "XDG_DATA_HOME",
+    "GIT_CONFIG_GLOBAL",
+    "GIT_CONFIG_SYSTEM",
+    "GIT_CONFIG_PARAMETERS",
+    "GIT_DIR",
+    "GIT_AUTHOR_NAME",
+    "GIT_AUTHOR_EMAIL",
+    "GIT_COMMITTER_NAME",
+    "GIT_COMMITTER_EMAIL",
+    "SSH_AUTH_SOCK",
+    "GIT_ID_READONLY",
+];
+
+struct Fixture {
+    home: PathBuf,
+}
+
+impl Fixture {
+    fn command(&self, program: impl AsRef<std::ffi::OsStr>, dir: &Path) -> Command {
+        let mut cmd = Command::new(program);
+        for var in CLEARED_ENV {
+            cmd.env_remove(var);
+        }
+        cmd.env("HOME", &self.home)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .current_dir(dir)
+            .stdin(Stdio::null());
+        cmd
+    }
+
+    fn run(&self, mut cmd: Command) -> Result<()> {
+        let args: Vec<String> = cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        let line = format!("{} {}", cmd.get_program().to_string_lossy(), args.join(" "));
+        let out = crate::log::output(&mut cmd)
+            .map_err(|e| GitIdError::Io(format!("Cannot run {line}: {e}")))?;
+        if !out.status.success() {
+            return Err(GitIdError::Io(format!(
+                "{line} failed: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+
+    fn git(&self, dir: &Path, args: &[&str]) -> Result<()> {
+        let mut cmd = self.command("git", dir);
+        cmd.args(args);
+        self.run(cmd)
+    }
+
+    /// Runs this git-id binary inside the fixture.
+    fn git_id(&self, dir: &Path, args: &[&str]) -> Result<()> {
+        let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("git-id"));
+        let mut cmd = self.command(exe, dir);
+        cmd.args(args);
+        self.run(cmd)
+    }
+
+    /// A repo for `owner` with one commit and `origin` on `host`.
+    fn repo(&self, owner: &str, host: &str) -> Result<PathBuf> {
+        let dir = self.home.join("src").join(owner).join("project");
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| GitIdError::Io(format!("Cannot create {}: {e}", dir.display())))?;
+        self.git(&dir, &["init", "-q", "-b", "main"])?;
+        crate::log::write(dir.join("README.md"), format!("# {owner}/project\n"))
+            .map_err(|e| GitIdError::Io(format!("Cannot write in {}: {e}", dir.display())))?;
+        self.git(&dir, &["add", "README.md"])?;
+        let ident = [("GIT_AUTHOR_NAME", owner), ("GIT_COMMITTER_NAME", owner)];
+        let email = format!("{owner}@example.com");
+        let mut cmd = self.command("git", &dir);
+        cmd.envs(ident)
+            .env("GIT_AUTHOR_EMAIL", &email)
+            .env("GIT_COMMITTER_EMAIL", &email)
+            .args(["commit", "-q", "-m", "Initial commit"]);
+        self.run(cmd)?;
+        let url = format!("git@{host}:{owner}/project.git");
+        self.git(&dir, &["remote", "add", "origin", &url])?;
+        Ok(dir)
+    }
+}
+
+/// Builds a throwaway HOME with `count` accounts, their SSH keys and
+/// stanzas, and a sample repo per account, by running git-id itself in it.
+/// Used as a sandbox to try commands in.
+pub fn cmd_debug_fixture(
+    scenario: &str,
+    count: usize,
+    dir: Option<PathBuf>,
+    dry_run: bool,
+) -> Result<()> {
+    if !(1..=20).contains(&count) {
+        return Err(GitIdError::Usage("--accounts must be between 1 and 20".to_string()));
+    }
+    let home = dir.unwrap_or_else(|| {
+        std::env::temp_dir().join(format!("git-id-fixture-{scenario}-{}", std::process::id()))
+    });
+    let home = std::path::absolute(&home).unwrap_or(home);
+    if home == dirs_home() {
+        return Err(GitIdError::Usage(format!(
+            "{} is your real home directory - pick an empty one with --dir",
+            home.display()
+        )));
+    }
+    if std::fs::read_dir(&home).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(GitIdError::Usage(format!(
+            "{} is not empty - pick an empty or new directory with --dir",
+            home.display()
+        )));
+    }
+    let names: Vec<String> = (0..count)
+        .map(|i| NAMES.get(i).map_or_else(|| format!("user{}", i + 1), |n| n.to_string()))
+        .collect();
+    let host_of = |i: usize| if scenario == "hosts" { HOSTS[i % HOSTS.len()] } else { HOSTS[0] };
+    if dry_run {
+        print_info(&format!(
+            "[dry-run] Would create the '{scenario}' fixture in {} with accounts: {}",
+            home.display(),
+            names.join(", ")
+        ));
+        return Ok(());
+    }
+
+    let fixture = Fixture { home: home.clone() };
+    let ssh_dir = home.join(".ssh");
+    std::fs::create_dir_all(&ssh_dir)
+        .map_err(|e| GitIdError::Io(format!("Cannot create {}: {e}", ssh_dir.display())))?;
+    for (i, name) in names.iter().enumerate() {
+        let host = host_of(i);
+        let key = ssh_dir.join(format!("id_ed25519_{name}"));
+        let mut keygen = fixture.command("ssh-keygen", &home);
+        keygen.args(["-q", "-t", "ed25519", "-N", "", "-C"]).arg(format!("{name}@example.com"));
+        keygen.arg("-f").arg(&key);
+        fixture.run(keygen)?;
+        let email = format!("{name}@example.com");
+        let key = key.to_string_lossy().to_string();
+        let args = ["add", "--username", name, "--email", &email, "--host", host];
+        fixture.git_id(&home, &[&args[..], &["--ssh-key", &key]].concat())?;
+        let repo = fixture.repo(name, host)?;
+        fixture.git_id(&repo, &["use", &format!("{name}@{host}")])?;
+        print_ok(&format!("Account {name}@{host} with repo {}", repo.display()));
+    }
+
+    if scenario == "broken" {
+        let first = fixture.home.join("src").join(&names[0]).join("project");
+        fixture.git(&first, &["config", "user.email", "old-address@example.com"])?;
+        print_ok(&format!("{}: user.email belongs to no account", first.display()));
+        let stranger = fixture.repo("stranger", HOSTS[0])?;
+        print_ok(&format!("{}: origin is owned by no account", stranger.display()));
+        let last = names.last().map(String::as_str).unwrap_or_default();
+        let key = ssh_dir.join(format!("id_ed25519_{last}"));
+        let result = std::fs::remove_file(&key);
+        crate::log::file("deleted", &key, &result);
+        result.map_err(|e| GitIdError::Io(format!("Cannot delete {}: {e}", key.display())))?;
+        print_ok(&format!("{last}: private key {} is missing", key.display()));
+    }
+
+    print_hdr(&format!("Fixture '{scenario}' ready in {}", home.display()));
+    println!("  Try it without touching your own setup:");
+    println!("    git-id --home '{}' list", home.display());
+    println!("  or start a shell in it:");
+    println!(
+        "    env -u XDG_CONFIG_HOME -u XDG_CACHE_HOME -u XDG_DATA_HOME -u GIT_CONFIG_GLOBAL \
+         HOME='{}' GIT_CONFIG_NOSYSTEM=1 \"$SHELL\"",
+        home.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,344 @@
+use crate::commands::with::ssh_command;
+use crate::config::{
+    account_id, config_dir, dirs_home, expand_home, find_account, has_email, load_accounts,
+    normalize_email,
+};
+use crate::error::{GitIdError, Result};
+use crate::git::{
+    config_file_path, get_git_config_regexp, run_git, set_git_config, unset_git_config,
+};
+use crate::models::Account;
+use crate::repos::{load_repos, repo_root};
+use crate::ui::{backup, color, print_hdr, print_info, print_ok};
+use std::path::{Path, PathBuf};
+
+/// Include fragment holding the identity of one account.
+pub fn fragment_path(acc: &Account) -> PathBuf {
+    config_dir().join(format!("{}.gitconfig", account_id(acc)))
+}
+
+/// `includeIf "gitdir:..."` rules in the global config, as (directory,
+/// included file).
+pub fn dir_rules() -> Vec<(String, String)> {
+    get_git_config_regexp(r"^includeif\.gitdir:.*\.path$", "global")
+        .into_iter()
+        .filter_map(|(key, path)| {
+            let dir = key.strip_prefix("includeif.gitdir:")?.strip_suffix(".path")?;
+            Some((dir.to_string(), path))
+        })
+        .collect()
+}
+
+pub fn cmd_dirs_add(path: &str, username: &str, dry_run: bool) -> Result<()> {
+    let acc = find_account(username)?.ok_or_else(|| {
+        GitIdError::Usage(format!("Account '{username}' not found. Run: git-id list"))
+    })?;
+    let acc = crate::secrets::resolved(&acc)?;
+    let dir = gitdir_pattern(path);
+    let fragment = fragment_path(&acc);
+    write_fragment(&acc, &fragment, dry_run)?;
+    set_git_config(
+        &format!("includeIf.gitdir:{dir}.path"),
+        &fragment.to_string_lossy(),
+        "global",
+        dry_run,
+    );
+    print_ok(&format!("Repos under {dir} now use '{}'", account_id(&acc)));
+    Ok(())
+}
+
+pub fn cmd_dirs_remove(path: &str, dry_run: bool) -> Result<()> {
+    let dir = gitdir_pattern(path);
+    if !dir_rules().iter().any(|(d, _)| *d == dir) {
+        return Err(GitIdError::Usage(format!(
+            "No directory rule for {dir}. Run: git-id dirs list"
+        )));
+    }
+    unset_git_config(&format!("includeIf.gitdir:{dir}.path"), "global", dry_run);
+    if !dry_run {
+        print_ok(&format!("Removed directory rule for {dir}"));
+    }
+    Ok(())
+}
+
+pub fn cmd_dirs_list() -> Result<()> {
+    let rules = dir_rules();
+    if rules.is_empty() {
+        print_info("No directory rules. Add one with: git-id dirs add <path> <account>");
+        return Ok(());
+    }
+    print_hdr("Directory identities");
+    for (dir, fragment) in rules {
+        let owner = Path::new(&fragment)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(".gitconfig"))
+            .unwrap_or("(not managed by git-id)")
+            .to_string();
+        println!("  {}  {}  {}", color("bold", &dir), owner, color("dim", &fragment));
+    }
+    println!();
+    Ok(())
+}
+
+/// The account whose include fragment `fragment` is.
+fn rule_account<'a>(accounts: &'a [Account], fragment: &str) -> Option<&'a Account> {
+    let fragment = expand_home(fragment);
+    accounts.iter().find(|a| fragment_path(a) == fragment)
+}
+
+/// `path` with the home directory shown as `~`.
+fn tilde(path: &Path) -> String {
+    match path.strip_prefix(dirs_home()) {
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+/// Directory rules nested as the directories are, each with the account it
+/// applies, and the registered repos under them with the account they end
+/// up with. Repos whose local identity overrides their rule stand out.
+pub fn cmd_dirs_tree() -> Result<()> {
+    let accounts = load_accounts()?;
+    // In config order: when rules nest, git includes every matching one and
+    // the last one wins.
+    let rules: Vec<(PathBuf, String, Option<&Account>)> = dir_rules()
+        .into_iter()
+        .map(|(dir, fragment)| {
+            let acc = rule_account(&accounts, &fragment);
+            let name = acc.map_or_else(|| "(not managed by git-id)".to_string(), account_id);
+            (expand_home(dir.trim_end_matches('/')), name, acc)
+        })
+        .collect();
+    if rules.is_empty() {
+        print_info("No directory rules. Add one with: git-id dirs add <path> <account>");
+        return Ok(());
+    }
+    let (inside, outside): (Vec<PathBuf>, Vec<PathBuf>) = load_repos()?
+        .into_iter()
+        .partition(|repo| rules.iter().any(|(dir, ..)| repo.starts_with(dir)));
+
+    // Rules first where a repo sits right at a rule's directory.
+    let mut nodes: Vec<(PathBuf, Option<usize>)> = rules
+        .iter()
+        .enumerate()
+        .map(|(i, (dir, ..))| (dir.clone(), Some(i)))
+        .chain(inside.into_iter().map(|repo| (repo, None)))
+        .collect();
+    nodes.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.is_some().cmp(&a.1.is_some())));
+
+    let mut lines = vec![];
+    let mut parents: Vec<&Path> = vec![];
+    for (path, rule) in &nodes {
+        while parents.last().is_some_and(|parent| !path.starts_with(parent)) {
+            parents.pop();
+        }
+        let label = match parents.last().map(|parent| path.strip_prefix(parent)) {
+            Some(Ok(rest)) if rest.as_os_str().is_empty() => ".".to_string(),
+            Some(Ok(rest)) => rest.display().to_string(),
+            _ => tilde(path),
+        };
+        let label = format!("{}{label}", "  ".repeat(parents.len()));
+        let detail = match rule {
+            Some(i) => color("cyan", &rules[*i].1),
+            None => repo_identity(path, &rules, &accounts),
+        };
+        let label = if rule.is_some() { format!("{label}/") } else { label };
+        lines.push((label, rule.is_some(), detail));
+        parents.push(path);
+    }
+
+    print_hdr("Directory identities");
+    let width = lines.iter().map(|(label, ..)| label.chars().count()).max().unwrap_or(0);
+    for (label, is_rule, detail) in lines {
+        let padded = format!("{label:<width$}");
+        let shown = if is_rule { color("bold", &padded) } else { padded };
+        println!("  {shown}  {detail}");
+    }
+    println!();
+    if !outside.is_empty() {
+        print_info(&format!(
+            "{} registered repo(s) are outside every rule - see: git-id repos list",
+            outside.len()
+        ));
+    }
+    Ok(())
+}
+
+/// The account a repo under `rules` gets, and whether its local identity
+/// overrides the rule.
+fn repo_identity(
+    repo: &Path,
+    rules: &[(PathBuf, String, Option<&Account>)],
+    accounts: &[Account],
+) -> String {
+    let Some((_, rule_name, rule_acc)) = rules.iter().rev().find(|(dir, ..)| repo.starts_with(dir))
+    else {
+        return String::new();
+    };
+    if repo_root(repo).is_none() {
+        return color("red", "missing");
+    }
+    let path = repo.to_string_lossy();
+    let (_, email, _) = run_git(&["-C", &path, "config", "--local", "--get", "user.email"]);
+    if email.is_empty() {
+        return format!("{rule_name} {}", color("dim", "(from the rule)"));
+    }
+    let wanted = normalize_email(&email, false);
+    if rule_acc.is_some_and(|acc| has_email(acc, &wanted, false)) {
+        return format!("{rule_name} {}", color("dim", "(also set locally)"));
+    }
+    let local = match accounts.iter().find(|a| has_email(a, &wanted, false)) {
+        Some(acc) => account_id(acc),
+        None => format!("<{email}>"),
+    };
+    color("yellow", &format!("{local} (overrides {rule_name} locally)"))
+}
+
+/// Header `write_fragment` puts on the files it owns.
+const FRAGMENT_HEADER: &str = "# managed by git-id for ";
+
+/// Whether the directory of a `gitdir:` pattern is gone. Patterns with
+/// wildcards, and relative ones (which git matches anywhere), never are.
+fn dir_missing(dir: &str) -> bool {
+    let absolute = dir.starts_with('/') || dir.starts_with("~/");
+    absolute && !dir.contains(['*', '?', '[']) && !expand_home(dir).exists()
+}
+
+/// Include fragments `write_fragment` wrote into the config dir.
+fn managed_fragments() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(config_dir()) else {
+        return vec![];
+    };
+    let mut found: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "gitconfig"))
+        .filter(|path| {
+            crate::log::read_to_string(path).is_ok_and(|c| c.starts_with(FRAGMENT_HEADER))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+/// Removes what the directory rules leave behind: rules including the
+/// fragment of an account that no longer exists or covering a directory
+/// that is gone, and fragments no include or includeIf of any kind still
+/// names. Rules pointing outside the config dir are the user's own and are
+/// left alone. The global gitconfig and each fragment are backed up before
+/// they change.
+pub fn cmd_dirs_gc(dry_run: bool) -> Result<()> {
+    let accounts = load_accounts()?;
+    let cfg_dir = config_dir();
+    let mut stale_rules: Vec<(String, String)> = vec![];
+    let mut rewrite: Vec<&Account> = vec![];
+    for (dir, fragment) in dir_rules() {
+        let path = expand_home(&fragment);
+        if !path.starts_with(&cfg_dir) {
+            continue;
+        }
+        let acc = rule_account(&accounts, &fragment);
+        let reason = if acc.is_none() {
+            "its account no longer exists"
+        } else if dir_missing(&dir) {
+            "the directory no longer exists"
+        } else {
+            if let Some(acc) = acc.filter(|_| !path.exists()) {
+                rewrite.push(acc);
+            }
+            continue;
+        };
+        stale_rules.push((dir, reason.to_string()));
+    }
+    // Any other include still using a fragment keeps it, even one whose key
+    // is not a gitdir rule (onbranch:, hasconfig:, gitdir/i:, plain include).
+    let removed: Vec<String> =
+        stale_rules.iter().map(|(dir, _)| format!("includeif.gitdir:{dir}.path")).collect();
+    let referenced: Vec<PathBuf> = get_git_config_regexp(r"^include(if\..*)?\.path$", "global")
+        .into_iter()
+        .filter(|(key, _)| !removed.contains(key))
+        .map(|(_, path)| expand_home(&path))
+        .collect();
+    let stale_fragments: Vec<(PathBuf, &str)> = managed_fragments()
+        .into_iter()
+        .filter(|path| !referenced.contains(path))
+        .map(|path| {
+            let owned = accounts.iter().any(|a| fragment_path(a) == path);
+            let reason = if owned {
+                "nothing includes it"
+            } else {
+                "its account no longer exists"
+            };
+            (path, reason)
+        })
+        .collect();
+
+    if stale_rules.is_empty() && stale_fragments.is_empty() && rewrite.is_empty() {
+        print_info("No orphaned directory rules or fragments");
+        return Ok(());
+    }
+    for acc in rewrite {
+        print_info(&format!("The fragment of '{}' is missing; writing it again", account_id(acc)));
+        write_fragment(acc, &fragment_path(acc), dry_run)?;
+    }
+    if !stale_rules.is_empty() && !dry_run {
+        backup(&config_file_path("global").0);
+    }
+    for (dir, reason) in &stale_rules {
+        unset_git_config(&format!("includeIf.gitdir:{dir}.path"), "global", dry_run);
+        if dry_run {
+            print_info(&format!("[dry-run] Would remove the directory rule for {dir} ({reason})"));
+        } else {
+            print_ok(&format!("Removed directory rule for {dir} ({reason})"));
+        }
+    }
+    for (path, reason) in &stale_fragments {
+        if dry_run {
+            print_info(&format!("[dry-run] Would delete {} ({reason})", tilde(path)));
+            continue;
+        }
+        backup(path);
+        let result = std::fs::remove_file(path);
+        crate::log::file("deleted", path, &result);
+        result.map_err(|e| GitIdError::Io(format!("Cannot delete {}: {e}", path.display())))?;
+        print_ok(&format!("Deleted {} ({reason})", tilde(path)));
+    }
+    Ok(())
+}
+
+/// Absolute directory with a trailing slash, so the rule matches every
+/// repository below it.
+fn gitdir_pattern(path: &str) -> String {
+    let path = expand_home(path);
+    let path = std::fs::canonicalize(&path).unwrap_or(path);
+    let mut dir = path.to_string_lossy().to_string();
+    if !dir.ends_with('/') {
+        dir.push('/');
+    }
+    dir
+}
+
+pub fn write_fragment(acc: &Account, fragment: &Path, dry_run: bool) -> Result<()> {
+    let quote = |v: &str| format!("\"{}\"", v.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut content = format!(
+        "{FRAGMENT_HEADER}{}\n[user]\n\tname = {}\n\temail = {}\n",
+        account_id(acc),
+        quote(&acc.username),
+        quote(&acc.email)
+    );
+    if !acc.ssh_key.is_empty() {
+        content.push_str(&format!("[core]\n\tsshCommand = {}\n", quote(&ssh_command(acc))));
+    }
+    if dry_run {
+        print_info(&format!("[dry-run] Would write {}:", fragment.display()));
+        print!("{content}");
+        return Ok(());
+    }
+    std::fs::create_dir_all(config_dir())
+        .map_err(|e| GitIdError::Io(format!("Cannot create config dir: {e}")))?;
+    crate::log::write(fragment, content)
+        .map_err(|e| GitIdError::Io(format!("Failed to write {}: {e}", fragment.display())))?;
+    print_ok(&format!("Wrote {}", fragment.display()));
+    Ok(())
+}
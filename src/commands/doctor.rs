@@ -0,0 +1,451 @@
+use crate::commands::ssh::banner_login;
+use crate::config::{
+    account_id, accounts_file, expand_home, is_expired, key_age_days, read_accounts_file,
+    ssh_host_alias, stale_key_age,
+};
+use crate::error::{GitIdError, Result};
+use crate::git::{get_git_config, identity_override, in_git_repo, run_git};
+use crate::models::Account;
+use crate::secrets::{is_reference, references, resolve, resolved};
+use crate::ssh::{
+    STANZA_FORMAT, agent_public_keys, all_ssh_config, current_stanza, effective_ssh_config,
+    is_security_key, make_stanza, managed_config_path, managed_layout_problem, ssh_probe,
+    stanza_formats,
+};
+use crate::ui::{color, print_hdr, print_ok, print_warn};
+use std::path::Path;
+use std::time::Duration;
+
+/// How long one connection attempt may take before it counts as hanging.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Oldest git that supports everything git-id writes (`includeIf "gitdir:"`).
+const MIN_GIT_VERSION: (u32, u32) = (2, 13);
+
+/// Tally of failed checks; each one is printed with a suggested fix.
+#[derive(Default)]
+struct Report {
+    problems: usize,
+}
+
+impl Report {
+    fn ok(&self, msg: &str) {
+        println!("    {} {msg}", color("green", "OK"));
+    }
+
+    fn problem(&mut self, msg: &str, fix: &str) {
+        self.problems += 1;
+        println!("    {} {msg}", color("red", "!!"));
+        println!("       {} {fix}", color("dim", "fix:"));
+    }
+
+    fn note(&self, msg: &str) {
+        println!("    {} {}", color("dim", "--"), color("dim", msg));
+    }
+}
+
+pub fn cmd_doctor(network: bool) -> Result<()> {
+    print_hdr("git-id doctor");
+    let mut report = Report::default();
+
+    println!("\n  {}", color("bold", "git"));
+    check_git_version(&mut report);
+
+    println!("\n  {}", color("bold", "identity"));
+    check_identity_overrides(&mut report);
+
+    println!("\n  {}", color("bold", "accounts.toml"));
+    let path = accounts_file();
+    let accounts = if !path.exists() {
+        report.note(&format!("{} does not exist yet - run: git-id add", path.display()));
+        vec![]
+    } else {
+        match read_accounts_file(&path) {
+            Ok(accounts) => {
+                report.ok(&format!("{} parses ({} account(s))", path.display(), accounts.len()));
+                accounts
+            }
+            Err(e) => {
+                let fix = format!("correct the syntax in {}", path.display());
+                report.problem(&e.to_string(), &fix);
+                vec![]
+            }
+        }
+    };
+
+    let ssh_config = all_ssh_config();
+    let formats = stanza_formats(&ssh_config);
+    if let Some(problem) = managed_layout_problem() {
+        report.problem(&problem, "git-id ssh config");
+    }
+    for acc in &accounts {
+        let id = account_id(acc);
+        println!("\n  {}", color("bold", &id));
+        if is_expired(acc) {
+            report.problem(
+                &format!("expired on {}", acc.expires),
+                &format!("git-id remove {id}"),
+            );
+        }
+        check_key(&mut report, acc);
+        check_stanza(&mut report, acc, &ssh_config, &formats);
+        check_token(&mut report, acc);
+        check_client_cert(&mut report, acc, network);
+        check_secrets(&mut report, acc);
+        if network {
+            check_network(&mut report, acc);
+        }
+    }
+
+    println!();
+    if report.problems == 0 {
+        print_ok("No problems found");
+        Ok(())
+    } else {
+        print_warn(&format!("{} problem(s) found", report.problems));
+        Err(GitIdError::CheckFailed)
+    }
+}
+
+/// Parses "git version 2.43.0" (and vendor suffixes) into (major, minor).
+fn parse_git_version(out: &str) -> Option<(u32, u32)> {
+    let version = out.split_whitespace().nth(2)?;
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+fn check_git_version(report: &mut Report) {
+    let (code, out, _) = run_git(&["--version"]);
+    if code != 0 {
+        report.problem("git is not installed or not on PATH", "install git");
+        return;
+    }
+    match parse_git_version(&out) {
+        Some(version) if version < MIN_GIT_VERSION => report.problem(
+            &format!("{out} is too old"),
+            &format!("upgrade to git {}.{} or newer", MIN_GIT_VERSION.0, MIN_GIT_VERSION.1),
+        ),
+        Some(_) => report.ok(&out),
+        None => report.note(&format!("could not parse version from {out:?}")),
+    }
+}
+
+/// Flags identity settings that `use` cannot win against, typically a
+/// managed config included from /etc or exported environment variables.
+fn check_identity_overrides(report: &mut Report) {
+    let scope = if in_git_repo() && !get_git_config("user.email", "local").is_empty() {
+        "local"
+    } else {
+        "global"
+    };
+    let mut clean = true;
+    for key in ["user.name", "user.email"] {
+        if let Some(source) = identity_override(key, scope) {
+            clean = false;
+            let fix = if source.starts_with("environment variable") {
+                "unset the variable in your shell profile".to_string()
+            } else {
+                format!("remove {key} from that file, or ask whoever manages it")
+            };
+            report.problem(&format!("{key} is forced by {source} over the {scope} value"), &fix);
+        }
+    }
+    if clean {
+        report.ok(&format!("identity from {scope} config is not overridden"));
+    }
+}
+
+fn check_key(report: &mut Report, acc: &Account) {
+    let id = account_id(acc);
+    if acc.ssh_key.is_empty() {
+        report.note("no SSH key configured (HTTPS only)");
+        return;
+    }
+    let key = expand_home(&acc.ssh_key);
+    let pub_key = key.with_extension("pub");
+
+    let security_key = is_security_key(&pub_key);
+    if security_key {
+        report.note("FIDO2 security key: the key file is a handle, the key stays on the token");
+    }
+    if acc.identity_agent.is_empty() {
+        if !key.exists() && security_key {
+            report.problem(
+                &format!("security key handle {} is missing", acc.ssh_key),
+                &format!("cd ~/.ssh && ssh-keygen -K, then: git-id ssh pick {id}"),
+            );
+            return;
+        }
+        if !key.exists() {
+            report.problem(
+                &format!("private key {} is missing", acc.ssh_key),
+                &format!("git-id ssh gen {id}  (or: git-id ssh pick {id})"),
+            );
+            return;
+        }
+        check_permissions(report, &key);
+    }
+
+    if !pub_key.exists() {
+        report.problem(
+            &format!("public key {} is missing", pub_key.display()),
+            &format!("ssh-keygen -y -f {} > {}", key.display(), pub_key.display()),
+        );
+        return;
+    }
+    let sock = (!acc.identity_agent.is_empty()).then(|| expand_home(&acc.identity_agent));
+    check_agent(report, acc, &pub_key, sock.as_deref());
+    if let Some((age, limit)) = stale_key_age(acc) {
+        report.problem(
+            &format!("SSH key is {age} days old (keys are rotated after {limit} days)"),
+            &format!("git-id ssh rotate {id}"),
+        );
+    } else if let Some(age) = key_age_days(acc) {
+        report.ok(&format!("SSH key is {age} day(s) old"));
+    }
+}
+
+fn check_permissions(report: &mut Report, key: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    let Ok(meta) = std::fs::metadata(key) else {
+        return;
+    };
+    let mode = meta.permissions().mode() & 0o777;
+    if mode & 0o077 != 0 {
+        report.problem(
+            &format!("{} is readable by others (mode {mode:o}); ssh will refuse it", key.display()),
+            &format!("chmod 600 {}", key.display()),
+        );
+    } else {
+        report.ok(&format!("{} (mode {mode:o})", key.display()));
+    }
+}
+
+fn check_agent(report: &mut Report, acc: &Account, pub_key: &Path, sock: Option<&Path>) {
+    let content = crate::log::read_to_string(pub_key).unwrap_or_default();
+    let Some(blob) = content.split_whitespace().nth(1) else {
+        report.problem(
+            &format!("{} is not a valid public key", pub_key.display()),
+            &format!("git-id ssh pick {}", account_id(acc)),
+        );
+        return;
+    };
+    let loaded = agent_public_keys(sock)
+        .iter()
+        .any(|line| line.split_whitespace().nth(1) == Some(blob));
+    let agent = sock.map_or("ssh-agent".to_string(), |s| s.display().to_string());
+    if loaded {
+        report.ok(&format!("key loaded in {agent}"));
+    } else if sock.is_some() {
+        report.problem(
+            &format!("key is not offered by {agent}"),
+            "unlock the agent and enable the key in its settings",
+        );
+    } else {
+        report.problem(
+            "key is not loaded in ssh-agent",
+            &format!("ssh-add {}", expand_home(&acc.ssh_key).display()),
+        );
+    }
+}
+
+fn check_stanza(report: &mut Report, acc: &Account, ssh_config: &str, formats: &[(String, u32)]) {
+    let id = account_id(acc);
+    let Some(stanza) = current_stanza(ssh_config, &id) else {
+        let cfg = managed_config_path();
+        report.problem(
+            &format!("no Host {} stanza in {}", ssh_host_alias(acc), cfg.display()),
+            "git-id ssh config",
+        );
+        return;
+    };
+    let version = formats.iter().find(|(f, _)| *f == id).map_or(1, |(_, v)| *v);
+    if version < STANZA_FORMAT {
+        report.problem(
+            &format!("stanza uses format v{version} (current v{STANZA_FORMAT})"),
+            "git-id ssh config",
+        );
+    } else if stanza != make_stanza(&resolved(acc).unwrap_or_else(|_| acc.clone())) {
+        report.problem(
+            "stanza does not match the account (edited by hand or account changed)",
+            "git-id ssh config",
+        );
+    } else {
+        report.ok(&format!("Host {} stanza up to date", ssh_host_alias(acc)));
+    }
+}
+
+fn check_token(report: &mut Report, acc: &Account) {
+    let id = account_id(acc);
+    if !acc.app_id.is_empty() {
+        // Without app_installation_id the token is minted for the app's only
+        // installation; `token check` reports an app installed several times.
+        if acc.app_key.is_empty() {
+            report.problem(
+                "GitHub App account is missing app_key",
+                &format!("set it in {}", accounts_file().display()),
+            );
+        } else if !expand_home(&acc.app_key).exists() {
+            report.problem(
+                &format!("GitHub App key {} is missing", acc.app_key),
+                &format!("point app_key at the App's private key in {}", accounts_file().display()),
+            );
+        } else {
+            report.ok("GitHub App credentials configured");
+        }
+        return;
+    }
+    if acc.https_token.is_empty() {
+        report.note("no HTTPS token (HTTPS remotes will prompt for credentials)");
+    } else if acc.https_token.trim().is_empty() || acc.https_token.trim() != acc.https_token {
+        report.problem(
+            "https_token contains only whitespace or has surrounding spaces",
+            &format!("re-enter the token for {id} in {}", accounts_file().display()),
+        );
+    } else if !is_reference(&acc.https_token) {
+        report.ok("HTTPS token set");
+    }
+}
+
+/// The account's HTTPS client certificate and key exist and, with
+/// `network`, that its host completes a TLS handshake presenting them.
+fn check_client_cert(report: &mut Report, acc: &Account, network: bool) {
+    if acc.https_cert.is_empty() {
+        if !acc.https_key.is_empty() {
+            report.problem(
+                "https_key is set without https_cert",
+                &format!("set https_cert in {}", accounts_file().display()),
+            );
+        }
+        return;
+    }
+    let cert = expand_home(&acc.https_cert);
+    let key = (!acc.https_key.is_empty()).then(|| expand_home(&acc.https_key));
+    for (field, path) in [("https_cert", Some(&cert)), ("https_key", key.as_ref())] {
+        if let Some(path) = path
+            && !path.is_file()
+        {
+            report.problem(
+                &format!("{field} {} is missing", path.display()),
+                &format!("git-id edit {} --{} <PATH>", account_id(acc), field.replace('_', "-")),
+            );
+            return;
+        }
+    }
+    if !network {
+        report.ok(&format!("HTTPS client certificate {}", cert.display()));
+        return;
+    }
+    let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    let mut cmd = std::process::Command::new("curl");
+    cmd.args(["-sS", "-o", "/dev/null", "-w", "%{http_code}", "--max-time"])
+        .arg(PROBE_TIMEOUT.as_secs().to_string())
+        .arg("--cert")
+        .arg(&cert);
+    if let Some(key) = &key {
+        cmd.arg("--key").arg(key);
+    }
+    cmd.arg(format!("https://{host}/"));
+    match crate::log::output(&mut cmd) {
+        Err(_) => report.note("curl is not installed - cannot try the client certificate"),
+        Ok(out) if out.status.success() => report.ok(&format!(
+            "{host} accepted the client certificate (HTTP {})",
+            String::from_utf8_lossy(&out.stdout).trim()
+        )),
+        Ok(out) => report.problem(
+            &format!(
+                "TLS handshake with {host} failed: {}",
+                last_line(&String::from_utf8_lossy(&out.stderr))
+            ),
+            &format!("curl -v --cert {} https://{host}/", cert.display()),
+        ),
+    }
+}
+
+/// Looks up every `secret:` reference of the account, so a missing pass
+/// entry or a locked vault shows up here rather than mid-push.
+fn check_secrets(report: &mut Report, acc: &Account) {
+    for (field, reference) in references(acc) {
+        match resolve(&reference) {
+            Ok(_) => report.ok(&format!("{field} resolves ({reference})")),
+            Err(e) => report.problem(
+                &format!("{field} does not resolve ({reference}): {e}"),
+                "unlock the secret store or fix the reference in accounts.toml",
+            ),
+        }
+    }
+}
+
+/// Connects to the account's host alias the way git would. With a
+/// ProxyJump, each jump host is tried first (through the hops before it)
+/// so the report names the hop that fails.
+fn check_network(report: &mut Report, acc: &Account) {
+    let alias = ssh_host_alias(acc);
+    let config = effective_ssh_config(&alias);
+    let endpoint = format!(
+        "{}:{}",
+        config.get("hostname").map_or(alias.as_str(), String::as_str),
+        config.get("port").map_or("22", String::as_str)
+    );
+    let jump = config.get("proxyjump").filter(|j| *j != "none").cloned();
+
+    if let Some(jump) = &jump {
+        let hops: Vec<&str> = jump.split(',').collect();
+        for (i, hop) in hops.iter().enumerate() {
+            let via = hops[..i].join(",");
+            let mut args = vec![];
+            if !via.is_empty() {
+                args.extend(["-J", via.as_str()]);
+            }
+            args.extend([*hop, "true"]);
+            // 255 is ssh's own failure; any other status means the hop
+            // answered and authenticated, even if it refused the command.
+            match ssh_probe(&args, PROBE_TIMEOUT) {
+                (Some(255), out) => {
+                    report.problem(
+                        &format!("jump host {hop} failed: {}", last_line(&out)),
+                        &format!("ssh -v {}{hop}", jump_flag(&via)),
+                    );
+                    return;
+                }
+                (None, _) => {
+                    report.problem(
+                        &format!(
+                            "jump host {hop} did not answer within {}s",
+                            PROBE_TIMEOUT.as_secs()
+                        ),
+                        &format!("check the network path to {hop} (VPN, firewall)"),
+                    );
+                    return;
+                }
+                _ => report.ok(&format!("jump host {hop} reachable")),
+            }
+        }
+    }
+
+    let through = jump.as_ref().map_or(String::new(), |j| format!(" through {j}"));
+    let target = format!("git@{alias}");
+    match ssh_probe(&["-T", &target], PROBE_TIMEOUT) {
+        (None, _) => report.problem(
+            &format!("{endpoint}{through} did not answer within {}s", PROBE_TIMEOUT.as_secs()),
+            &format!("ssh -vT {target}"),
+        ),
+        (_, out) => match banner_login(&out) {
+            Some(login) => report.ok(&format!("{endpoint}{through} authenticated as {login}")),
+            None => report.problem(
+                &format!("{endpoint}{through} failed: {}", last_line(&out)),
+                &format!("ssh -vT {target}"),
+            ),
+        },
+    }
+}
+
+fn jump_flag(via: &str) -> String {
+    if via.is_empty() { String::new() } else { format!("-J {via} ") }
+}
+
+fn last_line(out: &str) -> &str {
+    out.lines().rev().map(str::trim).find(|l| !l.is_empty()).unwrap_or("no output")
+}
@@ -0,0 +1,260 @@
+use crate::cli::EditArgs;
+use crate::commands::add::{
+    check_agent_lifetime, check_client_file, check_signing_key, check_timezone, stored_confirm,
+    stored_forge, stored_identity_agent, stored_protocol,
+};
+use crate::commands::dirs::{dir_rules, fragment_path, write_fragment};
+use crate::commands::remove::remove_ssh_config_stanza;
+use crate::config::{account_id, expand_home, find_account, load_accounts, save_accounts};
+use crate::error::{GitIdError, Result};
+use crate::git::set_git_config;
+use crate::models::Account;
+use crate::secrets::warn_unresolved;
+use crate::ssh::{add_key_to_agent, fix_key_permissions, update_ssh_config};
+use crate::ui::{color, print_hdr, print_info, print_ok};
+use dialoguer::{Input, Password};
+
+pub fn cmd_edit(args: &EditArgs, dry_run: bool) -> Result<()> {
+    let old = find_account(&args.username)?.ok_or_else(|| {
+        GitIdError::Usage(format!("Account '{}' not found. Run: git-id list", args.username))
+    })?;
+    let mut acc = old.clone();
+    if args.is_empty() {
+        edit_interactive(&mut acc)?;
+    } else {
+        edit_from_flags(&mut acc, args)?;
+    }
+    save_edit(&old, acc, dry_run)
+}
+
+/// Saves `acc` in place of `old` and brings the SSH config and directory
+/// rules in line with what changed.
+pub fn save_edit(old: &Account, mut acc: Account, dry_run: bool) -> Result<()> {
+    if acc.host != old.host
+        && load_accounts()?
+            .iter()
+            .any(|a| account_id(a).eq_ignore_ascii_case(&account_id(&acc)))
+    {
+        return Err(GitIdError::Usage(format!("Account '{}' already exists.", account_id(&acc))));
+    }
+    if acc.ssh_key != old.ssh_key {
+        acc.key_created.clear();
+    }
+    if acc.ssh_key != old.ssh_key && !acc.ssh_key.is_empty() {
+        let key = expand_home(&acc.ssh_key);
+        if !key.exists() {
+            return Err(GitIdError::Usage(format!("SSH key not found: {}", key.display())));
+        }
+        if !dry_run {
+            fix_key_permissions(&key);
+        }
+        add_key_to_agent(&key, &acc, dry_run);
+    }
+
+    if !acc.email.eq_ignore_ascii_case(&old.email) {
+        remember_email(&mut acc, &old.email);
+    }
+    let changed = changed_fields(old, &acc);
+    if changed.is_empty() {
+        print_info("Nothing changed.");
+        return Ok(());
+    }
+
+    let old_id = account_id(old);
+    let accounts: Vec<Account> = load_accounts()?
+        .into_iter()
+        .map(|a| if account_id(&a) == old_id { acc.clone() } else { a })
+        .collect();
+    save_accounts(&accounts, dry_run)?;
+
+    if acc.host != old.host {
+        remove_ssh_config_stanza(&old_id, dry_run)?;
+    }
+    if acc.host != old.host
+        || acc.ssh_key != old.ssh_key
+        || acc.ssh_alias != old.ssh_alias
+        || acc.identity_agent != old.identity_agent
+    {
+        update_ssh_config(&accounts, dry_run)?;
+    }
+    if acc.email != old.email || acc.host != old.host || acc.ssh_key != old.ssh_key {
+        refresh_dir_rules(old, &acc, dry_run)?;
+    }
+
+    if !dry_run {
+        print_ok(&format!("Account '{}' updated ({}).", account_id(&acc), changed.join(", ")));
+    }
+    Ok(())
+}
+
+fn edit_from_flags(acc: &mut Account, args: &EditArgs) -> Result<()> {
+    if let Some(email) = &args.email {
+        acc.email = email.clone();
+    }
+    if let Some(host) = &args.host {
+        acc.host = host.clone();
+    }
+    if let Some(key) = &args.ssh_key {
+        acc.ssh_key = key.clone();
+    }
+    if let Some(protocol) = &args.protocol {
+        acc.protocol = stored_protocol(protocol);
+    }
+    if let Some(cert) = &args.https_cert {
+        check_client_file("--https-cert", cert)?;
+        acc.https_cert = cert.clone();
+    }
+    if let Some(key) = &args.https_key {
+        check_client_file("--https-key", key)?;
+        acc.https_key = key.clone();
+    }
+    if let Some(tz) = &args.commit_timezone {
+        check_timezone(tz)?;
+        acc.commit_timezone = tz.clone();
+    }
+    if let Some(key) = &args.signing_key {
+        check_signing_key(key)?;
+        acc.signing_key = key.clone();
+    }
+    if let Some(template) = &args.commit_template {
+        check_client_file("--commit-template", template)?;
+        acc.commit_template = template.clone();
+    }
+    if let Some(forge) = &args.forge {
+        acc.forge = stored_forge(forge);
+    }
+    if let Some(agent) = &args.identity_agent {
+        acc.identity_agent = stored_identity_agent(agent)?;
+    }
+    if let Some(lifetime) = &args.agent_lifetime {
+        check_agent_lifetime(lifetime)?;
+        acc.agent_lifetime = lifetime.clone();
+    }
+    if let Some(confirm) = &args.agent_confirm {
+        acc.agent_confirm = stored_confirm(confirm);
+    }
+    if args.token_stdin {
+        let mut token = String::new();
+        std::io::stdin()
+            .read_line(&mut token)
+            .map_err(|e| GitIdError::Io(format!("Failed to read token from stdin: {e}")))?;
+        acc.https_token = token.trim().to_string();
+        warn_unresolved("https_token", &acc.https_token);
+    }
+    Ok(())
+}
+
+/// Prompts for each field with the current value as the default. The token
+/// is never shown: leave it blank to keep it, or enter `-` to clear it.
+pub fn edit_interactive(acc: &mut Account) -> Result<()> {
+    print_hdr(&format!("Edit account '{}'", account_id(acc)));
+    println!();
+    let prompt = |label: &str, current: &str| -> Result<String> {
+        Input::new()
+            .with_prompt(format!("  {}", color("cyan", label)))
+            .default(current.to_string())
+            .allow_empty(true)
+            .interact_text()
+            .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))
+    };
+    acc.email = prompt("Commit email", &acc.email)?;
+    let host = prompt("Host", if acc.host.is_empty() { "github.com" } else { &acc.host })?;
+    if !(acc.host.is_empty() && host == "github.com") {
+        acc.host = host;
+    }
+    acc.ssh_key = prompt("SSH private key (empty for none)", &acc.ssh_key)?;
+
+    let token_state = if acc.https_token.is_empty() { "not set" } else { "set" };
+    let token = Password::new()
+        .with_prompt(format!(
+            "  {}",
+            color("cyan", &format!("HTTPS token ({token_state}; blank keeps, - clears)"))
+        ))
+        .allow_empty_password(true)
+        .interact()
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
+    match token.trim() {
+        "" => {}
+        "-" => acc.https_token.clear(),
+        t => {
+            acc.https_token = t.to_string();
+            warn_unresolved("https_token", t);
+        }
+    }
+    Ok(())
+}
+
+/// Keeps `old_email` in the account's previous emails so commits made with
+/// it still count as the account's; an email switched back to leaves the
+/// list, and one promoted from `alt_emails` leaves that one.
+fn remember_email(acc: &mut Account, old_email: &str) {
+    let known = acc.previous_emails.iter().any(|e| e.eq_ignore_ascii_case(old_email));
+    if !old_email.is_empty() && !known {
+        acc.previous_emails.push(old_email.to_string());
+    }
+    let current = acc.email.clone();
+    acc.previous_emails.retain(|e| !e.eq_ignore_ascii_case(&current));
+    acc.alt_emails.retain(|e| !e.eq_ignore_ascii_case(&current));
+}
+
+fn changed_fields(old: &Account, new: &Account) -> Vec<&'static str> {
+    [
+        ("email", old.email != new.email),
+        ("host", old.host != new.host),
+        ("ssh_key", old.ssh_key != new.ssh_key),
+        ("https_token", old.https_token != new.https_token),
+        ("remote", old.remote != new.remote),
+        ("expires", old.expires != new.expires),
+        ("ssh_alias", old.ssh_alias != new.ssh_alias),
+        ("protocol", old.protocol != new.protocol),
+        ("https_cert", old.https_cert != new.https_cert),
+        ("https_key", old.https_key != new.https_key),
+        ("commit_timezone", old.commit_timezone != new.commit_timezone),
+        ("signing_key", old.signing_key != new.signing_key),
+        ("commit_template", old.commit_template != new.commit_template),
+        ("forge", old.forge != new.forge),
+        ("identity_agent", old.identity_agent != new.identity_agent),
+        ("agent_lifetime", old.agent_lifetime != new.agent_lifetime),
+        ("agent_confirm", old.agent_confirm != new.agent_confirm),
+        ("owners", old.owners != new.owners),
+        ("aka", old.aka != new.aka),
+        ("alt_emails", old.alt_emails != new.alt_emails),
+    ]
+    .into_iter()
+    .filter(|(_, changed)| *changed)
+    .map(|(name, _)| name)
+    .collect()
+}
+
+/// Rewrites the include fragment used by `dirs add` rules so directories
+/// keep the edited identity, moving the rules if the account id changed.
+fn refresh_dir_rules(old: &Account, acc: &Account, dry_run: bool) -> Result<()> {
+    let old_fragment = fragment_path(old);
+    let rules: Vec<String> = dir_rules()
+        .into_iter()
+        .filter(|(_, path)| expand_home(path) == old_fragment)
+        .map(|(dir, _)| dir)
+        .collect();
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let fragment = fragment_path(acc);
+    write_fragment(acc, &fragment, dry_run)?;
+    if fragment == old_fragment {
+        return Ok(());
+    }
+    for dir in &rules {
+        set_git_config(
+            &format!("includeIf.gitdir:{dir}.path"),
+            &fragment.to_string_lossy(),
+            "global",
+            dry_run,
+        );
+    }
+    if dry_run {
+        print_info(&format!("[dry-run] Would delete {}", old_fragment.display()));
+    } else {
+        let _ = std::fs::remove_file(&old_fragment);
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use crate::commands::verify::active_account;
+use crate::commands::with::{env_names, resolved_env};
+use crate::config::{account_id, find_account, load_accounts};
+use crate::error::{GitIdError, Result};
+
+/// Prints `export` lines for the account's `env` table and commit timezone,
+/// preceded by `unset` lines for variables only other accounts declare, so
+/// evaluating the output after switching identities leaves no stale values
+/// behind.
+pub fn cmd_env(account: Option<&str>) -> Result<()> {
+    let acc = match account {
+        Some(name) => find_account(name)?.ok_or_else(|| {
+            GitIdError::Usage(format!("Account '{name}' not found. Run: git-id list"))
+        })?,
+        None => active_account()?.ok_or_else(|| {
+            GitIdError::Usage(
+                "Active email does not match any account. Pass the account to export.".to_string(),
+            )
+        })?,
+    };
+    let acc = crate::secrets::resolved(&acc)?;
+
+    let id = account_id(&acc);
+    if let Some(name) = acc.env.keys().find(|k| !is_env_name(k)) {
+        return Err(GitIdError::Usage(format!("Invalid variable name {name:?} in env of '{id}'")));
+    }
+    let names = env_names(&acc);
+    let mut stale: Vec<String> = load_accounts()?
+        .iter()
+        .filter(|a| account_id(a) != id)
+        .flat_map(env_names)
+        .filter(|k| !names.contains(k))
+        .collect();
+    stale.sort();
+    stale.dedup();
+    for name in stale {
+        println!("unset {name}");
+    }
+    for (name, value) in resolved_env(&acc)? {
+        println!("export {name}='{}'", value.replace('\'', r"'\''"));
+    }
+    Ok(())
+}
+
+fn is_env_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,293 @@
+use crate::commands::use_cmd::remote_belongs_to;
+use crate::config::{
+    account_id, expand_home, has_email, load_accounts, normalize_email, ssh_host_alias,
+};
+use crate::error::{GitIdError, Result};
+use crate::git::{
+    display_url, get_git_config, get_remote_url, in_git_repo, list_remotes, parse_remote_url,
+    run_git, run_git_env, strip_plus_address_enabled,
+};
+use crate::models::Account;
+use crate::ssh::{key_fingerprint, ssh_config_args};
+use crate::ui::{color, print_err, print_hdr, print_info, print_ok, print_warn};
+
+/// A key ssh offered, from its `-v` output.
+struct TriedKey {
+    /// File path, or the comment of an agent key without a file.
+    label: String,
+    fingerprint: String,
+    /// Came from ssh-agent rather than from `IdentityFile` or `-i`.
+    from_agent: bool,
+}
+
+/// What ssh and the server said during the git operation.
+#[derive(Default)]
+struct Transcript {
+    /// Host patterns whose options ssh applied.
+    applied: Vec<String>,
+    /// "host [address] port N".
+    connected: Option<String>,
+    offered: Vec<TriedKey>,
+    accepted: Option<TriedKey>,
+    /// Why ssh could not reach the server at all.
+    connect_error: Option<String>,
+    /// Login in GitHub's "Permission to owner/repo.git denied to <login>".
+    denied_to: Option<String>,
+    not_found: bool,
+}
+
+/// "<path or comment> <TYPE> SHA256:... [explicit] [agent]".
+fn tried_key(rest: &str) -> Option<TriedKey> {
+    let tokens: Vec<&str> = rest.split_whitespace().collect();
+    let at = tokens.iter().position(|t| t.starts_with("SHA256:"))?;
+    Some(TriedKey {
+        label: tokens[..at.saturating_sub(1)].join(" "),
+        fingerprint: tokens[at].to_string(),
+        from_agent: tokens[at + 1..].contains(&"agent"),
+    })
+}
+
+fn parse_transcript(stderr: &str) -> Transcript {
+    let mut t = Transcript::default();
+    for line in stderr.lines().map(str::trim) {
+        let debug = line.strip_prefix("debug1: ").unwrap_or(line);
+        if let Some((_, pattern)) = debug.split_once(": Applying options for ") {
+            t.applied.push(pattern.to_string());
+        } else if let Some(rest) = debug.strip_prefix("Connecting to ") {
+            t.connected = Some(rest.trim_end_matches('.').to_string());
+        } else if let Some(rest) = debug.strip_prefix("Offering public key: ") {
+            t.offered.extend(tried_key(rest));
+        } else if let Some(rest) = debug.strip_prefix("Server accepts key: ") {
+            t.accepted = tried_key(rest);
+        } else if let Some(rest) = line.strip_prefix("ssh: ") {
+            t.connect_error = Some(rest.to_string());
+        } else if let Some((_, login)) = line.split_once(" denied to ") {
+            t.denied_to = Some(login.trim_end_matches('.').to_string());
+        } else if line.contains("Repository not found") || line.contains("could not be found") {
+            t.not_found = true;
+        }
+    }
+    t
+}
+
+/// The account whose key has `fingerprint`.
+fn key_owner<'a>(accounts: &'a [Account], fingerprint: &str) -> Option<&'a Account> {
+    accounts.iter().filter(|a| !a.ssh_key.is_empty()).find(|a| {
+        let public = expand_home(&a.ssh_key).with_extension("pub");
+        key_fingerprint(&public).is_some_and(|(fp, _)| fp == fingerprint)
+    })
+}
+
+/// "~/.ssh/id_ed25519_bob (bob@github.com, from the agent)".
+fn describe_key(key: &TriedKey, accounts: &[Account]) -> String {
+    let mut notes = vec![];
+    if let Some(acc) = key_owner(accounts, &key.fingerprint) {
+        notes.push(account_id(acc));
+    }
+    if key.from_agent {
+        notes.push("from the agent".to_string());
+    }
+    let label = if key.label.is_empty() { &key.fingerprint } else { &key.label };
+    if notes.is_empty() { label.to_string() } else { format!("{label} ({})", notes.join(", ")) }
+}
+
+/// Runs a network operation against a remote again with `ssh -v` - `git
+/// ls-remote`, or with `push` a `git push --dry-run` - and explains why it
+/// fails: which Host stanza matched the alias, where it connected, the keys
+/// offered and the one accepted, set against the account the repo expects.
+pub fn cmd_explain_failure(remote: Option<&str>, push: bool) -> Result<()> {
+    if !in_git_repo() {
+        return Err(GitIdError::Usage("Not inside a git repository.".to_string()));
+    }
+    let remotes = list_remotes();
+    let remote = match remote {
+        Some(name) if remotes.iter().any(|r| r == name) => name.to_string(),
+        Some(name) => {
+            return Err(GitIdError::Usage(format!("No remote named '{name}'. Run: git remote -v")));
+        }
+        None => remotes
+            .iter()
+            .find(|r| *r == "origin")
+            .or(remotes.first())
+            .cloned()
+            .ok_or_else(|| GitIdError::Usage("This repository has no remotes.".to_string()))?,
+    };
+    let url = get_remote_url(&remote);
+    let Some((fmt, host, owner, repo)) = parse_remote_url(&url) else {
+        return Err(GitIdError::Usage(format!(
+            "Cannot tell the host of '{remote}' ({})",
+            display_url(&url)
+        )));
+    };
+    let accounts = load_accounts()?;
+    let expected: Vec<&Account> =
+        accounts.iter().filter(|a| remote_belongs_to(a, &url, &host, &owner)).collect();
+    let strip_plus = strip_plus_address_enabled();
+    let email = normalize_email(&get_git_config("user.email", "local"), strip_plus);
+    let active = accounts
+        .iter()
+        .find(|a| has_email(a, &email, strip_plus));
+
+    let op = if push { "push --dry-run" } else { "ls-remote" };
+    print_hdr(&format!("git-id explain-failure {remote}"));
+    println!("  remote  : {remote} -> {}", display_url(&url));
+    let owners: Vec<String> = expected.iter().map(|a| account_id(a)).collect();
+    if owners.is_empty() {
+        println!("  expects : no account owns it");
+    } else {
+        println!("  expects : {}", owners.join(", "));
+    }
+    println!("  active  : {}", active.map_or("(no account)".to_string(), account_id));
+    print_info(&format!("Running git {op} {remote} with ssh -v ..."));
+
+    let args = if push {
+        vec!["push", "--dry-run", "--no-verify", &remote, "HEAD"]
+    } else {
+        vec!["ls-remote", "--heads", &remote]
+    };
+    let (code, _, stderr) = if fmt == "ssh" {
+        let (_, configured, _) = run_git(&["config", "core.sshCommand"]);
+        let base = std::env::var("GIT_SSH_COMMAND").unwrap_or(configured);
+        let base = if base.is_empty() { "ssh".to_string() } else { base };
+        let quote = |s: &str| format!("'{}'", s.replace('\'', r"'\''"));
+        let config: Vec<String> = ssh_config_args().iter().map(|a| quote(a)).collect();
+        let ssh = format!("{base} {} -v -o ConnectTimeout=15", config.join(" "));
+        run_git_env(&args, &[("GIT_SSH_COMMAND", &ssh), ("GIT_TERMINAL_PROMPT", "0")])
+    } else {
+        run_git_env(&args, &[("GIT_TERMINAL_PROMPT", "0")])
+    };
+    println!();
+    if fmt != "ssh" {
+        return explain_https(code, &stderr, &expected);
+    }
+
+    let t = parse_transcript(&stderr);
+    let alias = url.strip_prefix("git@").and_then(|r| r.split_once(':')).map_or("", |(h, _)| h);
+    let forge = if host == "github.com" { "GitHub".to_string() } else { host.clone() };
+    if t.applied.iter().any(|p| p.split_whitespace().any(|p| p == alias)) {
+        print_ok(&format!("ssh applied the Host {alias} stanza"));
+    } else if alias != host {
+        print_err(&format!("No Host stanza matched {alias}, so ssh used it as a host name"));
+        eprintln!("  Fix with: git-id ssh config");
+    }
+    if let Some(connected) = &t.connected {
+        print_ok(&format!("{alias} resolved to {connected}"));
+    }
+    if let Some(error) = &t.connect_error {
+        print_err(&format!("ssh could not reach the server: {error}"));
+        eprintln!("  Fix with: git-id doctor --network");
+        return Err(GitIdError::CheckFailed);
+    }
+    for (i, key) in t.offered.iter().enumerate() {
+        println!("  offered #{}: {}", i + 1, describe_key(key, &accounts));
+    }
+
+    let wanted = expected.first().copied().or(active);
+    let accepted_by = t.accepted.as_ref().and_then(|k| key_owner(&accounts, &k.fingerprint));
+    let login = t.denied_to.clone().or_else(|| accepted_by.map(|a| a.username.clone()));
+    let mut wrong_account = false;
+    match (&t.accepted, wanted) {
+        (None, _) if t.offered.is_empty() => {
+            print_err(&format!("ssh offered no key to {forge}"));
+            let fix = wanted.map_or("git-id ssh pick <account>".to_string(), |a| {
+                format!("git-id ssh test {}", account_id(a))
+            });
+            eprintln!("  Fix with: {fix}");
+        }
+        (None, Some(acc)) => {
+            let key = expand_home(&acc.ssh_key).with_extension("pub");
+            let fingerprint = key_fingerprint(&key).map(|(fp, _)| fp).unwrap_or_default();
+            if t.offered.iter().any(|k| k.fingerprint == fingerprint) {
+                print_err(&format!(
+                    "{forge} accepted none of the keys, including {}'s - it is not registered \
+                     on the {} account",
+                    account_id(acc),
+                    acc.username
+                ));
+                let (key, user) = (key.display(), &acc.username);
+                eprintln!("  Fix with: add {key} to {user}'s SSH keys on {forge}");
+            } else {
+                print_err(&format!(
+                    "{forge} accepted none of the keys, and {}'s key {} was never offered",
+                    account_id(acc),
+                    acc.ssh_key
+                ));
+                // Through the account's own alias, its stanza or key file is at fault.
+                let fix = if alias == ssh_host_alias(acc) {
+                    "git-id doctor".to_string()
+                } else {
+                    format!("git-id use {}", account_id(acc))
+                };
+                eprintln!("  Fix with: {fix}");
+            }
+        }
+        (None, None) => {
+            print_err(&format!("{forge} accepted none of the offered keys"));
+            eprintln!("  Fix with: git-id use <account>");
+        }
+        (Some(key), Some(acc)) if accepted_by.is_some_and(|b| account_id(b) != account_id(acc)) => {
+            let other = accepted_by.map(|b| b.username.clone()).unwrap_or_default();
+            let mut msg = format!(
+                "{forge} authenticated you as {other}, but this repo expects {}",
+                account_id(acc)
+            );
+            let position = t.offered.iter().position(|k| k.fingerprint == key.fingerprint);
+            if key.from_agent && position == Some(0) {
+                msg.push_str(&format!(
+                    "; key {} is loaded in the agent and was offered first",
+                    key.label
+                ));
+            }
+            print_err(&msg);
+            wrong_account = true;
+            if alias != ssh_host_alias(acc) {
+                eprintln!("  Fix with: git-id use {}", account_id(acc));
+            } else {
+                eprintln!("  Fix with: git-id use {} --exclusive", account_id(acc));
+            }
+        }
+        (Some(key), _) => {
+            let who = login.clone().unwrap_or_else(|| "an account git-id does not know".into());
+            print_ok(&format!("{forge} accepted {} as {who}", describe_key(key, &accounts)));
+        }
+    }
+
+    if code == 0 {
+        print_ok(&format!("git {op} {remote} worked"));
+        return Ok(());
+    }
+    // Access errors after authenticating as another account need no more words.
+    if wrong_account {
+        return Err(GitIdError::CheckFailed);
+    }
+    if let Some(login) = &t.denied_to {
+        print_err(&format!("{login} has no write access to {owner}/{repo}"));
+        eprintln!("  Fix with: ask for access, or push as an account that has it");
+    } else if t.not_found {
+        let who = login.unwrap_or_else(|| "this key".to_string());
+        print_err(&format!("{owner}/{repo} does not exist, or {who} cannot see it"));
+    }
+    Err(GitIdError::CheckFailed)
+}
+
+/// The HTTPS side: what git printed, and where the account's credentials
+/// can be checked.
+fn explain_https(code: i32, stderr: &str, expected: &[&Account]) -> Result<()> {
+    print_info("The remote uses HTTPS, so SSH keys play no part");
+    if code == 0 {
+        print_ok("The operation worked");
+        return Ok(());
+    }
+    for line in stderr.lines().filter(|l| !l.trim().is_empty()) {
+        println!("    {}", color("dim", &display_url(line.trim())));
+    }
+    match expected.first() {
+        Some(acc) if acc.https_token.is_empty() && acc.app_id.is_empty() => {
+            print_warn(&format!("{} has no HTTPS token", account_id(acc)));
+            eprintln!("  Fix with: git-id edit {} --token-stdin", account_id(acc));
+        }
+        Some(acc) => eprintln!("  Check the token with: git-id token check {}", account_id(acc)),
+        None => eprintln!("  Fix with: git-id use <account>"),
+    }
+    Err(GitIdError::CheckFailed)
+}
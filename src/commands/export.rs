@@ -0,0 +1,140 @@
+use crate::config::{account_id, account_tables, dirs_home, expand_home, load_accounts};
+use crate::error::{GitIdError, Result};
+use crate::models::Account;
+use crate::secrets::is_reference;
+use crate::ui::{print_info, print_ok, print_warn};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+/// An SSH key carried in an export. `path` starts with `~/` when the key is
+/// under the home directory, so it lands in the same place on the new
+/// machine.
+#[derive(Serialize, Deserialize)]
+pub struct KeyFile {
+    pub path: String,
+    /// Empty for keys held by an agent (e.g. 1Password), where only the
+    /// public half exists on disk.
+    #[serde(default)]
+    pub private_key: String,
+    #[serde(default)]
+    pub public_key: String,
+}
+
+/// The file written by `export` and read by `import`.
+#[derive(Deserialize)]
+pub struct Bundle {
+    #[serde(default)]
+    pub accounts: Vec<Account>,
+    #[serde(default)]
+    pub keys: Vec<KeyFile>,
+}
+
+#[derive(Serialize)]
+struct Keys<'a> {
+    keys: &'a [KeyFile],
+}
+
+/// `path` with the home directory written as `~`.
+pub fn portable_path(path: &str) -> String {
+    let full = expand_home(path);
+    match full.strip_prefix(dirs_home()) {
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => full.to_string_lossy().to_string(),
+    }
+}
+
+/// Reads the key files of every account, once per distinct key.
+pub fn collect_keys(accounts: &[Account]) -> Vec<KeyFile> {
+    let mut keys: Vec<KeyFile> = vec![];
+    for acc in accounts.iter().filter(|a| !a.ssh_key.is_empty()) {
+        if keys.iter().any(|k| k.path == acc.ssh_key) {
+            continue;
+        }
+        let path = expand_home(&acc.ssh_key);
+        let private_key = if acc.identity_agent.is_empty() {
+            match crate::log::read_to_string(&path) {
+                Ok(key) => key,
+                Err(e) => {
+                    let id = account_id(acc);
+                    print_warn(&format!("Skipping key of {id}: {}: {e}", path.display()));
+                    continue;
+                }
+            }
+        } else {
+            String::new()
+        };
+        let public_key = crate::log::read_to_string(path.with_extension("pub")).unwrap_or_default();
+        keys.push(KeyFile { path: acc.ssh_key.clone(), private_key, public_key });
+    }
+    keys
+}
+
+/// The `[[accounts]]` and `[[keys]]` tables of a bundle.
+pub fn bundle_tables(accounts: &[Account], keys: &[KeyFile]) -> Result<String> {
+    let mut content = account_tables(accounts);
+    if !keys.is_empty() {
+        let tables = toml::to_string(&Keys { keys })
+            .map_err(|e| GitIdError::Io(format!("Failed to encode keys: {e}")))?;
+        content.push_str(&tables);
+    }
+    Ok(content)
+}
+
+pub fn cmd_export(
+    output: Option<&Path>,
+    include_keys: bool,
+    no_tokens: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let mut accounts = load_accounts()?;
+    for acc in &mut accounts {
+        if !acc.ssh_key.is_empty() {
+            acc.ssh_key = portable_path(&acc.ssh_key);
+        }
+        if no_tokens && !is_reference(&acc.https_token) {
+            acc.https_token.clear();
+        }
+    }
+    let keys = if include_keys { collect_keys(&accounts) } else { vec![] };
+
+    let secret = keys.iter().any(|k| !k.private_key.is_empty())
+        || accounts.iter().any(|a| !a.https_token.is_empty() && !is_reference(&a.https_token));
+    let mut content = "# git-id export - restore with: git-id import <file>\n".to_string();
+    if secret {
+        content.push_str("# Contains tokens or private keys: keep this file secret.\n");
+    }
+    content.push('\n');
+    content.push_str(&bundle_tables(&accounts, &keys)?);
+
+    let Some(output) = output else {
+        print!("{content}");
+        return Ok(());
+    };
+    if dry_run {
+        print_info(&format!("[dry-run] Would write {}", output.display()));
+        return Ok(());
+    }
+    use std::os::unix::fs::OpenOptionsExt;
+    let opened = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(output);
+    crate::log::file("wrote", output, &opened);
+    opened
+        .and_then(|mut f| f.write_all(content.as_bytes()))
+        .map_err(|e| GitIdError::Io(format!("Failed to write {}: {e}", output.display())))?;
+    let keys_note =
+        if keys.is_empty() { String::new() } else { format!(" and {} key(s)", keys.len()) };
+    print_ok(&format!(
+        "Exported {} account(s){keys_note} to {}",
+        accounts.len(),
+        output.display()
+    ));
+    if secret {
+        print_warn("The file contains tokens or private keys - move it securely, then delete it");
+    }
+    Ok(())
+}
@@ -0,0 +1,391 @@
+use crate::commands::rules::{broken_rule, describe};
+use crate::commands::verify::active_account;
+use crate::config::{account_id, find_account, has_email, load_accounts, normalize_email};
+use crate::error::{GitIdError, Result};
+use crate::forge::{is_github, noreply_email, private_emails};
+use crate::git::{
+    get_git_config, in_git_repo, repo_kind, run_git, set_git_config, strip_plus_address_enabled,
+    unset_git_config,
+};
+use crate::ui::{print_err, print_info, print_ok, print_warn};
+use std::io::Read;
+use std::path::PathBuf;
+
+const BLOCK_START: &str = "# >>> git-id identity guard >>>";
+const BLOCK_END: &str = "# <<< git-id identity guard <<<";
+
+/// Local config key naming the account a repo's hook insists on.
+pub const PINNED_KEY: &str = "git-id.hookAccount";
+
+/// Hooks git-id can put a check in.
+const HOOKS: [&str; 2] = ["pre-commit", "pre-push"];
+
+/// The repo's `name` hook, wherever `core.hooksPath` puts it.
+fn hook_path(name: &str) -> Result<PathBuf> {
+    if !in_git_repo() {
+        return Err(GitIdError::Usage("Not inside a git repository.".to_string()));
+    }
+    let (code, out, err) = run_git(&["rev-parse", "--git-path", &format!("hooks/{name}")]);
+    if code != 0 || out.is_empty() {
+        return Err(GitIdError::Io(format!("Cannot locate the hooks directory: {err}")));
+    }
+    Ok(PathBuf::from(out))
+}
+
+/// The lines git-id owns in the `name` hook: run `git-id hook check` (or
+/// `hook pre-push`) with this binary and stop the commit or push when it
+/// fails. git hands pre-push the pushed refs on stdin, so the block reads
+/// them once and passes them on to the rest of the hook.
+fn guard_block(name: &str) -> String {
+    let exe = std::env::current_exe()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "git-id".to_string());
+    let exe = exe.replace('\'', r"'\''");
+    let body = if name == "pre-push" {
+        format!(
+            "git_id_refs=$(cat)\n\
+             printf '%s\\n' \"$git_id_refs\" | '{exe}' hook pre-push \"$@\" || exit 1\n\
+             if [ -n \"$git_id_refs\" ]; then exec <<EOF\n\
+             $git_id_refs\n\
+             EOF\n\
+             else exec </dev/null; fi\n"
+        )
+    } else {
+        format!("'{exe}' hook check || exit 1\n")
+    };
+    format!("{BLOCK_START}\n{body}{BLOCK_END}\n")
+}
+
+/// `content` without the guard block, or None if it has none.
+fn strip_block(content: &str) -> Option<String> {
+    let start = content.find(BLOCK_START)?;
+    let end = content[start..].find(BLOCK_END)? + start + BLOCK_END.len();
+    let end = if content[end..].starts_with('\n') { end + 1 } else { end };
+    Some(format!("{}{}", &content[..start], &content[end..]))
+}
+
+/// Puts the guard block right after the shebang so it runs before whatever
+/// the hook already does. An existing hook that is not a shell script is
+/// left alone: the block would not parse there.
+fn insert_block(content: &str, hook: &std::path::Path, block: &str) -> Result<String> {
+    if content.trim().is_empty() {
+        return Ok(format!("#!/bin/sh\n{block}"));
+    }
+    let (first, rest) = content.split_once('\n').unwrap_or((content, ""));
+    if !first.starts_with("#!") {
+        return Ok(format!("#!/bin/sh\n{block}{content}"));
+    }
+    let shell = first.split_whitespace().any(|w| {
+        let name = w.rsplit('/').next().unwrap_or(w);
+        ["sh", "bash", "zsh", "dash", "ksh"].contains(&name)
+    });
+    if !shell {
+        let lines: Vec<&str> = block.lines().filter(|l| !l.starts_with("# ")).collect();
+        return Err(GitIdError::Usage(format!(
+            "{} is not a shell script. Add {} to it yourself:\n  {}",
+            hook.display(),
+            if lines.len() == 1 { "this line" } else { "these lines" },
+            lines.join("\n  ")
+        )));
+    }
+    Ok(format!("{first}\n{block}{rest}"))
+}
+
+fn write_hook(path: &std::path::Path, content: &str) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.exists()) {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| GitIdError::Io(format!("Cannot create {}: {e}", dir.display())))?;
+    }
+    crate::log::write(path, content)
+        .map_err(|e| GitIdError::Io(format!("Failed to write {}: {e}", path.display())))?;
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path).map(|m| m.permissions().mode()).unwrap_or(0o644);
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode | 0o111))
+        .map_err(|e| GitIdError::Io(format!("Cannot make {} executable: {e}", path.display())))
+}
+
+/// Adds the identity check to the repo's pre-commit hook, keeping any hook
+/// that is already there. With `account`, commits must use that account's
+/// email rather than any configured one. With `pre_push`, the pre-push hook
+/// also gets the private email check.
+pub fn cmd_hook_install(account: Option<&str>, pre_push: bool, dry_run: bool) -> Result<()> {
+    let pinned = match account {
+        Some(name) => Some(find_account(name)?.ok_or_else(|| {
+            GitIdError::Usage(format!("Account '{name}' not found. Run: git-id list"))
+        })?),
+        None => None,
+    };
+    let names = if pre_push { &HOOKS[..] } else { &HOOKS[..1] };
+    let mut plans = vec![];
+    for name in names {
+        let path = hook_path(name)?;
+        let content = crate::log::read_to_string(&path).unwrap_or_default();
+        let existing = strip_block(&content);
+        let base = existing.as_deref().unwrap_or(&content);
+        let updated = insert_block(base, &path, &guard_block(name))?;
+        plans.push((*name, path, content, updated, existing.is_some()));
+    }
+
+    let hooks_path = repo_kind().hooks_path;
+    if !hooks_path.is_empty() {
+        print_warn(&format!(
+            "core.hooksPath is {hooks_path}: every repo using that directory gets the check"
+        ));
+    }
+    match &pinned {
+        Some(acc) => set_git_config(PINNED_KEY, &account_id(acc), "local", dry_run),
+        None => {
+            if !get_git_config(PINNED_KEY, "local").is_empty() {
+                unset_git_config(PINNED_KEY, "local", dry_run);
+            }
+        }
+    }
+    for (name, path, content, updated, existed) in plans {
+        if dry_run {
+            print_info(&format!("[dry-run] Would write {}", path.display()));
+            continue;
+        }
+        if updated != content {
+            write_hook(&path, &updated)?;
+        }
+        let verb = if existed { "Updated" } else { "Installed" };
+        if name == "pre-push" {
+            print_ok(&format!(
+                "{verb} private email check in {} (pushes GitHub would reject are stopped)",
+                path.display()
+            ));
+            continue;
+        }
+        let target = match &pinned {
+            Some(acc) => format!("'{}'", account_id(acc)),
+            None => "a configured account".to_string(),
+        };
+        let path = path.display();
+        print_ok(&format!("{verb} identity check in {path} (commits must use {target})"));
+    }
+    Ok(())
+}
+
+/// Moves the repo's pinned account to `acc`, which `use` just chose for the
+/// repo, so the identity check does not refuse the commits it set up.
+pub fn repin(acc: &crate::models::Account, dry_run: bool) {
+    let pinned = get_git_config(PINNED_KEY, "local");
+    let id = account_id(acc);
+    if pinned.is_empty() || pinned == id {
+        return;
+    }
+    set_git_config(PINNED_KEY, &id, "local", dry_run);
+    print_info(&format!("The identity check now requires '{id}' instead of '{pinned}'"));
+}
+
+/// Removes the identity checks and the pinned account of the current repo.
+pub fn cmd_hook_uninstall(dry_run: bool) -> Result<()> {
+    if !get_git_config(PINNED_KEY, "local").is_empty() {
+        unset_git_config(PINNED_KEY, "local", dry_run);
+    }
+    let mut found = false;
+    for name in HOOKS {
+        let path = hook_path(name)?;
+        if strip_block(&crate::log::read_to_string(&path).unwrap_or_default()).is_some() {
+            found = true;
+            remove_guard(&path, dry_run)?;
+        }
+    }
+    if !found {
+        print_info("No identity check installed in this repo");
+    }
+    Ok(())
+}
+
+/// The current repo's hooks that have a git-id check.
+pub fn installed_hooks() -> Vec<PathBuf> {
+    HOOKS
+        .iter()
+        .filter_map(|name| {
+            let path = hook_path(name).ok()?;
+            let content = crate::log::read_to_string(&path).ok()?;
+            strip_block(&content)?;
+            std::path::absolute(&path).ok()
+        })
+        .collect()
+}
+
+/// Takes the identity check out of `path`, deleting the hook if nothing
+/// else is left in it.
+pub fn remove_guard(path: &std::path::Path, dry_run: bool) -> Result<()> {
+    let content = crate::log::read_to_string(path).unwrap_or_default();
+    let Some(stripped) = strip_block(&content) else {
+        return Ok(());
+    };
+    let only_shebang = stripped.lines().all(|l| l.trim().is_empty() || l.starts_with("#!"));
+    if dry_run {
+        let action = if only_shebang { "delete" } else { "remove the identity check from" };
+        print_info(&format!("[dry-run] Would {action} {}", path.display()));
+        return Ok(());
+    }
+    if only_shebang {
+        let result = std::fs::remove_file(path);
+        crate::log::file("deleted", path, &result);
+        result.map_err(|e| GitIdError::Io(format!("Failed to delete {}: {e}", path.display())))?;
+    } else {
+        crate::log::write(path, stripped)
+            .map_err(|e| GitIdError::Io(format!("Failed to write {}: {e}", path.display())))?;
+    }
+    print_ok(&format!("Removed identity check from {}", path.display()));
+    Ok(())
+}
+
+/// Name and email git would author a commit with here, after environment
+/// overrides; empty when it has none.
+pub fn author_ident() -> (String, String) {
+    let (_, ident, _) = run_git(&["var", "GIT_AUTHOR_IDENT"]);
+    ident
+        .split_once(" <")
+        .and_then(|(name, rest)| Some((name.to_string(), rest.split_once('>')?.0.to_string())))
+        .unwrap_or_default()
+}
+
+/// Run by the hook: fails with `CheckFailed` when the email the commit
+/// would be authored with belongs to no account, not to the pinned one, or
+/// breaks an email rule.
+pub fn cmd_hook_check() -> Result<()> {
+    let (_, email) = author_ident();
+    let strip_plus = strip_plus_address_enabled();
+    let norm = normalize_email(&email, strip_plus);
+    let accounts = load_accounts()?;
+    let pinned = get_git_config(PINNED_KEY, "local");
+
+    let use_fix = format!("git-id use {}", if pinned.is_empty() { "<account>" } else { &pinned });
+    let (problem, fix) = if !pinned.is_empty() {
+        match accounts.iter().find(|a| account_id(a) == pinned) {
+            Some(acc) if has_email(acc, &norm, strip_plus) => {
+                return check_rule(&email);
+            }
+            Some(acc) => {
+                (format!("this repo commits as {pinned} <{}>, not <{email}>", acc.email), use_fix)
+            }
+            None => (
+                format!("the account pinned for this repo ({pinned}) no longer exists"),
+                "git-id hook install --account <account>".to_string(),
+            ),
+        }
+    } else if accounts.iter().any(|a| has_email(a, &norm, strip_plus)) {
+        return check_rule(&email);
+    } else if email.is_empty() {
+        ("no user.email is set".to_string(), use_fix)
+    } else {
+        (format!("<{email}> belongs to no git-id account"), use_fix)
+    };
+    blocked(&problem, &fix)
+}
+
+/// Fails when an email rule requires another email in this repo.
+fn check_rule(email: &str) -> Result<()> {
+    let Some(rule) = broken_rule(email) else {
+        return Ok(());
+    };
+    let accounts = load_accounts()?;
+    let rule_email = normalize_email(&rule.email, false);
+    let fix = match accounts.iter().find(|a| has_email(a, &rule_email, false)) {
+        Some(acc) => format!("git-id use {}", account_id(acc)),
+        None => format!("git config user.email {}", rule.email),
+    };
+    blocked(&format!("{} must use <{}>, not <{email}>", describe(&rule), rule.email), &fix)
+}
+
+fn blocked(problem: &str, fix: &str) -> Result<()> {
+    print_err(&format!("git-id: commit blocked - {problem}"));
+    eprintln!("  Fix with: {fix}   (or bypass once with: git commit --no-verify)");
+    Err(GitIdError::CheckFailed)
+}
+
+/// Run by the pre-push hook with the pushed refs on stdin: fails with
+/// `CheckFailed` when outgoing commits are authored with an email the active
+/// GitHub account keeps private, which GitHub would reject (GH007). When the
+/// account has no token or the API cannot be reached, the push goes ahead.
+pub fn cmd_hook_pre_push(url: &str) -> Result<()> {
+    let Some(acc) = active_account()? else {
+        return Ok(());
+    };
+    let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    if !is_github(&acc) || acc.https_token.is_empty() || !url.contains(host) {
+        return Ok(());
+    }
+    let mut refs = String::new();
+    let _ = std::io::stdin().read_to_string(&mut refs);
+    let mut authors: Vec<(String, usize)> = vec![];
+    let mut seen = std::collections::HashSet::new();
+    for line in refs.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [_, local_oid, _, remote_oid] = fields[..] else {
+            continue;
+        };
+        let zero = |oid: &str| oid.bytes().all(|b| b == b'0');
+        if zero(local_oid) {
+            continue;
+        }
+        // A new branch sends whatever no remote-tracking branch has yet.
+        let range = if zero(remote_oid) {
+            vec![local_oid.to_string(), "--not".to_string(), "--remotes".to_string()]
+        } else {
+            vec![format!("{remote_oid}..{local_oid}")]
+        };
+        let mut args = vec!["log", "--format=%H %ae"];
+        args.extend(range.iter().map(String::as_str));
+        let (code, out, err) = run_git(&args);
+        if code != 0 {
+            print_warn(&format!("git-id: cannot list the commits to push: {err}"));
+            continue;
+        }
+        for (hash, email) in out.lines().filter_map(|l| l.split_once(' ')) {
+            if !seen.insert(hash.to_string()) {
+                continue;
+            }
+            match authors.iter_mut().find(|(e, _)| e.eq_ignore_ascii_case(email)) {
+                Some((_, count)) => *count += 1,
+                None => authors.push((email.to_string(), 1)),
+            }
+        }
+    }
+    if authors.is_empty() {
+        return Ok(());
+    }
+
+    let private = match private_emails(&acc) {
+        Ok(private) => private,
+        Err(e) => {
+            print_warn(&format!(
+                "git-id: cannot check the email settings of '{}' ({e}) - pushing anyway",
+                acc.username
+            ));
+            return Ok(());
+        }
+    };
+    let exposed: Vec<&(String, usize)> = authors
+        .iter()
+        .filter(|(email, _)| private.iter().any(|p| p.eq_ignore_ascii_case(email)))
+        .collect();
+    if exposed.is_empty() {
+        return Ok(());
+    }
+    for (email, count) in exposed {
+        print_err(&format!(
+            "git-id: push blocked - {count} commit(s) authored with <{email}>, which '{}' \
+             keeps private on {host} (GH007)",
+            acc.username
+        ));
+    }
+    let noreply = noreply_email(&acc)
+        .unwrap_or_else(|_| format!("<id>+{}@users.noreply.{host}", acc.username));
+    eprintln!(
+        "  Fix with: git-id edit {} --email {noreply}, then reauthor the commits \
+         (git commit --amend --reset-author --no-edit for the last one)",
+        account_id(&acc)
+    );
+    eprintln!(
+        "  or turn off \"Block command line pushes that expose my email\" at \
+         https://{host}/settings/emails"
+    );
+    eprintln!("  (or bypass once with: git push --no-verify)");
+    Err(GitIdError::CheckFailed)
+}
@@ -0,0 +1,120 @@
+use crate::commands::ssh::banner_login;
+use crate::config::{account_id, load_accounts, ssh_host_alias};
+use crate::error::{GitIdError, Result};
+use crate::forge;
+use crate::git::{default_host, set_git_config};
+use crate::models::Account;
+use crate::ssh::ssh_probe;
+use crate::ui::{color, print_hdr, print_info, print_ok, print_warn};
+use std::time::Duration;
+
+/// How long one connection attempt may take before it counts as hanging.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Every host that has accounts, in the order they first appear, with the
+/// accounts on it.
+fn hosts(accounts: &[Account]) -> Vec<(String, Vec<&Account>)> {
+    let mut hosts: Vec<(String, Vec<&Account>)> = vec![];
+    for acc in accounts {
+        let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+        match hosts.iter_mut().find(|(h, _)| h == host) {
+            Some((_, accs)) => accs.push(acc),
+            None => hosts.push((host.to_string(), vec![acc])),
+        }
+    }
+    hosts
+}
+
+/// The accounts on `host`; an error if it has none.
+fn accounts_on(accounts: &[Account], host: &str) -> Result<Vec<Account>> {
+    let found = hosts(accounts).into_iter().find(|(h, _)| h == host);
+    match found {
+        Some((_, accs)) => Ok(accs.into_iter().cloned().collect()),
+        None => Err(GitIdError::Usage(format!(
+            "No account uses host '{host}'. Run: git-id host list"
+        ))),
+    }
+}
+
+pub fn cmd_host_list() -> Result<()> {
+    let accounts = load_accounts()?;
+    if accounts.is_empty() {
+        print_info("No accounts configured yet. Run: git-id add");
+        return Ok(());
+    }
+    let default = default_host();
+    print_hdr("Hosts");
+    for (host, accs) in hosts(&accounts) {
+        let tag = if host == default { color("green", " (default)") } else { String::new() };
+        let api = if forge::is_github(accs[0]) {
+            forge::api_base(accs[0])
+        } else {
+            "no GitHub API".to_string()
+        };
+        println!("  {}{tag}  {}", color("bold", &host), color("dim", &api));
+        for acc in accs {
+            println!("    {}", account_id(acc));
+        }
+    }
+    println!();
+    Ok(())
+}
+
+/// Checks that `host` answers on SSH, that each of its accounts' keys
+/// authenticates, and where its REST API lives. Fails with `CheckFailed`
+/// if any of it does not work.
+pub fn cmd_host_test(host: &str) -> Result<()> {
+    let accounts = accounts_on(&load_accounts()?, host)?;
+    let mut failed = false;
+
+    // Any answer from the server, even a refused key, proves the host is
+    // reachable; 255 without one is ssh's own failure.
+    let target = format!("git@{host}");
+    match ssh_probe(&["-T", &target], PROBE_TIMEOUT) {
+        (None, _) => {
+            print_warn(&format!("{host} did not answer within {}s", PROBE_TIMEOUT.as_secs()));
+            failed = true;
+        }
+        (Some(255), out) if !out.contains("Permission denied") => {
+            print_warn(&format!("Cannot reach {host}: {}", last_line(&out)));
+            failed = true;
+        }
+        _ => print_ok(&format!("{host} answers on SSH")),
+    }
+
+    for acc in accounts.iter().filter(|a| !a.ssh_key.is_empty()) {
+        let target = format!("git@{}", ssh_host_alias(acc));
+        let (_, out) = ssh_probe(&["-T", &target], PROBE_TIMEOUT);
+        match banner_login(&out) {
+            Some(login) => print_ok(&format!("{} authenticates as {login}", account_id(acc))),
+            None => {
+                print_warn(&format!("{} failed: {}", account_id(acc), last_line(&out)));
+                failed = true;
+            }
+        }
+    }
+
+    match forge::discover_api_base(host) {
+        Ok((base, kind)) => print_ok(&format!("{kind} API at {base}")),
+        Err(e) => {
+            print_warn(&format!("No REST API found: {e}"));
+            failed = true;
+        }
+    }
+
+    if failed { Err(GitIdError::CheckFailed) } else { Ok(()) }
+}
+
+/// Makes `host` the one `git-id add` offers when no --host is given.
+pub fn cmd_host_set_default(host: &str, dry_run: bool) -> Result<()> {
+    accounts_on(&load_accounts()?, host)?;
+    set_git_config("git-id.defaultHost", host, "global", dry_run);
+    if !dry_run {
+        print_ok(&format!("New accounts default to {host}"));
+    }
+    Ok(())
+}
+
+fn last_line(out: &str) -> &str {
+    out.lines().rev().map(str::trim).find(|l| !l.is_empty()).unwrap_or("no output")
+}
@@ -0,0 +1,386 @@
+use crate::cli::AddArgs;
+use crate::commands::add::cmd_add;
+use crate::commands::export::{Bundle, KeyFile};
+use crate::config::{
+    account_id, ensure_accounts_file, expand_home, load_accounts, normalize_email, save_accounts,
+    ssh_host_alias,
+};
+use crate::dates::format_timestamp;
+use crate::error::{GitIdError, Result};
+use crate::git::{default_host, in_git_repo, run_git};
+use crate::models::Account;
+use crate::repos::{load_repos, repo_root};
+use crate::ssh::{fix_key_permissions, unmanaged_aliases, update_ssh_config};
+use crate::ui::{color, is_tty, print_hdr, print_info, print_ok, print_warn};
+use dialoguer::{Input, MultiSelect};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+pub fn cmd_import(file: &Path, dry_run: bool) -> Result<()> {
+    let content = crate::log::read_to_string(file)
+        .map_err(|e| GitIdError::Usage(format!("Cannot read {}: {e}", file.display())))?;
+    let bundle: Bundle = toml::from_str(&content)
+        .map_err(|e| GitIdError::Config(format!("Failed to parse {}: {e}", file.display())))?;
+
+    ensure_accounts_file()?;
+    let mut accounts = load_accounts()?;
+    let mut imported: Vec<Account> = vec![];
+    for mut acc in bundle.accounts {
+        let id = account_id(&acc);
+        if accounts.iter().any(|a| account_id(a) == id) {
+            print_warn(&format!("Account '{id}' already exists - skipping"));
+            continue;
+        }
+        adopt_alias(&mut acc, &accounts);
+        accounts.push(acc.clone());
+        imported.push(acc);
+    }
+    if imported.is_empty() {
+        print_info("Nothing to import");
+        return Ok(());
+    }
+
+    for key in &bundle.keys {
+        if imported.iter().any(|a| a.ssh_key == key.path) {
+            write_key(key, dry_run)?;
+        }
+    }
+    let mut checked = vec![];
+    for acc in imported.iter().filter(|a| !a.ssh_key.is_empty()) {
+        let key = expand_home(&acc.ssh_key);
+        if checked.contains(&key) {
+            continue;
+        }
+        checked.push(key.clone());
+        let present = if acc.identity_agent.is_empty() {
+            key.exists()
+        } else {
+            key.with_extension("pub").exists()
+        };
+        if !present && !dry_run {
+            print_warn(&format!(
+                "SSH key of '{}' is missing: {} (copy it over or run: git-id ssh pick {})",
+                account_id(acc),
+                key.display(),
+                account_id(acc)
+            ));
+        } else if present && !dry_run {
+            fix_key_permissions(&key);
+        }
+    }
+
+    save_accounts(&accounts, dry_run)?;
+    if imported.iter().any(|a| !a.ssh_key.is_empty()) {
+        update_ssh_config(&accounts, dry_run)?;
+    }
+    print_ok(&format!("Imported {} account(s)", imported.len()));
+    Ok(())
+}
+
+/// Uses a hand-written `Host` alias on this machine that already points at
+/// the account's host with the account's key, so remotes that use it keep
+/// working.
+fn adopt_alias(acc: &mut Account, accounts: &[Account]) {
+    if !acc.ssh_alias.is_empty() || acc.ssh_key.is_empty() {
+        return;
+    }
+    let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    let key = expand_home(&acc.ssh_key);
+    let found = unmanaged_aliases(host).into_iter().find(|(alias, identity)| {
+        expand_home(identity) == key && !accounts.iter().any(|a| ssh_host_alias(a) == *alias)
+    });
+    if let Some((alias, _)) = found {
+        print_info(&format!("Using your existing 'Host {alias}' for '{}'", account_id(acc)));
+        acc.ssh_alias = alias;
+    }
+}
+
+/// Writes a bundled key (and its public half) unless a different key is
+/// already at that path.
+pub fn write_key(key: &KeyFile, dry_run: bool) -> Result<()> {
+    let path = expand_home(&key.path);
+    let pub_path = path.with_extension("pub");
+    let files = [(&path, &key.private_key, 0o600), (&pub_path, &key.public_key, 0o644)];
+    for (dest, content, mode) in files {
+        if content.is_empty() {
+            continue;
+        }
+        if dest.exists() {
+            if crate::log::read_to_string(dest).is_ok_and(|c| c == *content) {
+                continue;
+            }
+            print_warn(&format!("{} exists with other contents - not overwritten", dest.display()));
+            continue;
+        }
+        if dry_run {
+            print_info(&format!("[dry-run] Would write {}", dest.display()));
+            continue;
+        }
+        if let Some(dir) = dest.parent().filter(|d| !d.exists()) {
+            use std::os::unix::fs::DirBuilderExt;
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .map_err(|e| GitIdError::Io(format!("Cannot create {}: {e}", dir.display())))?;
+        }
+        use std::os::unix::fs::OpenOptionsExt;
+        let opened = std::fs::OpenOptions::new().create_new(true).write(true).mode(mode).open(dest);
+        crate::log::file("wrote", dest, &opened);
+        use std::io::Write;
+        opened
+            .and_then(|mut f| f.write_all(content.as_bytes()))
+            .map_err(|e| GitIdError::Io(format!("Failed to write {}: {e}", dest.display())))?;
+        print_ok(&format!("Wrote {}", dest.display()));
+    }
+    Ok(())
+}
+
+/// How deep `import --from-history` looks for repositories under a directory.
+const SCAN_DEPTH: usize = 4;
+
+/// Directories never worth descending into while looking for repositories.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "vendor", "venv", ".venv"];
+
+/// Authors sharing an email, or a forge login through its noreply address.
+#[derive(Default)]
+struct Identity {
+    /// (address, commits), most used first once sorted.
+    emails: BTreeMap<String, usize>,
+    names: BTreeMap<String, usize>,
+    /// Host and login, when the identity is a forge noreply address.
+    login: Option<(String, String)>,
+    commits: usize,
+    repos: BTreeSet<PathBuf>,
+    /// Unix time of the newest commit.
+    last: u64,
+}
+
+impl Identity {
+    fn top(counts: &BTreeMap<String, usize>) -> Vec<&str> {
+        let mut items: Vec<(&String, &usize)> = counts.iter().collect();
+        items.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        items.into_iter().map(|(item, _)| item.as_str()).collect()
+    }
+
+    fn email(&self) -> &str {
+        Self::top(&self.emails).first().copied().unwrap_or_default()
+    }
+
+    /// The forge login, else the email's local part, as the username `add`
+    /// is offered.
+    fn username(&self) -> String {
+        if let Some((_, login)) = &self.login {
+            return login.clone();
+        }
+        let local = self.email().split('@').next().unwrap_or_default();
+        local.split('+').next().unwrap_or(local).to_string()
+    }
+
+    fn host(&self) -> String {
+        self.login.as_ref().map_or_else(default_host, |(host, _)| host.clone())
+    }
+}
+
+/// Host and login of a forge noreply address: `ID+login@users.noreply.github.com`
+/// (or the older `login@...`), `ID-login@users.noreply.gitlab.com`.
+fn noreply_login(email: &str) -> Option<(String, String)> {
+    let (local, domain) = email.split_once('@')?;
+    let host = domain.strip_prefix("users.noreply.")?;
+    let login = local.rsplit_once('+').map_or(local, |(_, login)| login);
+    let login = match login.split_once('-') {
+        Some((id, rest)) if id.chars().all(|c| c.is_ascii_digit()) => rest,
+        _ => login,
+    };
+    Some((host.to_string(), login.to_string()))
+}
+
+/// Commits by bots and forge web merges rather than by a person.
+fn is_bot(name: &str, email: &str) -> bool {
+    name.ends_with("[bot]") || email == "noreply@github.com" || email.contains("[bot]")
+}
+
+/// Git repositories at or under `dir`, not descending into a repository or
+/// hidden directories.
+fn find_repos(dir: &Path, depth: usize, found: &mut Vec<PathBuf>) {
+    if dir.join(".git").exists() {
+        found.push(dir.to_path_buf());
+        return;
+    }
+    if depth == 0 {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str())
+        })
+        .map(|e| e.path())
+        .collect();
+    dirs.sort();
+    for sub in dirs {
+        find_repos(&sub, depth - 1, found);
+    }
+}
+
+/// Lists the author identities in the history of the repositories under
+/// `dirs` (by default the registered ones and the current one) that no
+/// account covers yet, and offers to add accounts for them.
+pub fn cmd_import_history(dirs: &[PathBuf], min_commits: usize, dry_run: bool) -> Result<()> {
+    let mut repos = vec![];
+    if dirs.is_empty() {
+        repos.extend(load_repos()?.into_iter().filter(|r| r.exists()));
+        if in_git_repo()
+            && let Some(root) = std::env::current_dir().ok().and_then(|d| repo_root(&d))
+        {
+            repos.push(root);
+        }
+    } else {
+        for dir in dirs {
+            let dir = expand_home(&dir.to_string_lossy());
+            if !dir.is_dir() {
+                return Err(GitIdError::Usage(format!("No such directory: {}", dir.display())));
+            }
+            find_repos(&dir, SCAN_DEPTH, &mut repos);
+        }
+    }
+    let mut seen_repos = HashSet::new();
+    repos.retain(|r| seen_repos.insert(std::fs::canonicalize(r).unwrap_or_else(|_| r.clone())));
+    if repos.is_empty() {
+        return Err(GitIdError::Usage(
+            "No repositories to scan. Name the directories: git-id import --from-history ~/src"
+                .to_string(),
+        ));
+    }
+
+    let accounts = load_accounts()?;
+    let known: HashSet<String> = accounts
+        .iter()
+        .flat_map(|a| std::iter::once(&a.email).chain(&a.previous_emails))
+        .map(|e| normalize_email(e, false))
+        .collect();
+    let known_logins: HashSet<(String, String)> = accounts
+        .iter()
+        .map(|a| {
+            let host = if a.host.is_empty() { "github.com" } else { a.host.as_str() };
+            (host.to_string(), a.username.to_lowercase())
+        })
+        .collect();
+
+    let mut identities: BTreeMap<String, Identity> = BTreeMap::new();
+    let mut seen_commits = HashSet::new();
+    for repo in &repos {
+        let dir = repo.to_string_lossy();
+        let (code, out, _) =
+            run_git(&["-C", &dir, "log", "--all", "--format=%H%x1f%ae%x1f%an%x1f%at"]);
+        if code != 0 {
+            continue;
+        }
+        for line in out.lines() {
+            let mut fields = line.split('\x1f');
+            let (Some(hash), Some(email), Some(name), Some(time)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let email = normalize_email(email, false);
+            if email.is_empty() || is_bot(name, &email) || !seen_commits.insert(hash.to_string())
+            {
+                continue;
+            }
+            let login = noreply_login(&email);
+            let key = match &login {
+                Some((host, login)) => format!("{host}/{}", login.to_lowercase()),
+                None => email.clone(),
+            };
+            let identity = identities.entry(key).or_default();
+            *identity.emails.entry(email).or_default() += 1;
+            *identity.names.entry(name.to_string()).or_default() += 1;
+            identity.login = identity.login.take().or(login);
+            identity.commits += 1;
+            identity.repos.insert(repo.clone());
+            identity.last = identity.last.max(time.parse().unwrap_or(0));
+        }
+    }
+
+    let mut found: Vec<Identity> = identities
+        .into_values()
+        .filter(|id| !id.emails.keys().any(|e| known.contains(e)))
+        .filter(|id| {
+            id.login.as_ref().is_none_or(|(host, login)| {
+                !known_logins.contains(&(host.clone(), login.to_lowercase()))
+            })
+        })
+        .collect();
+    found.sort_by(|a, b| b.commits.cmp(&a.commits).then_with(|| a.email().cmp(b.email())));
+    let rare = found.iter().filter(|id| id.commits < min_commits).count();
+    found.retain(|id| id.commits >= min_commits);
+
+    print_hdr(&format!(
+        "Identities in commit history  ({} repositories, {} commits)",
+        repos.len(),
+        seen_commits.len()
+    ));
+    if found.is_empty() {
+        print_info("Every recurring identity already belongs to an account");
+    }
+    for id in &found {
+        let names = Identity::top(&id.names);
+        println!("\n  {}  {}", color("bold", id.email()), names[0]);
+        println!(
+            "    {} commit(s) in {} repositories, last {}",
+            id.commits,
+            id.repos.len(),
+            &format_timestamp(id.last)[..10]
+        );
+        let emails = Identity::top(&id.emails);
+        let others: Vec<&str> = names[1..].iter().chain(&emails[1..]).copied().collect();
+        if !others.is_empty() {
+            println!("    {}", color("dim", &format!("also: {}", others.join(", "))));
+        }
+        println!(
+            "    add: git-id add --username {} --email {} --host {}",
+            id.username(),
+            id.email(),
+            id.host()
+        );
+    }
+    if rare > 0 {
+        print_info(&format!(
+            "{rare} more identit(ies) with fewer than {min_commits} commits (see --min-commits)"
+        ));
+    }
+    println!();
+    if found.is_empty() || !is_tty() {
+        return Ok(());
+    }
+
+    let items: Vec<String> =
+        found.iter().map(|id| format!("{} ({} commits)", id.email(), id.commits)).collect();
+    let picked = MultiSelect::new()
+        .with_prompt("Add accounts for (space to select, enter to confirm)")
+        .items(&items)
+        .interact()
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
+    for index in picked {
+        let id = &found[index];
+        let username: String = Input::new()
+            .with_prompt(format!("  Username for {} on {}", id.email(), id.host()))
+            .default(id.username())
+            .interact_text()
+            .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
+        let args = AddArgs {
+            username: Some(username),
+            email: Some(id.email().to_string()),
+            host: Some(id.host()),
+            ..Default::default()
+        };
+        cmd_add(&args, dry_run)?;
+    }
+    Ok(())
+}
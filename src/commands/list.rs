@@ -1,78 +1,420 @@
-use crate::config::{accounts_file, dirs_home, ensure_accounts_file, load_accounts, ssh_host_alias};
-use crate::git::{get_git_config, in_git_repo};
-use crate::ui::{color, print_hdr, print_info};
-use std::path::PathBuf;
-
-pub fn cmd_list() {
-    ensure_accounts_file();
-    let accounts = load_accounts();
-
-    if accounts.is_empty() {
-        print_info("No accounts configured yet. Run: git-id add");
-        print_info(&format!("Config file: {}", accounts_file().display()));
-        return;
+use crate::config::{
+    account_id, accounts_file, dirs_home, ensure_accounts_file, expand_home, has_email,
+    is_expired, key_age_days, load_accounts, normalize_email, remote_name, ssh_host_alias,
+    stale_key_age,
+};
+use crate::error::Result;
+use crate::forge;
+use crate::git::{get_git_config, in_git_repo, strip_plus_address_enabled};
+use crate::models::Account;
+use crate::output::{Format, Report, markdown_table, plain_table, render};
+use crate::ssh::{
+    agent_public_keys, hosts_using_key, key_body, key_fingerprint, onepassword_agent_socket,
+    ssh_dir,
+};
+use crate::ui::{color, print_hdr, print_info, print_warn};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// `list`: every account and whether it is active.
+#[derive(Serialize)]
+struct AccountsReport {
+    config_file: String,
+    accounts: Vec<AccountEntry>,
+}
+
+#[derive(Serialize)]
+struct AccountEntry {
+    id: String,
+    username: String,
+    host: String,
+    email: String,
+    ssh_key: String,
+    private_key_exists: bool,
+    public_key_exists: bool,
+    has_token: bool,
+    alias: String,
+    remote: String,
+    protocol: String,
+    owners: Vec<String>,
+    aka: Vec<String>,
+    previous_emails: Vec<String>,
+    alt_emails: Vec<String>,
+    identity_agent: String,
+    expires: String,
+    expired: bool,
+    /// Days since the SSH key was created (or its file last written).
+    key_age_days: Option<i64>,
+    /// Whether the key is past `[keys] rotate_after_days`.
+    key_stale: bool,
+    /// "local" and/or "global".
+    active: Vec<&'static str>,
+}
+
+pub fn cmd_list(format: Format) -> Result<()> {
+    if format == Format::Human {
+        ensure_accounts_file()?;
+    }
+    let strip_plus = strip_plus_address_enabled();
+    let (local_email, global_email) = active_emails(strip_plus);
+    let accounts = load_accounts()?
+        .iter()
+        .map(|acc| {
+            let key = expand_home(&acc.ssh_key);
+            let mut active = vec![];
+            if has_email(acc, &local_email, strip_plus) {
+                active.push("local");
+            }
+            if has_email(acc, &global_email, strip_plus) {
+                active.push("global");
+            }
+            AccountEntry {
+                id: account_id(acc),
+                username: acc.username.clone(),
+                host: if acc.host.is_empty() { "github.com".to_string() } else { acc.host.clone() },
+                email: acc.email.clone(),
+                ssh_key: acc.ssh_key.clone(),
+                private_key_exists: !acc.ssh_key.is_empty() && key.exists(),
+                public_key_exists: !acc.ssh_key.is_empty() && key.with_extension("pub").exists(),
+                has_token: !acc.https_token.is_empty(),
+                alias: ssh_host_alias(acc),
+                remote: remote_name(acc).to_string(),
+                protocol: acc.protocol.clone(),
+                owners: acc.owners.clone(),
+                aka: acc.aka.clone(),
+                previous_emails: acc.previous_emails.clone(),
+                alt_emails: acc.alt_emails.clone(),
+                identity_agent: acc.identity_agent.clone(),
+                expires: acc.expires.clone(),
+                expired: is_expired(acc),
+                key_age_days: key_age_days(acc),
+                key_stale: stale_key_age(acc).is_some(),
+                active,
+            }
+        })
+        .collect();
+    let report = AccountsReport {
+        config_file: accounts_file().to_string_lossy().to_string(),
+        accounts,
+    };
+    render(&report, format)
+}
+
+impl Report for AccountsReport {
+    fn human(&self) {
+        if self.accounts.is_empty() {
+            print_info("No accounts configured yet. Run: git-id add");
+            print_info(&format!("Config file: {}", self.config_file));
+            return;
+        }
+        print_hdr(&format!("Configured accounts  ({} total)", self.accounts.len()));
+
+        for acc in &self.accounts {
+            let priv_ok = if !acc.identity_agent.is_empty() {
+                color("green", "agent")
+            } else if acc.private_key_exists {
+                color("green", "yes")
+            } else {
+                color("red", "no")
+            };
+            let pub_ok =
+                if acc.public_key_exists { color("green", "yes") } else { color("red", "no") };
+            let tok_ok = if acc.has_token { color("green", "yes") } else { color("dim", "-") };
+
+            let mut tags = String::new();
+            if acc.active.contains(&"local") {
+                tags.push_str(&format!("  {}", color("green", "[active:local]")));
+            }
+            if acc.active.contains(&"global") {
+                tags.push_str(&format!("  {}", color("yellow", "[active:global]")));
+            }
+            if acc.expired {
+                let tag = format!("[expired {}]", acc.expires);
+                tags.push_str(&format!("  {}", color("red", &tag)));
+            } else if !acc.expires.is_empty() {
+                let tag = format!("[expires {}]", acc.expires);
+                tags.push_str(&format!("  {}", color("dim", &tag)));
+            }
+            if let Some(age) = acc.key_age_days.filter(|_| acc.key_stale) {
+                tags.push_str(&format!("  {}", color("yellow", &format!("[key {age} days old]"))));
+            }
+
+            let ssh_display = if acc.ssh_key.is_empty() {
+                color("dim", "(none)")
+            } else {
+                acc.ssh_key.clone()
+            };
+            let email = if acc.alt_emails.is_empty() {
+                acc.email.clone()
+            } else {
+                let also = format!("(also {})", acc.alt_emails.join(", "));
+                format!("{}  {}", acc.email, color("dim", &also))
+            };
+            let agent = if acc.identity_agent.is_empty() {
+                String::new()
+            } else {
+                format!("\n    agent  : {}", acc.identity_agent)
+            };
+
+            println!(
+                "\n  {}  {}{}\n    email  : {}\n    ssh    : {}  priv:{}  pub:{}\n    token  : {}\n    alias  : {}\n    remote : {}{}",
+                color("bold", &acc.username),
+                color("dim", &acc.host),
+                tags,
+                email,
+                ssh_display,
+                priv_ok,
+                pub_ok,
+                tok_ok,
+                acc.alias,
+                acc.remote,
+                agent
+            );
+        }
+        println!();
     }
 
-    let in_repo = in_git_repo();
-    let local_email = if in_repo {
+    fn markdown(&self) -> String {
+        let yes_no = |b: bool| if b { "yes" } else { "no" }.to_string();
+        let rows: Vec<Vec<String>> = self
+            .accounts
+            .iter()
+            .map(|a| {
+                vec![
+                    a.username.clone(),
+                    a.host.clone(),
+                    a.email.clone(),
+                    a.ssh_key.clone(),
+                    yes_no(a.has_token),
+                    a.alias.clone(),
+                    a.remote.clone(),
+                    a.active.join(", "),
+                    a.expires.clone(),
+                ]
+            })
+            .collect();
+        let headers = [
+            "Account", "Host", "Email", "SSH key", "Token", "Alias", "Remote", "Active", "Expires",
+        ];
+        format!("## Accounts\n\n{}", markdown_table(&headers, &rows))
+    }
+    fn table(&self) {
+        let rows: Vec<Vec<String>> = self
+            .accounts
+            .iter()
+            .map(|a| {
+                let key = if !a.identity_agent.is_empty() {
+                    "agent"
+                } else if a.ssh_key.is_empty() {
+                    "no"
+                } else if a.private_key_exists {
+                    "yes"
+                } else {
+                    "missing"
+                };
+                vec![
+                    a.id.clone(),
+                    a.host.clone(),
+                    a.email.clone(),
+                    key.to_string(),
+                    if a.has_token { "yes" } else { "no" }.to_string(),
+                    a.active.join(","),
+                ]
+            })
+            .collect();
+        let headers = ["Account", "Host", "Email", "Key", "Token", "Active"];
+        print!("{}", plain_table(&headers, &rows));
+    }
+}
+
+/// Emails active in the local and global scope, normalised for matching.
+fn active_emails(strip_plus: bool) -> (String, String) {
+    let local = if in_git_repo() {
         get_git_config("user.email", "local")
     } else {
         String::new()
     };
-    let global_email = get_git_config("user.email", "global");
-
-    print_hdr(&format!("Configured accounts  ({} total)", accounts.len()));
-
-    for acc in &accounts {
-        let username = &acc.username;
-        let email = &acc.email;
-        let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
-        let ssh_key = PathBuf::from(acc.ssh_key.replace('~', &dirs_home().to_string_lossy()));
-        let token = &acc.https_token;
-
-        let priv_ok = if !acc.ssh_key.is_empty() && ssh_key.exists() {
-            color("green", "yes")
-        } else {
-            color("red", "no")
-        };
-        let pub_ok = if !acc.ssh_key.is_empty() && ssh_key.with_extension("pub").exists() {
-            color("green", "yes")
-        } else {
-            color("red", "no")
-        };
-        let tok_ok = if !token.is_empty() {
-            color("green", "yes")
-        } else {
-            color("dim", "-")
-        };
-
-        let mut tags = String::new();
-        if !email.is_empty() && *email == local_email {
-            tags.push_str(&format!("  {}", color("green", "[active:local]")));
+    let global = get_git_config("user.email", "global");
+    (normalize_email(&local, strip_plus), normalize_email(&global, strip_plus))
+}
+
+/// One SSH key and everything that refers to it.
+#[derive(Serialize)]
+struct KeyUse {
+    #[serde(rename = "path")]
+    key: PathBuf,
+    fingerprint: String,
+    #[serde(rename = "type")]
+    kind: String,
+    accounts: Vec<String>,
+    in_agent: bool,
+    stanzas: Vec<String>,
+    /// Accounts whose forge has the key registered; None when not checked.
+    forges: Option<Vec<String>>,
+}
+
+/// `list --keys`.
+#[derive(Serialize)]
+struct KeysReport {
+    keys: Vec<KeyUse>,
+}
+
+/// Private key paths (without `.pub`) of every public key in ~/.ssh, plus
+/// keys of accounts that live elsewhere.
+fn key_files(accounts: &[Account]) -> Vec<PathBuf> {
+    let mut keys: Vec<PathBuf> = std::fs::read_dir(ssh_dir())
+        .map(|rd| {
+            rd.filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("pub"))
+                .map(|p| p.with_extension(""))
+                .collect()
+        })
+        .unwrap_or_default();
+    for acc in accounts.iter().filter(|a| !a.ssh_key.is_empty()) {
+        let key = expand_home(&acc.ssh_key);
+        if key.with_extension("pub").exists() && !keys.contains(&key) {
+            keys.push(key);
         }
-        if !email.is_empty() && *email == global_email {
-            tags.push_str(&format!("  {}", color("yellow", "[active:global]")));
+    }
+    keys.sort();
+    keys
+}
+
+/// Registered public keys per account with a GitHub token, as
+/// (account id, key bodies).
+fn forge_keys(accounts: &[Account]) -> Vec<(String, Vec<String>)> {
+    accounts
+        .iter()
+        .filter(|a| !a.https_token.is_empty() && forge::is_github(a))
+        .filter_map(|acc| match forge::public_keys(acc) {
+            Ok(keys) => Some((account_id(acc), keys.iter().map(|k| key_body(k)).collect())),
+            Err(e) => {
+                print_warn(&format!("Cannot list the keys of {}: {e}", account_id(acc)));
+                None
+            }
+        })
+        .collect()
+}
+
+fn key_uses(accounts: &[Account], network: bool) -> Vec<KeyUse> {
+    let mut agent_keys = agent_public_keys(None);
+    if let Some(sock) = onepassword_agent_socket() {
+        agent_keys.extend(agent_public_keys(Some(&sock)));
+    }
+    let agent_keys: Vec<String> = agent_keys.iter().map(|k| key_body(k)).collect();
+    let forges = network.then(|| forge_keys(accounts));
+
+    key_files(accounts)
+        .into_iter()
+        .map(|key| {
+            let pub_key = key.with_extension("pub");
+            let body = key_body(&crate::log::read_to_string(&pub_key).unwrap_or_default());
+            let (fingerprint, kind) = key_fingerprint(&pub_key).unwrap_or_default();
+            KeyUse {
+                accounts: accounts
+                    .iter()
+                    .filter(|a| !a.ssh_key.is_empty() && expand_home(&a.ssh_key) == key)
+                    .map(account_id)
+                    .collect(),
+                in_agent: !body.is_empty() && agent_keys.contains(&body),
+                stanzas: hosts_using_key(&key, ""),
+                forges: forges.as_ref().map(|forges| {
+                    forges
+                        .iter()
+                        .filter(|(_, keys)| !body.is_empty() && keys.contains(&body))
+                        .map(|(id, _)| id.clone())
+                        .collect()
+                }),
+                key,
+                fingerprint,
+                kind,
+            }
+        })
+        .collect()
+}
+
+/// `key` with the home directory shown as `~`.
+fn display_key(key: &Path) -> String {
+    match key.strip_prefix(dirs_home()) {
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => key.display().to_string(),
+    }
+}
+
+pub fn cmd_list_keys(format: Format, network: bool) -> Result<()> {
+    let accounts = load_accounts()?;
+    render(&KeysReport { keys: key_uses(&accounts, network) }, format)
+}
+
+impl Report for KeysReport {
+    fn human(&self) {
+        if self.keys.is_empty() {
+            print_info(&format!("No SSH keys found in {}", ssh_dir().display()));
+            return;
         }
 
-        let ssh_display = if acc.ssh_key.is_empty() {
-            color("dim", "(none)")
-        } else {
-            acc.ssh_key.clone()
-        };
-        let alias = ssh_host_alias(acc);
-
-        println!(
-            "\n  {}  {}{}\n    email  : {}\n    ssh    : {}  priv:{}  pub:{}\n    token  : {}\n    alias  : {}",
-            color("bold", username),
-            color("dim", host),
-            tags,
-            email,
-            ssh_display,
-            priv_ok,
-            pub_ok,
-            tok_ok,
-            alias
-        );
+        print_hdr(&format!("SSH keys  ({} total)", self.keys.len()));
+        let none = || color("dim", "(none)");
+        let list = |items: &[String]| if items.is_empty() { none() } else { items.join(", ") };
+        for u in &self.keys {
+            let unused = u.accounts.is_empty() && u.stanzas.is_empty();
+            let tag =
+                if unused { format!("  {}", color("yellow", "[unused]")) } else { String::new() };
+            let agent = if u.in_agent { color("green", "yes") } else { color("dim", "no") };
+            let forges = match &u.forges {
+                Some(forges) => list(forges),
+                None => color("dim", "(not checked, pass --network)"),
+            };
+            println!(
+                "\n  {}  {}{}\n    fingerprint : {}\n    accounts    : {}\n    stanzas     : {}\n    agent       : {}\n    forges      : {}",
+                color("bold", &display_key(&u.key)),
+                color("dim", &u.kind),
+                tag,
+                u.fingerprint,
+                list(&u.accounts),
+                list(&u.stanzas),
+                agent,
+                forges
+            );
+        }
+        println!();
+    }
+
+    fn markdown(&self) -> String {
+        let rows: Vec<Vec<String>> = self
+            .keys
+            .iter()
+            .map(|u| {
+                vec![
+                    display_key(&u.key),
+                    u.kind.clone(),
+                    u.fingerprint.clone(),
+                    u.accounts.join(", "),
+                    u.stanzas.join(", "),
+                    if u.in_agent { "yes" } else { "no" }.to_string(),
+                    u.forges.as_ref().map_or("not checked".to_string(), |f| f.join(", ")),
+                ]
+            })
+            .collect();
+        let headers = ["Key", "Type", "Fingerprint", "Accounts", "Stanzas", "Agent", "Forges"];
+        format!("## SSH keys\n\n{}", markdown_table(&headers, &rows))
+    }
+    fn table(&self) {
+        let rows: Vec<Vec<String>> = self
+            .keys
+            .iter()
+            .map(|u| {
+                vec![
+                    display_key(&u.key),
+                    u.kind.clone(),
+                    u.fingerprint.clone(),
+                    u.accounts.join(","),
+                    if u.in_agent { "yes" } else { "no" }.to_string(),
+                ]
+            })
+            .collect();
+        let headers = ["Key", "Type", "Fingerprint", "Accounts", "Agent"];
+        print!("{}", plain_table(&headers, &rows));
     }
-    println!();
 }
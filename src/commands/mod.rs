@@ -1,7 +1,33 @@
+pub mod accounts;
 pub mod add;
+pub mod backups;
+pub mod check;
+pub mod clone;
 pub mod completions;
+pub mod credential;
+pub mod current;
+pub mod debug;
+pub mod dirs;
+pub mod doctor;
+pub mod edit;
+pub mod env;
+pub mod explain;
+pub mod export;
+pub mod hook;
+pub mod host;
+pub mod import;
 pub mod list;
 pub mod remove;
+pub mod repos;
+pub mod restore;
+pub mod rules;
 pub mod ssh;
 pub mod status;
+pub mod token;
+pub mod transfer;
+pub mod tui;
+pub mod uninstall;
 pub mod use_cmd;
+pub mod verify;
+pub mod whoami;
+pub mod with;
@@ -1,12 +1,24 @@
-use crate::config::{account_id, find_account, load_accounts, save_accounts};
-use crate::ssh::{remove_stanza, ssh_config_path, MARKER_E, MARKER_S};
-use crate::ui::{backup, color, die, print_info, print_ok};
+use crate::commands::dirs::{dir_rules, fragment_path};
+use crate::config::{
+    account_id, expand_home, find_account, find_account_strict, load_accounts, save_accounts,
+    ssh_host_alias,
+};
+use crate::error::{GitIdError, Result};
+use crate::git::{run_git, unset_git_config};
+use crate::models::Account;
+use crate::repos::load_repos;
+use crate::ssh::{
+    current_stanza, hosts_using_key, managed_config_path, ssh_config_path, without_stanzas,
+};
+use crate::ui::{backup, color, print_info, print_ok, print_warn};
 use dialoguer::Input;
 use std::path::{Path, PathBuf};
 
-pub fn cmd_remove(username: &str, yes: bool, delete_keys: bool, dry_run: bool) {
-    let acc = find_account(username)
-        .unwrap_or_else(|| die(&format!("Account '{username}' not found. Run: git-id list"), 2));
+pub fn cmd_remove(username: &str, yes: bool, delete_keys: bool, dry_run: bool) -> Result<()> {
+    let found = if yes { find_account_strict(username)? } else { find_account(username)? };
+    let acc = found.ok_or_else(|| {
+        GitIdError::Usage(format!("Account '{username}' not found. Run: git-id list"))
+    })?;
 
     if !yes {
         let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
@@ -27,16 +39,28 @@ pub fn cmd_remove(username: &str, yes: bool, delete_keys: bool, dry_run: bool) {
             .unwrap_or_default();
         if ans.to_lowercase() != "y" {
             print_info("Aborted.");
-            return;
+            return Ok(());
         }
     }
 
-    remove_ssh_config_stanza(&account_id(&acc), dry_run);
+    let rules = account_dir_rules(&acc);
+    if !confirm_dir_rules(&rules, yes) {
+        print_info("Aborted - move those directories to another account first: git-id dirs add");
+        return Ok(());
+    }
+
+    let delete_keys = delete_keys
+        && !acc.ssh_key.is_empty()
+        && confirm_key_dependents(&key_dependents(&acc)?, yes);
+
+    remove_ssh_config_stanza(&account_id(&acc), dry_run)?;
 
     let uid = account_id(&acc);
-    let accounts = load_accounts();
+    let accounts = load_accounts()?;
     let new_accounts: Vec<_> = accounts.into_iter().filter(|a| account_id(a) != uid).collect();
-    save_accounts(&new_accounts, dry_run);
+    save_accounts(&new_accounts, dry_run)?;
+
+    remove_dir_rules(&acc, &rules, dry_run);
 
     if !acc.ssh_key.is_empty() {
         handle_key_files(&acc.ssh_key, delete_keys, dry_run);
@@ -45,31 +69,159 @@ pub fn cmd_remove(username: &str, yes: bool, delete_keys: bool, dry_run: bool) {
     if !dry_run {
         print_ok(&format!("Account '{}' removed.", account_id(&acc)));
     }
+    Ok(())
 }
 
-fn remove_ssh_config_stanza(acct_id: &str, dry_run: bool) {
-    let cfg = ssh_config_path();
-    if !cfg.exists() {
-        return;
+/// Everything other than `acc` that still references its SSH key: other
+/// accounts sharing the key, hand-written SSH config hosts, and registered
+/// repositories that reach the key through those or a `core.sshCommand`.
+fn key_dependents(acc: &Account) -> Result<Vec<String>> {
+    let key = expand_home(&acc.ssh_key);
+    let uid = account_id(acc);
+    let sharing: Vec<Account> = load_accounts()?
+        .into_iter()
+        .filter(|a| account_id(a) != uid && !a.ssh_key.is_empty() && expand_home(&a.ssh_key) == key)
+        .collect();
+    let mut dependents: Vec<String> =
+        sharing.iter().map(|a| format!("account {}", account_id(a))).collect();
+    let mut aliases: Vec<String> = sharing.iter().map(ssh_host_alias).collect();
+    for host in hosts_using_key(&key, &ssh_host_alias(acc)) {
+        if !aliases.contains(&host) {
+            dependents.push(format!("SSH config host {host}"));
+            aliases.push(host);
+        }
     }
-    let content = std::fs::read_to_string(&cfg).unwrap_or_default();
-    let start = MARKER_S.replace("{id}", acct_id);
-    let end_marker = MARKER_E.replace("{id}", acct_id);
-    if !content.contains(&start) {
-        print_info(&format!("No SSH config stanza found for '{acct_id}' - skipping"));
+    let key = key.to_string_lossy();
+    for repo in load_repos()?.into_iter().filter(|r| r.exists()) {
+        let dir = repo.to_string_lossy();
+        let (_, ssh_cmd, _) = run_git(&["-C", &dir, "config", "--local", "core.sshCommand"]);
+        let (_, urls, _) =
+            run_git(&["-C", &dir, "config", "--local", "--get-regexp", r"^remote\..*\.url$"]);
+        let via_alias = urls
+            .lines()
+            .filter_map(|line| line.split_once(' ').and_then(|(_, url)| ssh_host(url)))
+            .any(|host| aliases.iter().any(|a| a == host));
+        if via_alias || ssh_cmd.contains(key.as_ref()) {
+            dependents.push(format!("repository {dir}"));
+        }
+    }
+    Ok(dependents)
+}
+
+/// Host or alias an SSH remote URL connects to (`git@host:path` or
+/// `ssh://[user@]host[:port]/path`).
+fn ssh_host(url: &str) -> Option<&str> {
+    let rest = match url.strip_prefix("ssh://") {
+        Some(rest) => rest,
+        None if !url.contains("://") && url.contains(':') => url,
+        None => return None,
+    };
+    let rest = rest.split_once('@').map_or(rest, |(_, host)| host);
+    rest.split([':', '/']).next().filter(|host| !host.is_empty())
+}
+
+/// Asks once per dependent before a shared key is deleted. With `--yes`
+/// nothing is asked, so shared keys are kept instead.
+fn confirm_key_dependents(dependents: &[String], yes: bool) -> bool {
+    if dependents.is_empty() {
+        return true;
+    }
+    print_warn("The SSH key is still referenced by:");
+    for d in dependents {
+        println!("    {}", color("yellow", d));
+    }
+    if yes {
+        print_info("Keeping the key files (shared key; confirm interactively to delete).");
+        return false;
+    }
+    for d in dependents {
+        let ans: String = Input::new()
+            .with_prompt(format!("  Delete the key anyway, breaking {d}? [y/N]"))
+            .default("N".to_string())
+            .interact_text()
+            .unwrap_or_default();
+        if ans.to_lowercase() != "y" {
+            print_info("Keeping the key files.");
+            return false;
+        }
+    }
+    true
+}
+
+/// Directories whose `includeIf` rule includes this account's fragment.
+fn account_dir_rules(acc: &Account) -> Vec<String> {
+    let fragment = fragment_path(acc);
+    dir_rules()
+        .into_iter()
+        .filter(|(_, path)| expand_home(path) == fragment)
+        .map(|(dir, _)| dir)
+        .collect()
+}
+
+/// Lists directory rules that would dangle once the account is gone and
+/// asks whether to remove them too. With `--yes` they are removed.
+fn confirm_dir_rules(dirs: &[String], yes: bool) -> bool {
+    if dirs.is_empty() {
+        return true;
+    }
+    print_warn("These directories get their identity from this account:");
+    for d in dirs {
+        println!("    {}", color("yellow", d));
+    }
+    if yes {
+        return true;
+    }
+    let ans: String = Input::new()
+        .with_prompt("  Remove these directory rules too? [Y/n]")
+        .default("Y".to_string())
+        .interact_text()
+        .unwrap_or_default();
+    ans.to_lowercase() != "n"
+}
+
+/// Drops the account's `includeIf` rules and its include fragment, so git
+/// is not left including a file that no longer exists.
+fn remove_dir_rules(acc: &Account, dirs: &[String], dry_run: bool) {
+    for dir in dirs {
+        unset_git_config(&format!("includeIf.gitdir:{dir}.path"), "global", dry_run);
+        if !dry_run {
+            print_ok(&format!("Removed directory rule for {dir}"));
+        }
+    }
+    let fragment = fragment_path(acc);
+    if !fragment.exists() {
         return;
     }
-    let new_content = remove_stanza(&content, &start, &end_marker);
+    if dry_run {
+        print_info(&format!("[dry-run] Would delete {}", fragment.display()));
+    } else {
+        let _ = std::fs::remove_file(&fragment);
+        print_ok(&format!("Deleted {}", fragment.display()));
+    }
+}
+
+pub fn remove_ssh_config_stanza(acct_id: &str, dry_run: bool) -> Result<()> {
+    // Stanzas written by older releases are still in ~/.ssh/config.
+    let found = [managed_config_path(), ssh_config_path()].into_iter().find_map(|cfg| {
+        let content = crate::log::read_to_string(&cfg).ok()?;
+        current_stanza(&content, acct_id).is_some().then_some((cfg, content))
+    });
+    let Some((cfg, content)) = found else {
+        print_info(&format!("No SSH config stanza found for '{acct_id}' - skipping"));
+        return Ok(());
+    };
+    let new_content = without_stanzas(&content, &[acct_id.to_string()]);
     if dry_run {
         print_info(&format!("[dry-run] Would remove SSH config stanza for '{acct_id}'"));
     } else {
         backup(&cfg);
-        std::fs::write(&cfg, &new_content)
-            .unwrap_or_else(|e| crate::ui::die(&format!("Failed to write SSH config: {e}"), 1));
+        crate::log::write(&cfg, &new_content)
+            .map_err(|e| GitIdError::Io(format!("Failed to write SSH config: {e}")))?;
         use std::os::unix::fs::PermissionsExt;
         let _ = std::fs::set_permissions(&cfg, std::fs::Permissions::from_mode(0o600));
         print_ok(&format!("Removed SSH config stanza for '{acct_id}'"));
     }
+    Ok(())
 }
 
 fn handle_key_files(ssh_key: &str, delete_keys: bool, dry_run: bool) {
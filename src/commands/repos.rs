@@ -0,0 +1,141 @@
+use crate::config::{account_id, expand_home, has_email, load_accounts, normalize_email};
+use crate::error::{GitIdError, Result};
+use crate::git::{repo_kind_at, run_git};
+use crate::repos::{load_repos, repo_root, save_repos};
+use crate::ui::{color, print_hdr, print_info, print_ok};
+use std::path::{Path, PathBuf};
+
+/// `path` as given on the command line, made absolute.
+fn absolute(path: &Path) -> PathBuf {
+    let path = expand_home(&path.to_string_lossy());
+    std::path::absolute(&path).unwrap_or(path)
+}
+
+/// Registers repositories git-id did not set up itself; without paths, the
+/// current one.
+pub fn cmd_repos_add(paths: &[PathBuf], dry_run: bool) -> Result<()> {
+    let paths = if paths.is_empty() { vec![PathBuf::from(".")] } else { paths.to_vec() };
+    let mut roots = vec![];
+    for path in &paths {
+        let dir = absolute(path);
+        if !dir.is_dir() {
+            return Err(GitIdError::Usage(format!("No such directory: {}", dir.display())));
+        }
+        let root = repo_root(&dir).ok_or_else(|| {
+            GitIdError::Usage(format!("{} is not inside a git repository", dir.display()))
+        })?;
+        roots.push(std::fs::canonicalize(&root).unwrap_or(root));
+    }
+
+    let mut repos = load_repos()?;
+    let mut added = 0;
+    for root in roots {
+        if repos.contains(&root) {
+            print_info(&format!("{} is already registered", root.display()));
+            continue;
+        }
+        if dry_run {
+            print_info(&format!("[dry-run] Would register {}", root.display()));
+        } else {
+            print_ok(&format!("Registered {}", root.display()));
+        }
+        repos.push(root);
+        added += 1;
+    }
+    if added > 0 {
+        save_repos(&repos, dry_run)?;
+    }
+    Ok(())
+}
+
+/// Takes repositories out of the registry. The repositories themselves and
+/// their settings are left alone.
+pub fn cmd_repos_remove(paths: &[PathBuf], dry_run: bool) -> Result<()> {
+    let mut repos = load_repos()?;
+    let mut removed = vec![];
+    for path in paths {
+        let dir = absolute(path);
+        // A repo that still exists may be named by any directory inside it.
+        let root = repo_root(&dir).map(|r| std::fs::canonicalize(&r).unwrap_or(r));
+        let Some(pos) = repos.iter().position(|r| *r == dir || Some(r) == root.as_ref()) else {
+            return Err(GitIdError::Usage(format!(
+                "{} is not in the repo registry (see: git-id repos list)",
+                dir.display()
+            )));
+        };
+        removed.push(repos.remove(pos));
+    }
+    save_repos(&repos, dry_run)?;
+    for repo in removed {
+        if dry_run {
+            print_info(&format!("[dry-run] Would unregister {}", repo.display()));
+        } else {
+            print_ok(&format!("Unregistered {}", repo.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Lists the registered repositories with the account their local identity
+/// belongs to, marking bare repositories, mirrors and custom hook paths.
+pub fn cmd_repos_list() -> Result<()> {
+    let repos = load_repos()?;
+    if repos.is_empty() {
+        print_info("No repositories registered. Add one with: git-id repos add [<path>]");
+        return Ok(());
+    }
+    let accounts = load_accounts()?;
+    print_hdr("Known repositories");
+    let mut stale = 0;
+    for repo in &repos {
+        let path = repo.to_string_lossy();
+        if repo_root(repo).is_none() {
+            stale += 1;
+            println!("  {}  {}", color("bold", &path), color("red", "missing"));
+            continue;
+        }
+        let (_, email, _) = run_git(&["-C", &path, "config", "--local", "--get", "user.email"]);
+        let identity = if email.is_empty() {
+            color("dim", "no local identity")
+        } else {
+            let wanted = normalize_email(&email, false);
+            match accounts.iter().find(|a| has_email(a, &wanted, false)) {
+                Some(acc) => account_id(acc),
+                None => format!("<{email}> {}", color("yellow", "(no account)")),
+            }
+        };
+        let markers = repo_kind_at(repo).markers();
+        if markers.is_empty() {
+            println!("  {}  {identity}", color("bold", &path));
+        } else {
+            let markers = color("dim", &format!("({})", markers.join(", ")));
+            println!("  {}  {identity}  {markers}", color("bold", &path));
+        }
+    }
+    println!();
+    if stale > 0 {
+        print_info(&format!("{stale} missing - drop them with: git-id repos clean"));
+    }
+    Ok(())
+}
+
+/// Drops entries whose directory is gone or no longer a git repository.
+pub fn cmd_repos_clean(dry_run: bool) -> Result<()> {
+    let repos = load_repos()?;
+    let (kept, stale): (Vec<PathBuf>, Vec<PathBuf>) =
+        repos.into_iter().partition(|r| repo_root(r).is_some());
+    if stale.is_empty() {
+        print_info("Every registered repository still exists");
+        return Ok(());
+    }
+    save_repos(&kept, dry_run)?;
+    for repo in &stale {
+        let reason = if repo.exists() { "not a git repository" } else { "missing" };
+        if dry_run {
+            print_info(&format!("[dry-run] Would unregister {} ({reason})", repo.display()));
+        } else {
+            print_ok(&format!("Unregistered {} ({reason})", repo.display()));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use crate::config::accounts_file;
+use crate::dates::format_timestamp;
+use crate::error::{GitIdError, Result};
+use crate::ssh::{managed_config_path, ssh_config_path};
+use crate::ui::{backup, backups_of, color, print_hdr, print_info, print_ok};
+use std::path::PathBuf;
+
+/// Files `restore` can bring back from their backups.
+pub const TARGETS: [&str; 2] = ["accounts", "ssh-config"];
+
+/// One backup: the file it was made of and when.
+struct Candidate {
+    original: PathBuf,
+    backup: PathBuf,
+    stamp: u64,
+}
+
+/// The files git-id backs up, limited to `target`'s when given.
+pub fn originals(target: Option<&str>) -> Vec<PathBuf> {
+    match target {
+        Some("accounts") => vec![accounts_file()],
+        Some(_) => vec![ssh_config_path(), managed_config_path()],
+        None => vec![accounts_file(), ssh_config_path(), managed_config_path()],
+    }
+}
+
+/// Backups of the target's files, newest first.
+fn candidates(target: Option<&str>) -> Vec<Candidate> {
+    let mut found: Vec<Candidate> = originals(target)
+        .into_iter()
+        .flat_map(|original| {
+            backups_of(&original).into_iter().map(move |(stamp, backup)| Candidate {
+                original: original.clone(),
+                backup,
+                stamp,
+            })
+        })
+        .collect();
+    found.sort_by(|a, b| b.stamp.cmp(&a.stamp).then_with(|| a.backup.cmp(&b.backup)));
+    found
+}
+
+fn describe(c: &Candidate) -> String {
+    let name = c.backup.file_name().unwrap_or_default().to_string_lossy().to_string();
+    format!("{}  {name}", format_timestamp(c.stamp))
+}
+
+/// Lists the backups git-id made of accounts.toml and the SSH config, or
+/// puts one back in place. The current file is backed up first, so a
+/// restore can itself be undone.
+pub fn cmd_restore(
+    target: Option<&str>,
+    list: bool,
+    from: Option<usize>,
+    dry_run: bool,
+) -> Result<()> {
+    let mut found = candidates(target);
+    let command = match target {
+        Some(target) => format!("git-id restore {target}"),
+        None => "git-id restore".to_string(),
+    };
+    if found.is_empty() {
+        print_info("No backups found");
+        return Ok(());
+    }
+    if list {
+        print_hdr("Backups (newest first)");
+        for (i, c) in found.iter().enumerate() {
+            println!(
+                "  {:>3}  {}  {}",
+                i + 1,
+                describe(c),
+                color("dim", &format!("-> {}", c.original.display()))
+            );
+        }
+        println!();
+        print_info(&format!("Restore one with: {command} --from <number>"));
+        return Ok(());
+    }
+
+    let index = match from {
+        Some(n) if (1..=found.len()).contains(&n) => n - 1,
+        Some(n) => {
+            return Err(GitIdError::Usage(format!(
+                "No backup number {n} - there are {} (see: {command} --list)",
+                found.len()
+            )));
+        }
+        None => pick(&found, &command)?,
+    };
+    let chosen = found.swap_remove(index);
+    // Read it before anything is written: the safety backup made below can
+    // land on the same file name when both fall in the same second.
+    let content = std::fs::read(&chosen.backup).map_err(|e| {
+        GitIdError::Io(format!("Cannot read {}: {e}", chosen.backup.display()))
+    })?;
+    let original = &chosen.original;
+    if dry_run {
+        print_info(&format!(
+            "[dry-run] Would back up {} and restore it from {}",
+            original.display(),
+            chosen.backup.display()
+        ));
+        return Ok(());
+    }
+    backup(original);
+    crate::log::write(original, &content)
+        .map_err(|e| GitIdError::Io(format!("Failed to write {}: {e}", original.display())))?;
+    use std::os::unix::fs::PermissionsExt;
+    let _ = std::fs::set_permissions(original, std::fs::Permissions::from_mode(0o600));
+    print_ok(&format!(
+        "Restored {} from the backup of {}",
+        original.display(),
+        format_timestamp(chosen.stamp)
+    ));
+    if *original == accounts_file() {
+        print_info("Bring the SSH stanzas in line with the restored accounts: git-id ssh config");
+    }
+    Ok(())
+}
+
+/// Asks which backup to restore, when there is a terminal to ask on.
+fn pick(found: &[Candidate], command: &str) -> Result<usize> {
+    use std::io::IsTerminal;
+    if !std::io::stdin().is_terminal() {
+        return Err(GitIdError::Usage(format!(
+            "Pick a backup with --from <number> (see: {command} --list)"
+        )));
+    }
+    let items: Vec<String> = found
+        .iter()
+        .map(|c| format!("{}  -> {}", describe(c), c.original.display()))
+        .collect();
+    dialoguer::Select::new()
+        .with_prompt("  Restore which backup?")
+        .items(&items)
+        .default(0)
+        .interact()
+        .map_err(|_| GitIdError::Usage("No backup selected".to_string()))
+}
@@ -0,0 +1,120 @@
+use crate::config::{
+    ensure_accounts_file, load_accounts, load_rules, normalize_email, rule_matches, save_rules,
+    ssh_host_alias,
+};
+use crate::error::{GitIdError, Result};
+use crate::git::{
+    get_remote_url, in_git_repo, list_remotes, parse_remote_url, strip_plus_address_enabled,
+};
+use crate::models::EmailRule;
+use crate::ui::{color, print_hdr, print_info, print_ok};
+
+/// Who a rule applies to, for messages: "repos of acme-corp on github.com".
+pub fn describe(rule: &EmailRule) -> String {
+    match (rule.owner.is_empty(), rule.host.is_empty()) {
+        (false, false) => format!("repos of {} on {}", rule.owner, rule.host),
+        (false, true) => format!("repos of {}", rule.owner),
+        (true, false) => format!("repos on {}", rule.host),
+        (true, true) => "all repos".to_string(),
+    }
+}
+
+/// The first rule covering one of the current repo's remotes, origin first.
+/// Remotes using an account's custom SSH alias count as that account's host.
+pub fn repo_rule() -> Option<EmailRule> {
+    if !in_git_repo() {
+        return None;
+    }
+    let rules = load_rules().unwrap_or_default();
+    if rules.is_empty() {
+        return None;
+    }
+    let accounts = load_accounts().unwrap_or_default();
+    let mut remotes = list_remotes();
+    remotes.sort_by_key(|r| r != "origin");
+    for remote in remotes {
+        let Some((_, host, owner, _)) = parse_remote_url(&get_remote_url(&remote)) else {
+            continue;
+        };
+        let host = accounts
+            .iter()
+            .find(|a| ssh_host_alias(a) == host && !a.host.is_empty())
+            .map_or(host, |a| a.host.clone());
+        if let Some(rule) = rules.iter().find(|r| rule_matches(r, &host, &owner)) {
+            return Some(rule.clone());
+        }
+    }
+    None
+}
+
+/// The rule the current repo's commits would break when made as `email`.
+pub fn broken_rule(email: &str) -> Option<EmailRule> {
+    let rule = repo_rule()?;
+    let strip_plus = strip_plus_address_enabled();
+    (normalize_email(&rule.email, strip_plus) != normalize_email(email, strip_plus)).then_some(rule)
+}
+
+pub fn cmd_rules_list() -> Result<()> {
+    let rules = load_rules()?;
+    if rules.is_empty() {
+        print_info("No email rules. Add one with: git-id rules add --owner <owner> <email>");
+        return Ok(());
+    }
+    print_hdr("Email rules");
+    for (i, rule) in rules.iter().enumerate() {
+        println!("  {}  {}  {}", color("dim", &format!("{}.", i + 1)), describe(rule), rule.email);
+    }
+    if let Some(rule) = repo_rule() {
+        let line = format!("This repo falls under: {} -> {}", describe(&rule), rule.email);
+        println!("\n  {}", color("dim", &line));
+    }
+    println!();
+    Ok(())
+}
+
+pub fn cmd_rules_add(
+    owner: Option<&str>,
+    host: Option<&str>,
+    email: &str,
+    dry_run: bool,
+) -> Result<()> {
+    if owner.is_none() && host.is_none() {
+        return Err(GitIdError::Usage("Pass --owner, --host or both.".to_string()));
+    }
+    if !email.contains('@') {
+        return Err(GitIdError::Usage(format!("'{email}' is not an email address")));
+    }
+    let rule = EmailRule {
+        host: host.unwrap_or_default().to_string(),
+        owner: owner.unwrap_or_default().to_string(),
+        email: email.to_string(),
+    };
+    ensure_accounts_file()?;
+    let mut rules = load_rules()?;
+    let same_target = |r: &EmailRule| {
+        r.host.eq_ignore_ascii_case(&rule.host) && r.owner.eq_ignore_ascii_case(&rule.owner)
+    };
+    match rules.iter_mut().find(|r| same_target(r)) {
+        Some(existing) if existing.email == rule.email => {
+            print_info(&format!("{} already require {email}", describe(&rule)));
+            return Ok(());
+        }
+        Some(existing) => existing.email = rule.email.clone(),
+        None => rules.push(rule.clone()),
+    }
+    save_rules(&rules, dry_run)?;
+    print_ok(&format!("Commits in {} must use {email}", describe(&rule)));
+    Ok(())
+}
+
+/// Removes the rule numbered `index` in `rules list`.
+pub fn cmd_rules_remove(index: usize, dry_run: bool) -> Result<()> {
+    let mut rules = load_rules()?;
+    if index == 0 || index > rules.len() {
+        return Err(GitIdError::Usage(format!("No rule {index}. Run: git-id rules list")));
+    }
+    let rule = rules.remove(index - 1);
+    save_rules(&rules, dry_run)?;
+    print_ok(&format!("Removed rule for {}", describe(&rule)));
+    Ok(())
+}
@@ -1,38 +1,276 @@
-use crate::config::{account_id, find_account, load_accounts, save_accounts};
+use crate::commands::export::{Bundle, KeyFile, bundle_tables, collect_keys, portable_path};
+use crate::commands::import::write_key;
+use crate::commands::with::ssh_command;
+use crate::config::{
+    account_id, expand_home, find_account, find_account_strict, load_accounts, save_accounts,
+    ssh_host_alias,
+};
+use crate::dates::today;
+use crate::error::{GitIdError, Result};
+use crate::forge::{is_github, upload_public_key};
+use crate::history;
+use crate::models::Account;
 use crate::ssh::{
-    add_key_to_agent, fix_key_permissions, gen_ssh_key, make_stanza, ssh_dir, update_ssh_config,
+    EditedStanza, KeyOptions, Resolution, STANZA_FORMAT, add_key_to_agent, all_ssh_config,
+    default_key_path, fix_key_permissions, gen_ssh_key, make_stanza, report_new_key,
+    ssh_config_args, ssh_dir, stanza_formats, update_ssh_config, update_ssh_config_with,
+    write_new_key,
 };
-use crate::ui::{color, die, print_hdr, print_info, print_ok, print_warn};
-use dialoguer::{Input, Select};
-use std::path::PathBuf;
+use crate::ui::{color, print_hdr, print_info, print_ok, print_warn};
+use dialoguer::{Input, Password, Select};
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Offers to register the account's public key on GitHub when the account
+/// has a token, so the key works without pasting it into the settings page.
+pub fn offer_key_upload(acc: &Account, pub_key: &Path, dry_run: bool) {
+    if confirm_key_upload(acc) {
+        upload_key(acc, pub_key, dry_run);
+    }
+}
+
+/// Asks whether to upload the account's key; false when uploading is not
+/// possible (no token, or not GitHub).
+pub fn confirm_key_upload(acc: &Account) -> bool {
+    if acc.https_token.is_empty() || !is_github(acc) {
+        return false;
+    }
+    let ans: String = Input::new()
+        .with_prompt("  Upload this public key to GitHub now? [Y/n]")
+        .default("Y".to_string())
+        .interact_text()
+        .unwrap_or_default();
+    ans.to_lowercase() != "n"
+}
+
+/// Registers `pub_key` on the account's GitHub profile.
+pub fn upload_key(acc: &Account, pub_key: &Path, dry_run: bool) {
+    let title = format!("git-id {} ({})", account_id(acc), machine_name());
+    if dry_run {
+        print_info(&format!("[dry-run] Would upload {} as {title:?}", pub_key.display()));
+        return;
+    }
+    let key = crate::log::read_to_string(pub_key).unwrap_or_default();
+    match upload_public_key(acc, &title, key.trim()) {
+        Ok(()) => print_ok(&format!("Uploaded public key to {} as {title:?}", acc.username)),
+        Err(e) => print_warn(&format!("Could not upload the key: {e}")),
+    }
+}
 
-pub fn cmd_ssh_gen(username: &str, dry_run: bool) {
-    let acc = find_account(username)
-        .unwrap_or_else(|| die(&format!("Account '{username}' not found."), 2));
+fn machine_name() -> String {
+    crate::log::read_to_string("/etc/hostname")
+        .ok()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .or_else(|| {
+            crate::log::output(&mut Command::new("hostname"))
+                .ok()
+                .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "this machine".to_string())
+}
 
-    let key = gen_ssh_key(&acc.username, &acc.email, dry_run);
+pub fn cmd_ssh_gen(
+    username: &str,
+    key_type: &str,
+    bits: Option<u32>,
+    passphrase: bool,
+    passphrase_stdin: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let acc = find_account(username)?
+        .ok_or_else(|| GitIdError::Usage(format!("Account '{username}' not found.")))?;
+    let mut opts = KeyOptions::new(key_type, bits, String::new())?;
+
+    opts.passphrase = if passphrase_stdin {
+        let mut line = String::new();
+        std::io::stdin()
+            .read_line(&mut line)
+            .map_err(|e| GitIdError::Io(format!("Failed to read passphrase from stdin: {e}")))?;
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line.is_empty() {
+            return Err(GitIdError::Usage("An empty passphrase is not allowed.".to_string()));
+        }
+        line
+    } else if passphrase {
+        read_passphrase(true)?
+    } else {
+        String::new()
+    };
+    let fresh = !default_key_path(&acc.username, &opts).exists();
+    let key = gen_ssh_key(&acc, &opts, dry_run)?;
     fix_key_permissions(&key);
+    adopt_key(&acc, &key, fresh, dry_run)
+}
 
-    let mut accounts = load_accounts();
-    let uid = account_id(&acc);
+/// Points the account at `key`, just generated when `fresh`, and shows the
+/// public half to register on the forge.
+fn adopt_key(acc: &Account, key: &Path, fresh: bool, dry_run: bool) -> Result<()> {
+    let mut accounts = load_accounts()?;
+    let uid = account_id(acc);
+    let path = key.to_string_lossy().to_string();
     for a in accounts.iter_mut() {
         if account_id(a) == uid {
-            a.ssh_key = key.to_string_lossy().to_string();
+            if fresh {
+                a.key_created = today();
+            } else if a.ssh_key != path {
+                a.key_created.clear();
+            }
+            a.ssh_key = path.clone();
         }
     }
-    save_accounts(&accounts, dry_run);
-    update_ssh_config(&accounts, dry_run);
+    save_accounts(&accounts, dry_run)?;
+    update_ssh_config(&accounts, dry_run)?;
 
     let pub_key = key.with_extension("pub");
     if pub_key.exists() && !dry_run {
         print_hdr("Public key - paste into GitHub -> Settings -> SSH keys:");
-        println!("\n{}\n", std::fs::read_to_string(&pub_key).unwrap_or_default().trim());
+        println!("\n{}\n", crate::log::read_to_string(&pub_key).unwrap_or_default().trim());
+        offer_key_upload(acc, &pub_key, dry_run);
     }
+    Ok(())
 }
 
-pub fn cmd_ssh_pick(username: &str, dry_run: bool) {
-    let acc = find_account(username)
-        .unwrap_or_else(|| die(&format!("Account '{username}' not found."), 2));
+/// Type and size of the account's current key, read from its public half,
+/// so a rotation keeps RSA and ECDSA accounts on their kind of key.
+fn current_key_kind(acc: &Account) -> Option<(&'static str, Option<u32>)> {
+    if acc.ssh_key.is_empty() {
+        return None;
+    }
+    let pub_key = expand_home(&acc.ssh_key).with_extension("pub");
+    let key_type = public_key_type(&crate::log::read_to_string(&pub_key).ok()?)?;
+    if !matches!(key_type, "rsa" | "ecdsa") {
+        return Some((key_type, None));
+    }
+    // "4096 SHA256:... comment (RSA)"
+    let out = crate::log::output(Command::new("ssh-keygen").arg("-l").arg("-f").arg(&pub_key));
+    let bits = out.ok().and_then(|o| {
+        String::from_utf8_lossy(&o.stdout).split_whitespace().next()?.parse().ok()
+    });
+    Some((key_type, bits))
+}
+
+/// Replaces the account's key with a newly generated one. The new key is
+/// written next to the old one first; only once that worked is the old key
+/// renamed with an `-old-<timestamp>` suffix and the new one put in its
+/// place, so a failed rotation leaves the account as it was.
+pub fn cmd_ssh_rotate(
+    username: &str,
+    key_type: Option<&str>,
+    passphrase: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let acc = find_account(username)?
+        .ok_or_else(|| GitIdError::Usage(format!("Account '{username}' not found.")))?;
+    let uid = account_id(&acc);
+    let (key_type, bits) = match key_type {
+        Some(key_type) => (key_type, None),
+        None => current_key_kind(&acc).unwrap_or(("ed25519", None)),
+    };
+    let mut opts = KeyOptions::new(key_type, bits, String::new())?;
+    let key = ssh_dir().join(opts.file_name(&acc.username));
+    let sharing: Vec<String> = load_accounts()?
+        .iter()
+        .filter(|a| account_id(a) != uid && !a.ssh_key.is_empty())
+        .filter(|a| expand_home(&a.ssh_key) == key)
+        .map(account_id)
+        .collect();
+    if !sharing.is_empty() {
+        return Err(GitIdError::Usage(format!(
+            "{} is also used by {} - point them at their own keys first",
+            key.display(),
+            sharing.join(", ")
+        )));
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let name = key.file_name().unwrap_or_default().to_string_lossy();
+    let retired = key.exists().then(|| key.with_file_name(format!("{name}-old-{now}")));
+    let staged = key.with_file_name(format!("{name}-new-{now}"));
+    if dry_run {
+        print_info(&format!(
+            "[dry-run] Would generate a new {} key for '{uid}' at {}",
+            opts.describe(),
+            key.display()
+        ));
+        if let Some(retired) = &retired {
+            print_info(&format!("[dry-run] Would move {} to {}", key.display(), retired.display()));
+        }
+        return Ok(());
+    }
+
+    if passphrase {
+        opts.passphrase = read_passphrase(true)?;
+    }
+    write_new_key(&acc, &opts, &staged, false).inspect_err(|_| remove_key_files(&staged))?;
+    if let Some(retired) = &retired {
+        move_key_files(&key, retired).inspect_err(|_| remove_key_files(&staged))?;
+        print_ok(&format!("Moved the old key to {}", retired.display()));
+    }
+    if let Err(e) = move_key_files(&staged, &key) {
+        remove_key_files(&staged);
+        if let Some(retired) = &retired {
+            let _ = move_key_files(retired, &key);
+        }
+        return Err(e);
+    }
+    report_new_key(&acc, &opts, &key);
+    fix_key_permissions(&key);
+    adopt_key(&acc, &key, true, false)?;
+    let old = retired.unwrap_or_else(|| expand_home(&acc.ssh_key));
+    if !acc.ssh_key.is_empty() && old.exists() {
+        print_info(&format!(
+            "The old key stays in {} - remove it from the account's SSH keys on {} \
+             once the new one works",
+            old.display(),
+            if acc.host.is_empty() { "github.com" } else { &acc.host }
+        ));
+    }
+    Ok(())
+}
+
+/// Renames a key and its `.pub` from `from` to `to`.
+fn move_key_files(from: &Path, to: &Path) -> Result<()> {
+    for (from, to) in [
+        (from.to_path_buf(), to.to_path_buf()),
+        (from.with_extension("pub"), to.with_extension("pub")),
+    ] {
+        if !from.exists() {
+            continue;
+        }
+        let result = std::fs::rename(&from, &to);
+        crate::log::file("moved", &from, &result);
+        result.map_err(|e| {
+            GitIdError::Io(format!("Cannot move {} to {}: {e}", from.display(), to.display()))
+        })?;
+    }
+    Ok(())
+}
+
+fn remove_key_files(key: &Path) {
+    let _ = std::fs::remove_file(key);
+    let _ = std::fs::remove_file(key.with_extension("pub"));
+}
+
+pub fn cmd_ssh_pick(
+    username: &str,
+    from: Option<&Path>,
+    paste: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let acc = find_account(username)?
+        .ok_or_else(|| GitIdError::Usage(format!("Account '{username}' not found.")))?;
+    if from.is_some() || paste {
+        let key = install_key(&acc, from, dry_run)?;
+        if !dry_run {
+            add_key_to_agent(&key, &acc, dry_run);
+        }
+        return set_account_key(&acc, &key, dry_run);
+    }
 
     let pub_files: Vec<PathBuf> = {
         let mut v: Vec<PathBuf> = std::fs::read_dir(ssh_dir())
@@ -48,7 +286,7 @@ pub fn cmd_ssh_pick(username: &str, dry_run: bool) {
     };
 
     if pub_files.is_empty() {
-        die("No .pub files found in ~/.ssh/", 1);
+        return Err(GitIdError::Io("No .pub files found in ~/.ssh/".to_string()));
     }
 
     print_hdr(&format!("Pick SSH key for '{username}'"));
@@ -61,7 +299,7 @@ pub fn cmd_ssh_pick(username: &str, dry_run: bool) {
         .items(&items)
         .default(0)
         .interact()
-        .unwrap_or_else(|_| die("\nAborted.", 2));
+        .map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
 
     let chosen_pub = &pub_files[idx];
     let priv_key = chosen_pub.with_extension("");
@@ -74,37 +312,593 @@ pub fn cmd_ssh_pick(username: &str, dry_run: bool) {
             .interact_text()
             .unwrap_or_default();
         if yn.to_lowercase() == "y" {
-            gen_ssh_key(&acc.username, &acc.email, dry_run)
+            gen_ssh_key(&acc, &KeyOptions::default(), dry_run)?
         } else {
-            die("Cannot proceed without a private key.", 2);
+            return Err(GitIdError::Usage("Cannot proceed without a private key.".to_string()));
         }
     } else {
         fix_key_permissions(&priv_key);
-        add_key_to_agent(&priv_key, dry_run);
+        add_key_to_agent(&priv_key, &acc, dry_run);
         priv_key.clone()
     };
+    set_account_key(&acc, &final_priv, dry_run)
+}
 
-    let mut accounts = load_accounts();
-    let uid = account_id(&acc);
+/// Saves `key` as the account's key and rewrites the stanzas. A key from
+/// elsewhere drops the recorded creation day of the old one.
+fn set_account_key(acc: &Account, key: &Path, dry_run: bool) -> Result<()> {
+    let mut accounts = load_accounts()?;
+    let uid = account_id(acc);
+    let path = key.to_string_lossy().to_string();
     for a in accounts.iter_mut() {
         if account_id(a) == uid {
-            a.ssh_key = final_priv.to_string_lossy().to_string();
+            if a.ssh_key != path {
+                a.key_created.clear();
+            }
+            a.ssh_key = path.clone();
+        }
+    }
+    save_accounts(&accounts, dry_run)?;
+    update_ssh_config(&accounts, dry_run)?;
+    print_ok(&format!("SSH key for '{uid}' -> {}", key.display()));
+    Ok(())
+}
+
+/// Key type of an OpenSSH public key line, as `KEY_TYPES` names it.
+fn public_key_type(pub_key: &str) -> Option<&'static str> {
+    match pub_key.split_whitespace().next()? {
+        "ssh-ed25519" => Some("ed25519"),
+        "ssh-rsa" => Some("rsa"),
+        t if t.starts_with("ecdsa-sha2-") => Some("ecdsa"),
+        "sk-ssh-ed25519@openssh.com" => Some("ed25519-sk"),
+        "sk-ecdsa-sha2-nistp256@openssh.com" => Some("ecdsa-sk"),
+        _ => None,
+    }
+}
+
+/// Public half of a private key, from `ssh-keygen -y`. The key is written
+/// to a private temporary file for it, since ssh-keygen only reads files;
+/// it asks for the passphrase of an encrypted key on the terminal.
+fn derive_public_key(private_key: &str) -> Result<String> {
+    let tmp = std::env::temp_dir().join(format!("git-id-key-{}", std::process::id()));
+    use std::os::unix::fs::OpenOptionsExt;
+    let opened = std::fs::OpenOptions::new().create_new(true).write(true).mode(0o600).open(&tmp);
+    use std::io::Write;
+    opened
+        .and_then(|mut f| f.write_all(private_key.as_bytes()))
+        .map_err(|e| GitIdError::Io(format!("Cannot write {}: {e}", tmp.display())))?;
+    let mut cmd = Command::new("ssh-keygen");
+    cmd.arg("-y").arg("-f").arg(&tmp);
+    let out = crate::log::output(&mut cmd);
+    let _ = std::fs::remove_file(&tmp);
+    let out = out.map_err(|e| GitIdError::Io(format!("Cannot run ssh-keygen: {e}")))?;
+    if !out.status.success() {
+        return Err(GitIdError::Usage(format!(
+            "Not a usable private key: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// Installs a private key provisioned outside ~/.ssh, read from `from` or
+/// pasted on stdin, under the name git-id gives a generated key of its type
+/// (`id_<type>_<username>`), with its public half and 0600 permissions.
+/// Returns the installed path.
+fn install_key(acc: &Account, from: Option<&Path>, dry_run: bool) -> Result<PathBuf> {
+    let private_key = match from {
+        Some(path) => crate::log::read_to_string(path)
+            .map_err(|e| GitIdError::Usage(format!("Cannot read {}: {e}", path.display())))?,
+        None => {
+            if std::io::stdin().is_terminal() {
+                print_info("Paste the private key, then press Ctrl-D:");
+            }
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| GitIdError::Io(format!("Cannot read the key from stdin: {e}")))?;
+            text
         }
+    };
+    let private_key = format!("{}\n", private_key.trim());
+    if !private_key.starts_with("-----BEGIN") || !private_key.contains("PRIVATE KEY-----") {
+        let hint = if private_key.starts_with("ssh-") || private_key.starts_with("sk-") {
+            " - that is a public key; git-id needs the private one"
+        } else {
+            ""
+        };
+        return Err(GitIdError::Usage(format!("Not an SSH private key{hint}")));
+    }
+    // A public key next to the source saves the passphrase prompt.
+    let sibling = from.map(|p| p.with_extension("pub"));
+    let public_key = match sibling.and_then(|p| crate::log::read_to_string(p).ok()) {
+        Some(key) => key.trim().to_string(),
+        None => derive_public_key(&private_key)?,
+    };
+    let key_type = public_key_type(&public_key).ok_or_else(|| {
+        GitIdError::Usage(format!("Unsupported key type in {public_key:?}"))
+    })?;
+    let opts = KeyOptions { key_type: key_type.to_string(), ..Default::default() };
+    let dest = default_key_path(&acc.username, &opts);
+    if dest.exists() && crate::log::read_to_string(&dest).ok().as_deref() != Some(&private_key) {
+        return Err(GitIdError::Usage(format!(
+            "{} already holds another key - move it away first",
+            dest.display()
+        )));
     }
-    save_accounts(&accounts, dry_run);
-    update_ssh_config(&accounts, dry_run);
-    print_ok(&format!("SSH key for '{username}' -> {}", final_priv.display()));
+    let file = KeyFile {
+        path: dest.to_string_lossy().to_string(),
+        private_key,
+        public_key: format!("{public_key}\n"),
+    };
+    write_key(&file, dry_run)?;
+    Ok(dest)
 }
 
-pub fn cmd_ssh_config(dry_run: bool) {
-    let accounts = load_accounts();
+pub fn cmd_ssh_config(yes: bool, dry_run: bool) -> Result<()> {
+    let accounts = load_accounts()?;
     if accounts.is_empty() {
         print_info("No accounts configured. Run: git-id add");
-        return;
+        return Ok(());
+    }
+    if !confirm_format_upgrade(yes) {
+        print_info("Aborted - SSH config left unchanged.");
+        return Ok(());
+    }
+    let mut always_keep = vec![];
+    update_ssh_config_with(&accounts, dry_run, |acc, edited| {
+        if yes {
+            print_warn(&format!("Stanza of '{}' was edited by hand - left as is", account_id(acc)));
+            return Resolution::Defer;
+        }
+        let resolution = resolve_edited(acc, edited);
+        if resolution.is_none() {
+            always_keep.push(account_id(acc));
+        }
+        resolution.unwrap_or(Resolution::Keep)
+    })?;
+    if !always_keep.is_empty() {
+        let mut accounts = accounts.clone();
+        for acc in accounts.iter_mut().filter(|a| always_keep.contains(&account_id(a))) {
+            acc.ssh_keep_manual = true;
+        }
+        save_accounts(&accounts, dry_run)?;
     }
-    update_ssh_config(&accounts, dry_run);
     print_hdr("Generated SSH config stanzas:");
     for acc in &accounts {
         println!("{}", make_stanza(acc));
     }
+    Ok(())
+}
+
+/// Shows a stanza edited by hand next to what git-id generated before and
+/// would write now, and asks what to do with it. None means keep it and
+/// stop asking for this account.
+fn resolve_edited(acc: &Account, edited: &EditedStanza) -> Option<Resolution> {
+    print_hdr(&format!("The stanza of '{}' was edited by hand", account_id(acc)));
+    println!("\n  {}", color("bold", "Generated by git-id before"));
+    for line in edited.base.lines() {
+        println!("    {}", color("dim", line));
+    }
+    for (label, text) in [("Yours now", &edited.current), ("git-id would write", &edited.new)] {
+        println!("\n  {}", color("bold", label));
+        for line in text.lines() {
+            if edited.base.lines().any(|b| b == line) {
+                println!("    {line}");
+            } else {
+                println!("  {} {line}", color("green", "+"));
+            }
+        }
+        for line in edited.base.lines().filter(|b| !text.lines().any(|l| l == *b)) {
+            println!("  {} {}", color("red", "-"), line);
+        }
+    }
+    let choices = [
+        "Keep yours",
+        "Replace with git-id's",
+        "Merge (your edits on top of git-id's)",
+        "Always keep yours for this account",
+    ];
+    let idx = Select::new()
+        .with_prompt(format!("\n  {}", color("cyan", "What should happen to it?")))
+        .items(&choices)
+        .default(0)
+        .interact();
+    match idx {
+        Ok(0) => Some(Resolution::Keep),
+        Ok(1) => Some(Resolution::Replace),
+        Ok(2) => Some(Resolution::Merge),
+        Ok(_) => None,
+        Err(_) => Some(Resolution::Defer),
+    }
+}
+
+/// Reports managed blocks written in another stanza format and, for older
+/// ones, asks before they are regenerated. Returns false when declined.
+fn confirm_format_upgrade(yes: bool) -> bool {
+    let content = all_ssh_config();
+    let formats = stanza_formats(&content);
+    for (id, version) in formats.iter().filter(|(_, v)| *v > STANZA_FORMAT) {
+        print_warn(&format!(
+            "Stanza for '{id}' uses format v{version}, newer than this git-id (v{STANZA_FORMAT}) - upgrade git-id"
+        ));
+    }
+    let outdated: Vec<_> = formats.iter().filter(|(_, v)| *v < STANZA_FORMAT).collect();
+    if outdated.is_empty() {
+        return true;
+    }
+    print_warn(&format!(
+        "{} stanza(s) were written by an older git-id (current format v{STANZA_FORMAT}):",
+        outdated.len()
+    ));
+    for (id, version) in &outdated {
+        println!("    {id}  {}", color("dim", &format!("(v{version})")));
+    }
+    if yes {
+        return true;
+    }
+    let ans: String = Input::new()
+        .with_prompt("  Regenerate them now? [Y/n]")
+        .default("Y".to_string())
+        .interact_text()
+        .unwrap_or_default();
+    ans.to_lowercase() != "n"
+}
+
+/// Connects to the account's host alias with `ssh -T` and reports which
+/// forge login the key authenticated as. Fails with `CheckFailed` on
+/// failure or mismatch.
+pub fn cmd_ssh_test(username: &str) -> Result<()> {
+    let acc = find_account(username)?
+        .ok_or_else(|| GitIdError::Usage(format!("Account '{username}' not found.")))?;
+    let alias = ssh_host_alias(&acc);
+    print_info(&format!("Connecting to git@{alias} ..."));
+
+    let out = crate::log::output(
+        Command::new("ssh")
+            .args(ssh_config_args())
+            .args(["-T", "-o", "BatchMode=yes", "-o", "ConnectTimeout=10"])
+            .arg(format!("git@{alias}")),
+    )
+    .map_err(|e| GitIdError::Io(format!("Failed to run ssh: {e}")))?;
+    let banner = format!(
+        "{}{}",
+        String::from_utf8_lossy(&out.stderr),
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let Some(login) = banner_login(&banner) else {
+        print_warn(&format!("Authentication to {alias} failed:"));
+        for line in banner.lines().filter(|l| !l.trim().is_empty()) {
+            println!("    {}", color("dim", line.trim()));
+        }
+        return Err(GitIdError::CheckFailed);
+    };
+    if login.eq_ignore_ascii_case(&acc.username) {
+        print_ok(&format!("Authenticated to {alias} as {login}"));
+    } else {
+        print_warn(&format!(
+            "Key for '{}' authenticated as '{login}', not '{}' - check the key in {}",
+            account_id(&acc),
+            acc.username,
+            if acc.ssh_key.is_empty() { "(no key configured)" } else { &acc.ssh_key }
+        ));
+        return Err(GitIdError::CheckFailed);
+    }
+    Ok(())
+}
+
+/// Extracts the login from a forge's `ssh -T` greeting: GitHub's
+/// "Hi alice! You've successfully authenticated..." or GitLab's
+/// "Welcome to GitLab, @alice!".
+pub fn banner_login(banner: &str) -> Option<String> {
+    banner.lines().find_map(|line| {
+        let line = line.trim();
+        let rest = line
+            .strip_prefix("Hi ")
+            .or_else(|| line.split_once(", @").map(|(_, r)| r))?;
+        let login = rest.split('!').next()?.trim();
+        (!login.is_empty() && !login.contains(' ')).then(|| login.to_string())
+    })
+}
+
+/// Prints the ssh invocation git uses for the account, for pasting into
+/// other tools: a full `ssh ... git@<host>` line, or with `env` a
+/// `GIT_SSH_COMMAND="..."` assignment.
+pub fn cmd_ssh_command(username: &str, env: bool) -> Result<()> {
+    let acc = find_account(username)?
+        .ok_or_else(|| GitIdError::Usage(format!("Account '{username}' not found.")))?;
+    if acc.ssh_key.is_empty() {
+        return Err(GitIdError::Usage(format!(
+            "Account '{}' has no SSH key. Run: git-id ssh gen {username}",
+            account_id(&acc)
+        )));
+    }
+    let cmd = ssh_command(&acc);
+    if env {
+        let escaped: String = cmd
+            .chars()
+            .flat_map(|c| match c {
+                '"' | '\\' | '$' | '`' => vec!['\\', c],
+                _ => vec![c],
+            })
+            .collect();
+        println!("GIT_SSH_COMMAND=\"{escaped}\"");
+    } else {
+        let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+        println!("{cmd} git@{host}");
+    }
+    Ok(())
+}
+
+/// Encrypts the account's private key with `age` to the given recipients
+/// for organisations that require escrow of work credentials. Only ever run
+/// on request; every export is announced and recorded in the history file.
+pub fn cmd_ssh_export_escrow(
+    username: &str,
+    recipients: &[String],
+    output: Option<PathBuf>,
+    yes: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let found = if yes { find_account_strict(username)? } else { find_account(username)? };
+    let acc =
+        found.ok_or_else(|| GitIdError::Usage(format!("Account '{username}' not found.")))?;
+    let id = account_id(&acc);
+    if !acc.identity_agent.is_empty() {
+        return Err(GitIdError::Usage(format!(
+            "The key of '{id}' is held by an agent and cannot be exported."
+        )));
+    }
+    if acc.ssh_key.is_empty() {
+        return Err(GitIdError::Usage(format!("Account '{id}' has no SSH key.")));
+    }
+    let key = expand_home(&acc.ssh_key);
+    if !key.exists() {
+        return Err(GitIdError::Usage(format!("Private key not found: {}", key.display())));
+    }
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{id}.key.age")));
+    if output.exists() {
+        return Err(GitIdError::Usage(format!(
+            "{} already exists - refusing to overwrite it.",
+            output.display()
+        )));
+    }
+
+    print_warn(&format!(
+        "ESCROW EXPORT: the private key of '{id}' ({}) will be encrypted to {} recipient(s):",
+        key.display(),
+        recipients.len()
+    ));
+    for r in recipients {
+        println!("    {}", color("yellow", r));
+    }
+    if !yes {
+        let ans: String = Input::new()
+            .with_prompt("  Export the key? [y/N]")
+            .default("N".to_string())
+            .interact_text()
+            .unwrap_or_default();
+        if ans.to_lowercase() != "y" {
+            print_info("Aborted - nothing exported.");
+            return Ok(());
+        }
+    }
+
+    let mut args: Vec<String> = vec![];
+    for r in recipients {
+        // A path is a recipients file; anything else is a key on the line.
+        let flag = if Path::new(&expand_home(r)).is_file() { "-R" } else { "-r" };
+        args.extend([flag.to_string(), expand_home(r).to_string_lossy().to_string()]);
+    }
+    args.extend(["-o".to_string(), output.to_string_lossy().to_string()]);
+    args.push(key.to_string_lossy().to_string());
+    if dry_run {
+        print_info(&format!("[dry-run] Would run: age {}", args.join(" ")));
+    } else {
+        let out = crate::log::output(Command::new("age").args(&args))
+            .map_err(|e| GitIdError::Io(format!("Failed to run age (is it installed?): {e}")))?;
+        if !out.status.success() {
+            let _ = std::fs::remove_file(&output);
+            let err = String::from_utf8_lossy(&out.stderr);
+            return Err(GitIdError::Io(format!("age failed: {}", err.trim())));
+        }
+        print_ok(&format!("Encrypted key written to {}", output.display()));
+    }
+    history::record(
+        "escrow-export",
+        &id,
+        "",
+        &format!("recipients: {}; output: {}", recipients.join(", "), output.display()),
+        dry_run,
+    )?;
+    Ok(())
+}
+
+/// Environment variable that hands the passphrase to openssl, so it never
+/// appears on a command line.
+const PASSPHRASE_VAR: &str = "GIT_ID_BUNDLE_PASSPHRASE";
+
+/// Arguments for `openssl enc`: AES-256 with a PBKDF2-stretched passphrase.
+const CIPHER_ARGS: &[&str] =
+    &["enc", "-aes-256-cbc", "-pbkdf2", "-iter", "600000", "-md", "sha256", "-salt"];
+
+/// Runs `openssl enc` over `input`; `decrypt` adds `-d`.
+fn openssl_enc(input: &[u8], passphrase: &str, decrypt: bool) -> Result<Vec<u8>> {
+    let mut cmd = Command::new("openssl");
+    cmd.args(CIPHER_ARGS)
+        .args(if decrypt { &["-d"][..] } else { &[][..] })
+        .args(["-pass", &format!("env:{PASSPHRASE_VAR}")])
+        .env(PASSPHRASE_VAR, passphrase)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| GitIdError::Io(format!("Failed to run openssl (is it installed?): {e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        use std::io::Write;
+        let _ = stdin.write_all(input);
+    }
+    let out = child
+        .wait_with_output()
+        .map_err(|e| GitIdError::Io(format!("Failed to run openssl: {e}")))?;
+    // Stdout is the key material, so only stderr is traced.
+    crate::log::command(&cmd, out.status.code(), &[], &out.stderr);
+    if !out.status.success() {
+        return Err(GitIdError::Io(if decrypt {
+            "Cannot decrypt the bundle: wrong passphrase or not a git-id key bundle".to_string()
+        } else {
+            format!("openssl failed: {}", String::from_utf8_lossy(&out.stderr).trim())
+        }));
+    }
+    Ok(out.stdout)
+}
+
+pub fn read_passphrase(confirm: bool) -> Result<String> {
+    let mut prompt = Password::new().with_prompt(format!("  {}", color("cyan", "Passphrase")));
+    if confirm {
+        prompt = prompt.with_confirmation(
+            format!("  {}", color("cyan", "Repeat passphrase")),
+            "Passphrases do not match",
+        );
+    }
+    let passphrase =
+        prompt.interact().map_err(|_| GitIdError::Usage("\nAborted.".to_string()))?;
+    if passphrase.is_empty() {
+        return Err(GitIdError::Usage("An empty passphrase is not allowed.".to_string()));
+    }
+    Ok(passphrase)
+}
+
+/// Writes the account and its key pair to a passphrase-encrypted bundle
+/// that `ssh import` reads on another machine.
+pub fn cmd_ssh_export(username: &str, output: Option<PathBuf>, dry_run: bool) -> Result<()> {
+    let mut acc = find_account(username)?
+        .ok_or_else(|| GitIdError::Usage(format!("Account '{username}' not found.")))?;
+    let id = account_id(&acc);
+    if !acc.identity_agent.is_empty() {
+        return Err(GitIdError::Usage(format!(
+            "The key of '{id}' is held by an agent and cannot be exported."
+        )));
+    }
+    if acc.ssh_key.is_empty() {
+        return Err(GitIdError::Usage(format!("Account '{id}' has no SSH key.")));
+    }
+    let key = expand_home(&acc.ssh_key);
+    if !key.exists() {
+        return Err(GitIdError::Usage(format!("Private key not found: {}", key.display())));
+    }
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{id}.key.enc")));
+    if output.exists() {
+        return Err(GitIdError::Usage(format!(
+            "{} already exists - refusing to overwrite it.",
+            output.display()
+        )));
+    }
+    if dry_run {
+        print_info(&format!(
+            "[dry-run] Would write the key of '{id}' ({}) encrypted to {}",
+            key.display(),
+            output.display()
+        ));
+        return Ok(());
+    }
+
+    acc.ssh_key = portable_path(&acc.ssh_key);
+    let keys = collect_keys(std::slice::from_ref(&acc));
+    let content = format!(
+        "# git-id key bundle - restore with: git-id ssh import {id} <file>\n\n{}",
+        bundle_tables(&[acc], &keys)?
+    );
+    print_info("Choose a passphrase for the bundle (needed to import it).");
+    let passphrase = read_passphrase(true)?;
+    let encrypted = openssl_enc(content.as_bytes(), &passphrase, false)?;
+
+    use std::os::unix::fs::OpenOptionsExt;
+    let opened = std::fs::OpenOptions::new().create_new(true).write(true).mode(0o600).open(&output);
+    crate::log::file("wrote", &output, &opened);
+    use std::io::Write;
+    opened
+        .and_then(|mut f| f.write_all(&encrypted))
+        .map_err(|e| GitIdError::Io(format!("Failed to write {}: {e}", output.display())))?;
+    print_ok(&format!("Encrypted key bundle written to {}", output.display()));
+    history::record("ssh-export", &id, "", &format!("output: {}", output.display()), dry_run)
+}
+
+/// Restores a bundle written by `ssh export`: writes the key pair (to the
+/// account's `ssh_key` path if it has one) and points the account at it,
+/// adding the account first if this machine does not have it yet.
+pub fn cmd_ssh_import(username: &str, file: &Path, dry_run: bool) -> Result<()> {
+    let encrypted = std::fs::read(file);
+    crate::log::file("read", file, &encrypted);
+    let encrypted = encrypted
+        .map_err(|e| GitIdError::Usage(format!("Cannot read {}: {e}", file.display())))?;
+    let passphrase = read_passphrase(false)?;
+    let content = openssl_enc(&encrypted, &passphrase, true)?;
+    let bundle: Bundle = toml::from_str(&String::from_utf8_lossy(&content))
+        .map_err(|e| GitIdError::Config(format!("Failed to parse {}: {e}", file.display())))?;
+    let Some(bundled) = bundle.keys.into_iter().next() else {
+        return Err(GitIdError::Config(format!("{} holds no key", file.display())));
+    };
+
+    // The key file is written now, so its age comes from the bundle.
+    let key_created = bundle
+        .accounts
+        .iter()
+        .find(|a| a.username == username || account_id(a) == username)
+        .map(|a| a.key_created.clone())
+        .unwrap_or_default();
+    let mut accounts = load_accounts()?;
+    let acc = match find_account(username)? {
+        Some(acc) => acc,
+        None => {
+            let acc = bundle
+                .accounts
+                .into_iter()
+                .find(|a| a.username == username || account_id(a) == username)
+                .ok_or_else(|| {
+                    GitIdError::Usage(format!(
+                        "Account '{username}' not found here or in {}.",
+                        file.display()
+                    ))
+                })?;
+            print_info(&format!("Adding account '{}' from the bundle", account_id(&acc)));
+            accounts.push(acc.clone());
+            acc
+        }
+    };
+    let id = account_id(&acc);
+
+    let path = if acc.ssh_key.is_empty() || !acc.identity_agent.is_empty() {
+        bundled.path.clone()
+    } else {
+        acc.ssh_key.clone()
+    };
+    let dest = expand_home(&path);
+    if dest.exists()
+        && crate::log::read_to_string(&dest).is_ok_and(|c| c != bundled.private_key)
+    {
+        return Err(GitIdError::Usage(format!(
+            "{} already holds a different key - refusing to overwrite it.",
+            dest.display()
+        )));
+    }
+    write_key(&KeyFile { path: path.clone(), ..bundled }, dry_run)?;
+    if !dry_run {
+        fix_key_permissions(&dest);
+    }
+    // The key now lives in a file, so it goes to ssh-agent even if the
+    // account used another agent before.
+    add_key_to_agent(&dest, &Account { identity_agent: String::new(), ..acc }, dry_run);
+
+    for a in accounts.iter_mut().filter(|a| account_id(a) == id) {
+        a.ssh_key = path.clone();
+        a.identity_agent.clear();
+        a.key_created = key_created.clone();
+    }
+    save_accounts(&accounts, dry_run)?;
+    update_ssh_config(&accounts, dry_run)?;
+    print_ok(&format!("SSH key for '{id}' -> {}", dest.display()));
+    history::record("ssh-import", &id, "", &format!("bundle: {}", file.display()), dry_run)
 }
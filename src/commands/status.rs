@@ -1,57 +1,333 @@
-use crate::config::load_accounts;
-use crate::git::{get_git_config, get_remote_url, in_git_repo, repo_name};
-use crate::ui::{color, print_hdr};
+use crate::commands::rules::{broken_rule, describe, repo_rule};
+use crate::commands::use_cmd::remote_belongs_to;
+use crate::commands::with::current_timezone;
+use crate::config::{
+    account_id, commit_emails, has_email, load_accounts, load_settings, normalize_email,
+    stale_key_age,
+};
+use crate::error::{GitIdError, Result};
+use crate::git::{
+    RepoKind, config_file_path, display_url, get_git_config, get_remote_url, identity_override,
+    identity_scope, in_git_repo, list_remotes, parse_remote_url, repo_kind, repo_name,
+    strip_plus_address_enabled, worktree_config_enabled,
+};
+use crate::jj;
+use crate::models::{Account, EmailRule};
+use crate::output::{Format, Report, markdown_table, render};
+use crate::ui::{color, print_hdr, print_warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
-pub fn cmd_status() {
-    print_hdr("git-id status");
+/// `status`: the identity git would use, where it comes from, and the keys
+/// agents hold.
+#[derive(Serialize)]
+struct StatusReport {
+    global: Identity,
+    /// Only when the system gitconfig sets an email.
+    system: Option<Identity>,
+    config_files: BTreeMap<&'static str, ConfigFile>,
+    repo: Option<RepoStatus>,
+    /// Identity keys set by something that outranks the scope git-id writes.
+    overrides: Vec<Override>,
+    agent_keys: Vec<String>,
+    /// Keys of the `identity_agent` sockets accounts use.
+    identity_agents: Vec<AgentKeys>,
+    active_email: String,
+    active_scope: Option<&'static str>,
+    /// `TZ` commits from this shell are dated in; empty for local time.
+    commit_timezone: String,
+    /// The matched account's `commit_timezone`, when it has one.
+    account_timezone: Option<String>,
+    matched_account: Option<String>,
+    #[serde(skip)]
+    matched: Option<Account>,
+    /// An account whose email differs from the active one only by +alias.
+    #[serde(skip)]
+    near_match: Option<Account>,
+}
+
+#[derive(Serialize)]
+struct Identity {
+    name: String,
+    email: String,
+}
+
+#[derive(Serialize)]
+struct ConfigFile {
+    path: PathBuf,
+    from_env: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct RepoStatus {
+    name: String,
+    jj_workspace: bool,
+    #[serde(flatten)]
+    kind: RepoKind,
+    user_name: String,
+    user_email: String,
+    /// This worktree's own identity, under `extensions.worktreeConfig`.
+    worktree: Option<Identity>,
+    remotes: BTreeMap<String, String>,
+    /// The remotes looked at: the primary one, the one `--remote` names, or
+    /// all of them with `--all-remotes`.
+    shown_remotes: Vec<RemoteStatus>,
+    rule: Option<RuleStatus>,
+}
+
+#[derive(Serialize)]
+struct RemoteStatus {
+    name: String,
+    url: String,
+    /// Account owning the remote's repository or reaching it through its
+    /// SSH alias.
+    account: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RuleStatus {
+    #[serde(flatten)]
+    rule: EmailRule,
+    satisfied: bool,
+}
+
+#[derive(Serialize)]
+struct Override {
+    key: &'static str,
+    source: String,
+    scope: &'static str,
+}
+
+#[derive(Serialize)]
+struct AgentKeys {
+    socket: String,
+    keys: Vec<String>,
+}
+
+/// Names of the remotes `status` looks at, in the repo's order. By default
+/// the first one of `[remotes] primary` in settings.toml the repo has, else
+/// origin, else its first remote.
+fn shown_remotes(remotes: &[String], remote: Option<&str>, all: bool) -> Result<Vec<String>> {
+    if all {
+        return Ok(remotes.to_vec());
+    }
+    if let Some(name) = remote {
+        if !remotes.iter().any(|r| r == name) {
+            let known = if remotes.is_empty() { "none".to_string() } else { remotes.join(", ") };
+            return Err(GitIdError::Usage(format!(
+                "No remote named '{name}' in this repository (remotes: {known})"
+            )));
+        }
+        return Ok(vec![name.to_string()]);
+    }
+    let primary = load_settings()
+        .inspect_err(|e| print_warn(&format!("Ignoring the [remotes] settings: {e}")))
+        .map(|s| s.remotes.primary)
+        .unwrap_or_default();
+    let found = primary
+        .iter()
+        .chain(std::iter::once(&"origin".to_string()))
+        .find(|name| remotes.contains(name))
+        .or(remotes.first())
+        .cloned();
+    Ok(found.into_iter().collect())
+}
 
+pub fn cmd_status(format: Format, remote: Option<&str>, all_remotes: bool) -> Result<()> {
     let g_name = get_git_config("user.name", "global");
     let g_email = get_git_config("user.email", "global");
-    println!("\n  {}", color("bold", "Global git identity"));
-    println!(
-        "    name : {}",
-        if g_name.is_empty() { color("dim", "(not set)") } else { g_name.clone() }
-    );
-    println!(
-        "    email: {}",
-        if g_email.is_empty() { color("dim", "(not set)") } else { g_email.clone() }
-    );
-
-    if in_git_repo() {
-        let l_name = get_git_config("user.name", "local");
-        let l_email = get_git_config("user.email", "local");
-        let remote = get_remote_url("origin");
-        println!("\n  {}  ({})", color("bold", "Repo identity"), color("dim", &repo_name()));
-        println!(
-            "    name  : {}",
-            if l_name.is_empty() { color("dim", "(inherits global)") } else { l_name }
-        );
-        println!(
-            "    email : {}",
-            if l_email.is_empty() { color("dim", "(inherits global)") } else { l_email }
-        );
-        println!(
-            "    origin: {}",
-            if remote.is_empty() { color("dim", "(no remote)") } else { remote }
-        );
-    } else {
-        println!("\n  {}", color("dim", "(not in a git repository)"));
+    let in_repo = in_git_repo();
+    let l_email = if in_repo { get_git_config("user.email", "local") } else { String::new() };
+    let scope = identity_scope();
+    let active_email = get_git_config("user.email", scope);
+    let active_scope = (!active_email.is_empty()).then_some(scope);
+
+    let accounts = load_accounts()?;
+    let remote_names = if in_repo { list_remotes() } else { vec![] };
+    if !in_repo && (remote.is_some() || all_remotes) {
+        return Err(GitIdError::Usage("Not inside a git repository.".to_string()));
     }
+    let shown = shown_remotes(&remote_names, remote, all_remotes)?
+        .into_iter()
+        .map(|name| {
+            let url = get_remote_url(&name);
+            let account = parse_remote_url(&url).and_then(|(_, host, owner, _)| {
+                accounts.iter().find(|a| remote_belongs_to(a, &url, &host, &owner)).map(account_id)
+            });
+            RemoteStatus { name, url: display_url(&url), account }
+        })
+        .collect();
+
+    let s_email = get_git_config("user.email", "system");
+    let system = (!s_email.is_empty())
+        .then(|| Identity { name: get_git_config("user.name", "system"), email: s_email });
+
+    let repo = in_repo.then(|| RepoStatus {
+        name: repo_name(),
+        jj_workspace: jj::workspace_root().is_some(),
+        kind: repo_kind(),
+        user_name: get_git_config("user.name", "local"),
+        user_email: l_email.clone(),
+        worktree: worktree_config_enabled()
+            .then(|| Identity {
+                name: get_git_config("user.name", "worktree"),
+                email: get_git_config("user.email", "worktree"),
+            })
+            .filter(|id| !id.name.is_empty() || !id.email.is_empty()),
+        remotes: remote_names
+            .iter()
+            .map(|r| {
+                let url = display_url(&get_remote_url(r));
+                (r.clone(), url)
+            })
+            .collect(),
+        shown_remotes: shown,
+        rule: repo_rule()
+            .map(|rule| RuleStatus { rule, satisfied: broken_rule(&active_email).is_none() }),
+    });
 
-    print_ssh_agent_keys();
+    let config_files = ["global", "system"]
+        .into_iter()
+        .map(|scope| {
+            let (path, from_env) = config_file_path(scope);
+            (scope, ConfigFile { path, from_env })
+        })
+        .collect();
 
-    let active_email = if in_git_repo() {
-        let local = get_git_config("user.email", "local");
-        if local.is_empty() { g_email.clone() } else { local }
+    let mut sockets: Vec<String> = accounts
+        .iter()
+        .map(|a| a.identity_agent.clone())
+        .filter(|a| !a.is_empty())
+        .collect();
+    sockets.sort();
+    sockets.dedup();
+    let identity_agents = sockets
+        .into_iter()
+        .map(|socket| AgentKeys { keys: agent_key_lines(Some(Path::new(&socket))), socket })
+        .collect();
+
+    let strip_plus = strip_plus_address_enabled();
+    let wanted = normalize_email(&active_email, strip_plus);
+    let matched = if active_email.is_empty() {
+        None
+    } else {
+        accounts.iter().find(|a| has_email(a, &wanted, strip_plus)).cloned()
+    };
+    let loose = normalize_email(&active_email, true);
+    let near_match = if active_email.is_empty() || matched.is_some() {
+        None
     } else {
-        g_email.clone()
+        accounts.iter().find(|a| has_email(a, &loose, true)).cloned()
+    };
+
+    let report = StatusReport {
+        global: Identity { name: g_name, email: g_email },
+        system,
+        config_files,
+        repo,
+        overrides: identity_overrides(),
+        agent_keys: agent_key_lines(None),
+        identity_agents,
+        active_email,
+        active_scope,
+        commit_timezone: current_timezone(),
+        account_timezone: matched
+            .as_ref()
+            .map(|m| m.commit_timezone.trim_start_matches(':').to_string())
+            .filter(|tz| !tz.is_empty()),
+        matched_account: matched.as_ref().map(account_id),
+        matched,
+        near_match,
     };
+    render(&report, format)
+}
+
+impl Report for StatusReport {
+    fn human(&self) {
+        print_hdr("git-id status");
+        let or_unset = |v: &str, unset: &str| {
+            if v.is_empty() { color("dim", unset) } else { v.to_string() }
+        };
+
+        println!("\n  {}", color("bold", "Global git identity"));
+        println!("    name : {}", or_unset(&self.global.name, "(not set)"));
+        println!("    email: {}", or_unset(&self.global.email, "(not set)"));
+
+        if let Some(system) = &self.system {
+            println!("\n  {}", color("bold", "System git identity"));
+            println!("    name : {}", or_unset(&system.name, "(not set)"));
+            println!("    email: {}", system.email);
+        }
+
+        println!("\n  {}", color("bold", "Git config files"));
+        for (scope, file) in &self.config_files {
+            let origin = match file.from_env {
+                Some(var) => color("yellow", &format!("(from {var})")),
+                None => color("dim", "(default)"),
+            };
+            println!("    {scope:<6}: {}  {origin}", file.path.display());
+        }
+
+        if let Some(repo) = &self.repo {
+            let mut label = vec![repo.name.clone()];
+            if repo.jj_workspace {
+                label.push("jj workspace".to_string());
+            }
+            label.extend(repo.kind.markers());
+            let label = label.join(", ");
+            println!("\n  {}  ({})", color("bold", "Repo identity"), color("dim", &label));
+            println!("    name  : {}", or_unset(&repo.user_name, "(inherits global)"));
+            println!("    email : {}", or_unset(&repo.user_email, "(inherits global)"));
+            if let Some(worktree) = &repo.worktree {
+                println!("    worktree: {}", identity_line(worktree));
+            }
+            if repo.remotes.is_empty() {
+                println!("    remote: {}", color("dim", "(none - local-only repository)"));
+            }
+            for remote in &repo.shown_remotes {
+                let owner = match &remote.account {
+                    Some(id) if self.matched_account.as_ref().is_some_and(|m| m != id) => {
+                        color("yellow", &format!("(owned by {id}, not the active account)"))
+                    }
+                    Some(id) => color("dim", &format!("({id})")),
+                    None => color("dim", "(no account owns it)"),
+                };
+                println!("    {:<6}: {}  {owner}", remote.name, remote.url);
+            }
+            if let Some(status) = &repo.rule {
+                println!("    rule  : {} -> {}", describe(&status.rule), status.rule.email);
+                if !status.satisfied {
+                    let email = &self.active_email;
+                    println!(
+                        "    {} commits here would use {}, breaking this rule",
+                        color("yellow", "warning:"),
+                        if email.is_empty() { "no email" } else { email }
+                    );
+                }
+            }
+        } else {
+            println!("\n  {}", color("dim", "(not in a git repository)"));
+        }
+        for o in &self.overrides {
+            println!(
+                "\n  {} {} comes from {}, overriding the {} value",
+                color("yellow", "warning:"),
+                o.key,
+                o.source,
+                o.scope
+            );
+        }
+        let tz = or_unset(&self.commit_timezone, "local time");
+        println!("\n  {}: {tz}", color("bold", "Commit timezone"));
 
-    if !active_email.is_empty() {
-        let accounts = load_accounts();
-        let matched: Vec<_> = accounts.iter().filter(|a| a.email == active_email).collect();
-        if let Some(m) = matched.first() {
+        print_agent_keys("ssh-agent keys", &self.agent_keys);
+        for agent in &self.identity_agents {
+            print_agent_keys(&format!("Agent keys ({})", agent.socket), &agent.keys);
+        }
+
+        if let Some(m) = &self.matched {
             let host = if m.host.is_empty() { "github.com" } else { &m.host };
             println!(
                 "\n  {}: {}  {}",
@@ -59,32 +335,135 @@ pub fn cmd_status() {
                 color("green", &m.username),
                 color("dim", host)
             );
-        } else {
+            if !commit_emails(m).any(|e| *e == self.active_email) {
+                let note = format!("(account email is {}; matched ignoring case/alias)", m.email);
+                println!("    {}", color("dim", &note));
+            } else if m.email != self.active_email {
+                let note = format!("(an alternate email; the main one is {})", m.email);
+                println!("    {}", color("dim", &note));
+            }
+            if let Some((age, limit)) = stale_key_age(m) {
+                println!(
+                    "    {} the account's SSH key is {age} days old (rotate after {limit}); \
+                     run:\n      git-id ssh rotate {}",
+                    color("yellow", "hint:"),
+                    account_id(m)
+                );
+            }
+            if let Some(tz) = &self.account_timezone
+                && *tz != self.commit_timezone
+            {
+                println!(
+                    "    {} the account dates commits in {tz}; to do so in this shell run:\n      \
+                     eval \"$(git-id env)\"",
+                    color("yellow", "hint:")
+                );
+            }
+        } else if !self.active_email.is_empty() {
             println!("\n  {}", color("dim", "Active email does not match any configured account"));
+            if let Some(near) = &self.near_match {
+                println!(
+                    "    {} {} uses {} - differs only by +alias. To treat these as equal run:\n      git config --global git-id.stripPlusAddress true",
+                    color("yellow", "hint:"),
+                    near.username,
+                    near.email
+                );
+            }
         }
+        println!();
     }
-    println!();
-}
 
-fn print_ssh_agent_keys() {
-    let result = Command::new("ssh-add")
-        .arg("-l")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output();
-    println!("\n  {}", color("bold", "ssh-agent keys"));
-    match result {
-        Ok(out) if out.status.success() => {
-            let stdout = String::from_utf8_lossy(&out.stdout);
-            let lines: Vec<&str> = stdout.trim().lines().collect();
-            if lines.is_empty() {
-                println!("    {}", color("dim", "(no keys loaded, or agent not running)"));
-            } else {
-                for line in lines {
-                    println!("    {} {}", color("green", "OK"), line);
-                }
+    fn markdown(&self) -> String {
+        let mut rows = vec![
+            vec!["Global identity".to_string(), identity_line(&self.global)],
+        ];
+        if let Some(system) = &self.system {
+            rows.push(vec!["System identity".to_string(), identity_line(system)]);
+        }
+        if let Some(repo) = &self.repo {
+            rows.push(vec!["Repository".to_string(), repo.name.clone()]);
+            let local = Identity { name: repo.user_name.clone(), email: repo.user_email.clone() };
+            rows.push(vec!["Repo identity".to_string(), identity_line(&local)]);
+            if let Some(worktree) = &repo.worktree {
+                rows.push(vec!["Worktree identity".to_string(), identity_line(worktree)]);
+            }
+            for (name, url) in &repo.remotes {
+                rows.push(vec![format!("Remote {name}"), url.clone()]);
+            }
+            if repo.remotes.is_empty() {
+                rows.push(vec!["Remotes".to_string(), "none (local-only)".to_string()]);
+            }
+            for remote in &repo.shown_remotes {
+                let account = remote.account.clone().unwrap_or_default();
+                rows.push(vec![format!("Account of {}", remote.name), account]);
+            }
+            if let Some(status) = &repo.rule {
+                let state = if status.satisfied { "satisfied" } else { "broken" };
+                let rule = format!("{} -> {} ({state})", describe(&status.rule), status.rule.email);
+                rows.push(vec!["Email rule".to_string(), rule]);
             }
         }
-        _ => println!("    {}", color("dim", "(no keys loaded, or agent not running)")),
+        for o in &self.overrides {
+            rows.push(vec![format!("{} override", o.key), o.source.clone()]);
+        }
+        let scope = self.active_scope.map(|s| format!(" ({s})")).unwrap_or_default();
+        rows.push(vec!["Active email".to_string(), format!("{}{scope}", self.active_email)]);
+        let tz = if self.commit_timezone.is_empty() { "local time" } else { &self.commit_timezone };
+        rows.push(vec!["Commit timezone".to_string(), tz.to_string()]);
+        rows.push(vec![
+            "Matched account".to_string(),
+            self.matched_account.clone().unwrap_or_default(),
+        ]);
+        rows.push(vec!["ssh-agent keys".to_string(), self.agent_keys.join("\n")]);
+        format!("## git-id status\n\n{}", markdown_table(&["Field", "Value"], &rows))
+    }
+}
+
+fn identity_line(identity: &Identity) -> String {
+    match (identity.name.is_empty(), identity.email.is_empty()) {
+        (true, true) => String::new(),
+        (false, true) => identity.name.clone(),
+        (true, false) => format!("<{}>", identity.email),
+        _ => format!("{} <{}>", identity.name, identity.email),
+    }
+}
+
+/// Identity keys set by something with higher precedence than the scope
+/// git-id wrote (the worktree's or repo's config inside a repo, global
+/// otherwise).
+fn identity_overrides() -> Vec<Override> {
+    let scope = identity_scope();
+    ["user.name", "user.email"]
+        .into_iter()
+        .filter_map(|key| {
+            identity_override(key, scope).map(|source| Override { key, source, scope })
+        })
+        .collect()
+}
+
+/// Fingerprint lines from `ssh-add -l` for the default or given agent.
+fn agent_key_lines(sock: Option<&Path>) -> Vec<String> {
+    let mut cmd = Command::new("ssh-add");
+    cmd.arg("-l").stdout(Stdio::piped()).stderr(Stdio::null());
+    if let Some(sock) = sock {
+        cmd.env("SSH_AUTH_SOCK", sock);
+    }
+    match crate::log::output(&mut cmd) {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout)
+            .trim()
+            .lines()
+            .map(ToString::to_string)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn print_agent_keys(title: &str, lines: &[String]) {
+    println!("\n  {}", color("bold", title));
+    if lines.is_empty() {
+        println!("    {}", color("dim", "(no keys loaded, or agent not running)"));
+    }
+    for line in lines {
+        println!("    {} {}", color("green", "OK"), line);
     }
 }
@@ -0,0 +1,212 @@
+use crate::config::{account_id, find_account, owns};
+use crate::forge::{api_base, api_call, can_push, is_github, sso_url, unauthorized_sso_orgs};
+use crate::git::{get_remote_url, in_git_repo, parse_remote_url};
+use crate::models::Account;
+use crate::tokens::account_token;
+use crate::error::{GitIdError, Result};
+use crate::ui::{print_err, print_info, print_ok, print_warn};
+use std::process::{Command, Stdio};
+
+/// Prints the HTTPS token for an account, minting a GitHub App installation
+/// token when the account is backed by an app.
+pub fn cmd_token(username: &str) -> Result<()> {
+    let acc = find_account(username)?.ok_or_else(|| {
+        GitIdError::Usage(format!("Account '{username}' not found. Run: git-id list"))
+    })?;
+    match account_token(&acc) {
+        Ok(token) if token.is_empty() => Err(GitIdError::Usage(format!(
+            "Account '{username}' has no HTTPS token configured."
+        ))),
+        Ok(token) => {
+            println!("{token}");
+            Ok(())
+        }
+        Err(e) => Err(GitIdError::Io(format!("Could not get a token for '{username}': {e}"))),
+    }
+}
+
+/// Asks the account's GitHub host whether it accepts the token, and which
+/// organizations' SAML single sign-on it still has to be authorized for.
+/// Such a token works for everything else, so a push to one of those
+/// organizations fails with a bare 403; this names the page that fixes it.
+pub fn cmd_token_check(username: &str, open: bool) -> Result<()> {
+    let acc = find_account(username)?.ok_or_else(|| {
+        GitIdError::Usage(format!("Account '{username}' not found. Run: git-id list"))
+    })?;
+    let id = account_id(&acc);
+    if !is_github(&acc) {
+        print_info(&format!("Token checks are only supported for GitHub hosts - skipping '{id}'"));
+        return Ok(());
+    }
+    let token = match account_token(&acc) {
+        Ok(token) if token.is_empty() => {
+            return Err(GitIdError::Usage(format!("Account '{id}' has no HTTPS token configured.")));
+        }
+        Ok(token) => token,
+        Err(e) => return Err(GitIdError::Io(format!("Could not get a token for '{id}': {e}"))),
+    };
+
+    // Installation tokens act for a GitHub App, which has no `/user`.
+    let probe = if acc.app_id.is_empty() { "/user" } else { "/installation/repositories" };
+    let response = api_call(&acc, &token, "GET", probe, None)
+        .map_err(|e| GitIdError::Io(format!("Cannot reach {}: {e}", api_base(&acc))))?;
+    let message = response.json["message"].as_str().unwrap_or("request failed");
+    let mut sso: Vec<(String, String)> = vec![];
+    match response.status {
+        200..=299 => {
+            let login = response.json["login"].as_str().map(|l| format!(" (signed in as {l})"));
+            print_ok(&format!("Token for '{id}' is valid{}", login.unwrap_or_default()));
+            if let Some(missing) = response.scopes.as_deref().and_then(missing_push_scope) {
+                print_warn(&missing);
+            }
+            if acc.app_id.is_empty() {
+                match unauthorized_sso_orgs(&acc, &token) {
+                    Ok(orgs) => sso = orgs,
+                    Err(e) => print_warn(&format!("Cannot check single sign-on: {e}")),
+                }
+            }
+        }
+        401 => {
+            print_err(&format!(
+                "Token for '{id}' is invalid, expired or revoked (HTTP 401: {message})"
+            ));
+            eprintln!("  Fix with: create a new token, then: git-id edit {id} --token-stdin");
+            return Err(GitIdError::CheckFailed);
+        }
+        status => match sso_url(&response.sso) {
+            // SAML enforced for the whole instance (GitHub Enterprise Server).
+            Some(url) => {
+                let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+                sso.push((host.to_string(), url));
+            }
+            None => {
+                print_err(&format!("Token for '{id}' was refused (HTTP {status}: {message})"));
+                return Err(GitIdError::CheckFailed);
+            }
+        },
+    }
+    if sso.is_empty() {
+        return Ok(());
+    }
+    for (org, url) in &sso {
+        print_err(&format!(
+            "Token for '{id}' is not authorized for SAML single sign-on in '{org}'"
+        ));
+        eprintln!("  Authorize it at: {url}");
+        if open {
+            open_in_browser(url);
+        }
+    }
+    if !open {
+        eprintln!("  (or open the page with: git-id token check {id} --open)");
+    }
+    Err(GitIdError::CheckFailed)
+}
+
+/// Why a classic token with `scopes` cannot push, if it cannot: `repo`
+/// covers every repository, `public_repo` only public ones.
+fn missing_push_scope(scopes: &[String]) -> Option<String> {
+    if scopes.iter().any(|s| s == "repo") {
+        return None;
+    }
+    if scopes.iter().any(|s| s == "public_repo") {
+        return Some(
+            "The token has only the `public_repo` scope - pushes to private repositories \
+             will fail with 403"
+                .to_string(),
+        );
+    }
+    let has = if scopes.is_empty() { "none".to_string() } else { scopes.join(", ") };
+    Some(format!("The token lacks the `repo` scope needed to push (it has: {has})"))
+}
+
+/// Checks a token before `add` saves it for `acc`, so a bad one fails now
+/// rather than with a 403 at the first push. GitHub must accept it, and a
+/// classic token needs the `repo` scope. A fine-grained token lists no
+/// scopes, so when the current repo belongs to the account, its push
+/// permission there is tried instead. Returns why the token should be
+/// refused; a host that cannot be reached, or one that is not GitHub, only
+/// gets a warning.
+pub fn new_token_problem(acc: &Account) -> Option<String> {
+    if acc.https_token.is_empty() || !acc.app_id.is_empty() || !is_github(acc) {
+        return None;
+    }
+    let token = match crate::secrets::resolve(&acc.https_token) {
+        Ok(token) => token,
+        Err(e) => {
+            print_warn(&format!("Cannot check the token: {e}"));
+            return None;
+        }
+    };
+    let response = match api_call(acc, &token, "GET", "/user", None) {
+        Ok(response) => response,
+        Err(e) => {
+            print_warn(&format!("Cannot reach {} to check the token: {e}", api_base(acc)));
+            return None;
+        }
+    };
+    match response.status {
+        200..=299 => {}
+        401 => return Some("GitHub rejects the token: it is invalid, expired or revoked".into()),
+        status => {
+            let message = response.json["message"].as_str().unwrap_or("request failed");
+            print_warn(&format!("Cannot check the token (HTTP {status}: {message})"));
+            return None;
+        }
+    }
+    let login = response.json["login"].as_str().unwrap_or_default();
+    if !login.eq_ignore_ascii_case(&acc.username) {
+        print_warn(&format!("The token belongs to '{login}', not '{}'", acc.username));
+    }
+    match response.scopes.as_deref().map(|scopes| (scopes, missing_push_scope(scopes))) {
+        Some((scopes, None)) => {
+            print_ok(&format!("Token accepted (scopes: {})", scopes.join(", ")));
+        }
+        Some((scopes, Some(missing))) if scopes.iter().any(|s| s == "public_repo") => {
+            print_warn(&missing);
+        }
+        Some((_, Some(missing))) => return Some(missing),
+        None => return fine_grained_problem(acc, &token),
+    }
+    None
+}
+
+/// Tries a fine-grained token's push permission on the current repo when
+/// the account owns its origin; elsewhere there is nothing to try it on.
+fn fine_grained_problem(acc: &Account, token: &str) -> Option<String> {
+    let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    let origin = in_git_repo().then(|| parse_remote_url(&get_remote_url("origin"))).flatten();
+    let Some((_, _, owner, repo)) = origin.filter(|(_, h, owner, _)| {
+        h.eq_ignore_ascii_case(host) && owns(acc, h, owner)
+    }) else {
+        print_info(
+            "Token accepted. GitHub does not list a fine-grained token's permissions - \
+             make sure it grants Contents: Read and write on the repositories you push to",
+        );
+        return None;
+    };
+    match can_push(acc, token, &owner, &repo) {
+        Ok(true) => print_ok(&format!("Token accepted and can push to {owner}/{repo}")),
+        Ok(false) => {
+            return Some(format!(
+                "The token cannot push to {owner}/{repo} - a fine-grained token needs \
+                 Contents: Read and write on it"
+            ));
+        }
+        Err(e) => print_warn(&format!("Cannot check push access to {owner}/{repo}: {e}")),
+    }
+    None
+}
+
+fn open_in_browser(url: &str) {
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    let spawned = Command::new(opener)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(e) = spawned {
+        print_warn(&format!("Cannot run {opener} ({e}) - open the URL yourself"));
+    }
+}
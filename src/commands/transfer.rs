@@ -0,0 +1,141 @@
+use crate::commands::credential::{configure_client_cert, configure_helper};
+use crate::commands::use_cmd::{account_url, excluded_remotes, remote_belongs_to};
+use crate::config::{account_id, find_account};
+use crate::git::{
+    get_remote_url, in_git_repo, list_remotes, parse_remote_url, repo_name, run_git,
+    set_git_config, set_remote_url, unpushed_commit_count,
+};
+use crate::error::{GitIdError, Result};
+use crate::history;
+use crate::models::Account;
+use crate::ui::{print_info, print_ok};
+
+pub fn cmd_transfer(from: &str, to: &str, reauthor: usize, dry_run: bool) -> Result<()> {
+    let from_acc = find_account(from)?.ok_or_else(|| {
+        GitIdError::Usage(format!("Account '{from}' not found. Run: git-id list"))
+    })?;
+    let to_acc = find_account(to)?.ok_or_else(|| {
+        GitIdError::Usage(format!("Account '{to}' not found. Run: git-id list"))
+    })?;
+    let to_acc = crate::secrets::resolved(&to_acc)?;
+    if account_id(&from_acc) == account_id(&to_acc) {
+        return Err(GitIdError::Usage("Source and target account are the same.".to_string()));
+    }
+    if !in_git_repo() {
+        return Err(GitIdError::Usage("Not inside a git repository.".to_string()));
+    }
+    if reauthor > 0 {
+        let unpushed = unpushed_commit_count();
+        if reauthor > unpushed {
+            return Err(GitIdError::Usage(format!(
+                "Only {unpushed} unpushed commit(s) on HEAD - refusing to rewrite {reauthor}."
+            )));
+        }
+        let merges = merges_in_last(reauthor);
+        if merges > 0 {
+            return Err(GitIdError::Usage(format!(
+                "The last {reauthor} commit(s) include {merges} merge(s) - re-authoring would \
+                 flatten them. Pick a range without merges."
+            )));
+        }
+    }
+
+    set_git_config("user.name", &to_acc.username, "local", dry_run);
+    set_git_config("user.email", &to_acc.email, "local", dry_run);
+    print_ok(&format!(
+        "Git identity (local): {} <{}>",
+        to_acc.username, to_acc.email
+    ));
+
+    configure_helper(&to_acc, "local", dry_run);
+    configure_client_cert(&to_acc, "local", dry_run);
+
+    let moved = transfer_remotes(&from_acc, &to_acc, dry_run);
+    if moved == 0 {
+        print_info(&format!("No remotes used '{}' - none rewritten", account_id(&from_acc)));
+    }
+
+    if reauthor > 0 {
+        reauthor_commits(reauthor, dry_run)?;
+    }
+    crate::repos::remember_current_repo(dry_run);
+
+    history::record(
+        "transfer",
+        &account_id(&to_acc),
+        &repo_name(),
+        &format!("from {}", account_id(&from_acc)),
+        dry_run,
+    )?;
+    if !dry_run {
+        print_ok(&format!(
+            "Repo '{}' transferred from '{}' to '{}'",
+            repo_name(),
+            account_id(&from_acc),
+            account_id(&to_acc)
+        ));
+    }
+    Ok(())
+}
+
+/// Points every remote that belongs to `from` at `to`, keeping owner and
+/// repo and the current URL format. Returns the number of remotes rewritten.
+fn transfer_remotes(from: &Account, to: &Account, dry_run: bool) -> usize {
+    let excluded = excluded_remotes(from);
+    let mut moved = 0;
+    for remote in list_remotes() {
+        if excluded.contains(&remote) {
+            continue;
+        }
+        let url = get_remote_url(&remote);
+        let Some((fmt, host, owner, repo)) = parse_remote_url(&url) else {
+            continue;
+        };
+        if !remote_belongs_to(from, &url, &host, &owner) {
+            continue;
+        }
+        let new_url = account_url(to, &fmt, &host, &owner, &repo, false, false);
+        set_remote_url(&remote, &new_url, dry_run);
+        moved += 1;
+    }
+    moved
+}
+
+/// The commit `rebase` starts from to rewrite the last `count` commits:
+/// `HEAD~count`, or `--root` when that reaches the first commit.
+fn rebase_base(count: usize) -> String {
+    let (_, total, _) = run_git(&["rev-list", "--count", "--first-parent", "HEAD"]);
+    if total.parse::<usize>().unwrap_or(0) == count {
+        "--root".to_string()
+    } else {
+        format!("HEAD~{count}")
+    }
+}
+
+/// Number of merge commits among the last `count` first-parent commits.
+fn merges_in_last(count: usize) -> usize {
+    let base = rebase_base(count);
+    let range = if base == "--root" { "HEAD".to_string() } else { format!("{base}..HEAD") };
+    let (_, out, _) = run_git(&["rev-list", "--count", "--merges", &range]);
+    out.parse().unwrap_or(0)
+}
+
+/// Resets the author of the last `count` commits to the current identity.
+fn reauthor_commits(count: usize, dry_run: bool) -> Result<()> {
+    let exec = "git commit --amend --no-edit --reset-author --allow-empty";
+    let base = rebase_base(count);
+    if dry_run {
+        print_info(&format!("[dry-run] git rebase --exec {exec:?} {base}"));
+        return Ok(());
+    }
+    let (code, _, errmsg) = run_git(&["rebase", "--exec", exec, &base]);
+    if code != 0 {
+        let (_, state, _) = run_git(&["rev-parse", "--git-path", "rebase-merge"]);
+        if !state.is_empty() && std::path::Path::new(&state).exists() {
+            print_info("Resolve with 'git rebase --continue' or 'git rebase --abort'.");
+        }
+        return Err(GitIdError::Io(format!("Rewriting commit authors failed: {errmsg}")));
+    }
+    print_ok(&format!("Re-authored the last {count} commit(s)"));
+    Ok(())
+}
@@ -0,0 +1,363 @@
+use crate::cli::UseArgs;
+use crate::commands::edit::{edit_interactive, save_edit};
+use crate::commands::ssh::{cmd_ssh_rotate, cmd_ssh_test};
+use crate::commands::use_cmd::cmd_use;
+use crate::config::{
+    account_id, expand_home, find_account_exact, has_email, load_accounts, normalize_email,
+};
+use crate::error::{GitIdError, Result};
+use crate::git::{
+    display_url, get_git_config, get_remote_url, identity_scope, in_git_repo, list_remotes,
+    repo_name, strip_plus_address_enabled, worktree_config_enabled,
+};
+use crate::models::Account;
+use crate::ssh::{agent_public_keys, key_body, onepassword_agent_socket};
+use crate::ui::{print_err, print_hdr};
+use dialoguer::Confirm;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::io::IsTerminal;
+
+/// One account as the dashboard shows it.
+struct Entry {
+    acc: Account,
+    id: String,
+    /// "ok", "missing", "agent" (served by `identity_agent`) or "-".
+    key: &'static str,
+    in_agent: bool,
+    /// Scopes whose `user.email` is the account's: worktree, local, global.
+    active: Vec<&'static str>,
+}
+
+/// The current repository: its name, the identity commits get and where
+/// that comes from, and its remotes.
+struct RepoView {
+    name: String,
+    identity: String,
+    remotes: Vec<(String, String)>,
+}
+
+/// What a key press asks for; run outside the dashboard, on the normal
+/// screen, so commands can print and prompt as usual.
+enum Action {
+    Use(&'static str),
+    Edit,
+    Rotate,
+    Test,
+}
+
+impl Action {
+    fn label(&self) -> &'static str {
+        match self {
+            Action::Use("global") => "use --global",
+            Action::Use("worktree") => "use --worktree",
+            Action::Use(_) => "use",
+            Action::Edit => "edit",
+            Action::Rotate => "ssh rotate",
+            Action::Test => "ssh test",
+        }
+    }
+}
+
+struct Dashboard {
+    entries: Vec<Entry>,
+    table: TableState,
+    repo: Option<RepoView>,
+    global: String,
+    message: String,
+}
+
+impl Dashboard {
+    fn load() -> Result<Dashboard> {
+        let mut dashboard = Dashboard {
+            entries: vec![],
+            table: TableState::default(),
+            repo: None,
+            global: String::new(),
+            message: String::new(),
+        };
+        dashboard.reload()?;
+        Ok(dashboard)
+    }
+
+    /// Reads accounts, keys and git config again, keeping the selection.
+    fn reload(&mut self) -> Result<()> {
+        let strip_plus = strip_plus_address_enabled();
+        let in_repo = in_git_repo();
+        let mut scopes = vec![];
+        if in_repo && worktree_config_enabled() {
+            scopes.push("worktree");
+        }
+        if in_repo {
+            scopes.push("local");
+        }
+        scopes.push("global");
+        let emails: Vec<(&'static str, String)> = scopes
+            .into_iter()
+            .map(|scope| {
+                (scope, normalize_email(&get_git_config("user.email", scope), strip_plus))
+            })
+            .filter(|(_, email)| !email.is_empty())
+            .collect();
+
+        let mut agent_keys = agent_public_keys(None);
+        if let Some(sock) = onepassword_agent_socket() {
+            agent_keys.extend(agent_public_keys(Some(&sock)));
+        }
+        let agent_keys: Vec<String> = agent_keys.iter().map(|k| key_body(k)).collect();
+
+        self.entries = load_accounts()?
+            .into_iter()
+            .map(|acc| {
+                let path = expand_home(&acc.ssh_key);
+                let key = if !acc.identity_agent.is_empty() {
+                    "agent"
+                } else if acc.ssh_key.is_empty() {
+                    "-"
+                } else if path.exists() {
+                    "ok"
+                } else {
+                    "missing"
+                };
+                let body = crate::log::read_to_string(path.with_extension("pub"))
+                    .map(|line| key_body(&line))
+                    .unwrap_or_default();
+                let active = emails
+                    .iter()
+                    .filter(|(_, active)| has_email(&acc, active, strip_plus))
+                    .map(|(scope, _)| *scope)
+                    .collect();
+                Entry {
+                    id: account_id(&acc),
+                    key,
+                    in_agent: !body.is_empty() && agent_keys.contains(&body),
+                    active,
+                    acc,
+                }
+            })
+            .collect();
+        let last = self.entries.len().checked_sub(1);
+        let selected = self.table.selected().unwrap_or(0);
+        self.table.select(last.map(|last| selected.min(last)));
+
+        let identity = |scope: &str| {
+            let name = get_git_config("user.name", scope);
+            let email = get_git_config("user.email", scope);
+            if email.is_empty() { "(not set)".to_string() } else { format!("{name} <{email}>") }
+        };
+        self.global = identity("global");
+        self.repo = in_repo.then(|| {
+            let scope = identity_scope();
+            RepoView {
+                name: repo_name(),
+                identity: format!("{} ({scope})", identity(scope)),
+                remotes: list_remotes()
+                    .into_iter()
+                    .map(|remote| {
+                        let url = display_url(&get_remote_url(&remote));
+                        (remote, url)
+                    })
+                    .collect(),
+            }
+        });
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        self.table.selected().and_then(|i| self.entries.get(i))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let remotes = self.repo.as_ref().map_or(0, |repo| repo.remotes.len().max(1));
+        let [header, accounts, repo, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(5),
+            Constraint::Length(remotes as u16 + 3),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let dim = Style::default().fg(Color::DarkGray);
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                Span::styled(" git-id ", bold),
+                Span::styled(format!(" global: {}", self.global), dim),
+            ])),
+            header,
+        );
+
+        let rows: Vec<Row> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let key_color = match entry.key {
+                    "ok" | "agent" => Color::Green,
+                    "missing" => Color::Red,
+                    _ => Color::DarkGray,
+                };
+                let (agent, agent_color) =
+                    if entry.in_agent { ("loaded", Color::Green) } else { ("-", Color::DarkGray) };
+                Row::new(vec![
+                    Cell::from(entry.id.clone()),
+                    Cell::from(entry.acc.email.clone()),
+                    Cell::from(entry.key).style(Style::default().fg(key_color)),
+                    Cell::from(agent).style(Style::default().fg(agent_color)),
+                    Cell::from(entry.active.join(", ")).style(Style::default().fg(Color::Cyan)),
+                ])
+            })
+            .collect();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(2),
+                Constraint::Fill(3),
+                Constraint::Length(8),
+                Constraint::Length(7),
+                Constraint::Length(22),
+            ],
+        )
+        .header(Row::new(["Account", "Email", "Key", "Agent", "Active"]).style(bold))
+        .block(Block::bordered().title(format!(" Accounts ({}) ", self.entries.len())))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+        frame.render_stateful_widget(table, accounts, &mut self.table);
+
+        let (title, lines) = match &self.repo {
+            Some(view) => {
+                let mut lines = vec![Line::from(format!("identity: {}", view.identity))];
+                lines.extend(
+                    view.remotes.iter().map(|(name, url)| Line::from(format!("{name:<8}  {url}"))),
+                );
+                if view.remotes.is_empty() {
+                    lines.push(Line::styled("(no remotes - local-only repository)", dim));
+                }
+                (format!(" Repository {} ", view.name), lines)
+            }
+            None => {
+                (" Repository ".to_string(), vec![Line::styled("(not in a git repository)", dim)])
+            }
+        };
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), repo);
+
+        let keys = "↑/↓ select  u use here  w use in worktree  g use globally  \
+                    e edit  r rotate key  t test key  q quit";
+        frame.render_widget(
+            Paragraph::new(vec![Line::styled(keys, dim), Line::from(self.message.clone())]),
+            footer,
+        );
+    }
+}
+
+/// Full-screen view of every account, its key and agent state and where it
+/// is active, plus the current repo, with keys to act on the selected one.
+pub fn cmd_tui(dry_run: bool) -> Result<()> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err(GitIdError::Usage("git-id tui needs a terminal".to_string()));
+    }
+    let mut dashboard = Dashboard::load()?;
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut dashboard, dry_run);
+    ratatui::restore();
+    result
+}
+
+fn run(terminal: &mut DefaultTerminal, dashboard: &mut Dashboard, dry_run: bool) -> Result<()> {
+    let io_err = |e: std::io::Error| GitIdError::Io(format!("Terminal error: {e}"));
+    loop {
+        terminal.draw(|frame| dashboard.draw(frame)).map_err(io_err)?;
+        let Event::Key(key) = event::read().map_err(io_err)? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let action = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => {
+                dashboard.table.select_next();
+                continue;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                dashboard.table.select_previous();
+                continue;
+            }
+            KeyCode::Char('u') => Action::Use("local"),
+            KeyCode::Char('w') => Action::Use("worktree"),
+            KeyCode::Char('g') => Action::Use("global"),
+            KeyCode::Char('e') => Action::Edit,
+            KeyCode::Char('r') => Action::Rotate,
+            KeyCode::Char('t') => Action::Test,
+            _ => continue,
+        };
+        let Some(entry) = dashboard.selected() else {
+            dashboard.message = "No account selected".to_string();
+            continue;
+        };
+        if matches!(action, Action::Use("local" | "worktree")) && dashboard.repo.is_none() {
+            dashboard.message = "Not inside a git repository".to_string();
+            continue;
+        }
+        let id = entry.id.clone();
+
+        ratatui::restore();
+        print_hdr(&format!("git-id {} {id}", action.label()));
+        let outcome = perform(&action, &id, dry_run);
+        if let Err(e) = &outcome
+            && !matches!(e, GitIdError::CheckFailed)
+        {
+            print_err(&e.to_string());
+        }
+        println!("\nPress Enter to return to the dashboard");
+        let _ = std::io::stdin().read_line(&mut String::new());
+        *terminal = ratatui::init();
+
+        dashboard.message = match outcome {
+            Ok(()) => format!("{} {id}: done", action.label()),
+            Err(_) => format!("{} {id}: failed", action.label()),
+        };
+        dashboard.reload()?;
+    }
+}
+
+fn perform(action: &Action, id: &str, dry_run: bool) -> Result<()> {
+    match action {
+        Action::Use(scope) => {
+            let args = UseArgs {
+                username: Some(id.to_string()),
+                global: *scope == "global",
+                worktree: *scope == "worktree",
+                ..Default::default()
+            };
+            cmd_use(&args, dry_run)
+        }
+        Action::Edit => {
+            let old = find_account_exact(id)?
+                .ok_or_else(|| GitIdError::Usage(format!("Account '{id}' not found.")))?;
+            let mut acc = old.clone();
+            edit_interactive(&mut acc)?;
+            save_edit(&old, acc, dry_run)
+        }
+        Action::Rotate => {
+            let confirmed = Confirm::new()
+                .with_prompt(format!("Replace the SSH key of '{id}' with a new one?"))
+                .default(false)
+                .interact()
+                .unwrap_or(false);
+            if !confirmed {
+                return Err(GitIdError::Usage("Aborted.".to_string()));
+            }
+            let passphrase = Confirm::new()
+                .with_prompt("Protect the new key with a passphrase?")
+                .default(false)
+                .interact()
+                .unwrap_or(false);
+            cmd_ssh_rotate(id, None, passphrase, dry_run)
+        }
+        Action::Test => cmd_ssh_test(id),
+    }
+}
@@ -0,0 +1,473 @@
+use crate::commands::completions::{installed_scripts, strip_rc_block};
+use crate::commands::credential::has_own_helper;
+use crate::commands::dirs::dir_rules;
+use crate::commands::hook::{installed_hooks, remove_guard};
+use crate::config::{
+    accounts_file, cache_dir, config_dir, dirs_home, expand_home, load_accounts,
+};
+use crate::error::{GitIdError, Result};
+use crate::git::{get_git_config_regexp, in_git_repo, set_git_config_values, unset_git_config};
+use crate::repos::{load_repos, repo_root};
+use crate::ssh::{
+    all_ssh_config, managed_config_path, ssh_config_path, ssh_dir, stanza_formats, strip_include,
+    without_stanzas,
+};
+use crate::ui::{backup, backups_of, color, print_hdr, print_info, print_ok, print_warn};
+use dialoguer::Input;
+use std::path::{Path, PathBuf};
+
+/// Everything git-id created that `uninstall` will remove, gathered up front
+/// so it can be shown before anything is touched.
+struct Plan {
+    /// Account ids with a managed stanza.
+    stanzas: Vec<String>,
+    /// Whether ~/.ssh/config includes the managed stanza file.
+    ssh_include: bool,
+    /// Directories whose `includeIf` rule points into the config dir.
+    dir_rules: Vec<String>,
+    global_helper: bool,
+    /// `git-id.*` keys in the global gitconfig.
+    global_settings: Vec<String>,
+    /// Repositories and the git-id keys in their local config.
+    repos: Vec<(PathBuf, Vec<String>)>,
+    /// Hooks holding the checks of `hook install`.
+    hooks: Vec<PathBuf>,
+    completions: Vec<PathBuf>,
+    rc_files: Vec<PathBuf>,
+    backups: Vec<PathBuf>,
+    /// SSH keys of the accounts; never deleted, only reported.
+    keys: Vec<String>,
+}
+
+pub fn cmd_uninstall(
+    repos: &[PathBuf],
+    keep_backups: bool,
+    yes: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let plan = gather(repos)?;
+    print_plan(&plan, keep_backups);
+
+    if !yes && !dry_run {
+        let ans: String = Input::new()
+            .with_prompt("\n  Remove all of this? [y/N]")
+            .default("N".to_string())
+            .interact_text()
+            .unwrap_or_default();
+        if ans.to_lowercase() != "y" {
+            print_info("Aborted.");
+            return Ok(());
+        }
+    }
+
+    let mut removed: Vec<String> = vec![];
+    let mut failed: Vec<String> = vec![];
+
+    if !plan.stanzas.is_empty() || plan.ssh_include {
+        remove_stanzas(&plan.stanzas, keep_backups, dry_run)?;
+        removed.push(format!("{} SSH config stanza(s)", plan.stanzas.len()));
+    }
+    for dir in &plan.dir_rules {
+        unset_git_config(&format!("includeIf.gitdir:{dir}.path"), "global", dry_run);
+        removed.push(format!("directory rule {dir}"));
+    }
+    if plan.global_helper {
+        set_git_config_values("credential.helper", &[], "global", dry_run);
+        removed.push("global credential helper".to_string());
+    }
+    for key in &plan.global_settings {
+        set_git_config_values(key, &[], "global", dry_run);
+        removed.push(format!("global {key}"));
+    }
+    let start_dir = std::env::current_dir().ok();
+    for (repo, keys) in &plan.repos {
+        if std::env::set_current_dir(repo).is_err() {
+            failed.push(format!("settings in {}", repo.display()));
+            continue;
+        }
+        for key in keys {
+            set_git_config_values(key, &[], "local", dry_run);
+        }
+        removed.push(format!("{} in {}", keys.join(", "), repo.display()));
+    }
+    if let Some(dir) = start_dir {
+        let _ = std::env::set_current_dir(dir);
+    }
+    for hook in &plan.hooks {
+        match remove_guard(hook, dry_run) {
+            Ok(()) => removed.push(format!("identity check in {}", hook.display())),
+            Err(e) => failed.push(format!("{}: {e}", hook.display())),
+        }
+    }
+    for script in &plan.completions {
+        delete_file(script, dry_run, &mut removed, &mut failed);
+    }
+    for rc in &plan.rc_files {
+        match strip_rc(rc, keep_backups, dry_run) {
+            Ok(()) => removed.push(format!("completion lines in {}", rc.display())),
+            Err(e) => failed.push(format!("{}: {e}", rc.display())),
+        }
+    }
+    if !keep_backups {
+        for b in &plan.backups {
+            delete_file(b, dry_run, &mut removed, &mut failed);
+        }
+    }
+    remove_config_dir(keep_backups, dry_run, &mut removed, &mut failed);
+    remove_cache_dir(dry_run, &mut removed, &mut failed);
+
+    print_report(&plan, keep_backups, dry_run, &removed, &failed);
+    if !failed.is_empty() {
+        return Err(GitIdError::CheckFailed);
+    }
+    Ok(())
+}
+
+fn gather(repos: &[PathBuf]) -> Result<Plan> {
+    let content = all_ssh_config();
+    let stanzas = stanza_formats(&content).into_iter().map(|(id, _)| id).collect();
+
+    let cfg_dir = config_dir();
+    let dir_rules = dir_rules()
+        .into_iter()
+        .filter(|(_, path)| expand_home(path).starts_with(&cfg_dir))
+        .map(|(dir, _)| dir)
+        .collect();
+
+    // Resolve every path before changing into any of them.
+    let mut targets: Vec<PathBuf> = vec![];
+    if in_git_repo()
+        && let Ok(cwd) = std::env::current_dir()
+    {
+        targets.push(cwd);
+    }
+    for repo in repos {
+        let path = std::fs::canonicalize(expand_home(&repo.to_string_lossy()))
+            .map_err(|_| GitIdError::Usage(format!("No such directory: {}", repo.display())))?;
+        if !targets.contains(&path) {
+            targets.push(path);
+        }
+    }
+    // Registered repos that are gone are skipped quietly, unlike --repo.
+    let registered = load_repos().unwrap_or_else(|e| {
+        print_warn(&format!("Cannot read the repo registry: {e}"));
+        vec![]
+    });
+    for repo in registered {
+        if let Some(root) = repo_root(&repo)
+            && let Ok(path) = std::fs::canonicalize(&root)
+            && !targets.contains(&path)
+        {
+            targets.push(path);
+        }
+    }
+    let start_dir = std::env::current_dir().ok();
+    let mut repo_keys = vec![];
+    let mut hooks = vec![];
+    for path in targets {
+        if std::env::set_current_dir(&path).is_err() || !in_git_repo() {
+            print_warn(&format!("{} is not a git repository - skipping", path.display()));
+            continue;
+        }
+        for hook in installed_hooks() {
+            if !hooks.contains(&hook) {
+                hooks.push(hook);
+            }
+        }
+        let keys = repo_settings();
+        if !keys.is_empty() {
+            repo_keys.push((path, keys));
+        }
+    }
+    if let Some(dir) = start_dir {
+        let _ = std::env::set_current_dir(dir);
+    }
+
+    let home = dirs_home();
+    let rc_files = [".zshrc", ".bashrc"]
+        .iter()
+        .map(|f| home.join(f))
+        .filter(|p| {
+            crate::log::read_to_string(p).is_ok_and(|c| strip_rc_block(&c).is_some())
+        })
+        .collect();
+
+    let mut keys: Vec<String> = load_accounts()?
+        .into_iter()
+        .map(|a| a.ssh_key)
+        .filter(|k| !k.is_empty())
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    Ok(Plan {
+        stanzas,
+        ssh_include: crate::log::read_to_string(ssh_config_path())
+            .is_ok_and(|c| strip_include(&c).is_some()),
+        dir_rules,
+        global_helper: has_own_helper("global"),
+        global_settings: get_git_config_regexp(r"^git-id\.", "global")
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect(),
+        repos: repo_keys,
+        hooks,
+        completions: installed_scripts(&home),
+        rc_files,
+        backups: backups(),
+        keys,
+    })
+}
+
+/// git-id keys in the current repo's local config: its credential helper
+/// and the `git-id.*` settings.
+fn repo_settings() -> Vec<String> {
+    let mut keys = vec![];
+    if has_own_helper("local") {
+        keys.push("credential.helper".to_string());
+    }
+    for (key, _) in get_git_config_regexp(r"^git-id\.", "local") {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+/// Backup files written by git-id: `~/.ssh/config.bak.*`, the managed
+/// stanza file's and the accounts.toml backups in the config dir.
+fn backups() -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = [ssh_config_path(), managed_config_path(), accounts_file()]
+        .iter()
+        .flat_map(|path| backups_of(path))
+        .map(|(_, backup)| backup)
+        .collect();
+    found.sort();
+    found
+}
+
+fn print_plan(plan: &Plan, keep_backups: bool) {
+    print_hdr("git-id uninstall");
+    let item = |label: &str, value: &str| {
+        println!("  {} {value}", color("bold", &format!("{label:<20}")));
+    };
+    if !plan.stanzas.is_empty() {
+        item("SSH config stanzas", &plan.stanzas.join(", "));
+    }
+    if plan.ssh_include {
+        item("SSH config include", &ssh_config_path().to_string_lossy());
+    }
+    for dir in &plan.dir_rules {
+        item("directory rule", dir);
+    }
+    if plan.global_helper {
+        item("credential helper", "global gitconfig");
+    }
+    if !plan.global_settings.is_empty() {
+        item("global settings", &plan.global_settings.join(", "));
+    }
+    for (repo, keys) in &plan.repos {
+        item("repo settings", &format!("{} ({})", repo.display(), keys.join(", ")));
+    }
+    for hook in &plan.hooks {
+        item("hook check", &hook.to_string_lossy());
+    }
+    for script in &plan.completions {
+        item("completion script", &script.to_string_lossy());
+    }
+    for rc in &plan.rc_files {
+        item("completion lines", &rc.to_string_lossy());
+    }
+    if !keep_backups {
+        for b in &plan.backups {
+            item("backup", &b.to_string_lossy());
+        }
+    }
+    if config_dir().exists() {
+        item("config dir", &config_dir().to_string_lossy());
+    }
+    if cache_dir().exists() {
+        item("cache dir", &cache_dir().to_string_lossy());
+    }
+    println!(
+        "\n  {}",
+        color(
+            "dim",
+            "Repos missing from `git-id repos list` keep their settings; pass --repo to clean them."
+        )
+    );
+}
+
+/// Drops every managed stanza in one write per file. The managed file is
+/// deleted once nothing else is left in it, along with its `Include`.
+fn remove_stanzas(ids: &[String], keep_backups: bool, dry_run: bool) -> Result<()> {
+    let managed = managed_config_path();
+    let mut managed_gone = !managed.exists();
+    for cfg in [managed.clone(), ssh_config_path()] {
+        let Ok(original) = crate::log::read_to_string(&cfg) else {
+            continue;
+        };
+        let mut content = without_stanzas(&original, ids);
+        if cfg == managed && content.trim().is_empty() {
+            managed_gone = true;
+            if dry_run {
+                print_info(&format!("[dry-run] Would delete {}", cfg.display()));
+                continue;
+            }
+            let result = std::fs::remove_file(&cfg);
+            crate::log::file("deleted", &cfg, &result);
+            result.map_err(|e| GitIdError::Io(format!("Failed to delete {}: {e}", cfg.display())))?;
+            print_ok(&format!("Deleted {}", cfg.display()));
+            continue;
+        }
+        if cfg != managed && managed_gone {
+            content = strip_include(&content).unwrap_or(content);
+        }
+        if content == original {
+            continue;
+        }
+        if dry_run {
+            print_info(&format!("[dry-run] Would remove git-id's lines from {}", cfg.display()));
+            continue;
+        }
+        if keep_backups {
+            backup(&cfg);
+        }
+        crate::log::write(&cfg, content)
+            .map_err(|e| GitIdError::Io(format!("Failed to write SSH config: {e}")))?;
+        print_ok(&format!("Removed git-id's lines from {}", cfg.display()));
+    }
+    Ok(())
+}
+
+fn strip_rc(rc: &Path, keep_backups: bool, dry_run: bool) -> Result<()> {
+    let content = crate::log::read_to_string(rc).map_err(|e| GitIdError::Io(e.to_string()))?;
+    let Some(stripped) = strip_rc_block(&content) else {
+        return Ok(());
+    };
+    if dry_run {
+        print_info(&format!("[dry-run] Would remove completion lines from {}", rc.display()));
+        return Ok(());
+    }
+    if keep_backups {
+        backup(rc);
+    }
+    crate::log::write(rc, stripped).map_err(|e| GitIdError::Io(e.to_string()))?;
+    print_ok(&format!("Removed completion lines from {}", rc.display()));
+    Ok(())
+}
+
+fn delete_file(path: &Path, dry_run: bool, removed: &mut Vec<String>, failed: &mut Vec<String>) {
+    if dry_run {
+        print_info(&format!("[dry-run] Would delete {}", path.display()));
+        removed.push(path.display().to_string());
+        return;
+    }
+    match std::fs::remove_file(path) {
+        Ok(()) => removed.push(path.display().to_string()),
+        Err(e) => failed.push(format!("{}: {e}", path.display())),
+    }
+}
+
+/// Deletes the cache dir (cached app tokens).
+fn remove_cache_dir(dry_run: bool, removed: &mut Vec<String>, failed: &mut Vec<String>) {
+    let dir = cache_dir();
+    if !dir.exists() {
+        return;
+    }
+    if dry_run {
+        print_info(&format!("[dry-run] Would delete {}", dir.display()));
+    } else if let Err(e) = std::fs::remove_dir_all(&dir) {
+        failed.push(format!("{}: {e}", dir.display()));
+        return;
+    }
+    removed.push(dir.display().to_string());
+}
+
+/// Deletes the config dir. With `keep_backups` the backups inside it stay,
+/// next to a fresh backup of accounts.toml, and so does the dir itself.
+fn remove_config_dir(
+    keep_backups: bool,
+    dry_run: bool,
+    removed: &mut Vec<String>,
+    failed: &mut Vec<String>,
+) {
+    let dir = config_dir();
+    if !dir.exists() {
+        return;
+    }
+    if !keep_backups {
+        if dry_run {
+            print_info(&format!("[dry-run] Would delete {}", dir.display()));
+        } else if let Err(e) = std::fs::remove_dir_all(&dir) {
+            failed.push(format!("{}: {e}", dir.display()));
+            return;
+        }
+        removed.push(dir.display().to_string());
+        return;
+    }
+    if dry_run {
+        print_info(&format!("[dry-run] Would back up {}", accounts_file().display()));
+    } else {
+        backup(&accounts_file());
+    }
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().contains(".bak.") {
+            continue;
+        }
+        if dry_run {
+            print_info(&format!("[dry-run] Would delete {}", path.display()));
+        } else {
+            let result = if path.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            if let Err(e) = result {
+                failed.push(format!("{}: {e}", path.display()));
+                continue;
+            }
+        }
+        removed.push(path.display().to_string());
+    }
+}
+
+fn print_report(
+    plan: &Plan,
+    keep_backups: bool,
+    dry_run: bool,
+    removed: &[String],
+    failed: &[String],
+) {
+    print_hdr(if dry_run { "Uninstall report (dry run)" } else { "Uninstall report" });
+    let verb = if dry_run { "would remove" } else { "removed" };
+    if removed.is_empty() {
+        println!("  Nothing to remove - git-id left no files behind.");
+    }
+    for r in removed {
+        println!("  {}  {r}", color("green", verb));
+    }
+    for f in failed {
+        println!("  {}  {f}", color("red", "failed"));
+    }
+    if keep_backups {
+        println!(
+            "  {}  backups in {} and {}",
+            color("cyan", "kept"),
+            config_dir().display(),
+            ssh_dir().display()
+        );
+    }
+    for key in &plan.keys {
+        println!("  {}  SSH key {key}", color("cyan", "kept"));
+    }
+    let exe = std::env::current_exe()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "git-id".to_string());
+    println!("\n  {}", color("dim", &format!("Delete the binary itself with: rm {exe}")));
+    println!();
+}
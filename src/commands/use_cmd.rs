@@ -1,51 +1,462 @@
-use crate::config::find_account;
+use crate::cli::UseArgs;
+use crate::commands::credential::{configure_client_cert, configure_helper};
+use crate::commands::rules::{broken_rule, describe};
+use crate::commands::with::{current_timezone, ssh_command};
+use crate::config::{
+    account_id, commit_emails, expand_home, find_account, has_email, is_expired, load_accounts,
+    load_settings, normalize_email, owns, remote_name, ssh_host_alias,
+};
 use crate::git::{
-    build_https_url, build_ssh_url, get_remote_url, in_git_repo, list_remotes, parse_remote_url,
-    set_git_config, set_remote_url,
+    add_remote, build_https_url, build_ssh_url, display_url, enable_worktree_config,
+    get_git_config, get_git_config_all, get_remote_url, identity_override, in_git_repo,
+    list_remotes, parse_remote_url, refresh_remote, rename_remote, set_git_config,
+    set_remote_url, unset_git_config,
 };
+use crate::error::{GitIdError, Result};
+use crate::jj::{self, set_jj_config};
 use crate::models::Account;
-use crate::ui::{die, print_info, print_ok, print_warn};
+use crate::ssh::make_agent_exclusive;
+use crate::ui::{print_info, print_ok, print_warn};
 
-pub fn cmd_use(username: &str, global: bool, force_ssh: bool, force_https: bool, dry_run: bool) {
-    let acc = find_account(username)
-        .unwrap_or_else(|| die(&format!("Account '{username}' not found. Run: git-id list"), 2));
+pub fn cmd_use(args: &UseArgs, dry_run: bool) -> Result<()> {
+    let acc = match &args.username {
+        Some(username) => find_account(username)?.ok_or_else(|| {
+            GitIdError::Usage(format!("Account '{username}' not found. Run: git-id list"))
+        })?,
+        None => auto_account()?,
+    };
+    let mut acc = crate::secrets::resolved(&acc)?;
 
-    let scope = if global { "global" } else { "local" };
-    if scope == "local" && !in_git_repo() {
-        die("Not inside a git repository. Use --global or cd into a repo.", 2);
+    if is_expired(&acc) {
+        if !args.allow_expired {
+            return Err(GitIdError::Usage(format!(
+                "Account '{}' expired on {}. Pass --allow-expired to use it anyway, or: git-id remove {}",
+                account_id(&acc),
+                acc.expires,
+                account_id(&acc)
+            )));
+        }
+        print_warn(&format!("Account '{}' expired on {}", account_id(&acc), acc.expires));
     }
 
+    let scope = if args.global {
+        "global"
+    } else if args.worktree {
+        "worktree"
+    } else {
+        "local"
+    };
+    if scope != "global" && !in_git_repo() {
+        return Err(GitIdError::Usage(
+            "Not inside a git repository. Use --global or cd into a repo.".to_string(),
+        ));
+    }
+    acc.email = choose_email(&acc, args.email_index, scope)?;
+    if scope != "global"
+        && let Some(rule) = broken_rule(&acc.email)
+    {
+        let msg = format!(
+            "{} must use {}, but '{}' uses {}",
+            describe(&rule),
+            rule.email,
+            account_id(&acc),
+            acc.email
+        );
+        if !args.ignore_rules {
+            return Err(GitIdError::Usage(format!(
+                "{msg}. Pass --ignore-rules to use it anyway (see: git-id rules list)"
+            )));
+        }
+        print_warn(&msg);
+    }
+
+    let dual = scope == "local"
+        && (args.dual || get_git_config("git-id.dual", "local") == "true");
+    if dual && args.force_https {
+        return Err(GitIdError::Usage(
+            "Dual remotes keep the primary remote on SSH; drop --https \
+             (or run: git config --unset git-id.dual)."
+                .to_string(),
+        ));
+    }
+
+    if scope == "worktree" {
+        enable_worktree_config(dry_run);
+    }
+    let previous = current_account(scope).filter(|id| *id != account_id(&acc));
     set_git_config("user.name", &acc.username, scope, dry_run);
     set_git_config("user.email", &acc.email, scope, dry_run);
+    if let Some(previous) = previous {
+        set_git_config("git-id.previous", &previous, scope, dry_run);
+    }
+    if scope == "local" && jj::workspace_root().is_some() {
+        set_jj_config("user.name", &acc.username, dry_run);
+        set_jj_config("user.email", &acc.email, dry_run);
+    }
     print_ok(&format!("Git identity ({scope}): {} <{}>", acc.username, acc.email));
+    configure_commits(&acc, &load_accounts()?, scope, dry_run);
+    let tz = acc.commit_timezone.trim_start_matches(':');
+    if !tz.is_empty() && current_timezone() != tz {
+        print_info(&format!(
+            "Commits are dated in {} only through `git-id with`, or in a shell after: \
+             eval \"$(git-id env)\"",
+            acc.commit_timezone
+        ));
+    }
+    if args.exclusive || agent_exclusive() {
+        make_agent_exclusive(&acc, &load_accounts()?, dry_run);
+    }
+    if !dry_run {
+        for key in ["user.name", "user.email"] {
+            if let Some(source) = identity_override(key, scope) {
+                print_warn(&format!(
+                    "{key} is overridden by {source} - this {scope} setting has no effect"
+                ));
+            }
+        }
+    }
 
+    if scope == "worktree" {
+        configure_helper(&acc, scope, dry_run);
+        configure_client_cert(&acc, scope, dry_run);
+        if !acc.ssh_key.is_empty() {
+            set_git_config("core.sshCommand", &ssh_command(&acc), scope, dry_run);
+        } else if !get_git_config("core.sshCommand", scope).is_empty() {
+            unset_git_config("core.sshCommand", scope, dry_run);
+        }
+        print_info("Remotes are shared by all worktrees and were left as they are");
+        crate::repos::remember_current_repo(dry_run);
+    }
     if scope == "local" {
-        update_matching_remotes(&acc, force_ssh, force_https, dry_run);
+        configure_helper(&acc, scope, dry_run);
+        configure_client_cert(&acc, scope, dry_run);
+        let which = if args.all_remotes {
+            Which::All
+        } else if !args.remotes.is_empty() {
+            Which::Named(&args.remotes)
+        } else {
+            Which::Matching
+        };
+        if dual && acc.ssh_key.is_empty() {
+            print_warn("Dual remotes need an SSH key on the account - updating remotes normally");
+            update_remotes(&acc, which, args.force_ssh, args.force_https, false, dry_run)?;
+        } else {
+            let force_ssh = args.force_ssh || dual;
+            update_remotes(&acc, which, force_ssh, args.force_https, dual, dry_run)?;
+            if dual && !list_remotes().is_empty() {
+                sync_https_remote(&acc, dry_run);
+                if args.dual {
+                    set_git_config("git-id.dual", "true", "local", dry_run);
+                }
+            }
+        }
+        if args.refresh && !list_remotes().is_empty() {
+            refresh_account_remote(&acc, dry_run)?;
+        }
+        crate::commands::hook::repin(&acc, dry_run);
+        crate::repos::remember_current_repo(dry_run);
     }
+    Ok(())
 }
 
-fn update_matching_remotes(acc: &Account, force_ssh: bool, force_https: bool, dry_run: bool) {
-    let token = &acc.https_token;
-    let ssh_key = &acc.ssh_key;
+/// Whether `key` is an SSH key for `gpg.format = ssh` rather than a GPG
+/// key id: a public key file, or a key given inline.
+pub fn is_ssh_signing_key(key: &str) -> bool {
+    key.ends_with(".pub") || key.starts_with("ssh-") || key.starts_with("key::")
+}
+
+/// `signing_key` as git is given it: key files with `~` expanded.
+fn signing_key_value(key: &str) -> String {
+    if key.ends_with(".pub") {
+        expand_home(key).to_string_lossy().to_string()
+    } else {
+        key.to_string()
+    }
+}
+
+/// Turns on commit signing with the account's `signing_key` and sets its
+/// `commit_template` in `scope`. A key or template another account left
+/// there is removed, so commits are never signed as someone else.
+fn configure_commits(acc: &Account, accounts: &[Account], scope: &str, dry_run: bool) {
+    let current = get_git_config("user.signingkey", scope);
+    if !acc.signing_key.is_empty() {
+        let key = signing_key_value(&acc.signing_key);
+        set_git_config("user.signingkey", &key, scope, dry_run);
+        set_git_config("commit.gpgsign", "true", scope, dry_run);
+        if is_ssh_signing_key(&acc.signing_key) {
+            set_git_config("gpg.format", "ssh", scope, dry_run);
+        } else if get_git_config("gpg.format", scope) == "ssh" {
+            unset_git_config("gpg.format", scope, dry_run);
+        }
+        print_ok(&format!("Commits ({scope}) are signed with {}", acc.signing_key));
+    } else if !current.is_empty()
+        && accounts
+            .iter()
+            .any(|a| !a.signing_key.is_empty() && signing_key_value(&a.signing_key) == current)
+    {
+        for key in ["user.signingkey", "commit.gpgsign", "gpg.format"] {
+            if !get_git_config(key, scope).is_empty() {
+                unset_git_config(key, scope, dry_run);
+            }
+        }
+        print_info(&format!("Stopped signing commits ({scope}) with another account's key"));
+    }
+
+    let current = expand_home(&get_git_config("commit.template", scope));
+    if !acc.commit_template.is_empty() {
+        let template = expand_home(&acc.commit_template).to_string_lossy().to_string();
+        set_git_config("commit.template", &template, scope, dry_run);
+    } else if !current.as_os_str().is_empty()
+        && accounts
+            .iter()
+            .any(|a| !a.commit_template.is_empty() && expand_home(&a.commit_template) == current)
+    {
+        unset_git_config("commit.template", scope, dry_run);
+    }
+}
+
+/// The email `use` applies: the one `--email-index` names, else for an
+/// account with alternate emails the first one an email rule here accepts,
+/// else the one picked from a prompt (starting at the email `scope` holds).
+/// Without a terminal it is the main email.
+fn choose_email(acc: &Account, index: Option<usize>, scope: &str) -> Result<String> {
+    use std::io::IsTerminal;
+    let emails: Vec<&String> = commit_emails(acc).collect();
+    if let Some(index) = index {
+        return emails.get(index).map(|e| e.to_string()).ok_or_else(|| {
+            GitIdError::Usage(format!(
+                "'{}' has no email {index}; --email-index goes from 0 to {}",
+                account_id(acc),
+                emails.len().saturating_sub(1)
+            ))
+        });
+    }
+    if acc.alt_emails.is_empty() {
+        return Ok(acc.email.clone());
+    }
+    if scope != "global"
+        && broken_rule(&acc.email).is_some()
+        && let Some(email) = emails.iter().find(|e| broken_rule(e).is_none())
+    {
+        print_info(&format!("Using {email}, the email the rules here require"));
+        return Ok(email.to_string());
+    }
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return Ok(acc.email.clone());
+    }
+    let current = get_git_config("user.email", scope);
+    let default = emails.iter().position(|e| e.eq_ignore_ascii_case(&current)).unwrap_or(0);
+    let idx = dialoguer::Select::new()
+        .with_prompt(format!("  Email for {}", account_id(acc)))
+        .items(&emails)
+        .default(default)
+        .interact()
+        .map_err(|_| GitIdError::Usage("No email selected".to_string()))?;
+    Ok(emails[idx].clone())
+}
+
+/// Whether settings.toml asks `use` to keep only the selected account's key
+/// in ssh-agent.
+fn agent_exclusive() -> bool {
+    load_settings()
+        .inspect_err(|e| print_warn(&format!("Ignoring the [agent] defaults: {e}")))
+        .is_ok_and(|s| s.agent.exclusive)
+}
+
+/// The account the current repo belongs to, from the first remote (origin
+/// first) that some account owns or reaches through its SSH alias.
+fn auto_account() -> Result<Account> {
+    if !in_git_repo() {
+        return Err(GitIdError::Usage("Not inside a git repository.".to_string()));
+    }
+    let accounts = load_accounts()?;
+    let mut remotes = list_remotes();
+    remotes.sort_by_key(|r| r != "origin");
+    let mut seen = vec![];
+    for remote in remotes {
+        let url = get_remote_url(&remote);
+        let Some((_, host, owner, _)) = parse_remote_url(&url) else {
+            continue;
+        };
+        let owners: Vec<&Account> =
+            accounts.iter().filter(|a| remote_belongs_to(a, &url, &host, &owner)).collect();
+        match owners.as_slice() {
+            [acc] => {
+                print_info(&format!("'{remote}' belongs to {owner}: using '{}'", account_id(acc)));
+                return Ok((*acc).clone());
+            }
+            [] => seen.push(format!("{owner} on {host}")),
+            _ => {
+                let ids: Vec<String> = owners.iter().map(|a| account_id(a)).collect();
+                return Err(GitIdError::Usage(format!(
+                    "Several accounts own {owner} on {host}: {}. Name one: git-id use <account>",
+                    ids.join(", ")
+                )));
+            }
+        }
+    }
+    if seen.is_empty() {
+        return Err(GitIdError::Usage(
+            "No remote to infer the account from. Name one: git-id use <account>".to_string(),
+        ));
+    }
+    Err(GitIdError::Usage(format!(
+        "No account owns {}. Map the owner with: \
+         git-id add --update --username <name> --owner <owner>",
+        seen.join(", ")
+    )))
+}
+
+/// Id of the account whose identity `scope` holds now, matched by email
+/// (and name, when several accounts share the email).
+fn current_account(scope: &str) -> Option<String> {
+    let email = normalize_email(&get_git_config("user.email", scope), false);
+    if email.is_empty() {
+        return None;
+    }
+    let name = get_git_config("user.name", scope);
+    let accounts = load_accounts().ok()?;
+    let same_email: Vec<&Account> =
+        accounts.iter().filter(|a| has_email(a, &email, false)).collect();
+    let acc = same_email.iter().find(|a| a.username == name).or(same_email.first())?;
+    Some(account_id(acc))
+}
+
+/// Switches back to the account used before the current one in `scope`,
+/// like `cd -`.
+pub fn cmd_switch(global: bool, worktree: bool, dry_run: bool) -> Result<()> {
+    let (scope, flag, place) = if global {
+        ("global", " --global", " globally")
+    } else if worktree {
+        ("worktree", " --worktree", " in this worktree")
+    } else {
+        ("local", "", " in this repo")
+    };
+    let previous = get_git_config("git-id.previous", scope);
+    if previous.is_empty() {
+        return Err(GitIdError::Usage(format!(
+            "No previous account{place} yet. Run: git-id use <account>{flag}"
+        )));
+    }
+    let args = UseArgs { username: Some(previous), global, worktree, ..Default::default() };
+    cmd_use(&args, dry_run)
+}
+
+/// git/ssh messages that mean the remote rejected the credentials. GitHub
+/// answers "Repository not found" rather than revealing a private repo.
+const AUTH_ERRORS: &[&str] = &[
+    "Permission denied",
+    "Authentication failed",
+    "could not read Username",
+    "Repository not found",
+];
+
+/// Fetches the account's remote through its new URL, so an authentication
+/// problem shows up now rather than at the next pull.
+fn refresh_account_remote(acc: &Account, dry_run: bool) -> Result<()> {
+    let remote = remote_name(acc);
+    if !dry_run && !list_remotes().iter().any(|r| r == remote) {
+        print_info(&format!("No remote '{remote}' - nothing to refresh"));
+        return Ok(());
+    }
+    refresh_remote(remote, dry_run).map_err(|e| {
+        let auth_failed = AUTH_ERRORS.iter().any(|s| e.contains(s));
+        let check = if !auth_failed {
+            String::new()
+        } else if get_remote_url(remote).starts_with("https://") {
+            format!("\n  Check the token: git-id edit {} --token-stdin", account_id(acc))
+        } else {
+            format!("\n  Check the key: git-id ssh test {}", account_id(acc))
+        };
+        GitIdError::Io(format!("Fetching '{remote}' failed: {e}{check}"))
+    })
+}
+
+/// Name of the HTTPS companion remote kept in dual-stack mode.
+fn https_remote_name(acc: &Account) -> String {
+    format!("{}-https", remote_name(acc))
+}
+
+/// Points the `<remote>-https` companion at the same repository as the
+/// primary remote, over HTTPS, creating it when missing.
+fn sync_https_remote(acc: &Account, dry_run: bool) {
+    let primary = remote_name(acc);
+    let url = get_remote_url(primary);
+    let Some((_, host, owner, repo)) = parse_remote_url(&url) else {
+        print_warn(&format!("Remote '{primary}' not found - skipping HTTPS companion remote"));
+        return;
+    };
+    let https_url = build_https_url(&acc.username, &host, &owner, &repo);
+    let companion = https_remote_name(acc);
+    if list_remotes().contains(&companion) {
+        set_remote_url(&companion, &https_url, dry_run);
+    } else {
+        add_remote(&companion, &https_url, dry_run);
+    }
+}
+
+/// Which remotes `use` rewrites.
+#[derive(Clone, Copy)]
+enum Which<'a> {
+    /// The account's own remote (created if missing) and any other remote
+    /// that already points at the account.
+    Matching,
+    /// Only these remotes (`--remote`).
+    Named(&'a [String]),
+    /// Every remote with a recognised URL (`--all-remotes`).
+    All,
+}
+
+fn update_remotes(
+    acc: &Account,
+    which: Which,
+    force_ssh: bool,
+    force_https: bool,
+    dual: bool,
+    dry_run: bool,
+) -> Result<()> {
     let remotes = list_remotes();
 
     if remotes.is_empty() {
-        print_info("No remotes found - skipping remote URL update (identity set)");
-        return;
+        print_info(
+            "No remotes: local-only repository, nothing to rewrite. \
+             Run git-id use again after adding one",
+        );
+        return Ok(());
     }
 
     if force_ssh && force_https {
-        die("Cannot use --ssh and --https together.", 2);
+        return Err(GitIdError::Usage("Cannot use --ssh and --https together.".to_string()));
+    }
+
+    if let Which::Named(names) = which
+        && let Some(missing) = names.iter().find(|n| !remotes.contains(n))
+    {
+        return Err(GitIdError::Usage(format!(
+            "No remote named '{missing}' (remotes: {})",
+            remotes.join(", ")
+        )));
     }
 
-    let account_host = if acc.host.is_empty() {
-        "github.com"
+    let excluded = excluded_remotes(acc);
+    let companion = dual.then(|| https_remote_name(acc));
+    let primary = remote_name(acc);
+    let remotes = if let Which::Named(names) = which {
+        names.to_vec()
+    } else if remotes.iter().any(|r| r == primary) {
+        remotes
     } else {
-        &acc.host
+        ensure_account_remote(acc, &remotes, &excluded, force_ssh, force_https, dry_run);
+        list_remotes()
     };
-    let account_alias_prefix = format!("git@{}:", crate::config::ssh_host_alias(acc));
-
     for remote in remotes {
+        if companion.as_ref() == Some(&remote) {
+            continue;
+        }
+        if excluded.contains(&remote) {
+            print_info(&format!("Remote '{remote}' is excluded from rewriting - skipping"));
+            continue;
+        }
         let remote_url = get_remote_url(&remote);
         if remote_url.is_empty() {
             continue;
@@ -54,40 +465,113 @@ fn update_matching_remotes(acc: &Account, force_ssh: bool, force_https: bool, dr
             Some(p) => p,
             None => {
                 print_warn(&format!(
-                    "Unrecognised remote URL format for '{remote}': {remote_url:?} - skipping"
+                    "Unrecognised remote URL format for '{remote}': {:?} - skipping",
+                    display_url(&remote_url)
                 ));
                 continue;
             }
         };
         let (current_fmt, host, owner, repo) = parsed;
-        let is_origin = remote == "origin";
-        let matches_identity_remote = host == account_host && owner == acc.username;
-        let already_on_identity_alias = remote_url.starts_with(&account_alias_prefix);
-        if !is_origin && !matches_identity_remote && !already_on_identity_alias {
+        let is_primary = remote == primary;
+        if matches!(which, Which::Matching)
+            && !is_primary
+            && !remote_belongs_to(acc, &remote_url, &host, &owner)
+        {
             continue;
         }
+        let new_url = account_url(acc, &current_fmt, &host, &owner, &repo, force_ssh, force_https);
+        set_remote_url(&remote, &new_url, dry_run);
+    }
+    Ok(())
+}
 
-        let mut target_fmt = if force_ssh {
-            "ssh".to_string()
-        } else if force_https {
-            "https".to_string()
-        } else {
-            current_fmt
-        };
+/// Remotes that must never be rewritten: the account's `exclude_remotes`
+/// plus any `git-id.excludeRemote` entries in the repo's git config.
+pub fn excluded_remotes(acc: &Account) -> Vec<String> {
+    let mut excluded = acc.exclude_remotes.clone();
+    excluded.extend(get_git_config_all("git-id.excludeRemote", "local"));
+    excluded
+}
 
-        if target_fmt == "ssh" {
-            if ssh_key.is_empty() {
-                print_warn("No SSH key configured for this account; falling back to HTTPS");
-                target_fmt = "https".to_string();
-            } else {
-                let new_url = build_ssh_url(acc, &owner, &repo);
-                set_remote_url(&remote, &new_url, dry_run);
-                continue;
-            }
+/// Whether a remote already points at this account, either through its SSH
+/// alias (also the one generated from a former login) or by owner on the
+/// account's host.
+pub fn remote_belongs_to(acc: &Account, remote_url: &str, host: &str, owner: &str) -> bool {
+    let acc_host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    let url = remote_url.to_lowercase();
+    let mut aliases = vec![ssh_host_alias(acc)];
+    aliases.extend(acc.aka.iter().map(|old| format!("{acc_host}-{old}")));
+    owns(acc, host, owner)
+        || aliases.iter().any(|a| url.starts_with(&format!("git@{}:", a.to_lowercase())))
+}
+
+/// Creates the account's named remote when the repo does not have it yet.
+/// A remote already pointing at the account is renamed; otherwise a new
+/// remote is added for the same repository as `origin`.
+fn ensure_account_remote(
+    acc: &Account,
+    remotes: &[String],
+    excluded: &[String],
+    force_ssh: bool,
+    force_https: bool,
+    dry_run: bool,
+) {
+    let primary = remote_name(acc);
+    let owned = remotes.iter().filter(|r| !excluded.contains(r)).find(|r| {
+        let url = get_remote_url(r);
+        parse_remote_url(&url)
+            .map(|(_, host, owner, _)| remote_belongs_to(acc, &url, &host, &owner))
+            .unwrap_or(false)
+    });
+    if let Some(old) = owned {
+        rename_remote(old, primary, dry_run);
+        return;
+    }
+    if !remotes.iter().any(|r| r == "origin") {
+        print_info(&format!(
+            "No remote matches '{}' and no origin to copy - skipping remote '{primary}'",
+            ssh_host_alias(acc)
+        ));
+        return;
+    }
+    let origin_url = get_remote_url("origin");
+    let Some((current_fmt, host, owner, repo)) = parse_remote_url(&origin_url) else {
+        print_warn(&format!(
+            "Unrecognised remote URL format for 'origin': {:?} - skipping",
+            display_url(&origin_url)
+        ));
+        return;
+    };
+    let url = account_url(acc, &current_fmt, &host, &owner, &repo, force_ssh, force_https);
+    add_remote(primary, &url, dry_run);
+}
+
+/// Builds the remote URL for the account in the requested format: --ssh or
+/// --https, else the account's `protocol`, else the current one.
+pub fn account_url(
+    acc: &Account,
+    current_fmt: &str,
+    host: &str,
+    owner: &str,
+    repo: &str,
+    force_ssh: bool,
+    force_https: bool,
+) -> String {
+    let target_fmt = if force_ssh {
+        "ssh"
+    } else if force_https {
+        "https"
+    } else {
+        match acc.protocol.as_str() {
+            "ssh" | "https" => &acc.protocol,
+            _ => current_fmt,
         }
-        if target_fmt == "https" {
-            let new_url = build_https_url(token, &host, &owner, &repo);
-            set_remote_url(&remote, &new_url, dry_run);
+    };
+    if target_fmt == "ssh" {
+        if !acc.ssh_key.is_empty() {
+            return build_ssh_url(acc, owner, repo);
         }
+        print_warn("No SSH key configured for this account; falling back to HTTPS");
     }
+    build_https_url(&acc.username, host, owner, repo)
 }
@@ -0,0 +1,168 @@
+use crate::commands::ssh::cmd_ssh_test;
+use crate::config::{account_id, find_account, has_email, load_accounts, normalize_email};
+use crate::forge::{account_emails, is_github, is_noreply_for};
+use crate::git::{get_git_config, in_git_repo};
+use crate::models::Account;
+use crate::error::{GitIdError, Result};
+use crate::ui::{color, print_hdr, print_info};
+
+/// Checks that the account logs in over SSH as itself and that its commits
+/// are credited to it; with `attribution_only`, just the latter.
+pub fn cmd_verify(account: Option<&str>, attribution_only: bool) -> Result<()> {
+    let acc = match account {
+        Some(name) => find_account(name)?.ok_or_else(|| {
+            GitIdError::Usage(format!("Account '{name}' not found. Run: git-id list"))
+        })?,
+        None => active_account()?.ok_or_else(|| {
+            GitIdError::Usage(
+                "Active email does not match any account. Pass the account to verify.".to_string(),
+            )
+        })?,
+    };
+
+    print_hdr(&format!("Verifying '{}'", account_id(&acc)));
+    let mut failed = false;
+    if !attribution_only && (!acc.ssh_key.is_empty() || !acc.identity_agent.is_empty()) {
+        println!("\n  {}", color("bold", "SSH login"));
+        match cmd_ssh_test(&account_id(&acc)) {
+            Ok(()) => {}
+            Err(GitIdError::CheckFailed) => failed = true,
+            Err(e) => return Err(e),
+        }
+    }
+    failed |= !check_attribution(&acc)?;
+    if !acc.previous_emails.is_empty() {
+        check_previous_emails(&acc)?;
+    }
+    println!();
+    if failed {
+        return Err(GitIdError::CheckFailed);
+    }
+    Ok(())
+}
+
+/// The account matching the email git would commit with here.
+pub fn active_account() -> Result<Option<Account>> {
+    let local = if in_git_repo() { get_git_config("user.email", "local") } else { String::new() };
+    let email = if local.is_empty() { get_git_config("user.email", "global") } else { local };
+    if email.is_empty() {
+        return Ok(None);
+    }
+    let wanted = normalize_email(&email, false);
+    Ok(load_accounts()?
+        .into_iter()
+        .find(|a| has_email(a, &wanted, false)))
+}
+
+/// Checks whether commits made with the account's email are credited to
+/// that account on the forge. Returns false when they are not.
+fn check_attribution(acc: &Account) -> Result<bool> {
+    let email = &acc.email;
+    println!("\n  {}  {email}", color("bold", "Commit attribution"));
+    if is_noreply_for(acc, email) {
+        println!("    {} noreply address of '{}'", color("green", "OK"), acc.username);
+        return Ok(true);
+    }
+    if !is_github(acc) {
+        print_info("Attribution checks are only supported for GitHub hosts - skipping");
+        return Ok(true);
+    }
+    if acc.https_token.is_empty() {
+        print_info("No token configured - cannot read the account's emails (needs user:email scope)");
+        return Ok(true);
+    }
+    let emails = match account_emails(acc) {
+        Ok(e) => e,
+        Err(e) => {
+            println!("    {} could not list emails: {e}", color("red", "ERR"));
+            return Ok(false);
+        }
+    };
+    let wanted = normalize_email(email, false);
+    let credited = match emails.iter().find(|(e, _)| normalize_email(e, false) == wanted) {
+        Some((_, true)) => {
+            println!("    {} verified on '{}'", color("green", "OK"), acc.username);
+            true
+        }
+        Some((_, false)) => {
+            println!(
+                "    {} registered on '{}' but not verified - commits will not be linked",
+                color("red", "ERR"),
+                acc.username
+            );
+            false
+        }
+        None => {
+            match verified_elsewhere(acc, &wanted)? {
+                Some(other) => println!(
+                    "    {} verified on '{}' instead - commits will be credited to that account",
+                    color("red", "ERR"),
+                    account_id(&other)
+                ),
+                None => println!(
+                    "    {} not registered on '{}' - commits will not be linked to any account",
+                    color("red", "ERR"),
+                    acc.username
+                ),
+            }
+            false
+        }
+    };
+    Ok(credited)
+}
+
+/// Reports whether commits made with the account's earlier emails are still
+/// credited to it. They were made on purpose, so a lost link is a warning,
+/// not a failure.
+fn check_previous_emails(acc: &Account) -> Result<()> {
+    println!("\n  {}", color("bold", "Previous emails"));
+    let warn = color("yellow", "WARN");
+    let emails = if !is_github(acc) || acc.https_token.is_empty() {
+        None
+    } else {
+        match account_emails(acc) {
+            Ok(e) => Some(e),
+            Err(e) => {
+                println!("    {warn} could not list emails: {e}");
+                return Ok(());
+            }
+        }
+    };
+    for old in &acc.previous_emails {
+        if is_noreply_for(acc, old) {
+            println!("    {} {old}  noreply address of '{}'", color("green", "OK"), acc.username);
+            continue;
+        }
+        let Some(emails) = &emails else {
+            println!("    {} {old}  not checked (needs a GitHub token)", color("dim", "--"));
+            continue;
+        };
+        let wanted = normalize_email(old, false);
+        if emails.iter().any(|(e, verified)| *verified && normalize_email(e, false) == wanted) {
+            let ok = color("green", "OK");
+            println!("    {ok} {old}  still verified - older commits stay linked");
+        } else if let Some(other) = verified_elsewhere(acc, &wanted)? {
+            let id = account_id(&other);
+            println!("    {warn} {old}  verified on '{id}' - older commits count as theirs");
+        } else {
+            println!("    {warn} {old}  no longer verified - older commits are not linked");
+        }
+    }
+    Ok(())
+}
+
+/// Another configured account on the same host that has `email` verified.
+fn verified_elsewhere(acc: &Account, email: &str) -> Result<Option<Account>> {
+    let uid = account_id(acc);
+    Ok(load_accounts()?.into_iter().find(|other| {
+        account_id(other) != uid
+            && other.host == acc.host
+            && !other.https_token.is_empty()
+            && account_emails(other)
+                .map(|list| {
+                    list.iter()
+                        .any(|(e, verified)| *verified && normalize_email(e, false) == email)
+                })
+                .unwrap_or(false)
+    }))
+}
@@ -0,0 +1,42 @@
+use crate::config::{account_id, has_email, load_accounts, normalize_email};
+use crate::git::{
+    get_git_config, get_remote_url, identity_scope, in_git_repo, parse_remote_url,
+    strip_plus_address_enabled,
+};
+use crate::error::{GitIdError, Result};
+
+/// Prints the effective identity on one line, e.g.
+/// `alice <alice@example.com> (local) account=alice origin=ssh`.
+/// Fails with `CheckFailed` when no identity is configured at all.
+pub fn cmd_whoami() -> Result<()> {
+    let in_repo = in_git_repo();
+    let scope = identity_scope();
+    let (name, email) = (get_git_config("user.name", scope), get_git_config("user.email", scope));
+
+    if name.is_empty() && email.is_empty() {
+        println!("(no identity)");
+        return Err(GitIdError::CheckFailed);
+    }
+
+    let strip_plus = strip_plus_address_enabled();
+    let wanted = normalize_email(&email, strip_plus);
+    let account = if email.is_empty() {
+        None
+    } else {
+        load_accounts()?
+            .iter()
+            .find(|a| has_email(a, &wanted, strip_plus))
+            .map(account_id)
+    };
+
+    let mut line = format!("{name} <{email}> ({scope})");
+    line.push_str(&format!(" account={}", account.as_deref().unwrap_or("-")));
+    if in_repo {
+        let protocol = parse_remote_url(&get_remote_url("origin"))
+            .map(|(fmt, ..)| fmt)
+            .unwrap_or_else(|| "-".to_string());
+        line.push_str(&format!(" origin={protocol}"));
+    }
+    println!("{line}");
+    Ok(())
+}
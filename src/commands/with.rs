@@ -0,0 +1,96 @@
+use crate::config::{account_id, expand_home, find_account};
+use crate::models::Account;
+use crate::error::{GitIdError, Result};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+/// Runs `git <args>` as the account by injecting `-c` overrides, leaving
+/// git config and the environment untouched. Replaces the current process.
+pub fn cmd_with(username: &str, command: &[String]) -> Result<()> {
+    let acc = find_account(username)?.ok_or_else(|| {
+        GitIdError::Usage(format!("Account '{username}' not found. Run: git-id list"))
+    })?;
+    let acc = crate::secrets::resolved(&acc)?;
+    let Some((program, args)) = command.split_first() else {
+        return Err(GitIdError::Usage("Usage: git-id with <account> git <args...>".to_string()));
+    };
+    if program != "git" {
+        return Err(GitIdError::Usage(format!("'with' only wraps git commands, not '{program}'.")));
+    }
+
+    let mut cmd = Command::new("git");
+    for (key, value) in identity_overrides(&acc) {
+        cmd.arg("-c").arg(format!("{key}={value}"));
+    }
+    cmd.args(args).envs(resolved_env(&acc)?);
+    if crate::config::home_override().is_some() || std::env::var_os("HOME").is_none() {
+        cmd.env("HOME", crate::config::dirs_home());
+    }
+    crate::log::exec(&cmd);
+    let err = cmd.exec();
+    Err(GitIdError::Io(format!("Failed to run git: {err}")))
+}
+
+/// The account's `env` table with secret references looked up, plus `TZ`
+/// for its `commit_timezone` unless the table sets `TZ` itself.
+pub fn resolved_env(acc: &Account) -> Result<Vec<(String, String)>> {
+    let mut vars = acc
+        .env
+        .iter()
+        .map(|(name, value)| {
+            let value = crate::secrets::resolve(value).map_err(|e| {
+                GitIdError::Config(format!("env {name} of '{}': {e}", account_id(acc)))
+            })?;
+            Ok((name.clone(), value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if !acc.commit_timezone.is_empty() && !acc.env.contains_key("TZ") {
+        vars.push(("TZ".to_string(), acc.commit_timezone.clone()));
+    }
+    Ok(vars)
+}
+
+/// The timezone commits made from this process are dated in: `TZ` without
+/// its optional leading `:`, or empty for the system's local time.
+pub fn current_timezone() -> String {
+    let tz = std::env::var("TZ").unwrap_or_default();
+    tz.trim_start_matches(':').to_string()
+}
+
+/// Names of the variables `resolved_env` sets for the account.
+pub fn env_names(acc: &Account) -> Vec<String> {
+    let mut names: Vec<String> = acc.env.keys().cloned().collect();
+    if !acc.commit_timezone.is_empty() && !acc.env.contains_key("TZ") {
+        names.push("TZ".to_string());
+    }
+    names
+}
+
+/// The `git -c` settings that make a single git invocation use the account.
+pub fn identity_overrides(acc: &Account) -> Vec<(&'static str, String)> {
+    let mut overrides = vec![
+        ("user.name", acc.username.clone()),
+        ("user.email", acc.email.clone()),
+    ];
+    if !acc.ssh_key.is_empty() {
+        overrides.push(("core.sshCommand", ssh_command(acc)));
+    }
+    overrides
+}
+
+/// An ssh invocation that offers only the account's key.
+pub fn ssh_command(acc: &Account) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', r"'\''"));
+    let key = expand_home(&acc.ssh_key);
+    let mut cmd = format!(
+        "ssh -i {} -o IdentitiesOnly=yes",
+        quote(&key.to_string_lossy())
+    );
+    if !acc.identity_agent.is_empty() {
+        cmd.push_str(&format!(" -o IdentityAgent={}", quote(&acc.identity_agent)));
+    }
+    if !acc.proxy_jump.is_empty() {
+        cmd.push_str(&format!(" -o ProxyJump={}", quote(&acc.proxy_jump)));
+    }
+    cmd
+}
@@ -1,94 +1,454 @@
-use crate::models::{Account, AccountsFile};
-use crate::ui::{backup, die, print_info, print_ok};
-use std::path::PathBuf;
+use crate::error::{GitIdError, Result};
+use crate::models::{Account, AccountsFile, EmailRule, Settings};
+use crate::store::FileCache;
+use crate::ui::{backup, print_info, print_ok, print_warn};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static HOME_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+static HOME_DIR: OnceLock<PathBuf> = OnceLock::new();
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Makes every path git-id touches resolve under `home` instead of $HOME.
+pub fn set_home_override(home: PathBuf) {
+    let _ = HOME_OVERRIDE.set(home);
+}
+
+pub fn home_override() -> Option<&'static PathBuf> {
+    HOME_OVERRIDE.get()
+}
+
+/// Read-only mode (`--read-only`): the commands still allowed to run leave
+/// caches alone too.
+pub fn set_read_only(on: bool) {
+    READ_ONLY.store(on, Ordering::Relaxed);
+}
+
+pub fn read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+/// Home directory of another local user, from the password database
+/// (`getent passwd`, or /etc/passwd where there is no getent).
+pub fn user_home(user: &str) -> Option<PathBuf> {
+    if user.is_empty() || user.contains(':') {
+        return None;
+    }
+    let from_getent =
+        crate::log::output(std::process::Command::new("getent").args(["passwd", user]))
+            .ok()
+            .filter(|out| out.status.success())
+            .map(|out| String::from_utf8_lossy(&out.stdout).to_string());
+    let entries = from_getent.or_else(|| crate::log::read_to_string("/etc/passwd").ok())?;
+    entries
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 7 && fields[0] == user)
+        .map(|fields| PathBuf::from(fields[5]))
+        .filter(|home| home.is_absolute())
+}
+
+/// Gives files this run left in another user's home (`sudo git-id --user
+/// bob`) to the owner of that home. Only what git-id manages is looked at,
+/// and only entries owned by the user running git-id are changed.
+pub fn restore_ownership() {
+    use std::os::unix::fs::MetadataExt;
+    let Some(home) = home_override() else {
+        return;
+    };
+    let Ok(meta) = std::fs::metadata(home) else {
+        return;
+    };
+    let owner = (meta.uid(), meta.gid());
+    let Some(me) = crate::log::output(std::process::Command::new("id").arg("-u"))
+        .ok()
+        .and_then(|out| String::from_utf8_lossy(&out.stdout).trim().parse::<u32>().ok())
+    else {
+        return;
+    };
+    if me == owner.0 {
+        return;
+    }
+    let mut paths = vec![crate::ssh::ssh_dir(), config_dir(), cache_dir()];
+    paths.extend(crate::commands::completions::installed_scripts(home));
+    if let Ok(entries) = std::fs::read_dir(home) {
+        let ours = [".gitconfig", ".zshrc", ".bashrc"];
+        paths.extend(entries.flatten().map(|e| e.path()).filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            ours.iter().any(|own| name.starts_with(own))
+        }));
+    }
+    for path in paths {
+        // Directories create_dir_all made on the way count as well.
+        for dir in path.ancestors().skip(1).take_while(|d| d.starts_with(home) && *d != home) {
+            hand_over(dir, me, owner, false);
+        }
+        hand_over(&path, me, owner, true);
+    }
+}
+
+fn hand_over(path: &Path, me: u32, (uid, gid): (u32, u32), recurse: bool) {
+    use std::os::unix::fs::MetadataExt;
+    let Ok(meta) = std::fs::symlink_metadata(path) else {
+        return;
+    };
+    if meta.uid() == me {
+        let result = std::os::unix::fs::lchown(path, Some(uid), Some(gid));
+        crate::log::file("chowned", path, &result);
+    }
+    if recurse
+        && meta.is_dir()
+        && let Ok(entries) = std::fs::read_dir(path)
+    {
+        for entry in entries.flatten() {
+            hand_over(&entry.path(), me, (uid, gid), true);
+        }
+    }
+}
 
 pub fn dirs_home() -> PathBuf {
-    std::env::var("HOME")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| PathBuf::from("/tmp"))
+    if let Some(home) = home_override() {
+        return home.clone();
+    }
+    HOME_DIR
+        .get_or_init(|| {
+            std::env::var_os("HOME")
+                .filter(|h| !h.is_empty())
+                .map(PathBuf::from)
+                .or_else(current_user_home)
+                .unwrap_or_else(|| PathBuf::from("/tmp"))
+        })
+        .clone()
 }
 
-pub fn config_dir() -> PathBuf {
+/// Home directory of the current user from the password database, for
+/// when $HOME is unset (cron jobs, some service managers).
+pub fn current_user_home() -> Option<PathBuf> {
+    let out = crate::log::output(std::process::Command::new("id").arg("-un")).ok()?;
+    user_home(String::from_utf8_lossy(&out.stdout).trim())
+}
+
+/// An XDG base directory: `$var` when it holds an absolute path, else
+/// `default` under the home directory. The variables describe the caller's
+/// own setup, so they are ignored with --home/--user.
+fn xdg_dir(var: &str, default: &str) -> PathBuf {
+    if home_override().is_none()
+        && let Some(dir) = std::env::var_os(var).map(PathBuf::from).filter(|d| d.is_absolute())
+    {
+        return dir;
+    }
+    dirs_home().join(default)
+}
+
+/// `$XDG_CONFIG_HOME`, or `~/.config`.
+pub fn config_home() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_DATA_HOME`, or `~/.local/share`.
+pub fn data_home() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// Where git-id kept its config before it honoured `$XDG_CONFIG_HOME`.
+fn legacy_config_dir() -> PathBuf {
     dirs_home().join(".config").join("git-id")
 }
 
+/// Expands a leading `~/` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs_home().join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+pub fn config_dir() -> PathBuf {
+    let dir = config_home().join("git-id");
+    let legacy = legacy_config_dir();
+    // Until `migrate_config_dir` has run (it never does in read-only mode),
+    // keep using the old location.
+    if dir != legacy && !dir.exists() && legacy.exists() {
+        return legacy;
+    }
+    dir
+}
+
+/// Cache directory (`$XDG_CACHE_HOME/git-id` or `~/.cache/git-id`).
+pub fn cache_dir() -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME", ".cache").join("git-id")
+}
+
+/// Moves `~/.config/git-id` to `$XDG_CONFIG_HOME/git-id` the first time
+/// git-id runs with the variable pointing elsewhere, and repoints the
+/// `includeIf` rules whose fragments lived in the old directory.
+pub fn migrate_config_dir(dry_run: bool) {
+    let dir = config_home().join("git-id");
+    let legacy = legacy_config_dir();
+    if dir == legacy || dir.exists() || !legacy.exists() {
+        return;
+    }
+    if dry_run {
+        print_info(&format!("[dry-run] Would move {} to {}", legacy.display(), dir.display()));
+        return;
+    }
+    if let Some(parent) = dir.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Err(e) = std::fs::rename(&legacy, &dir) {
+        print_warn(&format!(
+            "Could not move {} to {}: {e} - still using the old location",
+            legacy.display(),
+            dir.display()
+        ));
+        return;
+    }
+    print_ok(&format!("Moved {} to {}", legacy.display(), dir.display()));
+    for (key, path) in crate::git::get_git_config_regexp(r"^includeif\..*\.path$", "global") {
+        if let Ok(rest) = expand_home(&path).strip_prefix(&legacy) {
+            crate::git::set_git_config(&key, &dir.join(rest).to_string_lossy(), "global", false);
+        }
+    }
+}
+
 pub fn accounts_file() -> PathBuf {
     config_dir().join("accounts.toml")
 }
 
+/// Tool-wide settings, edited by hand; git-id never writes it.
+pub fn settings_file() -> PathBuf {
+    config_dir().join("settings.toml")
+}
+
 const EXAMPLE_TOML: &str =
     "# git-id accounts - managed by git-id (safe to edit manually)\n\
      # Add one [[accounts]] section per GitHub identity.\n";
 
-fn accounts_to_toml(accounts: &[Account]) -> String {
+fn toml_escape(val: &str) -> String {
+    val.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn accounts_to_toml(accounts: &[Account], rules: &[EmailRule]) -> String {
+    let header = "# git-id accounts - managed by git-id (safe to edit manually)\n\
+                  # Add a new [[accounts]] section to register another identity.\n\n";
+    header.to_string() + &account_tables(accounts) + &rule_tables(rules)
+}
+
+/// The `[[rules]]` tables for `rules`, empty fields left out.
+fn rule_tables(rules: &[EmailRule]) -> String {
+    let mut lines = vec![];
+    for rule in rules {
+        lines.push("[[rules]]".to_string());
+        for (field, val) in [("host", &rule.host), ("owner", &rule.owner), ("email", &rule.email)] {
+            if !val.is_empty() {
+                lines.push(format!("{field} = \"{}\"", toml_escape(val)));
+            }
+        }
+        lines.push("".to_string());
+    }
+    lines.iter().map(|l| format!("{l}\n")).collect()
+}
+
+/// The `[[accounts]]` tables for `accounts`, in the `accounts.toml` format.
+pub fn account_tables(accounts: &[Account]) -> String {
     let fields = ["username", "email", "host", "ssh_key", "https_token"];
-    let mut lines = vec![
-        "# git-id accounts - managed by git-id (safe to edit manually)".to_string(),
-        "# Add a new [[accounts]] section to register another identity.".to_string(),
-        "".to_string(),
+    // Written only when set, to keep hand-edited files short.
+    let optional_fields = [
+        "remote",
+        "identity_agent",
+        "control_persist",
+        "control_path",
+        "proxy_jump",
+        "app_id",
+        "app_key",
+        "app_installation_id",
+        "expires",
+        "key_created",
+        "ssh_alias",
+        "protocol",
+        "https_cert",
+        "https_key",
+        "commit_timezone",
+        "signing_key",
+        "commit_template",
+        "forge",
     ];
+    let list_fields = ["exclude_remotes", "owners", "aka", "previous_emails", "alt_emails"];
+    let mut lines = vec![];
     for acc in accounts {
         lines.push("[[accounts]]".to_string());
-        for &field in &fields {
+        for &field in fields.iter().chain(&optional_fields) {
             let val = match field {
                 "username" => &acc.username,
                 "email" => &acc.email,
                 "host" => &acc.host,
                 "ssh_key" => &acc.ssh_key,
                 "https_token" => &acc.https_token,
+                "remote" => &acc.remote,
+                "identity_agent" => &acc.identity_agent,
+                "control_persist" => &acc.control_persist,
+                "control_path" => &acc.control_path,
+                "proxy_jump" => &acc.proxy_jump,
+                "app_id" => &acc.app_id,
+                "app_key" => &acc.app_key,
+                "app_installation_id" => &acc.app_installation_id,
+                "expires" => &acc.expires,
+                "key_created" => &acc.key_created,
+                "ssh_alias" => &acc.ssh_alias,
+                "protocol" => &acc.protocol,
+                "https_cert" => &acc.https_cert,
+                "https_key" => &acc.https_key,
+                "commit_timezone" => &acc.commit_timezone,
+                "signing_key" => &acc.signing_key,
+                "commit_template" => &acc.commit_template,
+                "forge" => &acc.forge,
                 _ => "",
             };
-            let escaped = val.replace('\\', "\\\\").replace('"', "\\\"");
-            lines.push(format!("{field} = \"{escaped}\""));
+            if val.is_empty() && optional_fields.contains(&field) {
+                continue;
+            }
+            lines.push(format!("{field} = \"{}\"", toml_escape(val)));
+        }
+        for &field in &list_fields {
+            let vals: &[String] = match field {
+                "exclude_remotes" => &acc.exclude_remotes,
+                "owners" => &acc.owners,
+                "aka" => &acc.aka,
+                "previous_emails" => &acc.previous_emails,
+                "alt_emails" => &acc.alt_emails,
+                _ => &[],
+            };
+            if vals.is_empty() {
+                continue;
+            }
+            let items: Vec<String> =
+                vals.iter().map(|v| format!("\"{}\"", toml_escape(v))).collect();
+            lines.push(format!("{field} = [{}]", items.join(", ")));
+        }
+        if acc.ssh_keep_manual {
+            lines.push("ssh_keep_manual = true".to_string());
+        }
+        if !acc.agent_lifetime.is_empty() {
+            lines.push(format!("agent_lifetime = \"{}\"", toml_escape(&acc.agent_lifetime)));
+        }
+        if let Some(confirm) = acc.agent_confirm {
+            lines.push(format!("agent_confirm = {confirm}"));
+        }
+        if !acc.env.is_empty() {
+            let items: Vec<String> = acc
+                .env
+                .iter()
+                .map(|(k, v)| format!("\"{}\" = \"{}\"", toml_escape(k), toml_escape(v)))
+                .collect();
+            lines.push(format!("env = {{ {} }}", items.join(", ")));
         }
         lines.push("".to_string());
     }
-    lines.join("\n") + "\n"
+    lines.iter().map(|l| format!("{l}\n")).collect()
 }
 
-pub fn load_accounts() -> Vec<Account> {
+static ACCOUNTS_CACHE: FileCache<Result<Vec<Account>>> = FileCache::new();
+static RULES_CACHE: FileCache<Result<Vec<EmailRule>>> = FileCache::new();
+static SETTINGS_CACHE: FileCache<Result<Settings>> = FileCache::new();
+
+pub fn load_accounts() -> Result<Vec<Account>> {
     let path = accounts_file();
     if !path.exists() {
-        return vec![];
+        return Ok(vec![]);
     }
-    let content = match std::fs::read_to_string(&path) {
-        Ok(c) => c,
-        Err(e) => die(&format!("Failed to read {}: {e}", path.display()), 1),
-    };
-    match toml::from_str::<AccountsFile>(&content) {
-        Ok(f) => f.accounts,
-        Err(e) => die(&format!("Failed to parse {}: {e}", path.display()), 1),
+    ACCOUNTS_CACHE
+        .get(&path, || read_accounts_file(&path))
+        .as_ref()
+        .clone()
+}
+
+/// Parses any accounts file in the `accounts.toml` format.
+pub fn read_accounts_file(path: &Path) -> Result<Vec<Account>> {
+    let content = crate::log::read_to_string(path)
+        .map_err(|e| GitIdError::Config(format!("Failed to read {}: {e}", path.display())))?;
+    toml::from_str::<AccountsFile>(&content)
+        .map(|f| f.accounts)
+        .map_err(|e| GitIdError::Config(format!("Failed to parse {}: {e}", path.display())))
+}
+
+/// The `[[rules]]` of accounts.toml.
+pub fn load_rules() -> Result<Vec<EmailRule>> {
+    let path = accounts_file();
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    RULES_CACHE
+        .get(&path, || {
+            let content = crate::log::read_to_string(&path).map_err(|e| {
+                GitIdError::Config(format!("Failed to read {}: {e}", path.display()))
+            })?;
+            toml::from_str::<AccountsFile>(&content)
+                .map(|f| f.rules)
+                .map_err(|e| GitIdError::Config(format!("Failed to parse {}: {e}", path.display())))
+        })
+        .as_ref()
+        .clone()
+}
+
+/// settings.toml, or the defaults when there is none.
+pub fn load_settings() -> Result<Settings> {
+    let path = settings_file();
+    if !path.exists() {
+        return Ok(Settings::default());
     }
+    SETTINGS_CACHE
+        .get(&path, || {
+            let content = crate::log::read_to_string(&path).map_err(|e| {
+                GitIdError::Config(format!("Failed to read {}: {e}", path.display()))
+            })?;
+            toml::from_str::<Settings>(&content)
+                .map_err(|e| GitIdError::Config(format!("Failed to parse {}: {e}", path.display())))
+        })
+        .as_ref()
+        .clone()
+}
+
+pub fn save_accounts(accounts: &[Account], dry_run: bool) -> Result<()> {
+    write_accounts_file(accounts, &load_rules()?, dry_run)
+}
+
+pub fn save_rules(rules: &[EmailRule], dry_run: bool) -> Result<()> {
+    write_accounts_file(&load_accounts()?, rules, dry_run)
 }
 
-pub fn save_accounts(accounts: &[Account], dry_run: bool) {
-    let content = accounts_to_toml(accounts);
+fn write_accounts_file(accounts: &[Account], rules: &[EmailRule], dry_run: bool) -> Result<()> {
+    let content = accounts_to_toml(accounts, rules);
     if dry_run {
         print_info("[dry-run] Would write accounts.toml:");
         print!("{content}");
-        return;
+        return Ok(());
     }
     let dir = config_dir();
     std::fs::create_dir_all(&dir)
-        .unwrap_or_else(|e| die(&format!("Cannot create config dir: {e}"), 1));
+        .map_err(|e| GitIdError::Io(format!("Cannot create config dir: {e}")))?;
     backup(&accounts_file());
-    std::fs::write(accounts_file(), &content)
-        .unwrap_or_else(|e| die(&format!("Failed to write accounts.toml: {e}"), 1));
+    crate::log::write(accounts_file(), &content)
+        .map_err(|e| GitIdError::Io(format!("Failed to write accounts.toml: {e}")))?;
+    ACCOUNTS_CACHE.invalidate();
+    RULES_CACHE.invalidate();
     print_ok(&format!("Saved {}", accounts_file().display()));
+    Ok(())
 }
 
-pub fn ensure_accounts_file() {
+pub fn ensure_accounts_file() -> Result<()> {
     if !accounts_file().exists() {
         let dir = config_dir();
         std::fs::create_dir_all(&dir)
-            .unwrap_or_else(|e| die(&format!("Cannot create config dir: {e}"), 1));
-        std::fs::write(accounts_file(), EXAMPLE_TOML)
-            .unwrap_or_else(|e| die(&format!("Failed to create accounts.toml: {e}"), 1));
+            .map_err(|e| GitIdError::Io(format!("Cannot create config dir: {e}")))?;
+        crate::log::write(accounts_file(), EXAMPLE_TOML)
+            .map_err(|e| GitIdError::Io(format!("Failed to create accounts.toml: {e}")))?;
         print_info(&format!(
             "Created {} (no accounts yet - run 'git-id add')",
             accounts_file().display()
         ));
     }
+    Ok(())
 }
 
 pub fn account_id(acc: &Account) -> String {
@@ -97,36 +457,225 @@ pub fn account_id(acc: &Account) -> String {
 }
 
 pub fn ssh_host_alias(acc: &Account) -> String {
+    if !acc.ssh_alias.is_empty() {
+        return acc.ssh_alias.clone();
+    }
     let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
     format!("{host}-{}", acc.username)
 }
 
-pub fn find_account(key: &str) -> Option<Account> {
-    let accounts = load_accounts();
-    if let Some((uname, host)) = key.split_once('@') {
-        return accounts
-            .into_iter()
-            .find(|a| a.username == uname && a.host == host);
+/// Whether repos of `owner` on `host` belong to the account: the owner is
+/// its username, a former login in `aka`, or listed in its `owners`. Hosts
+/// and owners compare case-insensitively, as forges treat them.
+pub fn owns(acc: &Account, host: &str, owner: &str) -> bool {
+    let acc_host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    acc_host.eq_ignore_ascii_case(host)
+        && std::iter::once(&acc.username)
+            .chain(&acc.aka)
+            .chain(&acc.owners)
+            .any(|o| o.eq_ignore_ascii_case(owner))
+}
+
+/// Whether `rule` covers repos of `owner` on `host`. Owners compare
+/// case-insensitively, and a rule for a GitLab group also covers its
+/// subgroups.
+pub fn rule_matches(rule: &EmailRule, host: &str, owner: &str) -> bool {
+    let owner = owner.to_lowercase();
+    let rule_owner = rule.owner.to_lowercase();
+    (rule.host.is_empty() || rule.host.eq_ignore_ascii_case(host))
+        && (rule_owner.is_empty()
+            || owner == rule_owner
+            || owner.starts_with(&format!("{rule_owner}/")))
+}
+
+/// Canonical form of an email for matching: lower-cased, and with any
+/// `+tag` in the local part removed when `strip_plus` is set.
+pub fn normalize_email(email: &str, strip_plus: bool) -> String {
+    let email = email.trim().to_lowercase();
+    if !strip_plus {
+        return email;
     }
-    let matches: Vec<Account> = accounts.into_iter().filter(|a| a.username == key).collect();
-    match matches.len() {
-        1 => Some(matches.into_iter().next().unwrap()),
-        0 => None,
-        _ => {
-            let hints: Vec<String> = matches
-                .iter()
-                .map(|a| {
-                    let host = if a.host.is_empty() { "github.com" } else { &a.host };
-                    format!("'{key}@{host}'")
-                })
-                .collect();
-            die(
-                &format!(
-                    "Multiple accounts with username '{key}'.\n  Specify host to disambiguate: {}",
-                    hints.join("  or  ")
-                ),
-                2,
-            )
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let local = local.split_once('+').map_or(local, |(base, _)| base);
+            format!("{local}@{domain}")
+        }
+        None => email,
+    }
+}
+
+/// The account's email followed by its `alt_emails`, skipping empty ones.
+pub fn commit_emails(acc: &Account) -> impl Iterator<Item = &String> {
+    std::iter::once(&acc.email).chain(&acc.alt_emails).filter(|e| !e.is_empty())
+}
+
+/// Whether `email`, already normalized with `strip_plus`, is one of the
+/// account's emails.
+pub fn has_email(acc: &Account, email: &str, strip_plus: bool) -> bool {
+    !email.is_empty() && commit_emails(acc).any(|e| normalize_email(e, strip_plus) == email)
+}
+
+/// Whether the account's `expires` date has passed. Accounts expire at the
+/// start of the given day (UTC); unparsable dates never expire.
+pub fn is_expired(acc: &Account) -> bool {
+    crate::dates::parse_date(&acc.expires).is_some_and(|day| day <= crate::dates::days_now())
+}
+
+/// Age in days of the account's SSH key: since `key_created`, else since
+/// the key file was last written. None without a key on disk, or for keys
+/// an agent holds that git-id did not generate.
+pub fn key_age_days(acc: &Account) -> Option<i64> {
+    if let Some(day) = crate::dates::parse_date(&acc.key_created) {
+        return Some(crate::dates::days_now() - day);
+    }
+    if acc.ssh_key.is_empty() || !acc.identity_agent.is_empty() {
+        return None;
+    }
+    let modified = std::fs::metadata(expand_home(&acc.ssh_key)).ok()?.modified().ok()?;
+    let secs = modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+    Some(crate::dates::days_now() - (secs / 86_400) as i64)
+}
+
+/// The age of the account's SSH key when it is past `[keys]
+/// rotate_after_days` in settings.toml, with that limit.
+pub fn stale_key_age(acc: &Account) -> Option<(i64, u64)> {
+    let limit = load_settings().map(|s| s.keys.rotate_after_days).unwrap_or(365);
+    let age = key_age_days(acc)?;
+    (limit > 0 && age > limit as i64).then_some((age, limit))
+}
+
+/// Name of the remote this account's repos use, defaulting to "origin".
+pub fn remote_name(acc: &Account) -> &str {
+    if acc.remote.is_empty() { "origin" } else { &acc.remote }
+}
+
+/// Looks an account up by `username` or `username@host`. Without an exact
+/// match, an unambiguous prefix ("ali" for alice@github.com) or substring is
+/// taken and the full id printed. Looser matches are only offered in a list
+/// to pick from, as is a choice between several accounts. Without a
+/// terminal, anything but an exact id is a usage error naming the accounts.
+pub fn find_account(key: &str) -> Result<Option<Account>> {
+    lookup_account(key, !prompt_available())
+}
+
+/// Like `find_account`, but for runs confirmed up front with `--yes`: only an
+/// exact id is accepted, so a near match cannot pick the wrong account.
+pub fn find_account_strict(key: &str) -> Result<Option<Account>> {
+    lookup_account(key, true)
+}
+
+fn lookup_account(key: &str, exact_only: bool) -> Result<Option<Account>> {
+    let accounts = load_accounts()?;
+    let exact = exact_matches(&accounts, key);
+    if exact.len() > 1 {
+        return pick_account(exact, &format!("Multiple accounts with username '{key}'")).map(Some);
+    }
+    if !exact.is_empty() {
+        return Ok(exact.into_iter().next());
+    }
+    let (candidates, close) = loose_matches(&accounts, key);
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    if exact_only {
+        let hints: Vec<String> =
+            candidates.iter().map(|a| format!("'{}'", account_id(a))).collect();
+        return Err(GitIdError::Usage(format!(
+            "No account is named '{key}'; the full id is needed here.\n  Did you mean: {}",
+            hints.join("  or  ")
+        )));
+    }
+    if close && candidates.len() == 1 {
+        let acc = candidates.into_iter().next();
+        if let Some(a) = &acc {
+            eprintln!("{} Using {} for '{key}'", crate::ui::color("cyan", "->"), account_id(a));
         }
+        return Ok(acc);
+    }
+    let problem = if close {
+        format!("'{key}' matches several accounts")
+    } else {
+        format!("No account is named '{key}'")
+    };
+    pick_account(candidates, &problem).map(Some)
+}
+
+/// Like `find_account`, but without guessing: for lookups on git's behalf,
+/// where a near match would hand out another account's credentials.
+pub fn find_account_exact(key: &str) -> Result<Option<Account>> {
+    let matches = exact_matches(&load_accounts()?, key);
+    if matches.len() > 1 {
+        return pick_account(matches, &format!("Multiple accounts with username '{key}'")).map(Some);
+    }
+    Ok(matches.into_iter().next())
+}
+
+fn exact_matches(accounts: &[Account], key: &str) -> Vec<Account> {
+    let (uname, host) = match key.split_once('@') {
+        Some((uname, host)) => (uname, Some(host)),
+        None => (key, None),
+    };
+    accounts
+        .iter()
+        .filter(|a| {
+            let acc_host = if a.host.is_empty() { "github.com" } else { &a.host };
+            a.username.eq_ignore_ascii_case(uname)
+                && host.is_none_or(|h| acc_host.eq_ignore_ascii_case(h))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Accounts whose `username@host` matches `key` loosely, from the closest
+/// kind of match there is: a prefix, then a substring, then the characters
+/// of `key` in order ("alcgh" for alice@github.com). The flag is false for
+/// that last kind, which is too loose to take without asking.
+fn loose_matches(accounts: &[Account], key: &str) -> (Vec<Account>, bool) {
+    let key = key.to_lowercase();
+    if key.is_empty() {
+        return (vec![], false);
+    }
+    let in_order = |id: &str| {
+        let mut chars = id.chars();
+        key.chars().all(|c| chars.any(|i| i == c))
+    };
+    let tiers: [&dyn Fn(&str) -> bool; 3] =
+        [&|id| id.starts_with(&key), &|id| id.contains(&key), &in_order];
+    for (tier, matches) in tiers.into_iter().enumerate() {
+        let found: Vec<Account> = accounts
+            .iter()
+            .filter(|a| matches(&account_id(a).to_lowercase()))
+            .cloned()
+            .collect();
+        if !found.is_empty() {
+            return (found, tier < 2);
+        }
+    }
+    (vec![], false)
+}
+
+/// Whether there is someone at a terminal to answer a prompt.
+fn prompt_available() -> bool {
+    use std::io::IsTerminal;
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Lets the user choose among `candidates`, or fails with `problem` and the
+/// full ids to use when there is no terminal to ask on.
+fn pick_account(mut candidates: Vec<Account>, problem: &str) -> Result<Account> {
+    let ids: Vec<String> = candidates.iter().map(account_id).collect();
+    if !prompt_available() {
+        let hints: Vec<String> = ids.iter().map(|id| format!("'{id}'")).collect();
+        return Err(GitIdError::Usage(format!(
+            "{problem}.\n  Specify one of: {}",
+            hints.join("  or  ")
+        )));
     }
+    let idx = dialoguer::Select::new()
+        .with_prompt(format!("  {problem} - which one?"))
+        .items(&ids)
+        .default(0)
+        .interact()
+        .map_err(|_| GitIdError::Usage("No account selected".to_string()))?;
+    Ok(candidates.swap_remove(idx))
 }
@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// Why a command failed. `main` prints the message and exits with
+/// `exit_code()`; everything below it returns these instead of exiting, so
+/// callers can recover or add context.
+#[derive(Debug, Clone)]
+pub enum GitIdError {
+    /// Bad input: unknown account, conflicting flags, invalid value.
+    Usage(String),
+    /// accounts.toml or another input file could not be read or parsed.
+    Config(String),
+    /// A file write or an external command (git, ssh, ssh-keygen, age) failed.
+    Io(String),
+    /// A check did not pass and the command has already printed why.
+    CheckFailed,
+    /// The command finished but printed this many warnings, and
+    /// `--strict-warnings` asked for that to fail the run.
+    Warnings(usize),
+}
+
+pub type Result<T> = std::result::Result<T, GitIdError>;
+
+impl GitIdError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GitIdError::Usage(_) => 2,
+            GitIdError::Config(_) | GitIdError::Io(_) | GitIdError::CheckFailed => 1,
+            GitIdError::Warnings(_) => 3,
+        }
+    }
+}
+
+impl fmt::Display for GitIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitIdError::Usage(msg) | GitIdError::Config(msg) | GitIdError::Io(msg) => {
+                f.write_str(msg)
+            }
+            GitIdError::CheckFailed => Ok(()),
+            GitIdError::Warnings(count) => {
+                write!(f, "{count} warning(s) printed - failing because of --strict-warnings")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GitIdError {}
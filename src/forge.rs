@@ -0,0 +1,350 @@
+use crate::models::Account;
+use serde_json::Value;
+use std::process::{Command, Stdio};
+
+/// Whether the account's host speaks the GitHub REST API: github.com, a host
+/// the account marks with `forge = "github"`, or one `host test` found a
+/// GitHub Enterprise API on. Any other host gets no GitHub API calls.
+pub fn is_github(acc: &Account) -> bool {
+    let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    match acc.forge.as_str() {
+        "" if host == "github.com" => true,
+        "" => discovered_kinds()[host].as_str() == Some("GitHub Enterprise"),
+        forge => forge == "github",
+    }
+}
+
+/// Forge kinds `discover_api_base` found, by host.
+fn discovered_kinds() -> Value {
+    crate::log::read_to_string(kinds_file())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or(Value::Null)
+}
+
+fn kinds_file() -> std::path::PathBuf {
+    crate::config::cache_dir().join("forges.json")
+}
+
+/// Remembers what `host` runs for `is_github`; a cache that cannot be
+/// written only means the host is not treated as GitHub.
+fn remember_kind(host: &str, kind: &str) {
+    if crate::config::read_only() {
+        return;
+    }
+    let mut kinds = discovered_kinds();
+    if !kinds.is_object() {
+        kinds = Value::Object(Default::default());
+    }
+    if kinds[host].as_str() == Some(kind) {
+        return;
+    }
+    kinds[host] = Value::String(kind.to_string());
+    let _ = std::fs::create_dir_all(crate::config::cache_dir());
+    let _ = crate::log::write(kinds_file(), kinds.to_string());
+}
+
+/// REST API base URL for the account's host.
+pub fn api_base(acc: &Account) -> String {
+    let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    if host == "github.com" {
+        "https://api.github.com".to_string()
+    } else {
+        format!("https://{host}/api/v3")
+    }
+}
+
+/// Finds the REST API of `host` by asking the usual locations without
+/// credentials: api.github.com for github.com, else `/api/v3` (GitHub
+/// Enterprise) and `/api/v4` (GitLab). An API answers with JSON even when it
+/// refuses an anonymous request. Returns the base URL and the forge kind,
+/// which is also remembered for `is_github`.
+pub fn discover_api_base(host: &str) -> Result<(String, &'static str), String> {
+    let candidates = if host == "github.com" {
+        vec![("https://api.github.com".to_string(), "/meta", "GitHub")]
+    } else {
+        vec![
+            (format!("https://{host}/api/v3"), "/meta", "GitHub Enterprise"),
+            (format!("https://{host}/api/v4"), "/version", "GitLab"),
+        ]
+    };
+    let mut last_error = String::new();
+    for (base, probe, kind) in candidates {
+        let mut cmd = Command::new("curl");
+        cmd.args(["-sS", "--max-time", "15", "-o", "/dev/null"])
+            .args(["-w", "%{http_code} %{content_type}"])
+            .arg(format!("{base}{probe}"));
+        let out = crate::log::output(&mut cmd).map_err(|e| format!("failed to run curl: {e}"))?;
+        if !out.status.success() {
+            last_error = String::from_utf8_lossy(&out.stderr).trim().to_string();
+            continue;
+        }
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let (status, content_type) = stdout.split_once(' ').unwrap_or((&stdout, ""));
+        if matches!(status, "200" | "401" | "403") && content_type.contains("json") {
+            remember_kind(host, kind);
+            return Ok((base, kind));
+        }
+        last_error = format!("no API at {base} (HTTP {status})");
+    }
+    Err(last_error)
+}
+
+/// Performs an authenticated GitHub API request through curl and parses the
+/// JSON body. Non-2xx responses become an error carrying the API message.
+pub fn api_request(
+    acc: &Account,
+    method: &str,
+    path: &str,
+    body: Option<&str>,
+) -> Result<Value, String> {
+    if acc.https_token.is_empty() {
+        return Err(format!("no token configured for '{}'", acc.username));
+    }
+    let token = crate::secrets::resolve(&acc.https_token)?;
+    api_request_with(acc, &token, method, path, body)
+}
+
+/// Like `api_request`, but authenticating with an explicit bearer token.
+pub fn api_request_with(
+    acc: &Account,
+    token: &str,
+    method: &str,
+    path: &str,
+    body: Option<&str>,
+) -> Result<Value, String> {
+    let response = api_call(acc, token, method, path, body)?;
+    if (200..300).contains(&response.status) {
+        Ok(response.json)
+    } else {
+        let message = response.json["message"].as_str().unwrap_or("request failed");
+        Err(format!("HTTP {}: {message}", response.status))
+    }
+}
+
+/// An API response whatever its status, with GitHub's `X-GitHub-SSO`
+/// header: `required; url=...` when the token must be authorized for an
+/// organization's SAML single sign-on first, `partial-results;
+/// organizations=...` when a list leaves such organizations out.
+pub struct ApiResponse {
+    pub status: u16,
+    pub sso: String,
+    /// Scopes of a classic token (`X-OAuth-Scopes`); None for fine-grained
+    /// and installation tokens, which GitHub answers without the header.
+    pub scopes: Option<Vec<String>>,
+    pub json: Value,
+}
+
+pub fn api_call(
+    acc: &Account,
+    token: &str,
+    method: &str,
+    path: &str,
+    body: Option<&str>,
+) -> Result<ApiResponse, String> {
+    let url = format!("{}{path}", api_base(acc));
+    let mut cmd = Command::new("curl");
+    cmd.args(["-sS", "-D", "-", "-X", method, "-w", "\n%{http_code}"])
+        .args(["-H", "Accept: application/vnd.github+json"])
+        .args(["-H", "X-GitHub-Api-Version: 2022-11-28"])
+        // Read the auth header from a curl config on stdin so the token never
+        // shows up in `ps`.
+        .args(["-K", "-"])
+        .arg(&url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(body) = body {
+        cmd.args(["-H", "Content-Type: application/json", "--data", body]);
+    }
+    let mut child = cmd.spawn().map_err(|e| format!("failed to run curl: {e}"))?;
+    // An empty token makes an anonymous request.
+    if let Some(mut stdin) = child.stdin.take()
+        && !token.is_empty()
+    {
+        use std::io::Write;
+        let header = format!("header = \"Authorization: Bearer {token}\"\n");
+        let _ = stdin.write_all(header.as_bytes());
+    }
+    let out = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run curl: {e}"))?;
+    // The response body can carry tokens, so only stderr is traced.
+    crate::log::command(&cmd, out.status.code(), &[], &out.stderr);
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+    }
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    // Header blocks come first; `100 Continue` ones precede the real one.
+    let mut rest = stdout.as_ref();
+    let mut sso = String::new();
+    let mut scopes = None;
+    while let Some((head, tail)) = rest.split_once("\r\n\r\n") {
+        let interim = head.split_whitespace().nth(1).is_some_and(|code| code.starts_with('1'));
+        for line in head.lines() {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            if name.eq_ignore_ascii_case("x-github-sso") {
+                sso = value.trim().to_string();
+            } else if name.eq_ignore_ascii_case("x-oauth-scopes") {
+                let list = value.split(',').map(str::trim).filter(|s| !s.is_empty());
+                scopes = Some(list.map(str::to_string).collect());
+            }
+        }
+        rest = tail;
+        if !interim {
+            break;
+        }
+    }
+    let (body, status) = rest.rsplit_once('\n').unwrap_or(("", rest));
+    let status: u16 = status.trim().parse().unwrap_or(0);
+    let json: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+    Ok(ApiResponse { status, sso, scopes, json })
+}
+
+/// Whether `token` may push to `owner/repo` on the account's host. Asks for
+/// the receive-pack advertisement a push starts with, which the server
+/// refuses unless the credentials have write access; nothing is pushed.
+pub fn can_push(acc: &Account, token: &str, owner: &str, repo: &str) -> Result<bool, String> {
+    let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    let url = format!("https://{host}/{owner}/{repo}.git/info/refs?service=git-receive-pack");
+    let mut cmd = Command::new("curl");
+    cmd.args(["-sS", "--max-time", "15", "-o", "/dev/null", "-w", "%{http_code}"])
+        // Credentials go through a curl config on stdin, as in `api_call`.
+        .args(["-K", "-"])
+        .arg(&url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = cmd.spawn().map_err(|e| format!("failed to run curl: {e}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        use std::io::Write;
+        let user = format!("user = \"{}:{token}\"\n", acc.username);
+        let _ = stdin.write_all(user.as_bytes());
+    }
+    let out = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run curl: {e}"))?;
+    crate::log::command(&cmd, out.status.code(), &out.stdout, &out.stderr);
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+    }
+    match String::from_utf8_lossy(&out.stdout).trim() {
+        "200" => Ok(true),
+        "401" | "403" | "404" => Ok(false),
+        status => Err(format!("unexpected HTTP {status} from {host}")),
+    }
+}
+
+/// The authorization URL in an `X-GitHub-SSO: required; url=...` header.
+pub fn sso_url(header: &str) -> Option<String> {
+    let url = header.strip_prefix("required;")?.trim().strip_prefix("url=")?;
+    Some(url.to_string())
+}
+
+/// Organizations whose SAML single sign-on `token` has not been authorized
+/// for, as (organization, URL to authorize it at). GitHub leaves them out
+/// of `/user/orgs` and names their ids in the SSO header instead.
+pub fn unauthorized_sso_orgs(acc: &Account, token: &str) -> Result<Vec<(String, String)>, String> {
+    let orgs = api_call(acc, token, "GET", "/user/orgs?per_page=100", None)?;
+    let ids = orgs
+        .sso
+        .strip_prefix("partial-results;")
+        .and_then(|rest| rest.trim().strip_prefix("organizations="))
+        .unwrap_or_default();
+    let mut found = vec![];
+    for id in ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        let org = api_call(acc, token, "GET", &format!("/organizations/{id}"), None)?;
+        let Some(url) = sso_url(&org.sso) else {
+            continue;
+        };
+        let name = url
+            .split_once("/orgs/")
+            .and_then(|(_, rest)| rest.split('/').next())
+            .unwrap_or(id)
+            .to_string();
+        found.push((name, url));
+    }
+    Ok(found)
+}
+
+/// Emails registered on the token's account, as (email, verified).
+pub fn account_emails(acc: &Account) -> Result<Vec<(String, bool)>, String> {
+    let json = api_request(acc, "GET", "/user/emails", None)?;
+    Ok(json
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .filter_map(|e| {
+                    let verified = e["verified"].as_bool().unwrap_or(false);
+                    Some((e["email"].as_str()?.to_string(), verified))
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Addresses GitHub refuses in pushed commits when the token's account
+/// keeps its email private: every registered address but the noreply ones,
+/// or none when the primary email is public.
+pub fn private_emails(acc: &Account) -> Result<Vec<String>, String> {
+    let json = api_request(acc, "GET", "/user/emails", None)?;
+    let list = json.as_array().cloned().unwrap_or_default();
+    let private = list.iter().any(|e| {
+        e["primary"].as_bool() == Some(true) && e["visibility"].as_str() == Some("private")
+    });
+    if !private {
+        return Ok(vec![]);
+    }
+    Ok(list
+        .iter()
+        .filter_map(|e| e["email"].as_str())
+        .filter(|e| !e.to_lowercase().contains("@users.noreply."))
+        .map(ToString::to_string)
+        .collect())
+}
+
+/// The account's `ID+login@users.noreply.<host>` address: from `GET /user`
+/// with the account's token, or from the public profile of its username
+/// (`GET /users/<username>`) when it has none.
+pub fn noreply_email(acc: &Account) -> Result<String, String> {
+    let json = if acc.https_token.is_empty() {
+        api_request_with(acc, "", "GET", &format!("/users/{}", acc.username), None)?
+    } else {
+        api_request(acc, "GET", "/user", None)?
+    };
+    let id = json["id"].as_u64().ok_or("no user id in the response")?;
+    let login = json["login"].as_str().unwrap_or(&acc.username);
+    let host = if acc.host.is_empty() { "github.com" } else { &acc.host };
+    Ok(format!("{id}+{login}@users.noreply.{host}"))
+}
+
+/// Registers an SSH public key on the token's account (`POST /user/keys`).
+pub fn upload_public_key(acc: &Account, title: &str, key: &str) -> Result<(), String> {
+    let body = serde_json::json!({ "title": title, "key": key }).to_string();
+    api_request(acc, "POST", "/user/keys", Some(&body)).map(|_| ())
+}
+
+/// SSH public keys registered on the token's account (`GET /user/keys`).
+pub fn public_keys(acc: &Account) -> Result<Vec<String>, String> {
+    let json = api_request(acc, "GET", "/user/keys?per_page=100", None)?;
+    Ok(json
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .filter_map(|k| k["key"].as_str().map(ToString::to_string))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Whether `email` is one of the account's GitHub noreply addresses.
+pub fn is_noreply_for(acc: &Account, email: &str) -> bool {
+    let email = email.to_lowercase();
+    let user = acc.username.to_lowercase();
+    let Some(local) = email.strip_suffix("@users.noreply.github.com") else {
+        return false;
+    };
+    local == user || local.split_once('+').is_some_and(|(_, name)| name == user)
+}
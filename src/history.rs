@@ -0,0 +1,37 @@
+use crate::config::config_dir;
+use crate::error::{GitIdError, Result};
+use crate::ui::print_info;
+use std::io::Write;
+use std::path::PathBuf;
+
+pub fn history_file() -> PathBuf {
+    config_dir().join("history.toml")
+}
+
+/// Appends one entry to the history file.
+pub fn record(action: &str, account: &str, repo: &str, detail: &str, dry_run: bool) -> Result<()> {
+    if dry_run {
+        print_info(&format!("[dry-run] Would record history: {action} {account}"));
+        return Ok(());
+    }
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let esc = |v: &str| v.replace('\\', "\\\\").replace('"', "\\\"");
+    let block = format!(
+        "[[entries]]\ntimestamp = {timestamp}\naction = \"{}\"\naccount = \"{}\"\nrepo = \"{}\"\ndetail = \"{}\"\n\n",
+        esc(action),
+        esc(account),
+        esc(repo),
+        esc(detail)
+    );
+    std::fs::create_dir_all(config_dir())
+        .map_err(|e| GitIdError::Io(format!("Cannot create config dir: {e}")))?;
+    let opened = std::fs::OpenOptions::new().create(true).append(true).open(history_file());
+    crate::log::file("appended", &history_file(), &opened);
+    let mut file = opened
+        .map_err(|e| GitIdError::Io(format!("Failed to open history file: {e}")))?;
+    let _ = file.write_all(block.as_bytes());
+    Ok(())
+}
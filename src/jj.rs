@@ -0,0 +1,56 @@
+use crate::git::set_git_dir_override;
+use crate::ui::{print_info, print_warn};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Root of the jj workspace containing the current directory, if any.
+pub fn workspace_root() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .find(|dir| dir.join(".jj").is_dir())
+        .map(Path::to_path_buf)
+}
+
+/// Git store backing a jj workspace that is not colocated with a `.git`
+/// directory. jj records its location in `.jj/repo/store/git_target`.
+pub fn backing_git_dir(root: &Path) -> Option<PathBuf> {
+    if root.join(".git").exists() {
+        return None;
+    }
+    let store = root.join(".jj").join("repo").join("store");
+    let target = crate::log::read_to_string(store.join("git_target")).ok()?;
+    let dir = store.join(target.trim());
+    dir.exists().then_some(dir)
+}
+
+/// Points git commands at the backing store when run inside a
+/// non-colocated jj workspace. Returns the workspace root when in jj.
+pub fn attach() -> Option<PathBuf> {
+    let root = workspace_root()?;
+    if let Some(dir) = backing_git_dir(&root) {
+        set_git_dir_override(dir);
+    }
+    Some(root)
+}
+
+/// Sets a repo-level jj config value (`jj config set --repo`).
+pub fn set_jj_config(key: &str, value: &str, dry_run: bool) {
+    if dry_run {
+        print_info(&format!("[dry-run] jj config set --repo {key} {value:?}"));
+        return;
+    }
+    let result = crate::log::output(
+        Command::new("jj")
+            .args(["config", "set", "--repo", key, value])
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped()),
+    );
+    match result {
+        Ok(out) if out.status.success() => {}
+        Ok(out) => print_warn(&format!(
+            "jj config set --repo {key}: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        )),
+        Err(e) => print_warn(&format!("Failed to run jj: {e}")),
+    }
+}
@@ -0,0 +1,132 @@
+use crate::git::display_url;
+use crate::ui::color;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// Environment overrides whose values are safe to show; any other variable
+/// (e.g. an account's `env` table) is printed by name only.
+const SHOWN_ENV: &[&str] = &["HOME", "GIT_DIR", "SSH_AUTH_SOCK"];
+
+/// Sets the verbosity from the number of `-v` flags. 1 traces external
+/// commands (arguments, exit code, stderr) and file access; 2 also prints
+/// the stdout of commands.
+pub fn set_level(level: u8) {
+    LEVEL.store(level, Ordering::Relaxed);
+}
+
+pub fn enabled(level: u8) -> bool {
+    LEVEL.load(Ordering::Relaxed) >= level
+}
+
+fn trace(msg: &str) {
+    eprintln!("{} {}", color("dim", "[v]"), display_url(msg));
+}
+
+fn trace_stream(name: &str, bytes: &[u8]) {
+    for line in String::from_utf8_lossy(bytes).lines().filter(|l| !l.trim().is_empty()) {
+        trace(&format!("  {name}: {line}"));
+    }
+}
+
+fn quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=@,+~%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// The command line as a shell would show it, with its environment
+/// overrides in front.
+fn describe(cmd: &Command) -> String {
+    let mut parts: Vec<String> = cmd
+        .get_envs()
+        .filter_map(|(k, v)| Some((k.to_string_lossy(), v?.to_string_lossy())))
+        .map(|(k, v)| {
+            if SHOWN_ENV.contains(&k.as_ref()) {
+                format!("{k}={}", quote(&v))
+            } else {
+                format!("{k}=***")
+            }
+        })
+        .collect();
+    parts.push(quote(&cmd.get_program().to_string_lossy()));
+    parts.extend(cmd.get_args().map(|a| quote(&a.to_string_lossy())));
+    parts.join(" ")
+}
+
+/// Traces a finished command. `code` is None when it was killed or timed out.
+pub fn command(cmd: &Command, code: Option<i32>, stdout: &[u8], stderr: &[u8]) {
+    if enabled(1) {
+        trace_command(&describe(cmd), code, stdout, stderr);
+    }
+}
+
+fn trace_command(line: &str, code: Option<i32>, stdout: &[u8], stderr: &[u8]) {
+    let status = code.map_or("killed".to_string(), |c| format!("exit {c}"));
+    trace(&format!("$ {line} ({status})"));
+    if enabled(2) {
+        trace_stream("stdout", stdout);
+    }
+    trace_stream("stderr", stderr);
+}
+
+/// Traces a command that is about to replace this process.
+pub fn exec(cmd: &Command) {
+    if enabled(1) {
+        trace(&format!("$ exec {}", describe(cmd)));
+    }
+}
+
+/// `cmd.output()`, traced.
+pub fn output(cmd: &mut Command) -> io::Result<Output> {
+    let result = cmd.output();
+    match &result {
+        Ok(out) => command(cmd, out.status.code(), &out.stdout, &out.stderr),
+        Err(e) if enabled(1) => trace(&format!("$ {} (failed to start: {e})", describe(cmd))),
+        Err(_) => {}
+    }
+    result
+}
+
+/// `cmd.output()` of a command that prints a secret, traced without its
+/// stdout.
+pub fn output_secret(cmd: &mut Command) -> io::Result<Output> {
+    let result = cmd.output();
+    match &result {
+        Ok(out) => command(cmd, out.status.code(), b"", &out.stderr),
+        Err(e) if enabled(1) => trace(&format!("$ {} (failed to start: {e})", describe(cmd))),
+        Err(_) => {}
+    }
+    result
+}
+
+/// Traces access to a file: `action` is "read", "wrote", "appended", ...
+pub fn file(action: &str, path: &Path, result: &io::Result<impl Sized>) {
+    if !enabled(1) {
+        return;
+    }
+    match result {
+        Ok(_) => trace(&format!("{action} {}", path.display())),
+        Err(e) => trace(&format!("{action} {} failed: {e}", path.display())),
+    }
+}
+
+/// `std::fs::read_to_string`, traced.
+pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+    let result = std::fs::read_to_string(path.as_ref());
+    file("read", path.as_ref(), &result);
+    result
+}
+
+/// `std::fs::write`, traced.
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let result = std::fs::write(path.as_ref(), contents);
+    file("wrote", path.as_ref(), &result);
+    result
+}
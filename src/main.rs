@@ -1,33 +1,255 @@
 mod cli;
 mod commands;
 mod config;
+mod dates;
+mod error;
+mod forge;
 mod git;
+mod history;
+mod jj;
+mod log;
 mod models;
+mod output;
+mod repos;
+mod secrets;
 mod ssh;
+mod store;
+mod tokens;
 mod ui;
 
-use cli::{Cli, Commands, SshCommands};
+use cli::{
+    AccountsCommands, BackupsCommands, Cli, Commands, DebugCommands, DirsCommands, HookCommands,
+    HostCommands, ReposCommands, RulesCommands, SshCommands, TokenCommands,
+};
 use clap::Parser;
+use error::{GitIdError, Result};
+use output::Format;
 
 fn main() {
-    let cli = Cli::parse();
+    // Run by ssh-keygen or ssh-add as their askpass program, for a key git-id
+    // just generated.
+    if let Ok(passphrase) = std::env::var(ssh::ASKPASS_VAR)
+        && std::env::var_os("SSH_ASKPASS_REQUIRE").is_some()
+    {
+        println!("{passphrase}");
+        return;
+    }
+    let result = run(Cli::parse());
+    config::restore_ownership();
+    if let Err(e) = result {
+        if !matches!(e, GitIdError::CheckFailed) {
+            ui::print_err(&e.to_string());
+        }
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    log::set_level(cli.verbose);
+    git::set_show_secrets(cli.show_secrets);
     let dry_run = cli.dry_run;
+    let strict_warnings = cli.strict_warnings;
+    let read_only = cli.read_only || std::env::var("GIT_ID_READONLY").is_ok_and(|v| v == "1");
+    config::set_read_only(read_only);
+    // A prompt segment runs on every redraw and must stay quiet.
+    let prompt = matches!(cli.command, Commands::Current { prompt: true });
+
+    if let Some(user) = &cli.user {
+        let home = config::user_home(user).ok_or_else(|| {
+            GitIdError::Usage(format!("Cannot resolve home directory of user '{user}'"))
+        })?;
+        config::set_home_override(home);
+    } else if let Some(home) = cli.home.clone() {
+        config::set_home_override(home);
+    } else if let Ok(sudo_user) = std::env::var("SUDO_USER")
+        && !prompt
+    {
+        ui::print_warn(&format!(
+            "Running under sudo: git-id will manage the setup in {}, not {sudo_user}'s.\n  \
+             Pass --user {sudo_user} to manage their accounts instead.",
+            config::dirs_home().display()
+        ));
+    }
+
+    jj::attach();
+
+    if read_only && cli.command.is_mutating() {
+        return Err(GitIdError::Usage(
+            "Read-only mode is enabled (--read-only or GIT_ID_READONLY=1); this command would modify files."
+                .to_string(),
+        ));
+    }
 
-    match cli.command {
-        Commands::Add => commands::add::cmd_add(dry_run),
-        Commands::List => commands::list::cmd_list(),
-        Commands::Use { username, global, force_ssh, force_https } => {
-            commands::use_cmd::cmd_use(&username, global, force_ssh, force_https, dry_run);
+    if !read_only && !prompt {
+        config::migrate_config_dir(dry_run);
+    }
+
+    let result = match cli.command {
+        Commands::Add(args) => commands::add::cmd_add(&args, dry_run),
+        Commands::List { format, json, keys: true, network } => {
+            commands::list::cmd_list_keys(Format::from_flags(format.as_deref(), json), network)
+        }
+        Commands::List { format, json, keys: false, .. } => {
+            commands::list::cmd_list(Format::from_flags(format.as_deref(), json))
+        }
+        Commands::Use(args) => commands::use_cmd::cmd_use(&args, dry_run),
+        Commands::Switch { global, worktree } => {
+            commands::use_cmd::cmd_switch(global, worktree, dry_run)
+        }
+        Commands::Clone { url, dest, account, force_ssh, force_https } => {
+            commands::clone::cmd_clone(
+                &url,
+                dest,
+                account.as_deref(),
+                force_ssh,
+                force_https,
+                dry_run,
+            )
         }
+        Commands::Edit(args) => commands::edit::cmd_edit(&args, dry_run),
         Commands::Remove { username, yes, delete_keys } => {
-            commands::remove::cmd_remove(&username, yes, delete_keys, dry_run);
+            commands::remove::cmd_remove(&username, yes, delete_keys, dry_run)
         }
         Commands::Ssh { subcommand } => match subcommand {
-            SshCommands::Gen { username } => commands::ssh::cmd_ssh_gen(&username, dry_run),
-            SshCommands::Pick { username } => commands::ssh::cmd_ssh_pick(&username, dry_run),
-            SshCommands::Config => commands::ssh::cmd_ssh_config(dry_run),
+            SshCommands::Gen { username, key_type, bits, passphrase, passphrase_stdin } => {
+                commands::ssh::cmd_ssh_gen(
+                    &username,
+                    &key_type,
+                    bits,
+                    passphrase,
+                    passphrase_stdin,
+                    dry_run,
+                )
+            }
+            SshCommands::Rotate { username, key_type, passphrase } => {
+                commands::ssh::cmd_ssh_rotate(&username, key_type.as_deref(), passphrase, dry_run)
+            }
+            SshCommands::Pick { username, from, paste } => {
+                commands::ssh::cmd_ssh_pick(&username, from.as_deref(), paste, dry_run)
+            }
+            SshCommands::Config { yes } => commands::ssh::cmd_ssh_config(yes, dry_run),
+            SshCommands::Command { username, env } => {
+                commands::ssh::cmd_ssh_command(&username, env)
+            }
+            SshCommands::ExportEscrow { username, recipients, output, yes } => {
+                commands::ssh::cmd_ssh_export_escrow(&username, &recipients, output, yes, dry_run)
+            }
+            SshCommands::Export { username, output } => {
+                commands::ssh::cmd_ssh_export(&username, output, dry_run)
+            }
+            SshCommands::Import { username, file } => {
+                commands::ssh::cmd_ssh_import(&username, &file, dry_run)
+            }
+            SshCommands::Test { username } => commands::ssh::cmd_ssh_test(&username),
+        },
+        Commands::Status { format, json, remote, all_remotes } => {
+            let format = Format::from_flags(format.as_deref(), json);
+            commands::status::cmd_status(format, remote.as_deref(), all_remotes)
+        }
+        Commands::Check { account, format, json } => {
+            let format = Format::from_flags(format.as_deref(), json);
+            commands::check::cmd_check(account.as_deref(), format)
+        }
+        Commands::ExplainFailure { remote, push } => {
+            commands::explain::cmd_explain_failure(remote.as_deref(), push)
+        }
+        Commands::Doctor { network } => commands::doctor::cmd_doctor(network),
+        Commands::Env { account } => commands::env::cmd_env(account.as_deref()),
+        Commands::Whoami => commands::whoami::cmd_whoami(),
+        Commands::Current { prompt } => commands::current::cmd_current(prompt),
+        Commands::Token { username, subcommand } => match subcommand {
+            Some(TokenCommands::Check { username, open }) => {
+                commands::token::cmd_token_check(&username, open)
+            }
+            None => commands::token::cmd_token(username.as_deref().unwrap_or_default()),
         },
-        Commands::Status => commands::status::cmd_status(),
+        Commands::With { username, command } => commands::with::cmd_with(&username, &command),
+        Commands::Transfer { from, to, reauthor } => {
+            commands::transfer::cmd_transfer(&from, &to, reauthor, dry_run)
+        }
+        Commands::Verify { account, attribution } => {
+            commands::verify::cmd_verify(account.as_deref(), attribution)
+        }
+        Commands::Dirs { subcommand } => match subcommand {
+            DirsCommands::Add { path, username } => {
+                commands::dirs::cmd_dirs_add(&path, &username, dry_run)
+            }
+            DirsCommands::Remove { path } => commands::dirs::cmd_dirs_remove(&path, dry_run),
+            DirsCommands::List => commands::dirs::cmd_dirs_list(),
+            DirsCommands::Tree => commands::dirs::cmd_dirs_tree(),
+            DirsCommands::Gc => commands::dirs::cmd_dirs_gc(dry_run),
+        },
+        Commands::Repos { subcommand } => match subcommand {
+            ReposCommands::Add { paths } => commands::repos::cmd_repos_add(&paths, dry_run),
+            ReposCommands::Remove { paths } => commands::repos::cmd_repos_remove(&paths, dry_run),
+            ReposCommands::List => commands::repos::cmd_repos_list(),
+            ReposCommands::Clean => commands::repos::cmd_repos_clean(dry_run),
+        },
+        Commands::Host { subcommand } => match subcommand {
+            HostCommands::List => commands::host::cmd_host_list(),
+            HostCommands::Test { host } => commands::host::cmd_host_test(&host),
+            HostCommands::SetDefault { host } => {
+                commands::host::cmd_host_set_default(&host, dry_run)
+            }
+        },
+        Commands::Rules { subcommand } => match subcommand {
+            RulesCommands::List => commands::rules::cmd_rules_list(),
+            RulesCommands::Add { email, owner, host } => commands::rules::cmd_rules_add(
+                owner.as_deref(),
+                host.as_deref(),
+                &email,
+                dry_run,
+            ),
+            RulesCommands::Remove { index } => commands::rules::cmd_rules_remove(index, dry_run),
+        },
+        Commands::Hook { subcommand } => match subcommand {
+            HookCommands::Install { account, pre_push } => {
+                commands::hook::cmd_hook_install(account.as_deref(), pre_push, dry_run)
+            }
+            HookCommands::Uninstall => commands::hook::cmd_hook_uninstall(dry_run),
+            HookCommands::Check => commands::hook::cmd_hook_check(),
+            HookCommands::PrePush { url, .. } => commands::hook::cmd_hook_pre_push(&url),
+        },
+        Commands::Accounts { subcommand } => match subcommand {
+            AccountsCommands::Diff { file } => commands::accounts::cmd_accounts_diff(&file),
+        },
+        Commands::Export { output, include_keys, no_tokens } => {
+            commands::export::cmd_export(output.as_deref(), include_keys, no_tokens, dry_run)
+        }
+        Commands::Import { from_history: Some(dirs), min_commits, .. } => {
+            commands::import::cmd_import_history(&dirs, min_commits, dry_run)
+        }
+        Commands::Import { file, .. } => {
+            commands::import::cmd_import(&file.unwrap_or_default(), dry_run)
+        }
+        Commands::Credential { operation, account } => {
+            commands::credential::cmd_credential(&operation, account.as_deref())
+        }
+        Commands::Restore { target, list, from } => {
+            commands::restore::cmd_restore(target.as_deref(), list, from, dry_run)
+        }
+        Commands::Tui => commands::tui::cmd_tui(dry_run),
+        Commands::Backups { subcommand } => match subcommand {
+            BackupsCommands::Clean { keep, days } => {
+                commands::backups::cmd_backups_clean(keep, days, dry_run)
+            }
+        },
+        Commands::Uninstall { repos, keep_backups, yes } => {
+            commands::uninstall::cmd_uninstall(&repos, keep_backups, yes, dry_run)
+        }
         Commands::Completions { shell } => commands::completions::cmd_completions(shell),
+        Commands::Debug { subcommand } => match subcommand {
+            DebugCommands::Fixture { scenario, accounts, dir } => {
+                commands::debug::cmd_debug_fixture(&scenario, accounts, dir, dry_run)
+            }
+        },
+    };
+
+    if dry_run && result.is_ok() {
+        git::print_dry_run_summary()
+    }
+    if strict_warnings && result.is_ok() && ui::warning_count() > 0 {
+        return Err(GitIdError::Warnings(ui::warning_count()));
     }
+    result
 }
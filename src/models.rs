@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Account {
@@ -12,10 +13,213 @@ pub struct Account {
     pub ssh_key: String,
     #[serde(default)]
     pub https_token: String,
+    #[serde(default)]
+    pub remote: String,
+    #[serde(default)]
+    pub identity_agent: String,
+    #[serde(default)]
+    pub exclude_remotes: Vec<String>,
+    #[serde(default)]
+    pub control_persist: String,
+    #[serde(default)]
+    pub control_path: String,
+    #[serde(default)]
+    pub proxy_jump: String,
+    #[serde(default)]
+    pub app_id: String,
+    #[serde(default)]
+    pub app_key: String,
+    #[serde(default)]
+    pub app_installation_id: String,
+    #[serde(default)]
+    pub expires: String,
+    /// Day (YYYY-MM-DD) git-id generated the account's SSH key; empty for
+    /// keys from elsewhere, whose file modification time stands in.
+    #[serde(default)]
+    pub key_created: String,
+    /// SSH `Host` alias to use instead of the generated `<host>-<username>`.
+    #[serde(default)]
+    pub ssh_alias: String,
+    /// Transport `use` and `clone` switch remotes to, "ssh" or "https";
+    /// empty keeps whatever the remote uses.
+    #[serde(default)]
+    pub protocol: String,
+    /// What the host runs, "github" or "gitlab". Empty means github.com is
+    /// GitHub and other hosts are whatever `host test` found there.
+    #[serde(default)]
+    pub forge: String,
+    /// Client certificate git presents to the account's host over HTTPS
+    /// (`http.<url>.sslCert`), for servers that require mutual TLS.
+    #[serde(default)]
+    pub https_cert: String,
+    /// Private key of `https_cert` (`http.<url>.sslKey`); empty when the
+    /// certificate file holds it too.
+    #[serde(default)]
+    pub https_key: String,
+    /// Users or organisations on the account's host whose repos belong to
+    /// this account, besides its own username.
+    #[serde(default)]
+    pub owners: Vec<String>,
+    /// Logins the account had before it was renamed. Repos under them, and
+    /// SSH aliases generated from them, still belong to the account.
+    #[serde(default)]
+    pub aka: Vec<String>,
+    /// Emails the account committed with before its email was changed.
+    /// Older commits made with them still count as the account's.
+    #[serde(default)]
+    pub previous_emails: Vec<String>,
+    /// Other emails the account commits with today, such as a work alias
+    /// or the noreply address. `use` can apply any of them, and each one
+    /// marks the account active.
+    #[serde(default)]
+    pub alt_emails: Vec<String>,
+    /// Leave the account's hand-edited SSH stanza alone instead of asking
+    /// when git-id would write a different one.
+    #[serde(default)]
+    pub ssh_keep_manual: bool,
+    /// How long ssh-agent keeps the account's key (`ssh-add -t`), in seconds
+    /// or ssh's time format such as `1h30m`; empty uses settings.toml.
+    #[serde(default, deserialize_with = "text_or_number")]
+    pub agent_lifetime: String,
+    /// Whether ssh-agent asks before each use of the key (`ssh-add -c`);
+    /// unset uses settings.toml.
+    #[serde(default)]
+    pub agent_confirm: Option<bool>,
+    /// Timezone the account's commits are dated in (`TZ`, e.g.
+    /// `America/New_York`), applied by `with` and `env`; empty keeps the
+    /// local one.
+    #[serde(default)]
+    pub commit_timezone: String,
+    /// Key `use` has git sign commits with: a GPG key id, or the path of an
+    /// SSH public key (signed with `gpg.format = ssh`).
+    #[serde(default)]
+    pub signing_key: String,
+    /// Commit message template `use` sets as `commit.template`.
+    #[serde(default)]
+    pub commit_template: String,
+    /// Extra environment variables exported while this identity is in use.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+/// Accepts `3600` as well as `"1h"` for a duration field.
+fn text_or_number<'de, D: serde::Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Text(String),
+        Number(u64),
+    }
+    Ok(match Raw::deserialize(d)? {
+        Raw::Text(s) => s,
+        Raw::Number(n) => n.to_string(),
+    })
+}
+
+/// Tool-wide defaults, read from `settings.toml` next to accounts.toml.
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct Settings {
+    #[serde(default)]
+    pub agent: AgentSettings,
+    #[serde(default)]
+    pub backups: BackupSettings,
+    #[serde(default)]
+    pub remotes: RemoteSettings,
+    #[serde(default)]
+    pub keys: KeySettings,
+}
+
+/// `[keys]`: when SSH keys are due for rotation.
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeySettings {
+    /// Age in days past which `list`, `status` and `doctor` warn about a
+    /// key; 0 turns the warning off.
+    #[serde(default = "default_rotate_after_days")]
+    pub rotate_after_days: u64,
+}
+
+fn default_rotate_after_days() -> u64 {
+    365
+}
+
+impl Default for KeySettings {
+    fn default() -> Self {
+        KeySettings { rotate_after_days: default_rotate_after_days() }
+    }
+}
+
+/// `[remotes]`: which remotes carry a repository's identity.
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct RemoteSettings {
+    /// Remote names `status` looks at, the first one a repo has wins;
+    /// origin when none of them exists.
+    #[serde(default)]
+    pub primary: Vec<String>,
+}
+
+/// `[agent]`: how keys are loaded into ssh-agent when the account does not
+/// say otherwise.
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct AgentSettings {
+    #[serde(default, deserialize_with = "text_or_number")]
+    pub lifetime: String,
+    #[serde(default)]
+    pub confirm: bool,
+    /// Unload the other accounts' keys whenever `use` switches account.
+    #[serde(default)]
+    pub exclusive: bool,
+}
+
+/// `[backups]`: how many `.bak.*` copies of each file are kept. A backup
+/// survives while it is one of the newest `keep`, or younger than `days`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BackupSettings {
+    /// 0 keeps every backup.
+    #[serde(default = "default_backup_keep")]
+    pub keep: usize,
+    /// 0 turns the age rule off.
+    #[serde(default)]
+    pub days: u64,
+}
+
+fn default_backup_keep() -> usize {
+    10
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings { keep: default_backup_keep(), days: 0 }
+    }
+}
+
+/// Commits in repos of `owner` on `host` must use `email`. An empty host
+/// or owner matches any.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct EmailRule {
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub owner: String,
+    #[serde(default)]
+    pub email: String,
+}
+
+/// `repos.toml`: the repositories git-id knows about.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RepoRegistry {
+    #[serde(default)]
+    pub repos: Vec<RepoEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RepoEntry {
+    pub path: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AccountsFile {
     #[serde(default)]
     pub accounts: Vec<Account>,
+    #[serde(default)]
+    pub rules: Vec<EmailRule>,
 }
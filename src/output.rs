@@ -0,0 +1,174 @@
+use crate::error::{GitIdError, Result};
+use clap::builder::{PossibleValue, PossibleValuesParser};
+use serde::Serialize;
+use serde_json::Value;
+
+/// How a read command prints its report (`--format`).
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The colored terminal view ("pretty", formerly "human").
+    Human,
+    /// Aligned columns, one line per item.
+    Table,
+    Json,
+    Yaml,
+    Markdown,
+}
+
+/// Values `--format` takes; "human" is still accepted for "pretty". Only
+/// commands that list items offer "table".
+pub fn format_parser(table: bool) -> PossibleValuesParser {
+    let mut values = vec![PossibleValue::new("pretty").alias("human")];
+    if table {
+        values.push(PossibleValue::new("table"));
+    }
+    values.extend(["json", "yaml", "markdown"].map(PossibleValue::new));
+    PossibleValuesParser::new(values)
+}
+
+impl Format {
+    /// The format picked by `--format`, with `--json` kept as a shorthand.
+    pub fn from_flags(format: Option<&str>, json: bool) -> Format {
+        match format {
+            Some("table") => Format::Table,
+            Some("json") => Format::Json,
+            Some("yaml") => Format::Yaml,
+            Some("markdown") => Format::Markdown,
+            Some(_) => Format::Human,
+            None if json => Format::Json,
+            None => Format::Human,
+        }
+    }
+}
+
+/// What a read command produces. The serialized form is what the json and
+/// yaml formats print, so field names are part of the scripting interface.
+pub trait Report: Serialize {
+    /// The colored terminal view.
+    fn human(&self);
+    /// A markdown document, e.g. for pasting into a wiki.
+    fn markdown(&self) -> String;
+    /// Aligned columns, for reports that are a list of items (the others
+    /// do not offer `--format table`).
+    fn table(&self) {
+        self.human();
+    }
+}
+
+pub fn render(report: &impl Report, format: Format) -> Result<()> {
+    match format {
+        Format::Human => report.human(),
+        Format::Table => report.table(),
+        Format::Markdown => print!("{}", report.markdown()),
+        Format::Json | Format::Yaml => {
+            let value = serde_json::to_value(report)
+                .map_err(|e| GitIdError::Io(format!("Failed to encode the report: {e}")))?;
+            if format == Format::Json {
+                println!("{}", serde_json::to_string_pretty(&value).unwrap_or_default());
+            } else {
+                let mut out = String::new();
+                yaml(&value, 0, &mut out);
+                print!("{out}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A markdown table. Cells are escaped so `|` and newlines in values keep
+/// the table intact.
+pub fn markdown_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let cell = |v: &str| {
+        let v = v.replace('|', "\\|").replace('\n', "<br>");
+        if v.is_empty() { "-".to_string() } else { v }
+    };
+    let mut out = format!("| {} |\n", headers.join(" | "));
+    out.push_str(&format!("|{}\n", "---|".repeat(headers.len())));
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|v| cell(v)).collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    out
+}
+
+/// Columns padded to their widest cell, headers in capitals. Empty cells
+/// show as `-`.
+pub fn plain_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let cell = |v: &str| if v.is_empty() { "-".to_string() } else { v.to_string() };
+    let mut lines: Vec<Vec<String>> = vec![headers.iter().map(|h| h.to_uppercase()).collect()];
+    lines.extend(rows.iter().map(|row| row.iter().map(|v| cell(v)).collect()));
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|i| lines.iter().map(|l| l.get(i).map_or(0, |v| v.chars().count())).max().unwrap_or(0))
+        .collect();
+    let mut out = String::new();
+    for line in &lines {
+        let cells: Vec<String> =
+            line.iter().zip(&widths).map(|(v, w)| format!("{v:<w$}")).collect();
+        out.push_str(cells.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Writes `value` as block-style YAML.
+fn yaml(value: &Value, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(fields) => {
+            for (key, item) in fields {
+                match item {
+                    Value::Object(m) if !m.is_empty() => {
+                        out.push_str(&format!("{pad}{}:\n", yaml_scalar(key)));
+                        yaml(item, indent + 2, out);
+                    }
+                    Value::Array(a) if !a.is_empty() => {
+                        out.push_str(&format!("{pad}{}:\n", yaml_scalar(key)));
+                        yaml(item, indent, out);
+                    }
+                    _ => out.push_str(&format!("{pad}{}: {}\n", yaml_scalar(key), inline(item))),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                match item {
+                    Value::Object(m) if !m.is_empty() => {
+                        // The first field goes on the dash line.
+                        let mut nested = String::new();
+                        yaml(item, indent + 2, &mut nested);
+                        out.push_str(&format!("{pad}- {}", &nested[indent + 2..]));
+                    }
+                    Value::Array(a) if !a.is_empty() => {
+                        out.push_str(&format!("{pad}-\n"));
+                        yaml(item, indent + 2, out);
+                    }
+                    _ => out.push_str(&format!("{pad}- {}\n", inline(item))),
+                }
+            }
+        }
+        _ => out.push_str(&format!("{pad}{}\n", inline(value))),
+    }
+}
+
+/// Scalars and empty collections, which fit on the key's line.
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => yaml_scalar(s),
+        Value::Array(_) => "[]".to_string(),
+        Value::Object(_) => "{}".to_string(),
+    }
+}
+
+/// `s` bare when YAML would read it back as the same string, quoted
+/// otherwise (JSON string syntax is valid YAML). Anything starting with a
+/// digit is quoted so dates and versions stay strings.
+fn yaml_scalar(s: &str) -> String {
+    let plain = s.starts_with(|c: char| c.is_alphabetic() || c == '/')
+        && s.chars().all(|c| c.is_alphanumeric() || "-_./@~+".contains(c))
+        && !["true", "false", "null", "yes", "no", "on", "off", "y", "n"]
+            .contains(&s.to_lowercase().as_str());
+    if plain { s.to_string() } else { serde_json::to_string(s).unwrap_or_default() }
+}
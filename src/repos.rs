@@ -0,0 +1,104 @@
+use crate::config::config_dir;
+use crate::error::{GitIdError, Result};
+use crate::git::run_git;
+use crate::models::{RepoEntry, RepoRegistry};
+use crate::store::FileCache;
+use crate::ui::{print_info, print_warn};
+use std::path::{Path, PathBuf};
+
+static REPOS_CACHE: FileCache<Result<Vec<PathBuf>>> = FileCache::new();
+
+/// The registry of repositories git-id knows about: those set up with
+/// `use`, `clone` or `transfer`, and those added with `repos add`.
+pub fn registry_file() -> PathBuf {
+    config_dir().join("repos.toml")
+}
+
+pub fn load_repos() -> Result<Vec<PathBuf>> {
+    let path = registry_file();
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    REPOS_CACHE
+        .get(&path, || {
+            let content = crate::log::read_to_string(&path).map_err(|e| {
+                GitIdError::Config(format!("Failed to read {}: {e}", path.display()))
+            })?;
+            let registry: RepoRegistry = toml::from_str(&content).map_err(|e| {
+                GitIdError::Config(format!("Failed to parse {}: {e}", path.display()))
+            })?;
+            Ok(registry.repos.into_iter().map(|r| PathBuf::from(r.path)).collect())
+        })
+        .as_ref()
+        .clone()
+}
+
+pub fn save_repos(repos: &[PathBuf], dry_run: bool) -> Result<()> {
+    if dry_run {
+        return Ok(());
+    }
+    let registry = RepoRegistry {
+        repos: repos
+            .iter()
+            .map(|p| RepoEntry { path: p.to_string_lossy().to_string() })
+            .collect(),
+    };
+    let content = toml::to_string(&registry)
+        .map_err(|e| GitIdError::Config(format!("Cannot serialize the repo registry: {e}")))?;
+    std::fs::create_dir_all(config_dir())
+        .map_err(|e| GitIdError::Io(format!("Cannot create config dir: {e}")))?;
+    let path = registry_file();
+    let header = "# Repositories known to git-id (git-id repos list)";
+    crate::log::write(&path, format!("{header}\n{content}"))
+        .map_err(|e| GitIdError::Io(format!("Failed to write {}: {e}", path.display())))?;
+    REPOS_CACHE.invalidate();
+    Ok(())
+}
+
+/// Top-level directory of the repository `dir` is in, or the git directory
+/// itself for a bare repository (a `clone --mirror`, say), which has no
+/// work tree.
+pub fn repo_root(dir: &Path) -> Option<PathBuf> {
+    let dir = dir.to_string_lossy();
+    let (code, out, _) =
+        run_git(&["-C", &dir, "rev-parse", "--is-bare-repository", "--absolute-git-dir"]);
+    match out.split_once('\n') {
+        Some(("true", git_dir)) if code == 0 => Some(PathBuf::from(git_dir)),
+        Some(_) if code == 0 => {
+            let (code, out, _) = run_git(&["-C", &dir, "rev-parse", "--show-toplevel"]);
+            (code == 0 && !out.is_empty()).then(|| PathBuf::from(out))
+        }
+        _ => None,
+    }
+}
+
+/// Adds the current repository to the registry unless it is there already.
+/// A registry that cannot be updated only warns: it never fails the command
+/// that set the repo up.
+pub fn remember_current_repo(dry_run: bool) {
+    let root = std::env::current_dir()
+        .ok()
+        .and_then(|dir| repo_root(&dir))
+        .or_else(crate::jj::workspace_root);
+    let Some(root) = root else {
+        return;
+    };
+    let mut repos = match load_repos() {
+        Ok(repos) => repos,
+        Err(e) => {
+            print_warn(&format!("Cannot update the repo registry: {e}"));
+            return;
+        }
+    };
+    if repos.contains(&root) {
+        return;
+    }
+    if dry_run {
+        print_info(&format!("[dry-run] Would add {} to the repo registry", root.display()));
+        return;
+    }
+    repos.push(root);
+    if let Err(e) = save_repos(&repos, false) {
+        print_warn(&format!("Cannot update the repo registry: {e}"));
+    }
+}
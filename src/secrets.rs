@@ -0,0 +1,166 @@
+use crate::config::expand_home;
+use crate::models::Account;
+use crate::ui::print_warn;
+use std::collections::BTreeMap;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Values in accounts.toml starting with this are references to a secret
+/// kept elsewhere: `secret:<backend>:<ref>`.
+pub const PREFIX: &str = "secret:";
+
+pub const BACKENDS: &[&str] = &["pass", "op", "env", "file", "keychain", "cmd"];
+
+/// Fields whose references are looked up only by the code that needs the
+/// credential: the token by the credential helper and forge calls, `env`
+/// by `with` and `env`. `resolved` leaves them as they are.
+const LAZY_FIELDS: &[&str] = &["https_token", "env"];
+
+/// Secrets already looked up in this run, by reference, so a backend that
+/// prompts (a gpg pinentry, a 1Password unlock) does so once.
+static RESOLVED: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+pub fn is_reference(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// The value a field stands for: the field itself, or for a reference the
+/// secret it points at. Backends run only when a command needs the value.
+pub fn resolve(value: &str) -> Result<String, String> {
+    let Some(rest) = value.strip_prefix(PREFIX) else {
+        return Ok(value.to_string());
+    };
+    if let Some(secret) = RESOLVED.lock().ok().and_then(|m| m.get(value).cloned()) {
+        return Ok(secret);
+    }
+    let (backend, reference) = rest
+        .split_once(':')
+        .filter(|(_, r)| !r.is_empty())
+        .ok_or_else(|| format!("'{value}' is not of the form secret:<backend>:<ref>"))?;
+    let secret = match backend {
+        "env" => std::env::var(reference).map_err(|_| format!("${reference} is not set"))?,
+        "file" => crate::log::read_to_string(expand_home(reference))
+            .map(|s| s.trim_end_matches(['\r', '\n']).to_string())
+            .map_err(|e| format!("cannot read {reference}: {e}"))?,
+        // The first line is the password; pass keeps metadata below it.
+        "pass" => run(Command::new("pass").args(["show", reference]))?
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        // Accept both secret:op:op://vault/item/field and secret:op://vault/item/field.
+        "op" => {
+            let uri = if reference.starts_with("//") {
+                format!("op:{reference}")
+            } else {
+                reference.to_string()
+            };
+            run(Command::new("op").args(["read", "--no-newline", &uri]))?
+        }
+        "keychain" => {
+            run(Command::new("security").args(["find-generic-password", "-s", reference, "-w"]))?
+        }
+        "cmd" => run(Command::new("sh").args(["-c", reference]))?,
+        other => {
+            return Err(format!(
+                "unknown secret backend '{other}' (use one of: {})",
+                BACKENDS.join(", ")
+            ));
+        }
+    };
+    let secret = secret.trim_end_matches(['\r', '\n']).to_string();
+    if secret.is_empty() {
+        return Err(format!("'{value}' resolved to an empty value"));
+    }
+    if let Ok(mut resolved) = RESOLVED.lock() {
+        resolved.insert(value.to_string(), secret.clone());
+    }
+    Ok(secret)
+}
+
+/// Warns when `value` is a reference that cannot be looked up right now, so
+/// a typo shows when the field is entered rather than on first use.
+pub fn warn_unresolved(field: &str, value: &str) {
+    if is_reference(value)
+        && let Err(e) = resolve(value)
+    {
+        print_warn(&format!("{field} does not resolve yet: {e}"));
+    }
+}
+
+/// Runs a backend command, its output kept out of verbose traces. Stdin is
+/// passed through so a backend can ask to unlock its store.
+fn run(cmd: &mut Command) -> Result<String, String> {
+    cmd.stdin(Stdio::inherit());
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let out = crate::log::output_secret(cmd).map_err(|e| format!("cannot run {program}: {e}"))?;
+    if !out.status.success() {
+        let err = String::from_utf8_lossy(&out.stderr).trim().to_string();
+        let err = if err.is_empty() { format!("{program} failed") } else { err };
+        return Err(err);
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+}
+
+/// Every field of the account holding a reference, as (field, reference).
+/// Walks the serialized account so fields added later are covered too.
+pub fn references(acc: &Account) -> Vec<(String, String)> {
+    fn walk(path: String, value: &serde_json::Value, found: &mut Vec<(String, String)>) {
+        match value {
+            serde_json::Value::String(s) if is_reference(s) => found.push((path, s.clone())),
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    walk(path.clone(), item, found);
+                }
+            }
+            serde_json::Value::Object(fields) => {
+                for (name, item) in fields {
+                    let field =
+                        if path.is_empty() { name.clone() } else { format!("{path}.{name}") };
+                    walk(field, item, found);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut found = vec![];
+    if let Ok(value) = serde_json::to_value(acc) {
+        walk(String::new(), &value, &mut found);
+    }
+    found
+}
+
+/// The account with every reference outside `LAZY_FIELDS` replaced by the
+/// secret it points at, for commands that apply the account's settings to
+/// git or ssh. Walks the serialized account like `references`, so fields
+/// added later are covered too; backends run only for the references found.
+pub fn resolved(acc: &Account) -> crate::error::Result<Account> {
+    fn walk(path: &str, value: &mut serde_json::Value) -> Result<(), String> {
+        match value {
+            serde_json::Value::String(s) if is_reference(s) => {
+                *s = resolve(s).map_err(|e| format!("{path} does not resolve: {e}"))?;
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    walk(path, item)?;
+                }
+            }
+            serde_json::Value::Object(fields) => {
+                for (name, item) in fields {
+                    walk(&format!("{path}.{name}"), item)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    let id = crate::config::account_id(acc);
+    let error = |e: String| crate::error::GitIdError::Config(format!("Account '{id}': {e}"));
+    let mut value = serde_json::to_value(acc).map_err(|e| error(e.to_string()))?;
+    if let serde_json::Value::Object(fields) = &mut value {
+        for (name, item) in fields.iter_mut().filter(|(n, _)| !LAZY_FIELDS.contains(&n.as_str())) {
+            walk(name, item).map_err(error)?;
+        }
+    }
+    serde_json::from_value(value).map_err(|e| error(e.to_string()))
+}
@@ -1,8 +1,12 @@
-use crate::config::{account_id, ssh_host_alias};
+use crate::config::{account_id, expand_home, load_settings, ssh_host_alias};
 use crate::models::Account;
-use crate::ui::{backup, die, print_info, print_ok, print_warn};
+use crate::error::{GitIdError, Result};
+use crate::ui::{backup, print_info, print_ok, print_warn};
+use std::collections::HashMap;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 
 pub fn ssh_dir() -> PathBuf {
     crate::config::dirs_home().join(".ssh")
@@ -12,12 +16,162 @@ pub fn ssh_config_path() -> PathBuf {
     crate::config::dirs_home().join(".ssh").join("config")
 }
 
-fn default_key_path(username: &str) -> PathBuf {
-    ssh_dir().join(format!("id_ed25519_{username}"))
+/// File git-id writes the account stanzas to. ~/.ssh/config only gets an
+/// `Include` line for it, so the hand-written config is never rewritten.
+pub fn managed_config_path() -> PathBuf {
+    ssh_dir().join("config.d").join("git-id.conf")
 }
 
-pub const MARKER_S: &str = "# >>> git-id: {id} >>>";
-pub const MARKER_E: &str = "# <<< git-id: {id} <<<";
+/// ~/.ssh/config followed by the managed file: every stanza git-id wrote,
+/// including ones older releases left in ~/.ssh/config, plus the hand-
+/// written hosts.
+pub fn all_ssh_config() -> String {
+    let main = crate::log::read_to_string(ssh_config_path()).unwrap_or_default();
+    let managed = crate::log::read_to_string(managed_config_path()).unwrap_or_default();
+    format!("{main}\n{managed}")
+}
+
+const INCLUDE_COMMENT: &str = "# Account stanzas managed by git-id";
+
+/// The `Include` line for the managed file. ssh expands `~` from the
+/// password database, so for any other home the path is spelled out.
+fn include_line() -> String {
+    let home = crate::config::dirs_home();
+    if crate::config::current_user_home().is_some_and(|h| h == home) {
+        "Include ~/.ssh/config.d/git-id.conf".to_string()
+    } else {
+        format!("Include \"{}\"", managed_config_path().display())
+    }
+}
+
+/// Whether `line` is an `Include` naming the managed file.
+fn includes_managed(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    if !words.next().is_some_and(|w| w.eq_ignore_ascii_case("include")) {
+        return false;
+    }
+    let managed = managed_config_path();
+    words.any(|w| {
+        let path = expand_home(w.trim_matches('"'));
+        let path = if path.is_relative() { ssh_dir().join(path) } else { path };
+        path == managed
+    })
+}
+
+/// `content` with the `Include` for the managed file at the top, where it
+/// applies to every host rather than to the last `Host` block.
+fn ensure_include(content: &str) -> String {
+    if content.lines().any(includes_managed) {
+        return content.to_string();
+    }
+    let include = format!("{INCLUDE_COMMENT}\n{}\n", include_line());
+    if content.trim().is_empty() {
+        include
+    } else {
+        format!("{include}\n{content}")
+    }
+}
+
+/// `content` without the `Include` for the managed file (and the comment
+/// git-id put above it), or None if it has none.
+pub fn strip_include(content: &str) -> Option<String> {
+    if !content.lines().any(includes_managed) {
+        return None;
+    }
+    let mut lines: Vec<&str> = vec![];
+    let mut dropped = false;
+    for line in content.lines() {
+        if includes_managed(line) {
+            if lines.last() == Some(&INCLUDE_COMMENT) {
+                lines.pop();
+            }
+            dropped = true;
+            continue;
+        }
+        if dropped && line.trim().is_empty() {
+            continue;
+        }
+        dropped = false;
+        lines.push(line);
+    }
+    Some(lines.iter().map(|l| format!("{l}\n")).collect())
+}
+
+/// Moves the managed stanzas older releases wrote into ~/.ssh/config over
+/// to the managed file, hand edits included. Returns ~/.ssh/config without
+/// them and the managed file's stanzas.
+fn move_legacy_stanzas(main: &str, managed: &str) -> (String, Managed) {
+    let mut from_main = split_managed(main);
+    let mut managed = split_managed(managed);
+    for (id, stanza) in std::mem::take(&mut from_main.stanzas) {
+        if !managed.stanzas.iter().any(|(other, _)| *other == id) {
+            managed.stanzas.push((id, stanza));
+        }
+    }
+    (from_main.render(), managed)
+}
+
+/// What keeps ssh from reading the stanzas the way git-id writes them:
+/// stanzas still in ~/.ssh/config, or a managed file it does not include.
+pub fn managed_layout_problem() -> Option<String> {
+    let main_cfg = ssh_config_path();
+    let main = crate::log::read_to_string(&main_cfg).unwrap_or_default();
+    let legacy = stanza_formats(&main).len();
+    if legacy > 0 {
+        return Some(format!("{legacy} stanza(s) still in {}", main_cfg.display()));
+    }
+    let managed = crate::log::read_to_string(managed_config_path()).unwrap_or_default();
+    let unmerged = split_managed(&managed).legacy;
+    if unmerged > 0 {
+        return Some(format!(
+            "{unmerged} stanza(s) in {} still have markers of their own instead of sharing \
+             the managed block",
+            managed_config_path().display()
+        ));
+    }
+    if !stanza_formats(&managed).is_empty() && !main.lines().any(includes_managed) {
+        return Some(format!(
+            "{} does not include {}",
+            main_cfg.display(),
+            managed_config_path().display()
+        ));
+    }
+    None
+}
+
+/// Writes `content` to an SSH config file, readable by its owner only.
+fn write_ssh_file(path: &Path, content: &str) -> Result<()> {
+    crate::log::write(path, content)
+        .map_err(|e| GitIdError::Io(format!("Failed to write SSH config: {e}")))?;
+    use std::os::unix::fs::PermissionsExt;
+    let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600));
+    Ok(())
+}
+
+pub fn default_key_path(username: &str, opts: &KeyOptions) -> PathBuf {
+    ssh_dir().join(opts.file_name(username))
+}
+
+/// Default socket path for multiplexed connections; `{alias}` keeps one
+/// master connection per account even when accounts share a host.
+pub const DEFAULT_CONTROL_PATH: &str = "~/.ssh/cm-{alias}-%r@%h:%p";
+
+/// Version of the stanza template. Bump it whenever `make_stanza` changes
+/// shape so blocks written by older releases can be found and regenerated.
+pub const STANZA_FORMAT: u32 = 2;
+const FORMAT_PREFIX: &str = "# git-id-format: ";
+
+/// Lines around the one block of a file that holds every managed stanza.
+pub const REGION_START: &str = "# >>> git-id managed >>>";
+pub const REGION_END: &str = "# <<< git-id managed <<<";
+
+/// First line of a stanza inside the block, naming its account.
+const STANZA_PREFIX: &str = "# git-id: ";
+
+/// Markers older releases put around each stanza on its own, scattered
+/// through the file; read only to move those stanzas into the block.
+const LEGACY_START: &str = "# >>> git-id: {id} >>>";
+const LEGACY_END: &str = "# <<< git-id: {id} <<<";
 
 pub fn make_stanza(acc: &Account) -> String {
     let acct_id = account_id(acc);
@@ -28,150 +182,738 @@ pub fn make_stanza(acc: &Account) -> String {
     } else {
         acc.ssh_key.clone()
     };
-    let start = MARKER_S.replace("{id}", &acct_id);
-    let end = MARKER_E.replace("{id}", &acct_id);
+    let agent = if acc.identity_agent.is_empty() {
+        String::new()
+    } else {
+        format!("    IdentityAgent \"{}\"\n", acc.identity_agent)
+    };
+    let security_key = !acc.ssh_key.is_empty()
+        && is_security_key(&expand_home(&acc.ssh_key).with_extension("pub"));
+    let note = if security_key {
+        "    # FIDO2 security key: touch it when git connects\n"
+    } else {
+        ""
+    };
+    let jump = if acc.proxy_jump.is_empty() {
+        String::new()
+    } else {
+        format!("    ProxyJump {}\n", acc.proxy_jump)
+    };
+    let multiplex = if acc.control_persist.is_empty() {
+        String::new()
+    } else {
+        let template = if acc.control_path.is_empty() {
+            DEFAULT_CONTROL_PATH
+        } else {
+            &acc.control_path
+        };
+        format!(
+            "    ControlMaster auto\n    ControlPath {}\n    ControlPersist {}\n",
+            template.replace("{alias}", &alias),
+            acc.control_persist
+        )
+    };
     format!(
-        "{start}\nHost {alias}\n    HostName {host}\n    User git\n    IdentityFile {keyfile}\n    IdentitiesOnly yes\n{end}\n"
+        "{STANZA_PREFIX}{acct_id}\n{FORMAT_PREFIX}{STANZA_FORMAT}\nHost {alias}\n    HostName {host}\n    User git\n    IdentityFile {keyfile}\n{note}{agent}    IdentitiesOnly yes\n{jump}{multiplex}"
     )
 }
 
-pub fn update_ssh_config(accounts: &[Account], dry_run: bool) {
+/// A file taken apart into its managed stanzas, as (account id, stanza),
+/// and everything else. The stanzas come from the managed block or from
+/// per-account markers (`legacy` counts those); either way they are
+/// returned in the block's form.
+struct Managed {
+    outside: String,
+    /// Where in `outside` the first stanza was, so the block goes back there.
+    at: Option<usize>,
+    stanzas: Vec<(String, String)>,
+    legacy: usize,
+}
+
+fn split_managed(content: &str) -> Managed {
+    let (legacy_prefix, legacy_suffix) = LEGACY_START.split_once("{id}").unwrap_or_default();
+    let mut managed = Managed { outside: String::new(), at: None, stanzas: vec![], legacy: 0 };
+    let mut in_region = false;
+    let mut legacy_end: Option<String> = None;
+    // A blank line after a stanza is dropped when one already comes before
+    // it, so no gap is left where the stanza was.
+    let mut after_stanza = false;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if after_stanza {
+            after_stanza = false;
+            let gap = managed.outside.is_empty() || managed.outside.ends_with("\n\n");
+            if trimmed.is_empty() && gap {
+                continue;
+            }
+        }
+        if let Some(end) = &legacy_end {
+            if trimmed == end {
+                legacy_end = None;
+                after_stanza = true;
+            } else if let Some((_, stanza)) = managed.stanzas.last_mut() {
+                stanza.push_str(&format!("{line}\n"));
+            }
+            continue;
+        }
+        if let Some(id) =
+            trimmed.strip_prefix(legacy_prefix).and_then(|l| l.strip_suffix(legacy_suffix))
+        {
+            managed.at.get_or_insert(managed.outside.len());
+            managed.stanzas.push((id.to_string(), format!("{STANZA_PREFIX}{id}\n")));
+            managed.legacy += 1;
+            legacy_end = Some(LEGACY_END.replace("{id}", id));
+            continue;
+        }
+        if trimmed == REGION_START {
+            managed.at.get_or_insert(managed.outside.len());
+            in_region = true;
+            continue;
+        }
+        if in_region {
+            if trimmed == REGION_END {
+                in_region = false;
+                after_stanza = true;
+            } else if let Some(id) = trimmed.strip_prefix(STANZA_PREFIX) {
+                managed.stanzas.push((id.to_string(), format!("{line}\n")));
+            } else if let Some((_, stanza)) = managed.stanzas.last_mut() {
+                stanza.push_str(&format!("{line}\n"));
+            } else if !trimmed.is_empty() {
+                managed.outside.push_str(&format!("{line}\n"));
+            }
+            continue;
+        }
+        managed.outside.push_str(&format!("{line}\n"));
+    }
+    if managed.at.is_none() {
+        managed.outside = content.to_string();
+    }
+    for (_, stanza) in &mut managed.stanzas {
+        let end = stanza.trim_end().len();
+        stanza.truncate(end);
+        stanza.push('\n');
+    }
+    managed
+}
+
+impl Managed {
+    /// The file with every stanza in one managed block, where the first one
+    /// was (at the end for a file that had none), and the rest around it.
+    /// A file without stanzas before or after comes back unchanged.
+    fn render(&self) -> String {
+        if self.at.is_none() && self.stanzas.is_empty() {
+            return self.outside.clone();
+        }
+        let (before, after) = self.outside.split_at(self.at.unwrap_or(self.outside.len()));
+        let mut parts = vec![];
+        if !before.trim().is_empty() {
+            parts.push(format!("{}\n", before.trim_end_matches('\n')));
+        }
+        if !self.stanzas.is_empty() {
+            let stanzas: Vec<&str> = self.stanzas.iter().map(|(_, s)| s.as_str()).collect();
+            parts.push(format!("{REGION_START}\n{}{REGION_END}\n", stanzas.join("\n")));
+        }
+        if !after.trim().is_empty() {
+            parts.push(format!("{}\n", after.trim_matches('\n')));
+        }
+        parts.join("\n")
+    }
+}
+
+/// `content` without the stanzas of `ids`; the block goes once it is empty.
+pub fn without_stanzas(content: &str, ids: &[String]) -> String {
+    let mut managed = split_managed(content);
+    managed.stanzas.retain(|(id, _)| !ids.contains(id));
+    managed.render()
+}
+
+/// Whether `pub_key` is the public half of a FIDO2 key
+/// (`sk-ssh-ed25519@openssh.com`, `sk-ecdsa-sha2-nistp256@openssh.com`).
+pub fn is_security_key(pub_key: &Path) -> bool {
+    crate::log::read_to_string(pub_key).is_ok_and(|k| k.starts_with("sk-"))
+}
+
+/// Copy of the stanzas as git-id last generated them, so hand edits to
+/// ~/.ssh/config can be told apart from stanzas that are merely outdated.
+fn generated_path() -> PathBuf {
+    crate::config::config_dir().join("ssh-config.generated")
+}
+
+/// A managed stanza edited by hand since git-id generated `base`, while
+/// git-id would now write `new`.
+pub struct EditedStanza {
+    pub base: String,
+    pub current: String,
+    pub new: String,
+}
+
+pub enum Resolution {
+    /// Leave the edit in place and ask again next time.
+    Defer,
+    /// Leave the edit in place until git-id's stanza changes again.
+    Keep,
+    Replace,
+    Merge,
+}
+
+/// Writes the stanza of every account. Stanzas edited by hand are kept,
+/// with a warning, when git-id would change them; `ssh config` resolves
+/// them interactively through `update_ssh_config_with`.
+pub fn update_ssh_config(accounts: &[Account], dry_run: bool) -> Result<()> {
+    update_ssh_config_with(accounts, dry_run, |acc, _| {
+        print_warn(&format!(
+            "Stanza of '{}' was edited by hand - left as is (resolve with: git-id ssh config)",
+            account_id(acc)
+        ));
+        Resolution::Defer
+    })
+}
+
+pub fn update_ssh_config_with(
+    accounts: &[Account],
+    dry_run: bool,
+    mut resolve: impl FnMut(&Account, &EditedStanza) -> Resolution,
+) -> Result<()> {
     let ssh = ssh_dir();
     if !ssh.exists() {
         use std::os::unix::fs::DirBuilderExt;
         std::fs::DirBuilder::new()
             .mode(0o700)
             .create(&ssh)
-            .unwrap_or_else(|e| die(&format!("Cannot create ~/.ssh: {e}"), 1));
+            .map_err(|e| GitIdError::Io(format!("Cannot create ~/.ssh: {e}")))?;
     }
-    let cfg = ssh_config_path();
-    let mut existing = if cfg.exists() {
-        std::fs::read_to_string(&cfg).unwrap_or_default()
-    } else {
-        String::new()
-    };
+    let main_cfg = ssh_config_path();
+    let cfg = managed_config_path();
+    let main_original = crate::log::read_to_string(&main_cfg).unwrap_or_default();
+    let original = crate::log::read_to_string(&cfg).unwrap_or_default();
+    let (main, mut managed) = move_legacy_stanzas(&main_original, &original);
+    let main = ensure_include(&main);
+    let unmerged = managed.legacy;
+    let generated_before = crate::log::read_to_string(generated_path()).unwrap_or_default();
+    let mut generated = vec![];
 
     for acc in accounts {
         let acct_id = account_id(acc);
-        let stanza = make_stanza(acc);
-        let start = MARKER_S.replace("{id}", &acct_id);
-        let end = MARKER_E.replace("{id}", &acct_id);
-        if existing.contains(&start) {
-            existing = replace_stanza(&existing, &start, &end, &stanza);
+        let stanza = make_stanza(&crate::secrets::resolved(acc)?);
+        let Some(slot) = managed.stanzas.iter_mut().find(|(id, _)| *id == acct_id) else {
+            managed.stanzas.push((acct_id.clone(), stanza.clone()));
+            generated.push((acct_id, stanza));
+            continue;
+        };
+        let current = slot.1.clone();
+        // Without a record of what was generated, the stanza counts as
+        // unedited, as before edits were tracked.
+        let base = current_stanza(&generated_before, &acct_id).unwrap_or_else(|| current.clone());
+        let mut recorded = stanza.clone();
+        let replacement = if current == base || current == stanza {
+            stanza
+        } else if base == stanza {
+            // Edited by hand, but git-id has nothing new to write.
+            current
+        } else if acc.ssh_keep_manual {
+            print_info(&format!("Keeping hand-edited stanza of '{acct_id}' (ssh_keep_manual)"));
+            current
         } else {
-            let trimmed = existing.trim_end_matches('\n');
-            existing = format!("{trimmed}\n\n{stanza}");
-        }
+            let edited = EditedStanza { base, current, new: stanza };
+            match resolve(acc, &edited) {
+                Resolution::Defer => {
+                    recorded = edited.base;
+                    edited.current
+                }
+                Resolution::Keep => edited.current,
+                Resolution::Replace => edited.new,
+                Resolution::Merge => {
+                    let (merged, conflicts) = merge_stanza(&edited);
+                    if !conflicts.is_empty() {
+                        print_warn(&format!(
+                            "You and git-id both changed {} in '{acct_id}' - kept yours",
+                            conflicts.join(", ")
+                        ));
+                    }
+                    merged
+                }
+            }
+        };
+        slot.1 = replacement;
+        generated.push((acct_id, recorded));
+    }
+    let existing = managed.render();
+    let generated = Managed { outside: String::new(), at: None, stanzas: generated, legacy: 0 };
+    let generated = generated.render();
+
+    if !dry_run && generated != generated_before {
+        let _ = std::fs::create_dir_all(crate::config::config_dir());
+        let _ = crate::log::write(generated_path(), &generated);
+    }
+
+    if existing == original && main == main_original {
+        print_info(&format!("{} already up to date", cfg.display()));
+        return Ok(());
     }
 
     if dry_run {
-        print_info("[dry-run] Would write ~/.ssh/config:");
-        print!("{existing}");
-        return;
+        if existing != original {
+            print_info(&format!("[dry-run] Would write {}:", cfg.display()));
+            print!("{existing}");
+        }
+        if main != main_original {
+            print_info(&format!("[dry-run] Would write {}:", main_cfg.display()));
+            print!("{main}");
+        }
+        return Ok(());
     }
 
-    backup(&cfg);
-    std::fs::write(&cfg, &existing)
-        .unwrap_or_else(|e| die(&format!("Failed to write SSH config: {e}"), 1));
-    use std::os::unix::fs::PermissionsExt;
-    let _ = std::fs::set_permissions(&cfg, std::fs::Permissions::from_mode(0o600));
-    print_ok(&format!("Updated {}", cfg.display()));
+    if existing != original {
+        if let Some(dir) = cfg.parent().filter(|d| !d.exists()) {
+            use std::os::unix::fs::DirBuilderExt;
+            std::fs::DirBuilder::new()
+                .mode(0o700)
+                .create(dir)
+                .map_err(|e| GitIdError::Io(format!("Cannot create {}: {e}", dir.display())))?;
+        }
+        backup(&cfg);
+        write_ssh_file(&cfg, existing.trim_start_matches('\n'))?;
+        print_ok(&format!("Updated {}", cfg.display()));
+        if unmerged > 0 {
+            print_ok(&format!("Gathered {unmerged} stanza(s) into one managed block"));
+        }
+    }
+    if main != main_original {
+        backup(&main_cfg);
+        write_ssh_file(&main_cfg, &main)?;
+        let moved = stanza_formats(&main_original).len();
+        if moved > 0 {
+            print_ok(&format!(
+                "Moved {moved} stanza(s) out of {} into {}",
+                main_cfg.display(),
+                cfg.display()
+            ));
+        } else {
+            print_ok(&format!("Included {} from {}", cfg.display(), main_cfg.display()));
+        }
+    }
+    Ok(())
 }
 
-pub fn replace_stanza(content: &str, start: &str, end: &str, replacement: &str) -> String {
-    let start_pos = match content.find(start) {
-        Some(p) => p,
-        None => return content.to_string(),
-    };
-    let end_offset = match content[start_pos..].find(end) {
-        Some(p) => p,
-        None => return content.to_string(),
-    };
-    let end_pos = start_pos + end_offset + end.len();
-    let end_pos = if content.as_bytes().get(end_pos) == Some(&b'\n') {
-        end_pos + 1
-    } else {
-        end_pos
-    };
-    format!("{}{}{}", &content[..start_pos], replacement, &content[end_pos..])
+/// The directives of a stanza below its header lines, grouped by keyword
+/// (lower-cased) in order of appearance. Comment lines are their own
+/// group, so hand-written notes survive a merge.
+fn stanza_directives(stanza: &str) -> Vec<(String, Vec<String>)> {
+    let mut groups: Vec<(String, Vec<String>)> = vec![];
+    for line in stanza.lines() {
+        let trimmed = line.trim();
+        let marker = trimmed.starts_with(STANZA_PREFIX) || trimmed.starts_with(FORMAT_PREFIX);
+        if trimmed.is_empty() || marker {
+            continue;
+        }
+        let key = if trimmed.starts_with('#') {
+            trimmed.to_string()
+        } else {
+            trimmed.split_whitespace().next().unwrap_or_default().to_lowercase()
+        };
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, lines)) => lines.push(line.trim_end().to_string()),
+            None => groups.push((key, vec![line.trim_end().to_string()])),
+        }
+    }
+    groups
 }
 
-pub fn remove_stanza(content: &str, start: &str, end: &str) -> String {
-    let start_pos = match content.find(start) {
-        Some(p) => p,
-        None => return content.to_string(),
+/// Three-way merge of an edited stanza, directive by directive: whatever
+/// only one side changed since `base` is taken from that side. Returns the
+/// merged stanza and the directives both sides changed differently, where
+/// the hand edit wins.
+pub fn merge_stanza(edited: &EditedStanza) -> (String, Vec<String>) {
+    let base = stanza_directives(&edited.base);
+    let current = stanza_directives(&edited.current);
+    let new = stanza_directives(&edited.new);
+    let get = |groups: &[(String, Vec<String>)], key: &str| {
+        groups.iter().find(|(k, _)| k == key).map(|(_, lines)| lines.clone())
     };
-    let end_offset = match content[start_pos..].find(end) {
-        Some(p) => p,
-        None => return content.to_string(),
-    };
-    let end_pos = start_pos + end_offset + end.len();
-    let end_pos = if content.as_bytes().get(end_pos) == Some(&b'\n') {
-        end_pos + 1
-    } else {
-        end_pos
-    };
-    let start_pos = if start_pos > 0 && content.as_bytes().get(start_pos - 1) == Some(&b'\n') {
-        start_pos - 1
-    } else {
-        start_pos
-    };
-    format!("{}{}", &content[..start_pos], &content[end_pos..])
+
+    let mut keys: Vec<String> = new.iter().map(|(k, _)| k.clone()).collect();
+    for (key, _) in &current {
+        if !keys.contains(key) {
+            keys.push(key.clone());
+        }
+    }
+    let mut body = vec![];
+    let mut conflicts = vec![];
+    for key in keys {
+        let (b, c, n) = (get(&base, &key), get(&current, &key), get(&new, &key));
+        let chosen = if c == b {
+            n
+        } else if n == b || c == n {
+            c
+        } else {
+            conflicts.push(key.clone());
+            c
+        };
+        body.extend(chosen.unwrap_or_default());
+    }
+
+    let mut lines: Vec<&str> = edited.new.lines().take(2).collect();
+    lines.extend(body.iter().map(String::as_str));
+    (lines.iter().map(|l| format!("{l}\n")).collect(), conflicts)
+}
+
+/// Format version of every managed stanza in `content`, as (account id,
+/// version). Stanzas without a version line predate versioning (format 1).
+pub fn stanza_formats(content: &str) -> Vec<(String, u32)> {
+    split_managed(content)
+        .stanzas
+        .into_iter()
+        .map(|(id, stanza)| {
+            let version = stanza
+                .lines()
+                .find_map(|l| l.trim().strip_prefix(FORMAT_PREFIX))
+                .map_or(1, |v| v.trim().parse().unwrap_or(1));
+            (id, version)
+        })
+        .collect()
 }
 
-pub fn gen_ssh_key(username: &str, email: &str, dry_run: bool) -> PathBuf {
-    let key = default_key_path(username);
+/// The managed stanza for `acct_id` as it currently appears in `content`.
+pub fn current_stanza(content: &str, acct_id: &str) -> Option<String> {
+    split_managed(content).stanzas.into_iter().find(|(id, _)| id == acct_id).map(|(_, s)| s)
+}
+
+/// Host patterns in the SSH config whose `IdentityFile` is `key`, skipping
+/// the alias in `skip_alias`.
+pub fn hosts_using_key(key: &Path, skip_alias: &str) -> Vec<String> {
+    let content = all_ssh_config();
+    let mut hosts = vec![];
+    let mut current = String::new();
+    for line in content.lines() {
+        let line = line.trim();
+        let Some((keyword, value)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        if keyword.eq_ignore_ascii_case("host") {
+            current = value.to_string();
+        } else if keyword.eq_ignore_ascii_case("identityfile")
+            && current != skip_alias
+            && crate::config::expand_home(value) == key
+            && !hosts.contains(&current)
+        {
+            hosts.push(current.clone());
+        }
+    }
+    hosts
+}
+
+/// Hand-written `Host` aliases in ~/.ssh/config (outside git-id's markers)
+/// that point at `host`, as (alias, IdentityFile if any). Wildcard patterns
+/// are skipped.
+pub fn unmanaged_aliases(host: &str) -> Vec<(String, String)> {
+    let content = crate::log::read_to_string(ssh_config_path()).unwrap_or_default();
+    let mut aliases: Vec<(String, String, String)> = vec![];
+    let mut managed = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with("# >>> git-id") {
+            managed = true;
+        } else if line.starts_with("# <<< git-id") {
+            managed = false;
+        }
+        let Some((keyword, value)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        if keyword.eq_ignore_ascii_case("host") {
+            aliases.push((String::new(), String::new(), String::new()));
+            if !managed && !value.contains(['*', '?', '!', ' ']) {
+                aliases.last_mut().unwrap().0 = value.to_string();
+            }
+        } else if let Some(last) = aliases.last_mut() {
+            if keyword.eq_ignore_ascii_case("hostname") {
+                last.1 = value.to_string();
+            } else if keyword.eq_ignore_ascii_case("identityfile") && last.2.is_empty() {
+                last.2 = value.to_string();
+            }
+        }
+    }
+    aliases
+        .into_iter()
+        .filter(|(alias, hostname, _)| {
+            !alias.is_empty() && alias != host && hostname.eq_ignore_ascii_case(host)
+        })
+        .map(|(alias, _, key)| (alias, key))
+        .collect()
+}
+
+/// Key types `ssh gen` and the add wizard can create.
+pub const KEY_TYPES: [&str; 5] = ["ed25519", "rsa", "ecdsa", "ed25519-sk", "ecdsa-sk"];
+
+/// How `gen_ssh_key` creates a key.
+#[derive(Clone)]
+pub struct KeyOptions {
+    /// One of `KEY_TYPES`.
+    pub key_type: String,
+    /// Key size; 0 for ed25519 and security keys, which have a fixed one.
+    pub bits: u32,
+    /// Empty for a key without a passphrase.
+    pub passphrase: String,
+}
+
+impl Default for KeyOptions {
+    fn default() -> Self {
+        KeyOptions { key_type: "ed25519".to_string(), bits: 0, passphrase: String::new() }
+    }
+}
+
+impl KeyOptions {
+    /// Checks `bits` against the key type. RSA defaults to 4096 bits and
+    /// ECDSA to 256.
+    pub fn new(key_type: &str, bits: Option<u32>, passphrase: String) -> Result<KeyOptions> {
+        let bits = match (key_type, bits) {
+            ("ed25519" | "ed25519-sk" | "ecdsa-sk", None) => Ok(0),
+            ("ed25519" | "ed25519-sk" | "ecdsa-sk", Some(_)) => {
+                Err(format!("--bits does not apply to {key_type} keys."))
+            }
+            ("rsa", None) => Ok(4096),
+            ("rsa", Some(b)) if (2048..=16384).contains(&b) => Ok(b),
+            ("rsa", Some(b)) => Err(format!("RSA keys need 2048 to 16384 bits, not {b}.")),
+            ("ecdsa", None) => Ok(256),
+            ("ecdsa", Some(b)) if [256, 384, 521].contains(&b) => Ok(b),
+            ("ecdsa", Some(b)) => Err(format!("ECDSA keys are 256, 384 or 521 bits, not {b}.")),
+            (other, _) => {
+                Err(format!("Unknown key type '{other}' (use one of: {}).", KEY_TYPES.join(", ")))
+            }
+        }
+        .map_err(GitIdError::Usage)?;
+        Ok(KeyOptions { key_type: key_type.to_string(), bits, passphrase })
+    }
+
+    /// A FIDO2 key (`ed25519-sk`, `ecdsa-sk`): the private key stays on the
+    /// hardware token and the file is only a handle to it.
+    pub fn security_key(&self) -> bool {
+        self.key_type.ends_with("-sk")
+    }
+
+    /// `id_ed25519_alice`, `id_ed25519_sk_alice`, ...
+    pub fn file_name(&self, username: &str) -> String {
+        format!("id_{}_{username}", self.key_type.replace('-', "_"))
+    }
+
+    /// "ed25519", "rsa 4096-bit", ...
+    pub fn describe(&self) -> String {
+        if self.bits == 0 {
+            self.key_type.clone()
+        } else {
+            format!("{} {}-bit", self.key_type, self.bits)
+        }
+    }
+}
+
+/// Environment variable through which git-id, run by `ssh-keygen` or
+/// `ssh-add` as their askpass program, receives the passphrase of a key it
+/// just generated.
+pub const ASKPASS_VAR: &str = "GIT_ID_ASKPASS_PASSPHRASE";
+
+pub fn gen_ssh_key(acc: &Account, opts: &KeyOptions, dry_run: bool) -> Result<PathBuf> {
+    let key = default_key_path(&acc.username, opts);
     if key.exists() {
         print_warn(&format!(
             "Key {} already exists - skipping (delete it first to regenerate)",
             key.display()
         ));
-        return key;
+        return Ok(key);
     }
+    write_new_key(acc, opts, &key, dry_run)?;
+    if !dry_run {
+        report_new_key(acc, opts, &key);
+    }
+    Ok(key)
+}
+
+/// Runs ssh-keygen to create the account's key at `key`, protected with
+/// `opts.passphrase` when there is one. Nothing is left at `key` if it fails.
+pub fn write_new_key(acc: &Account, opts: &KeyOptions, key: &Path, dry_run: bool) -> Result<()> {
+    let (username, email) = (acc.username.as_str(), acc.email.as_str());
     let ssh = ssh_dir();
     if !ssh.exists() {
         use std::os::unix::fs::DirBuilderExt;
         std::fs::DirBuilder::new()
             .mode(0o700)
             .create(&ssh)
-            .unwrap_or_else(|e| die(&format!("Cannot create ~/.ssh: {e}"), 1));
+            .map_err(|e| GitIdError::Io(format!("Cannot create ~/.ssh: {e}")))?;
     }
     let key_str = key.to_string_lossy().to_string();
-    let cmd_args = [
-        "ssh-keygen", "-t", "ed25519", "-C", email, "-f", &key_str, "-N", "",
-    ];
+    let bits = opts.bits.to_string();
+    let mut cmd_args = vec!["ssh-keygen", "-t", &opts.key_type];
+    if opts.bits > 0 {
+        cmd_args.extend(["-b", &bits]);
+    }
+    // Resident, so the handle can be recovered from the token with
+    // `ssh-keygen -K`; the application name tells the account's key apart
+    // from others on the same token.
+    let application = format!("application=ssh:git-id-{username}");
+    if opts.security_key() {
+        cmd_args.extend(["-O", "resident", "-O", &application]);
+    }
+    // The passphrase is set afterwards through askpass: on the command line
+    // any local user could read it from `ps` while the key is generated.
+    cmd_args.extend(["-C", email, "-f", &key_str, "-N", ""]);
     if dry_run {
-        print_info(&format!("[dry-run] Would run: {}", cmd_args.join(" ")));
-        return key;
-    }
-    let result = Command::new(cmd_args[0])
-        .args(&cmd_args[1..])
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped())
-        .output();
-    match result {
+        let line = format!("{} \"\"", cmd_args[..cmd_args.len() - 1].join(" "));
+        print_info(&format!("[dry-run] Would run: {line}"));
+        if !opts.passphrase.is_empty() {
+            print_info(&format!("[dry-run] Would run: ssh-keygen -p -f {key_str} (askpass)"));
+        }
+        return Ok(());
+    }
+    let mut cmd = Command::new(cmd_args[0]);
+    cmd.args(&cmd_args[1..]).stdout(Stdio::null());
+    if opts.security_key() {
+        // ssh-keygen asks for the token's PIN and for a touch on the terminal.
+        cmd.stdin(Stdio::inherit()).stderr(Stdio::inherit());
+        print_info("Touch your security key when it blinks (it may ask for its PIN first)");
+    } else {
+        cmd.stderr(Stdio::piped());
+    }
+    match crate::log::output(&mut cmd) {
         Ok(out) if out.status.success() => {}
-        Ok(out) => die(
-            &format!(
-                "ssh-keygen failed: {}",
-                String::from_utf8_lossy(&out.stderr).trim()
-            ),
-            1,
-        ),
-        Err(e) => die(&format!("Failed to run ssh-keygen: {e}"), 1),
+        Ok(out) => {
+            let err = String::from_utf8_lossy(&out.stderr).trim().to_string();
+            let err = if err.is_empty() { "see above".to_string() } else { err };
+            return Err(GitIdError::Io(format!("ssh-keygen failed: {err}")));
+        }
+        Err(e) => return Err(GitIdError::Io(format!("Failed to run ssh-keygen: {e}"))),
+    }
+    if !opts.passphrase.is_empty()
+        && let Err(e) = set_key_passphrase(key, &opts.passphrase)
+    {
+        // Never leave the key behind without the passphrase that was asked for.
+        let _ = std::fs::remove_file(key);
+        let _ = std::fs::remove_file(key.with_extension("pub"));
+        return Err(e);
     }
     use std::os::unix::fs::PermissionsExt;
-    let _ = std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o600));
+    let _ = std::fs::set_permissions(key, std::fs::Permissions::from_mode(0o600));
     let pub_key = key.with_extension("pub");
     if pub_key.exists() {
         let _ = std::fs::set_permissions(&pub_key, std::fs::Permissions::from_mode(0o644));
     }
-    print_ok(&format!("Generated {}", key.display()));
-    add_key_to_agent(&key, false);
-    key
+    Ok(())
+}
+
+/// Announces a key `write_new_key` created, now at `key`, and loads it into
+/// the agent.
+pub fn report_new_key(acc: &Account, opts: &KeyOptions, key: &Path) {
+    let protected = if opts.passphrase.is_empty() { "" } else { " (passphrase-protected)" };
+    print_ok(&format!("Generated {} key {}{protected}", opts.describe(), key.display()));
+    if opts.security_key() {
+        print_info(&format!(
+            "{} only points at the key on your security key. On another machine, run \
+             `ssh-keygen -K` in ~/.ssh to get it back, then: git-id ssh pick {}",
+            key.display(),
+            acc.username
+        ));
+    }
+    add_key_to_agent_with(key, &opts.passphrase, acc, false);
 }
 
-pub fn add_key_to_agent(key: &Path, dry_run: bool) {
+/// Protects the unencrypted `key` with `passphrase`, handed to
+/// `ssh-keygen -p` through git-id as its askpass program so it never
+/// appears in a command line.
+fn set_key_passphrase(key: &Path, passphrase: &str) -> Result<()> {
+    let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("git-id"));
+    let out = crate::log::output(
+        Command::new("ssh-keygen")
+            .args(["-p", "-f"])
+            .arg(key)
+            .env("SSH_ASKPASS", exe)
+            .env("SSH_ASKPASS_REQUIRE", "force")
+            .env(ASKPASS_VAR, passphrase)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped()),
+    );
+    match out {
+        Ok(out) if out.status.success() => Ok(()),
+        Ok(out) => Err(GitIdError::Io(format!(
+            "Setting the passphrase on {} failed: {}",
+            key.display(),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ))),
+        Err(e) => Err(GitIdError::Io(format!("Failed to run ssh-keygen: {e}"))),
+    }
+}
+
+/// Whether the private key needs a passphrase to be used.
+fn key_has_passphrase(key: &Path) -> bool {
+    let out = crate::log::output(
+        Command::new("ssh-keygen")
+            .args(["-y", "-P", "", "-f"])
+            .arg(key)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped()),
+    );
+    out.is_ok_and(|o| {
+        let msg = String::from_utf8_lossy(&o.stderr).to_lowercase();
+        !o.status.success() && msg.contains("passphrase")
+    })
+}
+
+pub fn add_key_to_agent(key: &Path, acc: &Account, dry_run: bool) {
+    add_key_to_agent_with(key, "", acc, dry_run);
+}
+
+/// Whether `value` is a lifetime `ssh-add -t` accepts: seconds, or ssh's
+/// time format (`90m`, `1h30m`, `2d`).
+pub fn valid_agent_lifetime(value: &str) -> bool {
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return false;
+        }
+        rest = &rest[digits..];
+        rest = rest.strip_prefix(|c: char| "smhdwSMHDW".contains(c)).unwrap_or(rest);
+    }
+    !value.is_empty()
+}
+
+/// The `ssh-add` flags for the account's key: its `agent_lifetime` and
+/// `agent_confirm`, else the `[agent]` defaults in settings.toml.
+fn agent_add_args(acc: &Account) -> Vec<String> {
+    let defaults = load_settings()
+        .inspect_err(|e| print_warn(&format!("Ignoring the [agent] defaults: {e}")))
+        .unwrap_or_default()
+        .agent;
+    let lifetime =
+        if acc.agent_lifetime.is_empty() { defaults.lifetime } else { acc.agent_lifetime.clone() };
+    let mut args = vec![];
+    if !lifetime.is_empty() {
+        if valid_agent_lifetime(&lifetime) {
+            args.extend(["-t".to_string(), lifetime]);
+        } else {
+            print_warn(&format!("Ignoring invalid agent lifetime {lifetime:?}"));
+        }
+    }
+    if acc.agent_confirm.unwrap_or(defaults.confirm) {
+        args.push("-c".to_string());
+    }
+    args
+}
+
+/// Loads `key` into the agent, with the account's lifetime and confirmation
+/// options; a key served by the account's `identity_agent` is skipped. For
+/// a passphrase-protected key, `passphrase` is handed to `ssh-add` through
+/// git-id as its askpass program; without it, `ssh-add` asks on the
+/// terminal, and with no terminal the key is left for the user to load.
+pub fn add_key_to_agent_with(key: &Path, passphrase: &str, acc: &Account, dry_run: bool) {
+    if !acc.identity_agent.is_empty() {
+        print_info(&format!(
+            "{} is served by {} - not loading it into ssh-agent",
+            key.display(),
+            acc.identity_agent
+        ));
+        return;
+    }
     if !key.exists() {
         print_warn(&format!(
             "Key {} not found - cannot add to ssh-agent",
@@ -179,30 +921,389 @@ pub fn add_key_to_agent(key: &Path, dry_run: bool) {
         ));
         return;
     }
+    let options = agent_add_args(acc);
+    let shown: String = options.iter().map(|o| format!("{o} ")).collect();
     if dry_run {
-        print_info(&format!("[dry-run] Would run: ssh-add {}", key.display()));
+        print_info(&format!("[dry-run] Would run: ssh-add {shown}{}", key.display()));
         return;
     }
-    if std::env::var("SSH_AUTH_SOCK").is_err() {
-        print_warn("SSH_AUTH_SOCK not set - ssh-agent may not be running");
+    let sock = std::env::var("SSH_AUTH_SOCK").unwrap_or_default();
+    if sock.is_empty() {
+        print_warn(&format!(
+            "No ssh-agent: SSH_AUTH_SOCK is not set - start one with: eval \"$(ssh-agent)\" \
+             && ssh-add {}",
+            key.display()
+        ));
+        return;
     }
-    let result = Command::new("ssh-add")
-        .arg(key)
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped())
-        .output();
-    match result {
-        Ok(out) if out.status.success() => {
-            print_ok(&format!("Added {} to ssh-agent", key.display()))
+    if passphrase.is_empty() && key_has_passphrase(key) {
+        use std::io::IsTerminal;
+        if !std::io::stdin().is_terminal() {
+            print_warn(&format!(
+                "{} has a passphrase and there is no terminal to ask for it - \
+                 load it later with: ssh-add {}",
+                key.display(),
+                key.display()
+            ));
+            return;
         }
-        Ok(out) => print_warn(&format!(
-            "ssh-add failed (is ssh-agent running?): {}",
-            String::from_utf8_lossy(&out.stderr).trim()
+        print_info(&format!("{} has a passphrase - ssh-add will ask for it", key.display()));
+    }
+    let (retries, mut delay) = agent_retry_settings();
+    let mut attempt = 0;
+    let mut waited = Duration::ZERO;
+    let (kind, msg) = loop {
+        let mut cmd = Command::new("ssh-add");
+        cmd.args(&options).arg(key).stdout(Stdio::null()).stderr(Stdio::piped());
+        if !passphrase.is_empty() {
+            let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("git-id"));
+            cmd.env("SSH_ASKPASS", exe)
+                .env("SSH_ASKPASS_REQUIRE", "force")
+                .env(ASKPASS_VAR, passphrase)
+                .stdin(Stdio::null());
+        }
+        let out = match crate::log::output(&mut cmd) {
+            Ok(out) if out.status.success() => {
+                let note = describe_agent(&options);
+                print_ok(&format!("Added {} to ssh-agent{note}", key.display()));
+                return;
+            }
+            Ok(out) => out,
+            Err(e) => {
+                print_warn(&format!("Failed to run ssh-add: {e}"));
+                return;
+            }
+        };
+        let msg = String::from_utf8_lossy(&out.stderr).trim().to_string();
+        let kind = AgentError::classify(&msg);
+        if !kind.is_transient() || attempt >= retries || waited + delay > MAX_RETRY_WAIT {
+            break (kind, msg);
+        }
+        attempt += 1;
+        print_info(&format!(
+            "ssh-add: {} - retrying in {:.1}s ({attempt}/{retries})",
+            kind.describe(),
+            delay.as_secs_f32()
+        ));
+        std::thread::sleep(delay);
+        waited += delay;
+        delay = (delay * 2).min(MAX_RETRY_DELAY);
+    };
+    let key = key.display();
+    match kind {
+        AgentError::NotRunning => print_warn(&format!(
+            "No ssh-agent answers at {sock} - start one with: eval \"$(ssh-agent)\" \
+             && ssh-add {key}"
         )),
-        Err(e) => print_warn(&format!("Failed to run ssh-add: {e}")),
+        AgentError::Refused => print_warn(&format!(
+            "ssh-agent refused {key} ({msg}) - it may be locked or still starting; \
+             try again with: ssh-add {key}"
+        )),
+        AgentError::UnsupportedKey => print_warn(&format!(
+            "ssh-agent cannot load {key}: unsupported or invalid key format ({msg})"
+        )),
+        AgentError::BadPassphrase => {
+            print_warn(&format!("Wrong passphrase for {key} - try again with: ssh-add {key}"))
+        }
+        AgentError::Other => print_warn(&format!("ssh-add {key} failed: {msg}")),
     }
 }
 
+/// What the `ssh-add` flags change, for the success message.
+fn describe_agent(options: &[String]) -> String {
+    let mut notes = vec![];
+    if let Some(i) = options.iter().position(|o| o == "-t") {
+        let lifetime = &options[i + 1];
+        let unit = if lifetime.chars().all(|c| c.is_ascii_digit()) { "s" } else { "" };
+        notes.push(format!("for {lifetime}{unit}"));
+    }
+    if options.iter().any(|o| o == "-c") {
+        notes.push("confirm on use".to_string());
+    }
+    if notes.is_empty() { String::new() } else { format!(" ({})", notes.join(", ")) }
+}
+
+/// Loads `acc`'s key into ssh-agent and unloads every other account's, so
+/// an agent that offers all its keys cannot authenticate as someone else on
+/// hosts that ignore `IdentitiesOnly`. Keys the agent does not hold and
+/// accounts with their own `identity_agent` are left alone.
+pub fn make_agent_exclusive(acc: &Account, accounts: &[Account], dry_run: bool) {
+    if std::env::var("SSH_AUTH_SOCK").unwrap_or_default().is_empty() {
+        print_warn("No ssh-agent: SSH_AUTH_SOCK is not set - nothing to unload");
+        return;
+    }
+    let own = (!acc.ssh_key.is_empty() && acc.identity_agent.is_empty())
+        .then(|| expand_home(&acc.ssh_key));
+    if let Some(key) = &own {
+        add_key_to_agent(key, acc, dry_run);
+    }
+    let own_body = own
+        .and_then(|k| crate::log::read_to_string(k.with_extension("pub")).ok())
+        .map(|c| key_body(&c));
+    let loaded: Vec<String> = agent_public_keys(None).iter().map(|l| key_body(l)).collect();
+    let mut unloaded = vec![];
+    for other in accounts.iter().filter(|a| account_id(a) != account_id(acc)) {
+        if other.ssh_key.is_empty() || !other.identity_agent.is_empty() {
+            continue;
+        }
+        let pub_key = expand_home(&other.ssh_key).with_extension("pub");
+        let Ok(content) = crate::log::read_to_string(&pub_key) else {
+            continue;
+        };
+        let body = key_body(&content);
+        let own = own_body.as_ref() == Some(&body);
+        if own || !loaded.contains(&body) || unloaded.contains(&body) {
+            continue;
+        }
+        unloaded.push(body);
+        if dry_run {
+            print_info(&format!("[dry-run] Would run: ssh-add -d {}", pub_key.display()));
+            continue;
+        }
+        let mut cmd = Command::new("ssh-add");
+        cmd.arg("-d").arg(&pub_key).stdout(Stdio::null()).stderr(Stdio::piped());
+        match crate::log::output(&mut cmd) {
+            Ok(out) if out.status.success() => print_ok(&format!(
+                "Unloaded {}'s key {} from ssh-agent",
+                account_id(other),
+                expand_home(&other.ssh_key).display()
+            )),
+            Ok(out) => print_warn(&format!(
+                "Could not unload {} from ssh-agent: {}",
+                pub_key.display(),
+                String::from_utf8_lossy(&out.stderr).trim()
+            )),
+            Err(e) => print_warn(&format!("Failed to run ssh-add: {e}")),
+        }
+    }
+}
+
+/// Why an `ssh-add` failed, from its error message.
+#[derive(Clone, Copy, PartialEq)]
+enum AgentError {
+    /// Nothing listens on SSH_AUTH_SOCK.
+    NotRunning,
+    /// The agent answered but failed the request; keyring-backed agents
+    /// (gpg-agent, gnome-keyring) do this for a while after login.
+    Refused,
+    /// The key file is not something the agent can use.
+    UnsupportedKey,
+    /// The passphrase given for a protected key was wrong, or none was.
+    BadPassphrase,
+    Other,
+}
+
+impl AgentError {
+    fn classify(stderr: &str) -> AgentError {
+        let msg = stderr.to_lowercase();
+        if msg.contains("could not open a connection")
+            || msg.contains("error connecting to agent")
+            || msg.contains("connection refused")
+        {
+            AgentError::NotRunning
+        } else if msg.contains("agent refused")
+            || msg.contains("communication with agent failed")
+            || msg.contains("agent failure")
+        {
+            AgentError::Refused
+        } else if msg.contains("bad passphrase") || msg.contains("incorrect passphrase") {
+            AgentError::BadPassphrase
+        } else if msg.contains("invalid format")
+            || msg.contains("unknown key type")
+            || msg.contains("unsupported")
+            || msg.contains("error loading key")
+        {
+            AgentError::UnsupportedKey
+        } else {
+            AgentError::Other
+        }
+    }
+
+    /// Whether trying again later can succeed.
+    fn is_transient(self) -> bool {
+        matches!(self, AgentError::NotRunning | AgentError::Refused)
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            AgentError::NotRunning => "agent not reachable",
+            AgentError::Refused => "agent refused the key",
+            AgentError::UnsupportedKey => "unsupported key format",
+            AgentError::BadPassphrase => "wrong passphrase",
+            AgentError::Other => "failed",
+        }
+    }
+}
+
+/// Longest single wait between `ssh-add` attempts, and longest wait over
+/// all of them, so a mistyped delay cannot hang `use` for hours.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(30);
+
+/// How often a transient `ssh-add` failure is retried and the first delay,
+/// doubled after each attempt up to `MAX_RETRY_DELAY`: `git-id.agentRetries`
+/// (default 2, at most 10) and `git-id.agentRetryDelay` in milliseconds
+/// (default 500).
+fn agent_retry_settings() -> (u32, Duration) {
+    let int = |key: &str| {
+        let (code, out, _) = crate::git::run_git(&["config", "--type=int", "--get", key]);
+        if code == 0 { out.parse::<u64>().ok() } else { None }
+    };
+    let retries = int("git-id.agentRetries").unwrap_or(2).min(10) as u32;
+    let delay = Duration::from_millis(int("git-id.agentRetryDelay").unwrap_or(500));
+    (retries, delay.min(MAX_RETRY_DELAY))
+}
+
+/// `-F <config>` pointing ssh at the config git-id manages. ssh resolves
+/// `~` from the password database, not `$HOME`, so without it `--home`
+/// and `--user` setups would be probed with the wrong file.
+pub fn ssh_config_args() -> Vec<String> {
+    let cfg = ssh_config_path();
+    if cfg.exists() {
+        vec!["-F".to_string(), cfg.to_string_lossy().to_string()]
+    } else {
+        vec![]
+    }
+}
+
+/// Options ssh would actually use for `host` (`ssh -G`), keyed by
+/// lower-case option name. Includes settings from hand-written blocks.
+pub fn effective_ssh_config(host: &str) -> HashMap<String, String> {
+    let out = crate::log::output(
+        Command::new("ssh")
+            .args(ssh_config_args())
+            .arg("-G")
+            .arg(host)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null()),
+    );
+    match out {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter_map(|l| l.split_once(' '))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Runs `ssh` non-interactively with `args`, killing it after `timeout`.
+/// Returns the exit code (None on timeout) and the combined output.
+pub fn ssh_probe(args: &[&str], timeout: Duration) -> (Option<i32>, String) {
+    let mut cmd = Command::new("ssh");
+    cmd.args(ssh_config_args())
+        .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=10"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let child = cmd.spawn();
+    let mut child = match child {
+        Ok(c) => c,
+        Err(e) => return (Some(255), format!("failed to run ssh: {e}")),
+    };
+    let started = Instant::now();
+    let code = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status.code(),
+            Ok(None) if started.elapsed() < timeout => std::thread::sleep(Duration::from_millis(100)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                crate::log::command(&cmd, None, &[], &[]);
+                return (None, String::new());
+            }
+        }
+    };
+    let mut stderr = String::new();
+    if let Some(mut err) = child.stderr.take() {
+        let _ = err.read_to_string(&mut stderr);
+    }
+    let mut stdout = String::new();
+    if let Some(mut out) = child.stdout.take() {
+        let _ = out.read_to_string(&mut stdout);
+    }
+    crate::log::command(&cmd, code, stdout.as_bytes(), stderr.as_bytes());
+    (code, stderr + &stdout)
+}
+
+/// Fingerprint and key type of a public key file, from `ssh-keygen -l`
+/// (e.g. "SHA256:...", "ED25519").
+pub fn key_fingerprint(pub_key: &Path) -> Option<(String, String)> {
+    let out = crate::log::output(
+        Command::new("ssh-keygen").arg("-lf").arg(pub_key).stderr(Stdio::null()),
+    )
+    .ok()
+    .filter(|o| o.status.success())?;
+    let line = String::from_utf8_lossy(&out.stdout);
+    let fingerprint = line.split_whitespace().nth(1)?.to_string();
+    let kind = line
+        .trim_end()
+        .rsplit_once('(')
+        .map(|(_, t)| t.trim_end_matches(')').to_string())
+        .unwrap_or_default();
+    Some((fingerprint, kind))
+}
+
+/// The "type base64" part of a public key line, without its comment, for
+/// comparing keys from different sources.
+pub fn key_body(line: &str) -> String {
+    line.split_whitespace().take(2).collect::<Vec<_>>().join(" ")
+}
+
+/// Locates the 1Password SSH agent socket, if the agent is enabled.
+pub fn onepassword_agent_socket() -> Option<PathBuf> {
+    let home = crate::config::dirs_home();
+    [
+        home.join(".1password").join("agent.sock"),
+        home.join("Library/Group Containers/2BUAN5HX7G.com.1password/t/agent.sock"),
+    ]
+    .into_iter()
+    .find(|p| p.exists())
+}
+
+/// Lists the public keys an agent offers (`ssh-add -L`), one per line.
+pub fn agent_public_keys(sock: Option<&Path>) -> Vec<String> {
+    let mut cmd = Command::new("ssh-add");
+    cmd.arg("-L").stdout(Stdio::piped()).stderr(Stdio::null());
+    if let Some(sock) = sock {
+        cmd.env("SSH_AUTH_SOCK", sock);
+    }
+    match crate::log::output(&mut cmd) {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Writes a public key served by an external agent to `~/.ssh` so the
+/// stanza's `IdentityFile` can select it. Returns the written path.
+pub fn write_agent_public_key(username: &str, pub_key: &str, dry_run: bool) -> Result<PathBuf> {
+    let path = ssh_dir().join(format!("id_1password_{username}.pub"));
+    if dry_run {
+        print_info(&format!("[dry-run] Would write {}", path.display()));
+        return Ok(path);
+    }
+    let ssh = ssh_dir();
+    if !ssh.exists() {
+        use std::os::unix::fs::DirBuilderExt;
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&ssh)
+            .map_err(|e| GitIdError::Io(format!("Cannot create ~/.ssh: {e}")))?;
+    }
+    crate::log::write(&path, format!("{pub_key}\n"))
+        .map_err(|e| GitIdError::Io(format!("Failed to write {}: {e}", path.display())))?;
+    use std::os::unix::fs::PermissionsExt;
+    let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644));
+    print_ok(&format!("Wrote {}", path.display()));
+    Ok(path)
+}
+
 pub fn fix_key_permissions(key: &Path) {
     use std::os::unix::fs::PermissionsExt;
     if key.exists() {
@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long a loaded value is trusted before the file is stat'ed again.
+/// Rapid successive reads (status, prompt segment, TUI refreshes) inside
+/// this window reuse the cached value without touching the filesystem.
+pub const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Identity of a file's contents as seen by stat: modification time and size.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+struct Entry<T> {
+    path: PathBuf,
+    stamp: Stamp,
+    checked: Instant,
+    value: Arc<T>,
+}
+
+/// A parsed file shared across the process. Readers get the cached value
+/// while the file is unchanged; a changed stamp triggers a reload. Safe to
+/// use from several threads.
+pub struct FileCache<T> {
+    entry: RwLock<Option<Entry<T>>>,
+}
+
+impl<T> FileCache<T> {
+    pub const fn new() -> Self {
+        FileCache { entry: RwLock::new(None) }
+    }
+
+    /// Returns the cached value for `path`, reloading it with `load` when the
+    /// file changed since it was last read.
+    pub fn get(&self, path: &Path, load: impl FnOnce() -> T) -> Arc<T> {
+        if let Ok(guard) = self.entry.read()
+            && let Some(entry) = guard.as_ref()
+            && entry.path == path
+            && entry.checked.elapsed() < DEBOUNCE
+        {
+            return Arc::clone(&entry.value);
+        }
+
+        let mut guard = self.entry.write().unwrap_or_else(|e| e.into_inner());
+        let current = stamp(path);
+        if let Some(entry) = guard.as_mut()
+            && entry.path == path
+            && entry.stamp == current
+        {
+            entry.checked = Instant::now();
+            return Arc::clone(&entry.value);
+        }
+        let value = Arc::new(load());
+        *guard = Some(Entry {
+            path: path.to_path_buf(),
+            stamp: current,
+            checked: Instant::now(),
+            value: Arc::clone(&value),
+        });
+        value
+    }
+
+    /// Drops the cached value, e.g. right after this process wrote the file.
+    pub fn invalidate(&self) {
+        if let Ok(mut guard) = self.entry.write() {
+            *guard = None;
+        }
+    }
+}
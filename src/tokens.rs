@@ -0,0 +1,148 @@
+use crate::config::{account_id, cache_dir, expand_home};
+use crate::forge::api_request_with;
+use crate::models::Account;
+use serde_json::json;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Installation tokens live for an hour; refresh when less than this is left.
+const REFRESH_MARGIN_SECS: u64 = 300;
+const TOKEN_LIFETIME_SECS: u64 = 3600;
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The HTTPS token to authenticate as this account: a freshly minted (or
+/// cached) installation token for GitHub App accounts, the stored personal
+/// access token (or the secret it refers to) otherwise.
+pub fn account_token(acc: &Account) -> Result<String, String> {
+    if acc.app_id.is_empty() {
+        return crate::secrets::resolve(&acc.https_token);
+    }
+    if let Some(token) = cached_token(acc) {
+        return Ok(token);
+    }
+    let token = mint_installation_token(acc)?;
+    store_token(acc, &token);
+    Ok(token)
+}
+
+fn cache_file(acc: &Account) -> PathBuf {
+    cache_dir()
+        .join("tokens")
+        .join(format!("{}.json", account_id(acc)))
+}
+
+fn cached_token(acc: &Account) -> Option<String> {
+    let content = crate::log::read_to_string(cache_file(acc)).ok()?;
+    let cached: serde_json::Value = serde_json::from_str(&content).ok()?;
+    let expires = cached["expires_at"].as_u64()?;
+    if expires < now() + REFRESH_MARGIN_SECS {
+        return None;
+    }
+    cached["token"].as_str().map(ToString::to_string)
+}
+
+fn store_token(acc: &Account, token: &str) {
+    if crate::config::read_only() {
+        return;
+    }
+    let path = cache_file(acc);
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let doc = json!({ "token": token, "expires_at": now() + TOKEN_LIFETIME_SECS });
+    use std::os::unix::fs::OpenOptionsExt;
+    let opened = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path);
+    crate::log::file("wrote", &path, &opened);
+    if let Ok(mut file) = opened {
+        let _ = file.write_all(doc.to_string().as_bytes());
+    }
+}
+
+/// Exchanges a signed app JWT for an installation access token.
+fn mint_installation_token(acc: &Account) -> Result<String, String> {
+    let jwt = app_jwt(acc)?;
+    let installation = if acc.app_installation_id.is_empty() {
+        let list = api_request_with(acc, &jwt, "GET", "/app/installations", None)?;
+        let ids: Vec<u64> = list
+            .as_array()
+            .map(|l| l.iter().filter_map(|i| i["id"].as_u64()).collect())
+            .unwrap_or_default();
+        match ids.as_slice() {
+            [id] => id.to_string(),
+            [] => return Err("the app has no installations".to_string()),
+            _ => return Err("the app has several installations; set app_installation_id".to_string()),
+        }
+    } else {
+        acc.app_installation_id.clone()
+    };
+    let path = format!("/app/installations/{installation}/access_tokens");
+    let resp = api_request_with(acc, &jwt, "POST", &path, None)?;
+    resp["token"]
+        .as_str()
+        .map(ToString::to_string)
+        .ok_or_else(|| "no token in the API response".to_string())
+}
+
+/// Builds the RS256 JWT that authenticates as the GitHub App, signing it
+/// with `openssl` so no crypto library is needed.
+fn app_jwt(acc: &Account) -> Result<String, String> {
+    let key = expand_home(&acc.app_key);
+    if !key.exists() {
+        return Err(format!("app private key not found: {}", key.display()));
+    }
+    let iat = now().saturating_sub(60);
+    let header = base64url(br#"{"alg":"RS256","typ":"JWT"}"#);
+    let claims = json!({ "iat": iat, "exp": iat + 540, "iss": acc.app_id }).to_string();
+    let signing_input = format!("{header}.{}", base64url(claims.as_bytes()));
+
+    let mut cmd = Command::new("openssl");
+    cmd.args(["dgst", "-sha256", "-sign"])
+        .arg(&key)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("failed to run openssl: {e}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(signing_input.as_bytes());
+    }
+    let out = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run openssl: {e}"))?;
+    crate::log::command(&cmd, out.status.code(), &[], &out.stderr);
+    if !out.status.success() {
+        return Err(format!(
+            "openssl could not sign the app JWT: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(format!("{signing_input}.{}", base64url(&out.stdout)))
+}
+
+/// Unpadded base64url encoding, as used by JWTs.
+fn base64url(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    out
+}
@@ -1,4 +1,5 @@
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub fn is_tty() -> bool {
     use std::io::IsTerminal;
@@ -27,10 +28,18 @@ pub fn print_ok(msg: &str) {
     println!("{} {}", color("green", "OK"), msg);
 }
 
+/// Warnings printed so far, for `--strict-warnings`.
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
 pub fn print_warn(msg: &str) {
+    WARNINGS.fetch_add(1, Ordering::Relaxed);
     eprintln!("{} {}", color("yellow", "!"), msg);
 }
 
+pub fn warning_count() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
 pub fn print_err(msg: &str) {
     eprintln!("{} {}", color("red", "ERR"), msg);
 }
@@ -43,11 +52,6 @@ pub fn print_hdr(msg: &str) {
     println!("\n{}", color("bold", msg));
 }
 
-pub fn die(msg: &str, code: i32) -> ! {
-    print_err(msg);
-    std::process::exit(code);
-}
-
 pub fn backup(path: &Path) -> Option<PathBuf> {
     if !path.exists() {
         return None;
@@ -60,14 +64,64 @@ pub fn backup(path: &Path) -> Option<PathBuf> {
         .parent()
         .unwrap_or(Path::new("."))
         .join(format!("{}.bak.{}", path.file_name().unwrap().to_string_lossy(), now));
-    if std::fs::copy(path, &dst).is_ok() {
+    let copied = std::fs::copy(path, &dst);
+    crate::log::file("wrote", &dst, &copied);
+    if copied.is_ok() {
         print_info(&format!(
             "Backed up {} -> {}",
             path.file_name().unwrap().to_string_lossy(),
             dst.file_name().unwrap().to_string_lossy()
         ));
+        if let Ok(settings) = crate::config::load_settings() {
+            let retention = settings.backups;
+            for old in expired_backups(path, retention.keep, retention.days) {
+                let result = std::fs::remove_file(&old);
+                crate::log::file("deleted", &old, &result);
+            }
+        }
         Some(dst)
     } else {
         None
     }
 }
+
+/// The backups `backup` made of `path`, as (Unix time, file), oldest first.
+pub fn backups_of(path: &Path) -> Vec<(u64, PathBuf)> {
+    let Some(name) = path.file_name().map(|n| format!("{}.bak.", n.to_string_lossy())) else {
+        return vec![];
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut found: Vec<(u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file = entry.file_name().to_string_lossy().to_string();
+            let stamp = file.strip_prefix(&name)?.parse().ok()?;
+            Some((stamp, entry.path()))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+/// The backups of `path` that fall outside the retention: past the newest
+/// `keep`, and older than `days` when that is set. `keep` 0 expires none.
+pub fn expired_backups(path: &Path, keep: usize, days: u64) -> Vec<PathBuf> {
+    if keep == 0 {
+        return vec![];
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut found = backups_of(path);
+    found.reverse();
+    found
+        .into_iter()
+        .skip(keep)
+        .filter(|(stamp, _)| days == 0 || now.saturating_sub(*stamp) > days * 86_400)
+        .map(|(_, backup)| backup)
+        .collect()
+}
@@ -0,0 +1,166 @@
+//! Builds `debug fixture` sandboxes and runs git-id against them, so the
+//! commands are exercised on real git repos, keys and config files without
+//! touching the developer's own setup.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+const GIT_ID: &str = env!("CARGO_BIN_EXE_git-id");
+
+/// Variables that would point git or git-id at the real configuration.
+const CLEARED_ENV: &[&str] = &[
+    "XDG_CONFIG_HOME",
+    "XDG_CACHE_HOME",
+    "XDG_DATA_HOME",
+    "GIT_CONFIG_GLOBAL",
+    "GIT_CONFIG_SYSTEM",
+    "GIT_CONFIG_PARAMETERS",
+    "GIT_DIR",
+    "SSH_AUTH_SOCK",
+    "GIT_ID_READONLY",
+    "SUDO_USER",
+];
+
+/// A fixture HOME under the temp dir, removed again when the test ends.
+struct Sandbox {
+    home: PathBuf,
+}
+
+impl Sandbox {
+    fn new(name: &str) -> Sandbox {
+        let home = std::env::temp_dir()
+            .join(format!("git-id-test-{name}-{}", std::process::id()))
+            .join("home");
+        let _ = std::fs::remove_dir_all(home.parent().unwrap());
+        Sandbox { home }
+    }
+
+    /// Builds `scenario` with `accounts` accounts, failing the test if it
+    /// cannot be built.
+    fn build(name: &str, scenario: &str, accounts: usize) -> Sandbox {
+        let sandbox = Sandbox::new(name);
+        let out = sandbox.fixture(scenario, accounts, &[]);
+        assert!(out.status.success(), "fixture failed: {}", stderr(&out));
+        sandbox
+    }
+
+    fn fixture(&self, scenario: &str, accounts: usize, extra: &[&str]) -> Output {
+        let dir = self.home.to_string_lossy().to_string();
+        let count = accounts.to_string();
+        let args = ["debug", "fixture", scenario, "--accounts", &count, "--dir", &dir];
+        // Building it from inside would trip the check for the real HOME.
+        let outside = self.home.parent().unwrap();
+        std::fs::create_dir_all(outside).unwrap();
+        git_id(outside, outside, &[&args[..], extra].concat())
+    }
+
+    fn repo(&self, owner: &str) -> PathBuf {
+        self.home.join("src").join(owner).join("project")
+    }
+
+    /// Runs git-id in `dir` with the sandbox as HOME.
+    fn run(&self, dir: &Path, args: &[&str]) -> Output {
+        git_id(&self.home, dir, args)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(self.home.parent().unwrap());
+    }
+}
+
+fn git_id(home: &Path, dir: &Path, args: &[&str]) -> Output {
+    let mut cmd = Command::new(GIT_ID);
+    for var in CLEARED_ENV {
+        cmd.env_remove(var);
+    }
+    cmd.env("HOME", home)
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .args(args)
+        .output()
+        .expect("cannot run git-id")
+}
+
+fn stdout(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stdout).to_string()
+}
+
+fn stderr(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stderr).to_string()
+}
+
+fn account_ids(sandbox: &Sandbox) -> Vec<String> {
+    let out = sandbox.run(&sandbox.home, &["list", "--format", "json"]);
+    assert!(out.status.success(), "list failed: {}", stderr(&out));
+    let json: serde_json::Value =
+        serde_json::from_slice(&out.stdout).expect("list printed no JSON");
+    json["accounts"]
+        .as_array()
+        .expect("no accounts array")
+        .iter()
+        .map(|a| a["id"].as_str().unwrap_or_default().to_string())
+        .collect()
+}
+
+#[test]
+fn basic_fixture_sets_up_every_account() {
+    let sandbox = Sandbox::build("basic", "basic", 3);
+    assert_eq!(account_ids(&sandbox), ["alice@github.com", "bob@github.com", "carol@github.com"]);
+    for name in ["alice", "bob", "carol"] {
+        assert!(sandbox.home.join(".ssh").join(format!("id_ed25519_{name}")).exists());
+        let out = sandbox.run(&sandbox.repo(name), &["check"]);
+        assert!(out.status.success(), "check in {name}'s repo failed: {}", stderr(&out));
+        let out = sandbox.run(&sandbox.repo(name), &["whoami"]);
+        assert!(stdout(&out).contains(&format!("account={name}@github.com")));
+    }
+}
+
+#[test]
+fn hosts_fixture_spreads_accounts_over_hosts() {
+    let sandbox = Sandbox::build("hosts", "hosts", 3);
+    assert_eq!(
+        account_ids(&sandbox),
+        ["alice@github.com", "bob@gitlab.com", "carol@github.example.com"]
+    );
+    let out = sandbox.run(&sandbox.home, &["host", "list"]);
+    let listing = stdout(&out);
+    for host in ["github.com", "gitlab.com", "github.example.com"] {
+        assert!(listing.contains(host), "host list is missing {host}:\n{listing}");
+    }
+    let out = sandbox.run(&sandbox.repo("bob"), &["status", "--format", "json"]);
+    let json: serde_json::Value = serde_json::from_slice(&out.stdout).expect("no status JSON");
+    assert_eq!(json["matched_account"], "bob@gitlab.com");
+}
+
+#[test]
+fn broken_fixture_has_problems_to_find() {
+    let sandbox = Sandbox::build("broken", "broken", 2);
+    let out = sandbox.run(&sandbox.repo("alice"), &["check"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(stderr(&out).contains("old-address@example.com"));
+    assert!(!sandbox.home.join(".ssh/id_ed25519_bob").exists());
+    let out = sandbox.run(&sandbox.home, &["doctor"]);
+    assert!(!out.status.success(), "doctor found nothing wrong:\n{}", stdout(&out));
+}
+
+#[test]
+fn fixture_dry_run_creates_nothing() {
+    let sandbox = Sandbox::new("dry-run");
+    let out = sandbox.fixture("basic", 2, &["--dry-run"]);
+    assert!(out.status.success(), "dry run failed: {}", stderr(&out));
+    assert!(!sandbox.home.exists());
+}
+
+#[test]
+fn fixture_refuses_a_directory_in_use() {
+    let sandbox = Sandbox::new("in-use");
+    std::fs::create_dir_all(&sandbox.home).unwrap();
+    std::fs::write(sandbox.home.join("keep.txt"), "mine").unwrap();
+    let out = sandbox.fixture("basic", 1, &[]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(stderr(&out).contains("not empty"));
+    assert!(!sandbox.home.join(".ssh").exists());
+}